@@ -0,0 +1,409 @@
+// SPDX-License-Identifier: MPL-2.0
+//! `hesinfo browse`: terminal UI for paging through a record set by map type.
+//!
+//! Keys: Tab/Left/Right switch map, Up/Down/PgUp/PgDn/Home/End move, `/`
+//! searches keys and TXT data, Enter toggles the full-screen detail view, `y`
+//! copies the selected record's TXT data (via the OSC 52 terminal escape),
+//! `q` quits.
+
+use std::io::Write;
+
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hesiod_lib::records::{HesiodRecord, MapType};
+use hesiod_lib::zone::HesiodZone;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Tabs, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+/// Rows moved by PgUp/PgDn.
+const PAGE: usize = 10;
+
+struct Browser {
+    domain: String,
+    lhs: String,
+    rhs: String,
+    /// Records per map type, sorted by key, in [`MapType::ALL`] order.
+    maps: Vec<(MapType, Vec<(String, HesiodRecord)>)>,
+    map_index: usize,
+    /// Indices into the current map's records that match the search.
+    visible: Vec<usize>,
+    list: ListState,
+    search: String,
+    searching: bool,
+    detail: bool,
+    status: String,
+}
+
+/// Run the browser until the user quits, restoring the terminal afterwards.
+pub fn run(zone: &HesiodZone) -> Result<()> {
+    let mut browser = Browser::new(zone);
+    let mut terminal = ratatui::init();
+    let result = browser.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Browser {
+    fn new(zone: &HesiodZone) -> Self {
+        let maps = MapType::ALL
+            .iter()
+            .map(|&map_type| {
+                let mut records: Vec<_> = zone
+                    .records()
+                    .filter(|(_, record)| record.map_type() == map_type)
+                    .map(|(name, record)| (name.to_string(), record.clone()))
+                    .collect();
+                records.sort_by(|a, b| a.0.cmp(&b.0));
+                (map_type, records)
+            })
+            .collect();
+        let mut browser = Self {
+            domain: zone.domain.clone(),
+            lhs: zone.lhs.clone(),
+            rhs: zone.rhs.clone(),
+            maps,
+            map_index: 0,
+            visible: Vec::new(),
+            list: ListState::default(),
+            search: String::new(),
+            searching: false,
+            detail: false,
+            status: String::new(),
+        };
+        browser.refilter();
+        browser
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)?
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Recompute which records of the current map match the search,
+    /// case-insensitively, and select the first of them.
+    fn refilter(&mut self) {
+        let needle = self.search.to_lowercase();
+        self.visible = self.maps[self.map_index]
+            .1
+            .iter()
+            .enumerate()
+            .filter(|(_, (name, record))| {
+                needle.is_empty()
+                    || name.to_lowercase().contains(&needle)
+                    || record.to_txt().to_lowercase().contains(&needle)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let select = if self.visible.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        self.list.select(select);
+    }
+
+    fn selected(&self) -> Option<&(String, HesiodRecord)> {
+        let index = *self.visible.get(self.list.selected()?)?;
+        self.maps[self.map_index].1.get(index)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.visible.len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, len as isize - 1);
+        self.list.select(Some(next as usize));
+    }
+
+    fn switch_map(&mut self, forward: bool) {
+        let count = self.maps.len();
+        self.map_index = if forward {
+            (self.map_index + 1) % count
+        } else {
+            (self.map_index + count - 1) % count
+        };
+        self.refilter();
+    }
+
+    /// Apply a key press. Returns `false` when the browser should exit.
+    fn handle_key(&mut self, key: KeyEvent) -> Result<bool> {
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => self.search.push(c),
+                _ => return Ok(true),
+            }
+            self.refilter();
+            return Ok(true);
+        }
+
+        self.status.clear();
+        match key.code {
+            KeyCode::Char('q') => return Ok(false),
+            KeyCode::Esc if self.detail => self.detail = false,
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => self.switch_map(true),
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => self.switch_map(false),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(PAGE as isize),
+            KeyCode::PageUp => self.move_selection(-(PAGE as isize)),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Enter => self.detail = !self.detail,
+            KeyCode::Char('y') => {
+                if let Some((name, record)) = self.selected() {
+                    let label = format!("{}.{}", name, record.map_type().label());
+                    copy_to_clipboard(&record.to_txt())?;
+                    self.status = format!("copied TXT of {label}");
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, body, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let titles: Vec<String> = self
+            .maps
+            .iter()
+            .map(|(map_type, records)| format!("{} ({})", map_type.label(), records.len()))
+            .collect();
+        frame.render_widget(
+            Tabs::new(titles)
+                .select(self.map_index)
+                .highlight_style(Style::new().bold().reversed())
+                .block(Block::bordered().title(format!(" {} ", self.domain))),
+            tabs_area,
+        );
+
+        if self.detail {
+            self.draw_detail(frame, body);
+        } else {
+            let [list_area, detail_area] =
+                Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                    .areas(body);
+            self.draw_list(frame, list_area);
+            self.draw_detail(frame, detail_area);
+        }
+
+        let footer_text = if self.searching {
+            format!("/{}", self.search)
+        } else if !self.status.is_empty() {
+            self.status.clone()
+        } else {
+            "tab: map  /: search  enter: detail  y: copy TXT  q: quit".to_string()
+        };
+        frame.render_widget(Paragraph::new(footer_text).dim(), footer);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let records = &self.maps[self.map_index].1;
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&index| ListItem::new(records[index].0.clone()))
+            .collect();
+        let title = if self.search.is_empty() {
+            format!(" {} keys ", items.len())
+        } else {
+            format!(" {} matching {:?} ", items.len(), self.search)
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" record ");
+        let Some((name, record)) = self.selected() else {
+            frame.render_widget(Paragraph::new("no records").block(block), area);
+            return;
+        };
+        let mut lines = vec![
+            Line::from(format!(
+                "{}.{}{}{}",
+                name,
+                record.map_type().label(),
+                self.lhs,
+                self.rhs
+            ))
+            .bold(),
+            Line::from(""),
+            Line::from("TXT").underlined(),
+            Line::from(record.to_txt()),
+            Line::from(""),
+            Line::from("Fields").underlined(),
+        ];
+        let fields = serde_json::to_string_pretty(record).unwrap_or_default();
+        lines.extend(fields.lines().map(|line| Line::from(line.to_string())));
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .wrap(Wrap { trim: false }),
+            area,
+        );
+    }
+}
+
+/// Put `text` on the clipboard of the terminal running us (OSC 52), which
+/// also works over SSH.
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(osc52(text).as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// OSC 52 escape that sets the clipboard selection to `text`.
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use hesiod_lib::records::{GroupRecord, ServiceRecord};
+
+    use super::*;
+
+    fn browser(services: usize) -> Browser {
+        let mut zone = HesiodZone::new("example.com", ".ns", ".example.com", 300);
+        for i in 0..services {
+            zone.add_record(
+                &format!("svc{i:02}"),
+                HesiodRecord::Service(ServiceRecord {
+                    host: format!("host{i}.example.com"),
+                    port: 8000 + i as u16,
+                    protocol: "tcp".into(),
+                }),
+            );
+        }
+        zone.add_record(
+            "ops",
+            HesiodRecord::Group(GroupRecord {
+                name: "ops".into(),
+                gid: 500,
+                members: vec!["alice".into()],
+            }),
+        );
+        let mut browser = Browser::new(&zone);
+        browser.map_index = MapType::ALL
+            .iter()
+            .position(|&map_type| map_type == MapType::Service)
+            .expect("TODO: handle error");
+        browser.refilter();
+        browser
+    }
+
+    fn press(browser: &mut Browser, code: KeyCode) {
+        assert!(
+            browser
+                .handle_key(KeyEvent::from(code))
+                .expect("TODO: handle error")
+        );
+    }
+
+    fn selected_key(browser: &Browser) -> Option<&str> {
+        browser.selected().map(|(name, _)| name.as_str())
+    }
+
+    #[test]
+    fn search_matches_keys_and_txt_case_insensitively() {
+        let mut browser = browser(12);
+        press(&mut browser, KeyCode::Char('/'));
+        for c in "SVC1".chars() {
+            press(&mut browser, KeyCode::Char(c));
+        }
+        assert_eq!(browser.visible.len(), 2);
+        assert_eq!(selected_key(&browser), Some("svc10"));
+
+        press(&mut browser, KeyCode::Esc);
+        for c in "/:8003:".chars() {
+            press(&mut browser, KeyCode::Char(c));
+        }
+        assert_eq!(browser.visible.len(), 1);
+        assert_eq!(selected_key(&browser), Some("svc03"));
+
+        for c in "nope".chars() {
+            press(&mut browser, KeyCode::Char(c));
+        }
+        assert!(browser.visible.is_empty());
+        assert_eq!(browser.selected(), None);
+        press(&mut browser, KeyCode::Enter);
+        press(&mut browser, KeyCode::Down);
+        assert_eq!(browser.list.selected(), None);
+    }
+
+    #[test]
+    fn switching_maps_reapplies_the_search() {
+        let mut browser = browser(3);
+        for c in "/ops".chars() {
+            press(&mut browser, KeyCode::Char(c));
+        }
+        press(&mut browser, KeyCode::Enter);
+        assert!(browser.visible.is_empty());
+        press(&mut browser, KeyCode::Left);
+        assert_eq!(browser.maps[browser.map_index].0, MapType::Group);
+        assert_eq!(selected_key(&browser), Some("ops"));
+    }
+
+    #[test]
+    fn selection_is_clamped_to_the_list() {
+        let mut browser = browser(25);
+        press(&mut browser, KeyCode::Up);
+        assert_eq!(browser.list.selected(), Some(0));
+        press(&mut browser, KeyCode::PageUp);
+        assert_eq!(browser.list.selected(), Some(0));
+        press(&mut browser, KeyCode::PageDown);
+        assert_eq!(browser.list.selected(), Some(PAGE));
+        press(&mut browser, KeyCode::End);
+        assert_eq!(selected_key(&browser), Some("svc24"));
+        press(&mut browser, KeyCode::Down);
+        press(&mut browser, KeyCode::PageDown);
+        assert_eq!(browser.list.selected(), Some(24));
+        press(&mut browser, KeyCode::PageUp);
+        assert_eq!(browser.list.selected(), Some(24 - PAGE));
+        press(&mut browser, KeyCode::Home);
+        assert_eq!(selected_key(&browser), Some("svc00"));
+    }
+
+    #[test]
+    fn osc52_wraps_base64_text() {
+        assert_eq!(osc52(""), "\x1b]52;c;\x07");
+        assert_eq!(
+            osc52("ops:*:500:alice"),
+            "\x1b]52;c;b3BzOio6NTAwOmFsaWNl\x07"
+        );
+        assert_eq!(osc52("ab"), "\x1b]52;c;YWI=\x07");
+    }
+}
@@ -3,19 +3,50 @@
 //!
 //! Subcommands:
 //!   lookup   - Query a Hesiod DNS record
+//!   bench    - Load-test a server with randomized lookups
+//!   probe    - One-shot health check lookup with Nagios-style exit codes
 //!   serve    - Start the DNS + HTTP server
-//!   generate - Generate a BIND-format zone file
+//!   generate - Generate a BIND zone file, tinydns data or dnsmasq config
+//!   watch    - Regenerate a zone file whenever the config changes
 //!   validate - Validate a zone file
+//!   dump     - Print the record set of a running server
+//!   monitor  - Live QPS/error/uptime view of running servers
+//!   diff     - Compare record sets from configs, zone files or servers
+//!   browse   - Interactive terminal browser for a record set
+//!   lint     - Cross-record consistency checks
+//!   doctor   - Check a new deployment end to end
+//!   import   - Convert passwd/group/services files into a config
+//!   export   - Write passwd/group files or autofs maps from a record set
+//!   add      - Add a record to a config file
+//!   remove   - Remove a record from a config file
 
 #![forbid(unsafe_code)]
-use std::path::PathBuf;
+mod browse;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use hesiod_lib::config::HesiodConfig;
-use hesiod_lib::records::MapType;
-use hesiod_lib::server::run_dns_server;
-use hesiod_lib::zone::HesiodZone;
+use hesiod_lib::audit::AuditLog;
+use hesiod_lib::client::{self, Answer, HesiodClient, RetryPolicy, TraceStep, Transport};
+use hesiod_lib::config::{FilsysEntry, GroupEntry, HesiodConfig, ServiceEntry, UserEntry};
+use hesiod_lib::config_edit::ConfigDocument;
+use hesiod_lib::export;
+use hesiod_lib::git::GitSource;
+use hesiod_lib::hesiod_conf::HesiodConf;
+use hesiod_lib::import::{self, ImportFilter};
+use hesiod_lib::lint::{self, Severity};
+use hesiod_lib::notify::Notifier;
+use hesiod_lib::nss;
+use hesiod_lib::records::{HesiodRecord, MapType};
+use hesiod_lib::s3::{S3Credentials, S3Source};
+use hesiod_lib::server::{
+    DnsServerHandle, DnsServerState, RestartPolicy, start_dns_server, start_view_server,
+};
+use hesiod_lib::signature::{PublicKey, TrustedKeys};
+use hesiod_lib::source::{ConfigSource, HttpSource};
+use hesiod_lib::zone::{HesiodZone, ZoneSet, ZoneSnapshot};
 
 #[derive(Parser)]
 #[command(name = "hesinfo", version, about = "Hesiod DNS naming system CLI")]
@@ -27,31 +58,132 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Look up a Hesiod record via DNS query
-    Lookup {
-        /// Record key (e.g. username, service name)
+    Lookup(LookupArgs),
+    /// Send lookups at a fixed rate and report latency and response codes;
+    /// queries are spread evenly over the --server list
+    Bench {
+        /// Queries per second
+        #[arg(long, default_value_t = 100)]
+        qps: u32,
+        /// How long to send queries for, in seconds
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+        /// `key map` lines (or a JSON array) to pick queries from, `-` for stdin
+        #[arg(long)]
+        keys: Option<PathBuf>,
+        /// Without --keys, query this many generated keys `bench0`, `bench1`, ...
+        #[arg(long, default_value_t = 1000)]
+        generate: usize,
+        /// Map type of generated keys
+        #[arg(long, default_value = "passwd")]
+        map: MapType,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+        #[command(flatten)]
+        query: QueryArgs,
+    },
+    /// Check one lookup for health checks; exits 0 on success, 1 when the
+    /// answer is missing or wrong, 2 on timeout or server failure
+    Probe {
+        /// Record key to look up
+        #[arg(long)]
         key: String,
-        /// Map type: passwd, group, service, filsys
-        map: String,
-        /// DNS server address
-        #[arg(long, default_value = "localhost")]
-        server: String,
-        /// DNS server port
-        #[arg(long, default_value_t = 5353)]
-        port: u16,
+        /// Map type of the key
+        #[arg(long)]
+        map: MapType,
+        /// TXT data the answer must contain
+        #[arg(long)]
+        expect: Option<String>,
+        /// Overall time limit in seconds
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+        #[command(flatten)]
+        query: QueryArgs,
     },
     /// Start the Hesiod DNS server
     Serve {
         /// Path to JSON config file (from `nickel export`)
+        #[arg(long, required_unless_present_any = ["config_url", "config_git"])]
+        config: Option<PathBuf>,
+        /// Fetch the JSON config from this http(s) URL, or from an
+        /// `s3://<bucket>/<key>` object, instead of a file. Reloads and
+        /// resyncs fetch it again, conditionally on its ETag and
+        /// Last-Modified; `server` thread settings are not read from it
+        #[arg(long, conflicts_with = "config")]
+        config_url: Option<String>,
+        /// Header sent when fetching `--config-url`, as `Name: value`
+        /// (repeatable), e.g. for authentication
+        #[arg(long, requires = "config_url", value_parser = parse_header)]
+        config_header: Vec<(String, String)>,
+        /// Endpoint of the S3-compatible store holding an `s3://` config,
+        /// e.g. `https://minio.internal:9000` (default: AWS S3). Requests
+        /// are signed with `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+        /// when set
+        #[arg(long, requires = "config_url")]
+        s3_endpoint: Option<String>,
+        /// Region the `s3://` config's bucket is in
+        #[arg(long, default_value = "us-east-1")]
+        s3_region: String,
+        /// Refuse an `s3://` config stored without a SHA-256 checksum
+        /// (one stored with a checksum is always checked)
+        #[arg(long, requires = "config_url")]
+        s3_require_checksum: bool,
+        /// Read the JSON config from this git repository (URL or path)
+        /// instead. Reloads and resyncs pull it and apply new commits, and
+        /// `/dns/version` reports the commit served
+        #[arg(long, conflicts_with_all = ["config", "config_url"])]
+        config_git: Option<String>,
+        /// Branch of `--config-git` to follow (default: the remote's default)
+        #[arg(long, requires = "config_git")]
+        config_git_branch: Option<String>,
+        /// Path of the config file in the `--config-git` repository
+        #[arg(long, default_value = "hesiod.json")]
+        config_git_path: PathBuf,
+        /// Directory `--config-git` is checked out to; local changes in it
+        /// are discarded (default: `hesiod-config` in the temp directory)
+        #[arg(long, requires = "config_git")]
+        config_git_dir: Option<PathBuf>,
+        /// Minisign public key the config must be signed with, or a file
+        /// holding one (repeatable). The signature is read from beside the
+        /// config, with `.minisig` appended, and checked on every load;
+        /// unsigned or tampered configs are refused
         #[arg(long)]
-        config: PathBuf,
+        config_pubkey: Vec<String>,
         /// UDP port for DNS
         #[arg(long, default_value_t = 53)]
         dns_port: u16,
         /// TCP port for HTTP health/metrics
         #[arg(long, default_value_t = 8080)]
         http_port: u16,
+        /// Start in drain mode (not ready) until undrained via the admin API
+        #[arg(long)]
+        drained: bool,
+        /// Seconds queries are still answered after a drain starts
+        #[arg(long, default_value_t = 30)]
+        drain_grace_secs: u64,
+        /// Times the DNS receive loop is restarted after a socket error
+        /// before the server exits
+        #[arg(long, default_value_t = 5)]
+        max_restarts: u32,
+        /// Seconds in-flight DNS queries and HTTP requests get to finish
+        /// after SIGTERM or Ctrl-C before the process exits
+        #[arg(long, default_value_t = 10)]
+        shutdown_grace_secs: u64,
+        /// Tokio worker threads (default: `server.runtime_threads`, else one
+        /// per CPU core)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        worker_threads: Option<u16>,
+        /// Run on a single thread, e.g. on small appliances (also
+        /// `server.current_thread`)
+        #[arg(long, conflicts_with = "worker_threads")]
+        current_thread: bool,
+        /// Load the config, build the zones and lint them, print what would
+        /// be served and exit without binding sockets; exits 1 on errors
+        #[arg(long)]
+        dry_run: bool,
     },
-    /// Generate a BIND-format zone file from config
+    /// Generate a BIND-format zone file (or another server's data file) from config
     Generate {
         /// Path to JSON config file
         #[arg(long)]
@@ -59,16 +191,402 @@ enum Commands {
         /// Output file path
         #[arg(long)]
         output: PathBuf,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = GenerateFormat::Bind)]
+        format: GenerateFormat,
+        /// Also write one `$INCLUDE`d file per map next to the output, which
+        /// then only holds the SOA, NS and include lines
+        #[arg(long)]
+        split_by_map: bool,
+        /// Also write PTR reverse zones for services with an address here,
+        /// one `<zone>.zone` file per network
+        #[arg(long)]
+        reverse_dir: Option<PathBuf>,
+        /// Check the written zone with named-checkzone or kzonecheck (or the
+        /// built-in parser if neither is installed) and fail on errors
+        #[arg(long)]
+        check: bool,
+    },
+    /// Regenerate a BIND zone file, bumping its serial, whenever the config changes
+    Watch {
+        /// Path to JSON config file
+        #[arg(long)]
+        config: PathBuf,
+        /// Zone file to keep up to date
+        #[arg(long)]
+        output: PathBuf,
+        /// Seconds between checks of the config file
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
     },
     /// Validate a zone file
     Validate {
         /// Path to zone file
         file: PathBuf,
+        /// Also run the cross-record checks of `lint`, flag keys defined
+        /// more than once and service hosts that don't resolve
+        #[arg(long)]
+        strict: bool,
+        /// Report format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        report: ReportFormat,
+    },
+    /// Print the full record set of a running server
+    Dump {
+        /// Base URL of the server's HTTP API
+        #[arg(long, default_value = "http://localhost:8080")]
+        server: String,
+        /// Only dump this map type
+        #[arg(long)]
+        map: Option<MapType>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DumpFormat::Json)]
+        output: DumpFormat,
+    },
+    /// Poll servers' metrics and show a live QPS, error rate and uptime table
+    Monitor {
+        /// Base URL of a server's HTTP API; repeat or comma-separate for several
+        #[arg(
+            long = "server",
+            value_delimiter = ',',
+            default_value = "http://localhost:8080"
+        )]
+        servers: Vec<String>,
+        /// Seconds between polls
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// Compare two record sets; exits 1 when they differ
+    Diff {
+        /// Old side: JSON config, zone file, or server URL
+        old: String,
+        /// New side: JSON config, zone file, or server URL
+        new: String,
+    },
+    /// Browse a record set interactively by map type
+    Browse {
+        /// JSON config, zone file, or server URL
+        source: String,
+    },
+    /// Add a record to a JSON config file in place
+    Add {
+        /// Path to JSON config file
+        #[arg(long)]
+        config: PathBuf,
+        /// Overwrite an existing entry with the same key
+        #[arg(long)]
+        replace: bool,
+        #[command(subcommand)]
+        record: AddRecord,
+    },
+    /// Remove a record from a JSON config file in place
+    Remove {
+        /// Path to JSON config file
+        #[arg(long)]
+        config: PathBuf,
+        /// Map type: passwd, group, service, filsys
+        map: MapType,
+        /// Record key
+        key: String,
+    },
+    /// Build a JSON config from passwd/group/services files or ypcat output
+    Import {
+        /// Hesiod domain of the new config
+        #[arg(long)]
+        domain: String,
+        #[arg(long, default_value = ".ns")]
+        lhs: String,
+        /// Right-hand side [default: .<domain>]
+        #[arg(long)]
+        rhs: Option<String>,
+        /// passwd-format file, `-` for stdin
+        #[arg(long)]
+        passwd: Option<PathBuf>,
+        /// group-format file, `-` for stdin
+        #[arg(long)]
+        group: Option<PathBuf>,
+        /// services-format file, `-` for stdin
+        #[arg(long, requires = "service_host")]
+        services: Option<PathBuf>,
+        /// Host every imported service points at
+        #[arg(long)]
+        service_host: Option<String>,
+        /// Only import users with a UID in this range, e.g. 1000-60000
+        #[arg(long)]
+        uid_range: Option<String>,
+        /// Only import groups with a GID in this range
+        #[arg(long)]
+        gid_range: Option<String>,
+        /// Only import users with this login shell (repeatable)
+        #[arg(long = "shell")]
+        shells: Vec<String>,
+        /// Write the config here instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write flat passwd/group files, LDIF, autofs maps or nss_hesiod client
+    /// config from a config, zone file or server
+    Export {
+        /// JSON config, zone file, or server URL
+        source: String,
+        /// File format to produce
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Write here instead of stdout; a directory for autofs and nss
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Directory auto.master refers to the autofs maps in
+        #[arg(long, default_value = "/etc")]
+        map_dir: String,
+        /// LDAP suffix for ldif; defaults to the zone domain as dc= components
+        #[arg(long)]
+        base_dn: Option<String>,
+    },
+    /// Check config, zone, ports, resolver path and hesiod.conf for a
+    /// deployment; exits 1 if any check fails
+    Doctor {
+        /// Path to JSON config file
+        #[arg(long)]
+        config: PathBuf,
+        /// DNS port the server is (or will be) started with
+        #[arg(long, default_value_t = 53)]
+        dns_port: u16,
+        /// HTTP port the server is (or will be) started with
+        #[arg(long, default_value_t = 8080)]
+        http_port: u16,
+    },
+    /// Run cross-record checks over a config or zone file; exits 1 on errors
+    Lint {
+        /// JSON config or BIND zone file
+        file: PathBuf,
+        /// Report format
+        #[arg(long, visible_alias = "report", value_enum, default_value_t = ReportFormat::Text)]
+        output: ReportFormat,
+    },
+}
+
+/// Record added by `hesinfo add`.
+#[derive(Subcommand)]
+enum AddRecord {
+    /// A passwd entry
+    User {
+        username: String,
+        #[arg(long)]
+        uid: u32,
+        #[arg(long)]
+        gid: u32,
+        #[arg(long, default_value = "")]
+        gecos: String,
+        #[arg(long)]
+        home: String,
+        #[arg(long, default_value = "/bin/bash")]
+        shell: String,
+    },
+    /// A group entry
+    Group {
+        name: String,
+        #[arg(long)]
+        gid: u32,
+        /// Comma-separated member usernames
+        #[arg(long, value_delimiter = ',')]
+        members: Vec<String>,
+    },
+    /// A service entry
+    Service {
+        name: String,
+        #[arg(long)]
+        host: String,
+        #[arg(long)]
+        port: u16,
+        #[arg(long, default_value = "tcp")]
+        protocol: String,
+        /// IP address of the host, for the reverse zone
+        #[arg(long)]
+        address: Option<std::net::IpAddr>,
+        /// Label for grouping the host, e.g. in an Ansible inventory; repeatable
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// A filesystem entry
+    Filsys {
+        name: String,
+        #[arg(long)]
+        fs_type: String,
+        #[arg(long)]
+        mount_path: String,
+        #[arg(long)]
+        source: String,
+        #[arg(long, default_value = "rw")]
+        mode: String,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+/// How `dump` prints the record set.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum DumpFormat {
+    /// The server's JSON snapshot
+    Json,
+    /// A BIND-format zone file
+    Zone,
+}
+
+/// Zone data format written by `generate`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum GenerateFormat {
+    /// BIND zone file with HS-class records
+    Bind,
+    /// tinydns-data lines; tinydns only serves class IN, so clients need
+    /// `classes=IN` in hesiod.conf
+    Tinydns,
+    /// dnsmasq `txt-record=`/`srv-host=` lines (class IN, like tinydns)
+    Dnsmasq,
+    /// unbound `local-zone`/`local-data` lines (class IN, like tinydns)
+    Unbound,
+    /// Class IN TXT records with absolute names for `$INCLUDE` into a zone
+    /// a resolver already serves
+    BindInclude,
+    /// Route53 change batch JSON upserting the TXT records (class IN)
+    Route53,
+    /// Cloudflare DNS import zone file of the TXT records (class IN)
+    Cloudflare,
+}
+
+/// Flat file written by `export`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ExportFormat {
+    /// `/etc/passwd` lines
+    Passwd,
+    /// `/etc/group` lines
+    Group,
+    /// posixAccount and posixGroup entries for an LDAP directory
+    Ldif,
+    /// Ansible inventory JSON of service hosts, grouped by service name and
+    /// (from a JSON config) by tag
+    AnsibleInventory,
+    /// auto.master plus indirect maps built from filsys records
+    Autofs,
+    /// hesiod.conf and nsswitch.conf lines for client hosts using nss_hesiod
+    Nss,
+}
+
+#[derive(clap::Args)]
+struct LookupArgs {
+    /// Record key (e.g. username, service name)
+    #[arg(required_unless_present = "batch")]
+    key: Option<String>,
+    /// Map type: passwd, group, service, filsys
+    #[arg(required_unless_present = "batch")]
+    map: Option<String>,
+    /// Read `key map` lines (or a JSON array) from a file, `-` for stdin
+    #[arg(long, conflicts_with_all = ["key", "map"])]
+    batch: Option<PathBuf>,
+    /// Concurrent queries in batch mode
+    #[arg(long, default_value_t = 16)]
+    parallelism: usize,
+    /// Seconds each server gets to connect and to answer
+    #[arg(long, default_value_t = client::DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
+    /// Print each resolution step (query, servers tried, replies) to stderr
+    #[arg(long, conflicts_with = "batch")]
+    trace: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    #[command(flatten)]
+    query: QueryArgs,
+}
+
+/// Server, naming and transport flags shared by `lookup` and `bench`.
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// DNS server address; repeat or comma-separate to try several in order
+    #[arg(long = "server", value_delimiter = ',', default_value = "localhost")]
+    servers: Vec<String>,
+    /// DNS server port, for servers given without one
+    /// [default: 5353, 853 with --tls, 443 with --https]
+    #[arg(long)]
+    port: Option<u16>,
+    /// Name left-hand side (overrides /etc/hesiod.conf)
+    #[arg(long)]
+    lhs: Option<String>,
+    /// Name right-hand side, i.e. the Hesiod domain (overrides HES_DOMAIN)
+    #[arg(long)]
+    rhs: Option<String>,
+    /// DNS class to query in
+    #[arg(long, value_enum, default_value_t = QueryClass::Hs)]
+    class: QueryClass,
+    /// Query over TCP instead of UDP
+    #[arg(long)]
+    tcp: bool,
+    /// Query over DNS-over-TLS
+    #[arg(long, conflicts_with_all = ["tcp", "https"])]
+    tls: bool,
+    /// Query over DNS-over-HTTPS; servers may be given as full URLs
+    #[arg(long, conflicts_with = "tcp")]
+    https: bool,
+    /// PEM file of additional CA certificates to trust for --tls/--https
+    #[arg(long)]
+    ca_file: Option<PathBuf>,
+    /// Name the --tls certificate is verified against (default: the server host)
+    #[arg(long)]
+    tls_server_name: Option<String>,
+    /// Accept any server certificate (testing only)
+    #[arg(long)]
+    insecure: bool,
+    /// Extra rounds over the servers once all of them have failed
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Milliseconds to wait before the first retry round, doubling (with
+    /// jitter) for each further round
+    #[arg(long, default_value_t = 200)]
+    retry_backoff_ms: u64,
+    /// Start each query at the next server instead of always the first
+    #[arg(long)]
+    rotate: bool,
+    /// Ask the /etc/resolv.conf nameservers in class IN when no server answers
+    #[arg(long)]
+    system_fallback: bool,
+}
+
+/// Human-readable or JSON output, for `lookup`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Plain text, one item per line
+    Text,
+    /// A JSON document
+    Json,
+}
+
+/// How `validate` and `lint` report findings.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReportFormat {
+    /// One `file:line: severity[code] map/key: message` line per finding
+    Text,
+    /// A JSON array of findings
+    Json,
+    /// A SARIF 2.1.0 log
+    Sarif,
+}
+
+/// DNS class used by `lookup`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum QueryClass {
+    /// Hesiod class (the classic default)
+    Hs,
+    /// Internet class, for resolvers that refuse HS
+    In,
+}
+
+impl From<QueryClass> for hickory_proto::rr::DNSClass {
+    fn from(class: QueryClass) -> Self {
+        match class {
+            QueryClass::Hs => Self::HS,
+            QueryClass::In => Self::IN,
+        }
+    }
+}
+
+fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
@@ -76,196 +594,1977 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    runtime(&cli.command)?.block_on(run(cli))
+}
 
+/// The tokio runtime for `command`: tokio's defaults, except that `serve`
+/// takes its thread settings from its flags or the config's `server` section.
+fn runtime(command: &Commands) -> Result<tokio::runtime::Runtime> {
+    let (current_thread, threads) = match command {
+        Commands::Serve {
+            config,
+            worker_threads,
+            current_thread,
+            ..
+        } => {
+            // A config that fails to load is reported by `serve` itself.
+            let server = config
+                .as_deref()
+                .and_then(|config| HesiodConfig::from_file(config).ok())
+                .map(|config| config.server)
+                .unwrap_or_default();
+            match worker_threads {
+                Some(threads) => (false, Some(usize::from(*threads))),
+                None => (
+                    *current_thread || server.current_thread,
+                    server.runtime_threads,
+                ),
+            }
+        }
+        _ => (false, None),
+    };
+    let mut builder = if current_thread {
+        tokio::runtime::Builder::new_current_thread()
+    } else {
+        tokio::runtime::Builder::new_multi_thread()
+    };
+    if let Some(threads) = threads {
+        anyhow::ensure!(threads > 0, "server.runtime_threads must be at least 1");
+        builder.worker_threads(threads);
+    }
+    Ok(builder.enable_all().build()?)
+}
+
+async fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Lookup {
+        Commands::Lookup(args) => {
+            let mut opts = LookupOptions::from_args(&args.query, args.output)?;
+            opts.client = opts
+                .client
+                .with_timeout(std::time::Duration::from_secs(args.timeout));
+            if args.trace {
+                opts.client = opts.client.with_trace(print_trace_step);
+            }
+            match &args.batch {
+                Some(batch) => cmd_lookup_batch(batch, args.parallelism, opts).await,
+                None => {
+                    let (key, map) = args.key.zip(args.map).context("key and map are required")?;
+                    cmd_lookup(&key, &map, &opts).await
+                }
+            }
+        }
+        Commands::Bench {
+            qps,
+            duration,
+            keys,
+            generate,
+            map,
+            output,
+            query,
+        } => {
+            let opts = LookupOptions::from_args(&query, output)?;
+            let queries = match keys {
+                Some(path) => parse_batch(&read_input(&path)?)?
+                    .into_iter()
+                    .map(|(key, map)| Ok((key, map.parse()?)))
+                    .collect::<Result<Vec<_>>>()?,
+                None => (0..generate).map(|n| (format!("bench{n}"), map)).collect(),
+            };
+            let run = BenchRun {
+                qps,
+                duration: std::time::Duration::from_secs(duration),
+            };
+            cmd_bench(queries, &run, opts).await
+        }
+        Commands::Probe {
             key,
             map,
-            server,
-            port,
-        } => cmd_lookup(&key, &map, &server, port).await,
+            expect,
+            timeout,
+            query,
+        } => {
+            let opts = LookupOptions::from_args(&query, OutputFormat::Text)?;
+            let timeout = std::time::Duration::from_secs(timeout);
+            let code = cmd_probe(&key, map, expect.as_deref(), timeout, &opts).await;
+            std::process::exit(code);
+        }
         Commands::Serve {
+            config,
+            config_url,
+            config_header,
+            s3_endpoint,
+            s3_region,
+            s3_require_checksum,
+            config_git,
+            config_git_branch,
+            config_git_path,
+            config_git_dir,
+            config_pubkey,
+            dns_port,
+            http_port,
+            drained,
+            drain_grace_secs,
+            max_restarts,
+            shutdown_grace_secs,
+            dry_run,
+            ..
+        } => {
+            let opts = ServeOptions {
+                dns_port,
+                http_port,
+                drained,
+                drain_grace: std::time::Duration::from_secs(drain_grace_secs),
+                max_restarts,
+                shutdown_grace: std::time::Duration::from_secs(shutdown_grace_secs),
+                dry_run,
+            };
+            let source = match (config, config_url, config_git) {
+                (_, _, Some(repo)) => {
+                    let checkout = config_git_dir
+                        .unwrap_or_else(|| std::env::temp_dir().join("hesiod-config"));
+                    let branch = config_git_branch.as_deref();
+                    ConfigSource::Git(GitSource::new(&repo, branch, &config_git_path, &checkout))
+                }
+                (_, Some(url), None) if url.starts_with("s3://") => {
+                    let credentials = S3Credentials::from_env();
+                    let source =
+                        S3Source::new(&url, s3_endpoint.as_deref(), &s3_region, credentials)?;
+                    ConfigSource::S3(source.require_checksum(s3_require_checksum))
+                }
+                (_, Some(url), None) => ConfigSource::Http(HttpSource::new(&url, &config_header)?),
+                (Some(path), None, None) => ConfigSource::File(path),
+                (None, None, None) => {
+                    anyhow::bail!("--config, --config-url or --config-git is required")
+                }
+            };
+            let source = match config_pubkey.as_slice() {
+                [] => source,
+                keys => source.signed(parse_public_keys(keys)?),
+            };
+            cmd_serve(&source, &opts).await
+        }
+        Commands::Generate {
+            config,
+            output,
+            format,
+            split_by_map,
+            reverse_dir,
+            check,
+        } => cmd_generate(
+            &config,
+            &output,
+            format,
+            split_by_map,
+            reverse_dir.as_deref(),
+            check,
+        ),
+        Commands::Watch {
+            config,
+            output,
+            interval,
+        } => cmd_watch(&config, &output, std::time::Duration::from_secs(interval)).await,
+        Commands::Validate {
+            file,
+            strict,
+            report,
+        } => cmd_validate(&file, strict, report).await,
+        Commands::Dump {
+            server,
+            map,
+            output,
+        } => cmd_dump(&server, map, output).await,
+        Commands::Browse { source } => browse::run(&load_zone(&source).await?),
+        Commands::Monitor { servers, interval } => {
+            cmd_monitor(&servers, std::time::Duration::from_secs(interval.max(1))).await
+        }
+        Commands::Diff { old, new } => cmd_diff(&old, &new).await,
+        Commands::Lint { file, output } => cmd_lint(&file, output),
+        Commands::Doctor {
             config,
             dns_port,
             http_port,
-        } => cmd_serve(&config, dns_port, http_port).await,
-        Commands::Generate { config, output } => cmd_generate(&config, &output),
-        Commands::Validate { file } => cmd_validate(&file),
+        } => cmd_doctor(&config, dns_port, http_port).await,
+        Commands::Import {
+            domain,
+            lhs,
+            rhs,
+            passwd,
+            group,
+            services,
+            service_host,
+            uid_range,
+            gid_range,
+            shells,
+            output,
+        } => {
+            let filter = ImportFilter {
+                uid_range: uid_range.as_deref().map(import::parse_range).transpose()?,
+                gid_range: gid_range.as_deref().map(import::parse_range).transpose()?,
+                shells,
+            };
+            let mut config =
+                HesiodConfig::new(&domain, &lhs, &rhs.unwrap_or_else(|| format!(".{domain}")));
+            if let Some(path) = passwd {
+                config.users = import::parse_passwd(&read_input(&path)?, &filter)?;
+            }
+            if let Some(path) = group {
+                config.groups = import::parse_group(&read_input(&path)?, &filter)?;
+            }
+            if let (Some(path), Some(host)) = (services, service_host) {
+                config.services = import::parse_services(&read_input(&path)?, &host)?;
+            }
+            cmd_import(&config, output.as_deref())
+        }
+        Commands::Add {
+            config,
+            replace,
+            record,
+        } => cmd_add(&config, record, replace),
+        Commands::Remove { config, map, key } => cmd_remove(&config, map, &key),
+        Commands::Export {
+            source,
+            format,
+            output,
+            map_dir,
+            base_dn,
+        } => {
+            cmd_export(
+                &source,
+                format,
+                output.as_deref(),
+                &map_dir,
+                base_dn.as_deref(),
+            )
+            .await
+        }
     }
 }
 
-/// Send a DNS query to a Hesiod server and print the result.
-async fn cmd_lookup(key: &str, map: &str, server: &str, port: u16) -> Result<()> {
-    use hickory_proto::op::{Message, MessageType, OpCode, Query};
-    use hickory_proto::rr::record_data::RData;
-    use hickory_proto::rr::{DNSClass, Name, RecordType};
-    use tokio::net::UdpSocket;
+/// Flags shared by single and batch `hesinfo lookup`, `bench` and `probe`.
+struct LookupOptions {
+    output: OutputFormat,
+    client: HesiodClient,
+}
 
+impl LookupOptions {
+    fn from_args(args: &QueryArgs, output: OutputFormat) -> Result<Self> {
+        let mut naming = HesiodConf::from_system()?;
+        if let Some(lhs) = &args.lhs {
+            naming.set_lhs(lhs);
+        }
+        if let Some(rhs) = &args.rhs {
+            naming.set_rhs(rhs);
+        }
+        let transport = match (args.tcp, args.tls, args.https) {
+            (_, true, _) => Transport::Tls,
+            (_, _, true) => Transport::Https,
+            (true, _, _) => Transport::Tcp,
+            _ => Transport::Udp,
+        };
+        let mut client = HesiodClient::new(args.servers.clone(), naming)?
+            .with_class(args.class.into())
+            .with_transport(transport)
+            .with_tls_config(client::tls_config(args.ca_file.as_deref(), args.insecure)?)?
+            .with_retries(RetryPolicy {
+                retries: args.retries,
+                initial_backoff: std::time::Duration::from_millis(args.retry_backoff_ms),
+                ..RetryPolicy::default()
+            })
+            .with_rotation(args.rotate)
+            .with_system_fallback(args.system_fallback);
+        if let Some(port) = args.port {
+            client = client.with_port(port);
+        }
+        if let Some(name) = &args.tls_server_name {
+            client = client.with_tls_server_name(name);
+        }
+        Ok(Self { output, client })
+    }
+}
+
+/// Send a DNS query to a Hesiod server and print the result.
+async fn cmd_lookup(key: &str, map: &str, opts: &LookupOptions) -> Result<()> {
     let map_type: MapType = map.parse()?;
+    let answer = opts.client.lookup(key, map_type).await?;
 
-    // Build the query name: <key>.<map>.ns.<server-inferred-domain>
-    // For simplicity, we construct the full name and let the server resolve it.
-    // The user is expected to provide the full domain or we use a reasonable default.
-    let qname = format!("{}.{}.ns", key, map_type.label());
-    let name: Name = qname.parse().context("invalid DNS name")?;
-
-    let mut query = Query::new();
-    query.set_name(name.clone());
-    query.set_query_type(RecordType::TXT);
-    query.set_query_class(DNSClass::HS);
-
-    let mut msg = Message::new();
-    msg.set_id(rand_id());
-    msg.set_message_type(MessageType::Query);
-    msg.set_op_code(OpCode::Query);
-    msg.set_recursion_desired(false);
-    msg.add_query(query);
-
-    let wire = msg.to_vec()?;
-
-    let sock = UdpSocket::bind("0.0.0.0:0").await?;
-    let addr = format!("{}:{}", server, port);
-    sock.send_to(&wire, &addr).await?;
-
-    let mut buf = vec![0u8; 4096];
-    let (len, _) =
-        tokio::time::timeout(std::time::Duration::from_secs(5), sock.recv_from(&mut buf))
-            .await
-            .context("DNS query timed out")??;
+    match opts.output {
+        OutputFormat::Text => {
+            if answer.txts.is_empty() {
+                println!("No records found for {}.{}", key, map_type.label());
+            }
+            for txt in &answer.txts {
+                println!("{}", txt);
+            }
+            if opts.client.server_addrs().len() > 1 {
+                eprintln!("; answered by {}", answer.server);
+            }
+        }
+        OutputFormat::Json => {
+            let doc = lookup_json(key, map_type, &answer);
+            println!("{}", serde_json::to_string_pretty(&doc)?);
+        }
+    }
+    Ok(())
+}
 
-    let response = Message::from_vec(&buf[..len])?;
+/// One `lookup --trace` line (or a few), on stderr so stdout stays parseable.
+fn print_trace_step(step: &TraceStep) {
+    let ms = |elapsed: &std::time::Duration| elapsed.as_secs_f64() * 1000.0;
+    match step {
+        TraceStep::Cached { qname } => eprintln!(";; {qname}: answered from the cache"),
+        TraceStep::Query {
+            qname,
+            class,
+            bytes,
+        } => eprintln!(";; query {qname} {class} TXT, {bytes} bytes"),
+        TraceStep::Send { server, transport } => {
+            eprintln!(";; -> {server} over {}", transport.label())
+        }
+        TraceStep::Response {
+            server,
+            rcode,
+            bytes,
+            elapsed,
+            txts,
+        } => {
+            eprintln!(
+                ";; <- {server}: {rcode}, {bytes} bytes in {:.1} ms",
+                ms(elapsed)
+            );
+            for txt in txts {
+                eprintln!(";;    TXT {txt:?}");
+            }
+        }
+        TraceStep::Failed {
+            server,
+            elapsed,
+            error,
+        } => eprintln!(";; {server} failed after {:.1} ms: {error}", ms(elapsed)),
+        TraceStep::Retry { round, backoff } => {
+            eprintln!(";; retry round {round} in {:.0} ms", ms(backoff))
+        }
+        TraceStep::Fallback { error } => {
+            eprintln!(";; {error}; asking the system resolvers in class IN")
+        }
+    }
+}
 
-    if response.answers().is_empty() {
-        println!("No records found for {}.{}", key, map_type.label());
-    } else {
-        for answer in response.answers() {
-            let rdata: &RData = answer.data();
-            if let RData::TXT(txt) = rdata {
-                for s in txt.iter() {
-                    println!("{}", std::str::from_utf8(s).unwrap_or("<binary>"));
+/// JSON document for one lookup, with each TXT string parsed into a typed record.
+fn lookup_json(key: &str, map_type: MapType, answer: &Answer) -> serde_json::Value {
+    let records: Vec<serde_json::Value> = answer
+        .txts
+        .iter()
+        .map(|txt| match HesiodRecord::from_txt(map_type, txt) {
+            Ok(record) => serde_json::to_value(record).unwrap_or_default(),
+            Err(e) => serde_json::json!({ "raw": txt, "error": e.to_string() }),
+        })
+        .collect();
+    serde_json::json!({
+        "key": key,
+        "map": map_type,
+        "server": answer.server,
+        "records": records,
+    })
+}
+
+/// One entry of a batch lookup input.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum BatchEntry {
+    Object { key: String, map: String },
+    Pair(String, String),
+}
+
+/// Parse batch input: either a JSON array of `{"key", "map"}` objects or
+/// `[key, map]` pairs, or whitespace-separated `key map` lines.
+fn parse_batch(content: &str) -> Result<Vec<(String, String)>> {
+    if content.trim_start().starts_with('[') {
+        let entries: Vec<BatchEntry> =
+            serde_json::from_str(content).context("parsing JSON batch input")?;
+        return Ok(entries
+            .into_iter()
+            .map(|entry| match entry {
+                BatchEntry::Object { key, map } | BatchEntry::Pair(key, map) => (key, map),
+            })
+            .collect());
+    }
+
+    let mut pairs = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(key), Some(map), None) => pairs.push((key.to_string(), map.to_string())),
+            _ => anyhow::bail!("line {}: expected `key map`", line_no + 1),
+        }
+    }
+    Ok(pairs)
+}
+
+/// Run many lookups concurrently, printing one result per line in input order.
+/// Exits non-zero if any lookup failed.
+async fn cmd_lookup_batch(
+    batch: &std::path::Path,
+    parallelism: usize,
+    opts: LookupOptions,
+) -> Result<()> {
+    let pairs = parse_batch(&read_input(batch)?)?;
+
+    let output = opts.output;
+    let opts = Arc::new(opts);
+    let permits = Arc::new(tokio::sync::Semaphore::new(parallelism.max(1)));
+    let tasks: Vec<_> = pairs
+        .into_iter()
+        .map(|(key, map)| {
+            let permits = Arc::clone(&permits);
+            let opts = Arc::clone(&opts);
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let result = match map.parse::<MapType>() {
+                    Ok(map_type) => opts
+                        .client
+                        .lookup(&key, map_type)
+                        .await
+                        .map(|answer| (map_type, answer)),
+                    Err(e) => Err(e),
+                };
+                (key, map, result)
+            })
+        })
+        .collect();
+
+    let mut failures = 0;
+    for task in tasks {
+        let (key, map, result) = task.await?;
+        let line = match (output, result) {
+            (OutputFormat::Json, Ok((map_type, answer))) => {
+                lookup_json(&key, map_type, &answer).to_string()
+            }
+            (OutputFormat::Json, Err(e)) => {
+                failures += 1;
+                serde_json::json!({ "key": key, "map": map, "error": format!("{e:#}") }).to_string()
+            }
+            (OutputFormat::Text, Ok((_, answer))) if answer.txts.is_empty() => {
+                format!("{key}.{map}\tNOTFOUND")
+            }
+            (OutputFormat::Text, Ok((_, answer))) => {
+                format!("{key}.{map}\t{}", answer.txts.join("\t"))
+            }
+            (OutputFormat::Text, Err(e)) => {
+                failures += 1;
+                format!("{key}.{map}\tERROR {e:#}")
+            }
+        };
+        println!("{}", line);
+    }
+
+    if failures > 0 {
+        eprintln!("{} lookups failed", failures);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Rate and length of a `hesinfo bench` run.
+struct BenchRun {
+    qps: u32,
+    duration: std::time::Duration,
+}
+
+/// Outcome of one benchmark query.
+enum BenchOutcome {
+    Response(hickory_proto::op::ResponseCode),
+    Timeout,
+    Error,
+}
+
+/// Whether `e` was caused by a query timeout: tokio's for UDP, TCP and DoT,
+/// reqwest's for DoH.
+fn timed_out(e: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(e), |e| e.source()).any(|cause| {
+        cause.is::<tokio::time::error::Elapsed>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(reqwest::Error::is_timeout)
+    })
+}
+
+/// Fire randomly chosen queries at `run.qps` for `run.duration`, then report
+/// latency percentiles and the response code breakdown.
+async fn cmd_bench(
+    queries: Vec<(String, MapType)>,
+    run: &BenchRun,
+    opts: LookupOptions,
+) -> Result<()> {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    if queries.is_empty() {
+        anyhow::bail!("no queries to send");
+    }
+    if run.qps == 0 {
+        anyhow::bail!("--qps must be at least 1");
+    }
+    let wires = queries
+        .iter()
+        .map(|(key, map_type)| opts.client.build_query(key, *map_type))
+        .collect::<hesiod_lib::Result<Vec<_>>>()?;
+    let addrs = opts.client.server_addrs();
+    let output = opts.output;
+    let opts = Arc::new(opts);
+
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(run.qps)));
+    let mut rng = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+        | 1;
+    let mut tasks = tokio::task::JoinSet::new();
+    let started = Instant::now();
+    let mut sent = 0usize;
+    while started.elapsed() < run.duration {
+        ticker.tick().await;
+        // xorshift64: spread queries over keys without a rand dependency.
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        let wire = wires[rng as usize % wires.len()].clone();
+        let addr = addrs[sent % addrs.len()].clone();
+        let opts = Arc::clone(&opts);
+        sent += 1;
+        tasks.spawn(async move {
+            let start = Instant::now();
+            let outcome = match opts.client.exchange(&wire, &addr).await {
+                Ok(response) => BenchOutcome::Response(response.response_code()),
+                Err(e) if timed_out(&e) => BenchOutcome::Timeout,
+                Err(e) => {
+                    tracing::debug!("{} failed: {:#}", addr, e);
+                    BenchOutcome::Error
                 }
+            };
+            (start.elapsed(), outcome)
+        });
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let mut latencies = Vec::with_capacity(sent);
+    let mut rcodes: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut timeouts, mut errors) = (0usize, 0usize);
+    while let Some(result) = tasks.join_next().await {
+        let (latency, outcome) = result?;
+        match outcome {
+            BenchOutcome::Response(rcode) => {
+                latencies.push(latency);
+                *rcodes.entry(format!("{rcode:?}")).or_default() += 1;
             }
+            BenchOutcome::Timeout => timeouts += 1,
+            BenchOutcome::Error => errors += 1,
         }
     }
+    latencies.sort_unstable();
+    let percentile = |p: f64| -> f64 {
+        if latencies.is_empty() {
+            return 0.0;
+        }
+        let index = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len()) - 1;
+        latencies[index].as_secs_f64() * 1000.0
+    };
+    let failed = timeouts + errors;
+    let error_rate = failed as f64 / sent as f64;
 
+    match output {
+        OutputFormat::Text => {
+            println!(
+                "sent {} queries in {:.1}s ({:.1} qps achieved)",
+                sent,
+                elapsed,
+                latencies.len() as f64 / elapsed
+            );
+            println!(
+                "latency ms: p50 {:.2}  p90 {:.2}  p99 {:.2}  max {:.2}",
+                percentile(0.5),
+                percentile(0.9),
+                percentile(0.99),
+                percentile(1.0)
+            );
+            println!(
+                "failures: {} timeouts, {} errors ({:.2}%)",
+                timeouts,
+                errors,
+                error_rate * 100.0
+            );
+            for (rcode, count) in &rcodes {
+                println!("  {:<10} {}", rcode, count);
+            }
+        }
+        OutputFormat::Json => {
+            let doc = serde_json::json!({
+                "sent": sent,
+                "elapsed_secs": elapsed,
+                "achieved_qps": latencies.len() as f64 / elapsed,
+                "latency_ms": {
+                    "p50": percentile(0.5),
+                    "p90": percentile(0.9),
+                    "p99": percentile(0.99),
+                    "max": percentile(1.0),
+                },
+                "timeouts": timeouts,
+                "errors": errors,
+                "error_rate": error_rate,
+                "rcodes": rcodes,
+            });
+            println!("{}", serde_json::to_string_pretty(&doc)?);
+        }
+    }
     Ok(())
 }
 
+/// Run a single lookup and print a one-line status; returns the exit code.
+async fn cmd_probe(
+    key: &str,
+    map_type: MapType,
+    expect: Option<&str>,
+    timeout: std::time::Duration,
+    opts: &LookupOptions,
+) -> i32 {
+    let name = format!("{}.{}", key, map_type.label());
+    let started = std::time::Instant::now();
+    let answer = match tokio::time::timeout(timeout, opts.client.lookup(key, map_type)).await {
+        Ok(Ok(answer)) => answer,
+        Ok(Err(e)) => {
+            println!("CRITICAL - {name}: {e:#}");
+            return 2;
+        }
+        Err(_) => {
+            println!("CRITICAL - {name}: no answer within {}s", timeout.as_secs());
+            return 2;
+        }
+    };
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let problem = if answer.txts.is_empty() {
+        Some("no records".to_string())
+    } else if let Some(expected) = expect
+        && !answer.txts.iter().any(|txt| txt == expected)
+    {
+        Some(format!(
+            "expected {expected:?}, got {:?}",
+            answer.txts.join(" | ")
+        ))
+    } else {
+        answer
+            .txts
+            .iter()
+            .find_map(|txt| HesiodRecord::from_txt(map_type, txt).err())
+            .map(|e| format!("unparseable record: {e:#}"))
+    };
+    match problem {
+        Some(problem) => {
+            println!("WARNING - {name} on {}: {problem}", answer.server);
+            1
+        }
+        None => {
+            println!(
+                "OK - {name} on {} in {elapsed_ms:.1} ms: {}",
+                answer.server,
+                answer.txts.join(" | ")
+            );
+            0
+        }
+    }
+}
+
+/// Flags for `hesinfo serve`.
+struct ServeOptions {
+    dns_port: u16,
+    http_port: u16,
+    drained: bool,
+    drain_grace: std::time::Duration,
+    max_restarts: u32,
+    shutdown_grace: std::time::Duration,
+    dry_run: bool,
+}
+
+/// A `Name: value` header argument.
+fn parse_header(arg: &str) -> std::result::Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("expected `Name: value`, got {arg:?}"))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// `--config-pubkey` values: minisign public keys, or files holding them.
+fn parse_public_keys(keys: &[String]) -> Result<TrustedKeys> {
+    keys.iter()
+        .map(|key| {
+            let text = if Path::new(key).is_file() {
+                std::fs::read_to_string(key).with_context(|| format!("reading public key {key}"))?
+            } else {
+                key.clone()
+            };
+            text.parse::<PublicKey>()
+                .with_context(|| format!("invalid public key {key}"))
+        })
+        .collect::<Result<_>>()
+        .map(TrustedKeys::new)
+}
+
 /// Start the DNS server and HTTP health endpoints.
-async fn cmd_serve(config_path: &std::path::Path, dns_port: u16, http_port: u16) -> Result<()> {
-    let config = HesiodConfig::from_file(config_path)?;
-    let zone = HesiodZone::from_config(&config)?;
+async fn cmd_serve(source: &ConfigSource, opts: &ServeOptions) -> Result<()> {
+    let config = source.load().await?;
+    let zones = ZoneSet::from_config(&config)?;
+
+    if opts.dry_run {
+        return serve_dry_run(source, &config, &zones, opts);
+    }
+    for zone in zones.iter() {
+        tracing::info!(
+            "loaded {} records for domain {}",
+            zone.record_count(),
+            zone.domain
+        );
+    }
+
+    let audit = match &config.admin.audit_log {
+        Some(path) => AuditLog::open(path)?,
+        None => AuditLog::default(),
+    };
+    let state = Arc::new(
+        DnsServerState::with_zones(zones)
+            .with_admin(config.admin.clone())
+            .with_audit_log(audit)
+            .with_notifier(Notifier::new(&config.notify))
+            .with_source(source.clone())
+            .with_drain_grace(opts.drain_grace)
+            .with_server(config.server.clone())
+            .with_restart_policy(RestartPolicy::restarts(opts.max_restarts)),
+    );
+    if opts.drained {
+        state.start_drain();
+    }
+    let mut listeners = vec![start_dns_server(Arc::clone(&state), opts.dns_port).await?];
+    for view in 0..config.server.views.len() {
+        listeners.push(start_view_server(Arc::clone(&state), view).await?);
+    }
+    hesiod_lib::metrics::spawn_stats_checkpoint(Arc::clone(&state), &config.metrics)?;
+    hesiod_lib::metrics::spawn_metrics_push(Arc::clone(&state), config.metrics.clone());
+    hesiod_lib::metrics::spawn_metrics_history(Arc::clone(&state));
+    hesiod_lib::source::spawn_periodic_sync(Arc::clone(&state), &config.sync);
+
+    let (stop_http, http_stopped) = tokio::sync::oneshot::channel::<()>();
+    let http = hesiod_lib::health::run_health_server(
+        Arc::clone(&state),
+        opts.http_port,
+        &config.http,
+        async {
+            http_stopped.await.ok();
+        },
+    );
+    let dns = wait_listeners(listeners);
+    tokio::pin!(http, dns);
+    tokio::select! {
+        result = &mut dns => return result.context("DNS server failed"),
+        result = &mut http => return Ok(result?),
+        result = shutdown_signal() => result.context("listening for shutdown signals")?,
+    }
 
     tracing::info!(
-        "loaded {} records for domain {}",
-        zone.record_count(),
-        zone.domain
+        "shutting down, {}s for in-flight requests",
+        opts.shutdown_grace.as_secs()
     );
+    state.start_drain();
+    state.stop_dns_server();
+    stop_http.send(()).ok();
+    match tokio::time::timeout(opts.shutdown_grace, async { tokio::join!(dns, http) }).await {
+        Ok((dns, http)) => {
+            dns.context("DNS server failed")?;
+            http?;
+        }
+        Err(_) => tracing::warn!("requests still in flight after the grace period"),
+    }
+    if let Some(path) = &config.metrics.state_file {
+        hesiod_lib::metrics::PersistedStats::capture(&state).save(path)?;
+    }
+    tracing::info!("shutdown complete");
+    Ok(())
+}
+
+/// Wait for every DNS listener to stop, failing as soon as one fails.
+async fn wait_listeners(listeners: Vec<DnsServerHandle>) -> Result<()> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tasks.spawn(listener.wait());
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.context("waiting for a DNS listener")??;
+    }
+    Ok(())
+}
+
+/// Resolves on SIGTERM or Ctrl-C (SIGINT).
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
 
-    let state = run_dns_server(zone, dns_port).await?;
-    hesiod_lib::health::run_health_server(state, http_port).await?;
+/// `serve --dry-run`: lint every zone and print the zones, listeners and
+/// runtime `serve` would start with.
+fn serve_dry_run(
+    source: &ConfigSource,
+    config: &HesiodConfig,
+    zones: &ZoneSet,
+    opts: &ServeOptions,
+) -> Result<()> {
+    let mut findings = lint::lint_config(config)?;
+    for extra in &config.zones {
+        findings.extend(lint::lint_config(&extra.to_config())?);
+    }
+    let (source, keys) = match source {
+        ConfigSource::Signed(source, keys) => (source.as_ref(), Some(keys)),
+        source => (source, None),
+    };
+    let config_path = match source {
+        ConfigSource::File(path) => path.clone(),
+        source => PathBuf::from(source.to_string()),
+    };
+    print_report(&config_path, &mut findings, ReportFormat::Text)?;
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
 
+    println!("Config: {}", config_path.display());
+    if let Some(keys) = keys {
+        println!("Signature: verified (keys {})", keys.ids().join(", "));
+    }
+    for zone in zones.iter() {
+        let per_map: Vec<_> = MapType::ALL
+            .iter()
+            .map(|map_type| {
+                let count = zone
+                    .records()
+                    .filter(|(_, record)| record.map_type() == *map_type)
+                    .count();
+                format!("{map_type} {count}")
+            })
+            .collect();
+        println!(
+            "Zone {} ({}{}): {} records ({})",
+            zone.domain,
+            zone.lhs,
+            zone.rhs,
+            zone.record_count(),
+            per_map.join(", ")
+        );
+    }
+    println!("DNS: udp 0.0.0.0:{}", opts.dns_port);
+    for view in &config.server.views {
+        println!(
+            "DNS view {}: udp 0.0.0.0:{} (tags {})",
+            view.name,
+            view.port,
+            view.tags.join(", ")
+        );
+    }
+    for (map_type, networks) in &config.server.map_acl {
+        let networks: Vec<_> = networks.iter().map(ToString::to_string).collect();
+        println!("DNS {map_type} map: only from {}", networks.join(", "));
+    }
+    if let Some(secs) = config.sync.interval_secs {
+        println!("Resync: every {secs}s (±{}%)", config.sync.jitter.min(100));
+    }
+    match &config.http.tls {
+        _ if !config.http.tcp => println!("HTTP: tcp off"),
+        Some(tls) => {
+            hesiod_lib::http_tls::server_config(tls)?;
+            println!(
+                "HTTPS: tcp {} (cert {})",
+                std::net::SocketAddr::new(config.http.bind, opts.http_port),
+                tls.cert.display()
+            );
+        }
+        None => println!(
+            "HTTP: tcp {}",
+            std::net::SocketAddr::new(config.http.bind, opts.http_port)
+        ),
+    }
+    if let Some(socket) = &config.http.unix_socket {
+        println!(
+            "HTTP: unix {} (mode {})",
+            socket.path.display(),
+            socket.mode
+        );
+    }
+    if let Some(limits) = &config.http.rate_limit {
+        println!(
+            "HTTP rate limit per client: reads {}/s (burst {}), writes {}/s (burst {})",
+            limits.read_per_second, limits.read_burst, limits.write_per_second, limits.write_burst
+        );
+    }
+    let runtime = tokio::runtime::Handle::current();
+    match runtime.runtime_flavor() {
+        tokio::runtime::RuntimeFlavor::CurrentThread => println!("Runtime: current-thread"),
+        _ => println!(
+            "Runtime: multi-thread, {} worker threads",
+            runtime.metrics().num_workers()
+        ),
+    }
+    println!(
+        "Query workers: {}, queue depth {}",
+        config.server.workers.max(1),
+        config.server.queue_depth.max(1)
+    );
+    println!("{} findings, {} errors", findings.len(), errors);
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-/// Generate a BIND-format zone file from JSON config.
-fn cmd_generate(config_path: &std::path::Path, output: &std::path::Path) -> Result<()> {
+/// Generate a BIND-format zone file from JSON config. The serial follows the
+/// one already in `output`, so secondaries always see it increase.
+fn cmd_generate(
+    config_path: &std::path::Path,
+    output: &std::path::Path,
+    format: GenerateFormat,
+    split_by_map: bool,
+    reverse_dir: Option<&std::path::Path>,
+    check: bool,
+) -> Result<()> {
+    use hesiod_lib::formats::tinydns_serial;
+    use hesiod_lib::zone::next_serial;
+    use hesiod_lib::zonefile::ZoneFile;
+
+    if (split_by_map || reverse_dir.is_some() || check) && format != GenerateFormat::Bind {
+        anyhow::bail!("--split-by-map, --reverse-dir and --check only apply to --format bind");
+    }
+
     let config = HesiodConfig::from_file(config_path)?;
     let zone = HesiodZone::from_config(&config)?;
-    let bind_zone = zone.to_bind_zone();
+    let previous = std::fs::read_to_string(output)
+        .ok()
+        .and_then(|text| match format {
+            GenerateFormat::Bind => ZoneFile::parse(&text).ok()?.serial,
+            GenerateFormat::Tinydns => tinydns_serial(&text),
+            GenerateFormat::Dnsmasq
+            | GenerateFormat::Unbound
+            | GenerateFormat::BindInclude
+            | GenerateFormat::Route53
+            | GenerateFormat::Cloudflare => None,
+        });
+    let serial = next_serial(previous, std::time::SystemTime::now());
+    let data = match format {
+        GenerateFormat::Tinydns => zone.to_tinydns(serial),
+        GenerateFormat::Dnsmasq => zone.to_dnsmasq(),
+        GenerateFormat::Unbound => zone.to_unbound(),
+        GenerateFormat::BindInclude => zone.to_bind_include(),
+        GenerateFormat::Route53 => zone.to_route53(),
+        GenerateFormat::Cloudflare => zone.to_cloudflare(),
+        GenerateFormat::Bind if split_by_map => {
+            let dir = output.parent().unwrap_or(std::path::Path::new(""));
+            let (master, files) = zone.to_split_bind_zone(serial, &dir.to_string_lossy());
+            for (name, content) in files {
+                let path = dir.join(name);
+                std::fs::write(&path, content)
+                    .with_context(|| format!("writing zone file to {}", path.display()))?;
+            }
+            master
+        }
+        GenerateFormat::Bind => zone.to_bind_zone_with_serial(serial),
+    };
 
-    std::fs::write(output, &bind_zone)
+    std::fs::write(output, &data)
         .with_context(|| format!("writing zone file to {}", output.display()))?;
 
+    if let Some(dir) = reverse_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        for (name, content) in hesiod_lib::reverse::reverse_zone_files(&config, serial)? {
+            let path = dir.join(format!("{name}.zone"));
+            std::fs::write(&path, content)
+                .with_context(|| format!("writing zone file to {}", path.display()))?;
+            println!("Generated reverse zone {} -> {}", name, path.display());
+        }
+    }
+
     println!(
-        "Generated zone file with {} records -> {}",
+        "Generated zone file with {} records (serial {}) -> {}",
         zone.record_count(),
+        serial,
         output.display()
     );
+
+    if check {
+        let origin = ZoneFile::parse(&data)?.origin.unwrap_or_default();
+        check_zone(output, &origin)?;
+    }
     Ok(())
 }
 
-/// Validate a zone file by parsing each TXT record line.
-fn cmd_validate(file: &std::path::Path) -> Result<()> {
-    let content =
-        std::fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+/// Check a written zone with the first of named-checkzone and kzonecheck
+/// that is installed, falling back to [`check_zone_internally`].
+fn check_zone(path: &std::path::Path, origin: &str) -> Result<()> {
+    let file = path.to_string_lossy();
+    let checkers = [
+        ("named-checkzone", vec!["-c", "HS", origin, &file]),
+        ("kzonecheck", vec!["-o", origin, &file]),
+    ];
+    for (program, args) in checkers {
+        match std::process::Command::new(program).args(&args).output() {
+            Ok(output) if output.status.success() => {
+                println!("{program}: {} OK", path.display());
+                return Ok(());
+            }
+            Ok(output) => anyhow::bail!(
+                "{program} rejected {}:\n{}{}",
+                path.display(),
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("running {program}")),
+        }
+    }
+    check_zone_internally(path)
+}
+
+/// Check a zone with [`validate_records`], for hosts without an external
+/// zone checker.
+fn check_zone_internally(path: &std::path::Path) -> Result<()> {
+    let file = hesiod_lib::zonefile::ZoneFile::read(path)?;
+    let errors = validate_records(&file);
+    if errors > 0 {
+        anyhow::bail!("{} has {} invalid records", path.display(), errors);
+    }
+    println!(
+        "Checked {} records in {}: OK",
+        file.entries.len(),
+        path.display()
+    );
+    Ok(())
+}
 
-    let mut errors = 0;
-    let mut records = 0;
+/// Poll `config_path` and rewrite `output` with a new serial each time the
+/// records change. Unreadable or invalid configs are reported and skipped.
+async fn cmd_watch(
+    config_path: &std::path::Path,
+    output: &std::path::Path,
+    interval: std::time::Duration,
+) -> Result<()> {
+    use hesiod_lib::zone::next_serial;
+    use hesiod_lib::zonefile::ZoneFile;
 
-    for (line_no, line) in content.lines().enumerate() {
-        let line = line.trim();
-        // Skip comments and empty lines
-        if line.is_empty()
-            || line.starts_with(';')
-            || line.starts_with('$')
-            || line.starts_with('@')
+    // Resume from the zone already on disk so restarts neither rewrite an
+    // unchanged zone nor reuse its serial.
+    let mut current: Option<(u32, String)> = std::fs::read_to_string(output)
+        .ok()
+        .and_then(|text| Some((ZoneFile::parse(&text).ok()?.serial?, text)));
+    let mut modified = None;
+
+    let mut ticker = tokio::time::interval(interval.max(std::time::Duration::from_millis(100)));
+    loop {
+        ticker.tick().await;
+        let mtime = std::fs::metadata(config_path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified == Some(mtime) {
+            continue;
+        }
+        modified = Some(mtime);
+
+        let zone = match HesiodConfig::from_file(config_path)
+            .and_then(|config| HesiodZone::from_config(&config))
+        {
+            Ok(zone) => zone,
+            Err(e) => {
+                tracing::warn!("{}: {:#}", config_path.display(), e);
+                continue;
+            }
+        };
+        // Unchanged if it renders identically under the current serial.
+        if let Some((serial, text)) = &current
+            && zone.to_bind_zone_with_serial(*serial) == *text
         {
             continue;
         }
-        // Look for HS TXT lines
-        if !line.contains("HS TXT") {
+
+        let next = next_serial(
+            current.as_ref().map(|(serial, _)| *serial),
+            std::time::SystemTime::now(),
+        );
+        let text = zone.to_bind_zone_with_serial(next);
+        let tmp = output.with_extension("tmp");
+        std::fs::write(&tmp, &text)
+            .and_then(|()| std::fs::rename(&tmp, output))
+            .with_context(|| format!("writing {}", output.display()))?;
+        tracing::info!(
+            "wrote {} records to {} (serial {})",
+            zone.record_count(),
+            output.display(),
+            next
+        );
+        current = Some((next, text));
+    }
+}
+
+/// Fetch `/dns/records` from a running server and print it.
+async fn cmd_dump(server: &str, map: Option<MapType>, output: DumpFormat) -> Result<()> {
+    let snapshot = fetch_snapshot(server, map).await?;
+    match output {
+        DumpFormat::Json => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+        DumpFormat::Zone => print!("{}", snapshot.into_zone().to_bind_zone()),
+    }
+    Ok(())
+}
+
+/// Fetch a server's `/dns/records` snapshot.
+async fn fetch_snapshot(server: &str, map: Option<MapType>) -> Result<ZoneSnapshot> {
+    let mut url = format!("{}/dns/records", server.trim_end_matches('/'));
+    if let Some(map) = map {
+        url = format!("{}?map={}", url, map.label());
+    }
+    reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("fetching {url}"))?
+        .json()
+        .await
+        .context("parsing records response")
+}
+
+/// The parts of `/dns/metrics` the monitor shows.
+#[derive(serde::Deserialize)]
+struct MetricsView {
+    query_count: u64,
+    uptime_seconds: u64,
+    queries_per_second: f64,
+    zone_records: usize,
+    errors: std::collections::BTreeMap<String, u64>,
+}
+
+impl MetricsView {
+    fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+/// Redraw a table of per-server rates every `interval` until interrupted.
+/// Rates are computed between polls; the first poll shows the server's own
+/// average since its counters were last reset.
+async fn cmd_monitor(servers: &[String], interval: std::time::Duration) -> Result<()> {
+    use std::io::IsTerminal;
+
+    let client = reqwest::Client::builder()
+        .timeout(interval.max(std::time::Duration::from_secs(1)))
+        .build()?;
+    let clear = std::io::stdout().is_terminal();
+    let mut previous: Vec<Option<(std::time::Instant, MetricsView)>> =
+        servers.iter().map(|_| None).collect();
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+        let polls: Vec<_> = servers
+            .iter()
+            .map(|server| {
+                let url = format!("{}/dns/metrics", server.trim_end_matches('/'));
+                let request = client.get(url).send();
+                tokio::spawn(async move {
+                    request
+                        .await
+                        .and_then(|r| r.error_for_status())?
+                        .json::<MetricsView>()
+                        .await
+                })
+            })
+            .collect();
+
+        let mut lines = vec![format!(
+            "{:<32} {:>10} {:>10} {:>7} {:>12} {:>8}",
+            "SERVER", "QPS", "ERRORS/s", "ERR%", "UPTIME", "RECORDS"
+        )];
+        for ((server, poll), previous) in servers.iter().zip(polls).zip(&mut previous) {
+            let now = std::time::Instant::now();
+            let view = match poll.await? {
+                Ok(view) => view,
+                Err(e) => {
+                    let reason = if e.is_timeout() { "timeout" } else { "DOWN" };
+                    lines.push(format!("{:<32} {:>10}", server, reason));
+                    *previous = None;
+                    continue;
+                }
+            };
+            let (qps, errors_per_second, error_ratio) = match previous {
+                // Counters going backwards means a reset; start over from this poll.
+                Some((at, before))
+                    if view.query_count >= before.query_count
+                        && view.error_count() >= before.error_count() =>
+                {
+                    let seconds = now.duration_since(*at).as_secs_f64().max(f64::EPSILON);
+                    let queries = view.query_count - before.query_count;
+                    let errors = view.error_count() - before.error_count();
+                    (
+                        queries as f64 / seconds,
+                        errors as f64 / seconds,
+                        errors as f64 / (queries + errors).max(1) as f64,
+                    )
+                }
+                _ => (
+                    view.queries_per_second,
+                    0.0,
+                    view.error_count() as f64
+                        / (view.query_count + view.error_count()).max(1) as f64,
+                ),
+            };
+            lines.push(format!(
+                "{:<32} {:>10.1} {:>10.1} {:>6.2}% {:>12} {:>8}",
+                server,
+                qps,
+                errors_per_second,
+                error_ratio * 100.0,
+                format_uptime(view.uptime_seconds),
+                view.zone_records
+            ));
+            *previous = Some((now, view));
+        }
+
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        println!("{}", lines.join("\n"));
+        if !clear {
+            println!();
+        }
+    }
+}
+
+/// `3d04h12m`-style uptime.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{days}d{hours:02}h{minutes:02}m")
+    } else if hours > 0 {
+        format!("{hours}h{minutes:02}m")
+    } else {
+        format!("{minutes}m{:02}s", seconds % 60)
+    }
+}
+
+/// Load a zone from a server URL, a `.json` config, or a BIND zone file.
+async fn load_zone(source: &str) -> Result<HesiodZone> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return Ok(fetch_snapshot(source, None).await?.into_zone());
+    }
+    let path = std::path::Path::new(source);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        Ok(HesiodZone::from_config(&HesiodConfig::from_file(path)?)?)
+    } else {
+        Ok(HesiodZone::from_bind_zone_file(path)?)
+    }
+}
+
+/// Print per-map record differences between two sources.
+async fn cmd_diff(old: &str, new: &str) -> Result<()> {
+    let old = load_zone(old).await?;
+    let new = load_zone(new).await?;
+    let diff = old.diff(&new);
+
+    for map_type in MapType::ALL {
+        let added: Vec<_> = diff
+            .added
+            .iter()
+            .filter(|(_, r)| r.map_type() == map_type)
+            .collect();
+        let removed: Vec<_> = diff
+            .removed
+            .iter()
+            .filter(|(_, r)| r.map_type() == map_type)
+            .collect();
+        let changed: Vec<_> = diff
+            .changed
+            .iter()
+            .filter(|(_, r, _)| r.map_type() == map_type)
+            .collect();
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
             continue;
         }
-        records += 1;
+        println!(
+            "[{}] +{} -{} ~{}",
+            map_type.label(),
+            added.len(),
+            removed.len(),
+            changed.len()
+        );
+        for (name, record) in removed {
+            println!("- {}: {}", name, record.to_txt());
+        }
+        for (name, record) in added {
+            println!("+ {}: {}", name, record.to_txt());
+        }
+        for (name, before, after) in changed {
+            println!("~ {}: {} -> {}", name, before.to_txt(), after.to_txt());
+        }
+    }
 
-        // Extract the TXT data between quotes
-        let txt_start = match line.find('"') {
-            Some(pos) => pos + 1,
-            None => {
-                eprintln!("line {}: missing opening quote", line_no + 1);
-                errors += 1;
-                continue;
+    if !diff.is_empty() {
+        std::process::exit(1);
+    }
+    println!("No differences");
+    Ok(())
+}
+
+/// Add or replace an entry in a config file.
+fn cmd_add(config_path: &std::path::Path, record: AddRecord, replace: bool) -> Result<()> {
+    let mut added = String::new();
+    edit_config(config_path, |doc| match record {
+        AddRecord::User {
+            username,
+            uid,
+            gid,
+            gecos,
+            home,
+            shell,
+        } => {
+            let entry = UserEntry {
+                username,
+                uid,
+                gid,
+                gecos,
+                home,
+                shell,
+            };
+            added = format!("passwd {}", entry.username);
+            doc.add(MapType::Passwd, &entry, replace)
+        }
+        AddRecord::Group { name, gid, members } => {
+            let entry = GroupEntry { name, gid, members };
+            added = format!("group {}", entry.name);
+            doc.add(MapType::Group, &entry, replace)
+        }
+        AddRecord::Service {
+            name,
+            host,
+            port,
+            protocol,
+            address,
+            tags,
+        } => {
+            let entry = ServiceEntry {
+                name,
+                host,
+                port,
+                protocol,
+                address,
+                tags,
+            };
+            added = format!("service {}", entry.name);
+            doc.add(MapType::Service, &entry, replace)
+        }
+        AddRecord::Filsys {
+            name,
+            fs_type,
+            mount_path,
+            source,
+            mode,
+        } => {
+            let entry = FilsysEntry {
+                name,
+                fs_type,
+                mount_path,
+                source,
+                mode,
+            };
+            added = format!("filsys {}", entry.name);
+            doc.add(MapType::Filsys, &entry, replace)
+        }
+    })?;
+    println!("Added {}", added);
+    Ok(())
+}
+
+/// Remove an entry from a config file.
+fn cmd_remove(config_path: &std::path::Path, map: MapType, key: &str) -> Result<()> {
+    edit_config(config_path, |doc| doc.remove(map, key))?;
+    println!("Removed {} {}", map.label(), key);
+    Ok(())
+}
+
+/// Load a config file, apply `edit`, and write it back.
+fn edit_config(
+    path: &std::path::Path,
+    edit: impl FnOnce(&mut ConfigDocument) -> hesiod_lib::Result<()>,
+) -> Result<()> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let mut doc = ConfigDocument::parse(&content)?;
+    edit(&mut doc)?;
+    std::fs::write(path, doc.render()?).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Read a file, or stdin for `-`.
+fn read_input(path: &std::path::Path) -> Result<String> {
+    if path == std::path::Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("reading stdin")
+    } else {
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))
+    }
+}
+
+/// Validate an imported config and write it out.
+fn cmd_import(config: &HesiodConfig, output: Option<&std::path::Path>) -> Result<()> {
+    let zone = HesiodZone::from_config(config)?;
+    let json = serde_json::to_string_pretty(config)? + "\n";
+    match output {
+        Some(path) => {
+            std::fs::write(path, json).with_context(|| format!("writing {}", path.display()))?;
+            eprintln!(
+                "Imported {} users, {} groups, {} services -> {}",
+                config.users.len(),
+                config.groups.len(),
+                config.services.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", json),
+    }
+    for finding in lint::lint_zone(&zone) {
+        eprintln!("{}", finding);
+    }
+    Ok(())
+}
+
+/// Render a record set as flat files.
+async fn cmd_export(
+    source: &str,
+    format: ExportFormat,
+    output: Option<&std::path::Path>,
+    map_dir: &str,
+    base_dn: Option<&str>,
+) -> Result<()> {
+    let zone = load_zone(source).await?;
+    let text = match format {
+        ExportFormat::Passwd => export::passwd_file(&zone),
+        ExportFormat::Group => export::group_file(&zone),
+        ExportFormat::Ldif => export::ldif(&zone, base_dn),
+        ExportFormat::AnsibleInventory => {
+            // Tags and addresses are only in the config, not the zone.
+            let config = source
+                .ends_with(".json")
+                .then(|| HesiodConfig::from_file(std::path::Path::new(source)))
+                .transpose()?;
+            serde_json::to_string_pretty(&export::ansible_inventory(&zone, config.as_ref()))? + "\n"
+        }
+        ExportFormat::Autofs => {
+            let files = export::autofs_files(&zone, map_dir)
+                .into_iter()
+                .map(|file| (file.name, file.content))
+                .collect();
+            return write_files(files, output);
+        }
+        ExportFormat::Nss => {
+            let files = vec![
+                ("hesiod.conf".to_string(), nss::hesiod_conf(&zone)),
+                ("nsswitch.conf".to_string(), nss::nsswitch_conf(&zone)),
+            ];
+            return write_files(files, output);
+        }
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+        }
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// Write `(name, content)` files into the `output` directory, or print
+/// them each under a `# name` header.
+fn write_files(files: Vec<(String, String)>, output: Option<&std::path::Path>) -> Result<()> {
+    let Some(dir) = output else {
+        for (name, content) in files {
+            print!("# {name}\n{content}");
+        }
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    for (name, content) in files {
+        let path = dir.join(&name);
+        std::fs::write(&path, content).with_context(|| format!("writing {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Outcome of one `doctor` check.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Print one `doctor` result, with an indented hint for anything not ok.
+fn report(status: CheckStatus, check: &str, detail: &str, hint: Option<&str>) {
+    let label = match status {
+        CheckStatus::Ok => " ok ",
+        CheckStatus::Warn => "warn",
+        CheckStatus::Fail => "FAIL",
+    };
+    println!("[{label}] {check}: {detail}");
+    if status != CheckStatus::Ok
+        && let Some(hint) = hint
+    {
+        println!("       -> {hint}");
+    }
+}
+
+/// UDP lookup options against one server, named the way `config` serves records.
+fn doctor_lookup_options(
+    server: &str,
+    port: u16,
+    config: &HesiodConfig,
+    class: QueryClass,
+) -> Result<LookupOptions> {
+    let args = QueryArgs {
+        servers: vec![server.to_string()],
+        port: Some(port),
+        lhs: Some(config.lhs.clone()),
+        rhs: Some(config.rhs.clone()),
+        class,
+        tcp: false,
+        tls: false,
+        https: false,
+        ca_file: None,
+        tls_server_name: None,
+        insecure: false,
+        retries: 0,
+        retry_backoff_ms: 0,
+        rotate: false,
+        system_fallback: false,
+    };
+    LookupOptions::from_args(&args, OutputFormat::Text)
+}
+
+/// Whether `server:port` answers `key` in `map_type` with `expected`.
+async fn answers_with(
+    server: &str,
+    port: u16,
+    config: &HesiodConfig,
+    class: QueryClass,
+    (key, map_type, expected): (&str, MapType, &str),
+) -> Result<bool> {
+    let opts = doctor_lookup_options(server, port, config, class)?;
+    let answer = opts.client.lookup(key, map_type).await?;
+    Ok(answer.txts.iter().any(|txt| txt == expected))
+}
+
+/// Run the deployment checks and print actionable findings.
+async fn cmd_doctor(config_path: &std::path::Path, dns_port: u16, http_port: u16) -> Result<()> {
+    use hesiod_lib::hesiod_conf;
+
+    let mut failed = false;
+    let mut fail = |check: &str, detail: &str, hint: Option<&str>| {
+        failed = true;
+        report(CheckStatus::Fail, check, detail, hint);
+    };
+
+    let config = match HesiodConfig::from_file(config_path) {
+        Ok(config) => {
+            report(
+                CheckStatus::Ok,
+                "config",
+                &format!(
+                    "{} parsed (domain {})",
+                    config_path.display(),
+                    config.domain
+                ),
+                None,
+            );
+            config
+        }
+        Err(e) => {
+            fail(
+                "config",
+                &format!("{e:#}"),
+                Some(
+                    "regenerate it with `nickel export` and check it against configs/hesiod/schema.ncl",
+                ),
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let zone = match HesiodZone::from_config(&config) {
+        Ok(zone) => zone,
+        Err(e) => {
+            fail("zone", &format!("{e:#}"), Some("fix the entry named above"));
+            std::process::exit(1);
+        }
+    };
+    let lint_errors = lint::lint_zone(&zone)
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let status = if lint_errors > 0 {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Ok
+    };
+    report(
+        status,
+        "zone",
+        &format!(
+            "{} records, {} lint errors",
+            zone.record_count(),
+            lint_errors
+        ),
+        Some(&format!(
+            "run `hesinfo lint {}` for details",
+            config_path.display()
+        )),
+    );
+
+    // A record to test lookups with; any will do.
+    let sample = zone
+        .records()
+        .next()
+        .map(|(name, record)| (name.to_string(), record.map_type(), record.to_txt()));
+    let sample = sample
+        .as_ref()
+        .map(|(name, map_type, txt)| (name.as_str(), *map_type, txt.as_str()));
+
+    match std::net::UdpSocket::bind(("0.0.0.0", dns_port)) {
+        Ok(_) => report(
+            CheckStatus::Ok,
+            "dns port",
+            &format!("udp {dns_port} is free"),
+            None,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            let serving = match sample {
+                Some(sample) => {
+                    answers_with("127.0.0.1", dns_port, &config, QueryClass::Hs, sample)
+                        .await
+                        .unwrap_or(false)
+                }
+                None => false,
+            };
+            if serving {
+                report(
+                    CheckStatus::Ok,
+                    "dns port",
+                    &format!("a server on udp {dns_port} already answers with this zone"),
+                    None,
+                );
+            } else {
+                report(
+                    CheckStatus::Warn,
+                    "dns port",
+                    &format!("udp {dns_port} is in use by something not serving this zone"),
+                    Some("stop the other process or pass a different --dns-port to serve"),
+                );
             }
-        };
-        let txt_end = match line.rfind('"') {
-            Some(pos) if pos > txt_start => pos,
-            _ => {
-                eprintln!("line {}: missing closing quote", line_no + 1);
-                errors += 1;
-                continue;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => fail(
+            "dns port",
+            &format!("binding udp {dns_port}: {e}"),
+            Some("run serve as root, grant it CAP_NET_BIND_SERVICE, or use a port above 1023"),
+        ),
+        Err(e) => fail("dns port", &format!("binding udp {dns_port}: {e}"), None),
+    }
+
+    match std::net::TcpListener::bind(("0.0.0.0", http_port)) {
+        Ok(_) => report(
+            CheckStatus::Ok,
+            "http port",
+            &format!("tcp {http_port} is free"),
+            None,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => report(
+            CheckStatus::Warn,
+            "http port",
+            &format!("tcp {http_port} is in use"),
+            Some("fine if this server is already running; otherwise pass a different --http-port"),
+        ),
+        Err(e) => fail("http port", &format!("binding tcp {http_port}: {e}"), None),
+    }
+
+    let nameservers = hesiod_conf::system_nameservers();
+    match (sample, nameservers.first()) {
+        (None, _) => report(
+            CheckStatus::Warn,
+            "resolver",
+            "zone has no records to test with",
+            Some("add a record, then run doctor again"),
+        ),
+        (_, None) => report(
+            CheckStatus::Warn,
+            "resolver",
+            "no nameserver in /etc/resolv.conf",
+            Some("clients need a resolver that forwards the Hesiod domain to this server"),
+        ),
+        (Some(sample), Some(_)) => {
+            let mut reached = None;
+            'servers: for ns in &nameservers {
+                for class in [QueryClass::Hs, QueryClass::In] {
+                    if answers_with(ns, 53, &config, class, sample)
+                        .await
+                        .unwrap_or(false)
+                    {
+                        reached = Some((ns, class));
+                        break 'servers;
+                    }
+                }
             }
-        };
-        let txt_data = &line[txt_start..txt_end];
-
-        // Determine map type from the name portion
-        let name_part = line.split_whitespace().next().unwrap_or("");
-        let map_type = if name_part.contains(".passwd") {
-            Some(MapType::Passwd)
-        } else if name_part.contains(".group") {
-            Some(MapType::Group)
-        } else if name_part.contains(".service") {
-            Some(MapType::Service)
-        } else if name_part.contains(".filsys") {
-            Some(MapType::Filsys)
-        } else {
-            None
-        };
+            match reached {
+                Some((ns, class)) => report(
+                    CheckStatus::Ok,
+                    "resolver",
+                    &format!(
+                        "{ns} resolves {}.{} in class {}",
+                        sample.0,
+                        sample.1.label(),
+                        if class == QueryClass::Hs { "HS" } else { "IN" }
+                    ),
+                    None,
+                ),
+                None => report(
+                    CheckStatus::Warn,
+                    "resolver",
+                    &format!(
+                        "{} did not return {}.{}",
+                        nameservers.join(", "),
+                        sample.0,
+                        sample.1.label()
+                    ),
+                    Some(&format!(
+                        "forward {} to this server in the local resolver (e.g. a stub zone)",
+                        config.rhs.trim_start_matches('.')
+                    )),
+                ),
+            }
+        }
+    }
 
-        if let Some(mt) = map_type
-            && let Err(e) = hesiod_lib::records::HesiodRecord::from_txt(mt, txt_data)
-        {
-            eprintln!("line {}: invalid {} record: {}", line_no + 1, mt.label(), e);
-            errors += 1;
+    match hesiod_conf::HesiodConf::from_system() {
+        Ok(conf) => {
+            let wanted_lhs = if config.lhs.starts_with('.') || config.lhs.is_empty() {
+                config.lhs.clone()
+            } else {
+                format!(".{}", config.lhs)
+            };
+            let wanted_rhs = if config.rhs.starts_with('.') {
+                config.rhs.clone()
+            } else {
+                format!(".{}", config.rhs)
+            };
+            if conf.lhs == wanted_lhs && conf.rhs == wanted_rhs {
+                report(
+                    CheckStatus::Ok,
+                    "hesiod.conf",
+                    &format!("lhs {} and rhs {} match the config", conf.lhs, conf.rhs),
+                    None,
+                );
+            } else {
+                report(
+                    CheckStatus::Warn,
+                    "hesiod.conf",
+                    &format!(
+                        "clients use lhs {:?} rhs {:?}, config serves lhs {:?} rhs {:?}",
+                        conf.lhs, conf.rhs, wanted_lhs, wanted_rhs
+                    ),
+                    Some(&format!(
+                        "set `lhs={wanted_lhs}` and `rhs={wanted_rhs}` in {} (or HES_DOMAIN)",
+                        hesiod_conf::DEFAULT_PATH
+                    )),
+                );
+            }
         }
+        Err(e) => fail(
+            "hesiod.conf",
+            &format!("{e:#}"),
+            Some(&format!("fix the syntax of {}", hesiod_conf::DEFAULT_PATH)),
+        ),
     }
 
-    if errors == 0 {
-        println!("Valid: {} records checked, no errors", records);
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Lint a config or zone file and print the findings.
+fn cmd_lint(path: &std::path::Path, report: ReportFormat) -> Result<()> {
+    let mut findings = if path.extension().is_some_and(|ext| ext == "json") {
+        lint::lint_config(&HesiodConfig::from_file(path)?)?
     } else {
-        println!("{} errors in {} records", errors, records);
+        let file = hesiod_lib::zonefile::ZoneFile::read(path)?;
+        let mut findings = lint::invalid_records(&file);
+        findings.extend(lint::lint_zone_file(&file));
+        findings
+    };
+
+    print_report(path, &mut findings, report)?;
+    if report == ReportFormat::Text {
+        println!("{} findings", findings.len());
+    }
+
+    if findings.iter().any(|f| f.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Validate a zone file (and the files it `$INCLUDE`s) by parsing every
+/// Hesiod record, and with `strict` also check the records against each
+/// other.
+async fn cmd_validate(path: &std::path::Path, strict: bool, report: ReportFormat) -> Result<()> {
+    use hesiod_lib::zonefile::ZoneFile;
+
+    let file = ZoneFile::read(path)?;
+    let records = file.entries.len();
+    let mut findings = lint::invalid_records(&file);
+    if strict {
+        findings.extend(lint::lint_zone_file(&file));
+        findings.extend(lint::check_service_hosts(&file.valid_zone()).await);
+    }
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+
+    print_report(path, &mut findings, report)?;
+    if report == ReportFormat::Text {
+        if errors == 0 {
+            println!("Valid: {} records checked, no errors", records);
+        } else {
+            println!("{} errors in {} records", errors, records);
+        }
+    }
+
+    if errors > 0 {
         std::process::exit(1);
     }
+    Ok(())
+}
 
+/// Print findings about `path` in the requested format. Findings without a
+/// file are attributed to `path`.
+fn print_report(
+    path: &std::path::Path,
+    findings: &mut [lint::Finding],
+    report: ReportFormat,
+) -> Result<()> {
+    for finding in findings.iter_mut() {
+        finding
+            .file
+            .get_or_insert_with(|| path.display().to_string());
+    }
+    let document = match report {
+        ReportFormat::Text => {
+            for finding in findings.iter() {
+                println!("{}", finding);
+            }
+            return Ok(());
+        }
+        ReportFormat::Json => serde_json::to_value(&*findings)?,
+        ReportFormat::Sarif => lint::sarif_report(findings),
+    };
+    println!("{}", serde_json::to_string_pretty(&document)?);
     Ok(())
 }
 
-/// Generate a simple random query ID.
-fn rand_id() -> u16 {
-    use std::time::SystemTime;
-    let t = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap_or_default()
-        .subsec_nanos();
-    (t & 0xFFFF) as u16
+/// Print each record of `file` that isn't valid for its map and return how
+/// many there were.
+fn validate_records(file: &hesiod_lib::zonefile::ZoneFile) -> usize {
+    let invalid = lint::invalid_records(file);
+    for finding in &invalid {
+        eprintln!("{}", finding);
+    }
+    invalid.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hesiod_lib::HesiodError;
+
+    use super::*;
+
+    fn pair(key: &str, map: &str) -> (String, String) {
+        (key.to_string(), map.to_string())
+    }
+
+    #[test]
+    fn batch_lines_skip_blanks_and_comments() {
+        let pairs = parse_batch("alice passwd\n\n  # staff\n\tops   group  \n")
+            .expect("TODO: handle error");
+        assert_eq!(pairs, [pair("alice", "passwd"), pair("ops", "group")]);
+        assert!(parse_batch("").expect("TODO: handle error").is_empty());
+    }
+
+    #[test]
+    fn malformed_batch_lines_are_reported_by_number() {
+        let err = parse_batch("alice passwd\n\nbob\n").expect_err("one field");
+        assert_eq!(err.to_string(), "line 3: expected `key map`");
+        let err = parse_batch("alice passwd extra").expect_err("three fields");
+        assert_eq!(err.to_string(), "line 1: expected `key map`");
+    }
+
+    #[test]
+    fn batch_json_takes_objects_and_pairs() {
+        let json = r#" [{"key": "alice", "map": "passwd"}, ["web", "service"]]"#;
+        let pairs = parse_batch(json).expect("TODO: handle error");
+        assert_eq!(pairs, [pair("alice", "passwd"), pair("web", "service")]);
+        assert!(parse_batch(r#"[{"key": "alice"}]"#).is_err());
+        assert!(parse_batch("[").is_err());
+    }
+
+    fn dns_error(source: impl std::error::Error + Send + Sync + 'static) -> HesiodError {
+        HesiodError::Dns {
+            message: "sending the query".into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    #[tokio::test]
+    async fn bench_counts_tokio_and_doh_timeouts() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .expect_err("pending never finishes");
+        assert!(timed_out(&dns_error(elapsed)));
+
+        // Accepted by the kernel but never answered.
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").expect("TODO: handle error");
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .expect("TODO: handle error");
+        let addr = silent.local_addr().expect("TODO: handle error");
+        let url = format!("http://{addr}/dns-query");
+        let e = http.get(&url).send().await.expect_err("no response");
+        assert!(timed_out(&dns_error(e)));
+
+        drop(silent);
+        let e = http.get(&url).send().await.expect_err("connection refused");
+        assert!(!timed_out(&dns_error(e)));
+        assert!(!timed_out(&HesiodError::dns("SERVFAIL")));
+    }
 }
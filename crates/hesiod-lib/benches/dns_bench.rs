@@ -15,7 +15,7 @@ fn bench_zone_service_lookup(c: &mut Criterion) {
         zone.add_record(
             &format!("service{}", i),
             HesiodRecord::Service(ServiceRecord {
-                host: format!("host{}.svc", i).into(),
+                host: format!("host{}.svc", i),
                 port: 1000 + (i as u16),
                 protocol: "tcp".into(),
             }),
@@ -44,11 +44,11 @@ fn bench_zone_passwd_lookup(c: &mut Criterion) {
         zone.add_record(
             &format!("user{}", i),
             HesiodRecord::Passwd(PasswdRecord {
-                username: format!("user{}", i).into(),
+                username: format!("user{}", i),
                 uid: 1000 + (i as u32),
                 gid: 1000,
-                gecos: format!("User {}", i).into(),
-                home: format!("/home/user{}", i).into(),
+                gecos: format!("User {}", i),
+                home: format!("/home/user{}", i),
                 shell: "/bin/bash".into(),
             }),
         );
@@ -218,7 +218,7 @@ fn bench_zone_bind_output(c: &mut Criterion) {
         zone.add_record(
             &format!("service{}", i),
             HesiodRecord::Service(ServiceRecord {
-                host: format!("host{}.svc", i).into(),
+                host: format!("host{}.svc", i),
                 port: 1000 + (i as u16),
                 protocol: "tcp".into(),
             }),
@@ -229,11 +229,11 @@ fn bench_zone_bind_output(c: &mut Criterion) {
         zone.add_record(
             &format!("user{}", i),
             HesiodRecord::Passwd(PasswdRecord {
-                username: format!("user{}", i).into(),
+                username: format!("user{}", i),
                 uid: 1000 + (i as u32),
                 gid: 1000,
-                gecos: format!("User {}", i).into(),
-                home: format!("/home/user{}", i).into(),
+                gecos: format!("User {}", i),
+                home: format!("/home/user{}", i),
                 shell: "/bin/bash".into(),
             }),
         );
@@ -255,7 +255,7 @@ fn bench_zone_iteration(c: &mut Criterion) {
             zone.add_record(
                 &format!("record{}", i),
                 HesiodRecord::Service(ServiceRecord {
-                    host: format!("host{}.svc", i).into(),
+                    host: format!("host{}.svc", i),
                     port: 1000 + (i as u16 % 64000),
                     protocol: "tcp".into(),
                 }),
@@ -278,8 +278,6 @@ fn bench_zone_iteration(c: &mut Criterion) {
 
 /// Benchmark: MapType parsing performance.
 fn bench_maptype_parsing(c: &mut Criterion) {
-    let map_types = vec!["passwd", "group", "service", "filsys"];
-
     c.bench_function("parse_maptype_passwd", |b| {
         b.iter(|| "passwd".parse::<MapType>())
     });
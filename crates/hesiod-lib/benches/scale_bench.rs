@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Criterion benchmarks over zones of 1k, 100k and 1M records: record
+//! lookup, name resolution, query handling, zone building from a config and
+//! TXT serialization.
+//!
+//! Every size is built up front, so expect a few hundred MB and several
+//! minutes even when a filter selects only the small cases.
+
+use std::collections::HashMap;
+
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use hesiod_lib::config::HesiodConfig;
+use hesiod_lib::idn::normalize_key;
+use hesiod_lib::naming::to_bind_name;
+use hesiod_lib::records::{HesiodRecord, MapType};
+use hesiod_lib::server::{DnsServerState, handle_query};
+use hesiod_lib::zone::{HesiodZone, ZoneSet};
+use hickory_proto::op::{Message, Query};
+use hickory_proto::rr::{DNSClass, RecordType};
+use serde_json::json;
+
+/// Zone sizes, in records.
+const SIZES: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+/// Config with `records` entries, spread evenly over the four maps.
+fn config(records: usize) -> HesiodConfig {
+    let per_map = records / 4;
+    let users: Vec<_> = (0..per_map)
+        .map(|i| {
+            json!({
+                "username": format!("user{i}"),
+                "uid": 1000 + i,
+                "gid": 1000,
+                "gecos": format!("User {i}"),
+                "home": format!("/home/user{i}"),
+                "shell": "/bin/bash",
+            })
+        })
+        .collect();
+    let groups: Vec<_> = (0..per_map)
+        .map(|i| {
+            json!({
+                "name": format!("group{i}"),
+                "gid": 1000 + i,
+                "members": [format!("user{i}"), "admin"],
+            })
+        })
+        .collect();
+    let services: Vec<_> = (0..per_map)
+        .map(|i| {
+            json!({
+                "name": format!("service{i}"),
+                "host": format!("host{i}.svc"),
+                "port": 1000 + i % 60_000,
+                "protocol": "tcp",
+            })
+        })
+        .collect();
+    let filesystems: Vec<_> = (0..records - 3 * per_map)
+        .map(|i| {
+            json!({
+                "name": format!("fs{i}"),
+                "fs_type": "nfs",
+                "mount_path": format!("/home/user{i}"),
+                "source": format!("nfs{}.svc:/export/home/user{i}", i % 16),
+            })
+        })
+        .collect();
+    serde_json::from_value(json!({
+        "domain": "bench.internal",
+        "lhs": ".ns",
+        "rhs": ".bench.internal",
+        "users": users,
+        "groups": groups,
+        "services": services,
+        "filesystems": filesystems,
+    }))
+    .expect("bench config deserializes")
+}
+
+fn zone(records: usize) -> HesiodZone {
+    HesiodZone::from_config(&config(records)).expect("bench zone builds")
+}
+
+/// A record in the middle of a zone of `records`.
+fn key(records: usize) -> String {
+    format!("user{}", records / 8)
+}
+
+/// Wire-format HS TXT query for `key`'s passwd record.
+fn query(key: &str) -> Vec<u8> {
+    let name = to_bind_name(key, MapType::Passwd, ".ns", ".bench.internal").expect("valid name");
+    let mut query = Query::query(name, RecordType::TXT);
+    query.set_query_class(DNSClass::HS);
+    let mut message = Message::new();
+    message.set_id(0x4853).add_query(query);
+    message.to_vec().expect("query encodes")
+}
+
+/// Benchmark: Looking a key up in a zone's index, against a SipHash
+/// `HashMap` keyed by owned `(key, map type)` pairs as the zone once used.
+fn bench_zone_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zone_lookup");
+    for records in SIZES {
+        let zone = zone(records);
+        let std_index: HashMap<(String, MapType), HesiodRecord> = zone
+            .records()
+            .map(|(name, record)| ((name.to_string(), record.map_type()), record.clone()))
+            .collect();
+        let key = key(records);
+        group.bench_with_input(BenchmarkId::new("zone", records), &key, |b, key| {
+            b.iter(|| zone.lookup(black_box(key), MapType::Passwd))
+        });
+        group.bench_with_input(BenchmarkId::new("std_hashmap", records), &key, |b, key| {
+            b.iter(|| std_index.get(&(normalize_key(black_box(key)).into_owned(), MapType::Passwd)))
+        });
+    }
+    group.finish();
+}
+
+/// Benchmark: Resolving a query name to its record's TXT data.
+fn bench_resolve_name(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resolve_name");
+    for records in SIZES {
+        let zones = ZoneSet::new(zone(records));
+        let name = to_bind_name(&key(records), MapType::Passwd, ".ns", ".bench.internal")
+            .expect("valid name");
+        group.bench_with_input(BenchmarkId::from_parameter(records), &name, |b, name| {
+            b.iter(|| {
+                let (zone, key, map_type) = zones.resolve(black_box(name))?;
+                zone.lookup(&key, map_type).map(|record| record.to_txt())
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Benchmark: Answering a wire-format query, as the UDP listener does.
+fn bench_handle_query(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handle_query");
+    for records in SIZES {
+        let state = DnsServerState::new(zone(records));
+        let wire = query(&key(records));
+        group.bench_with_input(BenchmarkId::from_parameter(records), &wire, |b, wire| {
+            b.iter(|| handle_query(black_box(wire), &state).expect("query answered"))
+        });
+    }
+    group.finish();
+}
+
+/// Benchmark: Building a zone from a large config.
+fn bench_zone_from_config(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zone_from_config");
+    group.sample_size(10);
+    for records in SIZES {
+        let config = config(records);
+        group.throughput(Throughput::Elements(records as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(records),
+            &config,
+            |b, config| {
+                b.iter(|| HesiodZone::from_config(black_box(config)).expect("bench zone builds"))
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Benchmark: Serializing every record of a zone to TXT data.
+fn bench_zone_to_txt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zone_to_txt");
+    group.sample_size(10);
+    for records in SIZES {
+        let zone = zone(records);
+        group.throughput(Throughput::Elements(records as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(records), &zone, |b, zone| {
+            b.iter(|| {
+                zone.records()
+                    .map(|(_, record)| record.to_txt().len())
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_zone_lookup,
+    bench_resolve_name,
+    bench_handle_query,
+    bench_zone_from_config,
+    bench_zone_to_txt,
+);
+criterion_main!(benches);
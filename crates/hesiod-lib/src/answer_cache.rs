@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Server-side cache of encoded responses, so a burst of identical lookups
+//! (a fleet booting at once, say) is answered without resolving and
+//! encoding each one again.
+//!
+//! Responses are keyed by the question as sent and the view answering it,
+//! and kept for the TTL of their answer, or until the zones are reloaded.
+//! The cache is split into shards by question, each locked on its own and
+//! making way for a new response by dropping its least recently used one.
+//! Only the ID and the RD and CD flags differ between the responses to
+//! identical questions; they are copied from each query on a hit.
+
+use std::hash::BuildHasher;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use lru::LruCache;
+use rustc_hash::FxBuildHasher;
+
+use crate::server::Resolved;
+
+/// Most shards a cache is split into.
+const MAX_SHARDS: usize = 16;
+/// Fewest entries a shard is given, so small caches stay close to one
+/// least-recently-used order.
+const MIN_SHARD_ENTRIES: usize = 64;
+
+/// The question in wire form (name as received, type and class) and the
+/// view answering it. The name keeps its case because it is echoed in the
+/// response.
+pub(crate) type AnswerKey = (Bytes, Option<usize>);
+
+/// An encoded response and what it holds.
+#[derive(Debug, Clone)]
+pub(crate) struct CachedResponse {
+    pub(crate) response: Vec<u8>,
+    /// Where the question went, for counting hits.
+    pub(crate) resolved: Resolved,
+    /// Where the answer's TTL is in `response`, if there is an answer.
+    pub(crate) ttl_offset: Option<usize>,
+}
+
+/// Encoded responses by question, in shards that each evict their least
+/// recently used response first.
+pub(crate) struct AnswerCache {
+    shards: Box<[Mutex<LruCache<AnswerKey, Entry>>]>,
+}
+
+struct Entry {
+    cached: CachedResponse,
+    /// Zone serial the response was built from.
+    serial: u64,
+    expires: Instant,
+}
+
+impl AnswerCache {
+    /// A cache of at most `max_entries` responses.
+    pub(crate) fn new(max_entries: usize) -> Self {
+        let shards = (max_entries / MIN_SHARD_ENTRIES).clamp(1, MAX_SHARDS);
+        let shards = match NonZeroUsize::new(max_entries / shards) {
+            Some(capacity) => (0..shards)
+                .map(|_| Mutex::new(LruCache::new(capacity)))
+                .collect(),
+            None => Box::default(),
+        };
+        Self { shards }
+    }
+
+    /// The cached response to `query` for `key`, built from the zones at
+    /// `serial` and unexpired at `now`.
+    pub(crate) fn get(
+        &self,
+        key: &AnswerKey,
+        query: &[u8],
+        serial: u64,
+        now: Instant,
+    ) -> Option<CachedResponse> {
+        let mut shard = self.shard(key)?;
+        let entry = shard.get(key)?;
+        if entry.serial != serial || entry.expires <= now {
+            shard.pop(key);
+            return None;
+        }
+        let mut cached = entry.cached.clone();
+        patch_header(&mut cached.response, query);
+        Some(cached)
+    }
+
+    /// Keep `cached` for `ttl`, dropping the least recently used response
+    /// of its shard if that is full.
+    pub(crate) fn insert(
+        &self,
+        key: AnswerKey,
+        cached: CachedResponse,
+        serial: u64,
+        ttl: Duration,
+        now: Instant,
+    ) {
+        if ttl.is_zero() {
+            return;
+        }
+        let Some(mut shard) = self.shard(&key) else {
+            return;
+        };
+        let entry = Entry {
+            cached,
+            serial,
+            expires: now + ttl,
+        };
+        shard.put(key, entry);
+    }
+
+    /// The locked shard holding `key`, or `None` if the cache is off.
+    fn shard(&self, key: &AnswerKey) -> Option<MutexGuard<'_, LruCache<AnswerKey, Entry>>> {
+        let index = (FxBuildHasher.hash_one(key) as usize).checked_rem(self.shards.len())?;
+        Some(self.shards[index].lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Give `response` the ID, RD and CD of `query`, as
+/// `Header::response_from_request` would.
+fn patch_header(response: &mut [u8], query: &[u8]) {
+    const RD: u8 = 0x01;
+    const CD: u8 = 0x10;
+    if response.len() < 4 || query.len() < 4 {
+        return;
+    }
+    response[..2].copy_from_slice(&query[..2]);
+    response[2] = (response[2] & !RD) | (query[2] & RD);
+    response[3] = (response[3] & !CD) | (query[3] & CD);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::MapType;
+
+    fn cached(response: Vec<u8>) -> CachedResponse {
+        CachedResponse {
+            response,
+            resolved: Resolved {
+                zone: "test.internal".into(),
+                key: "web".into(),
+                map_type: MapType::Service,
+                found: true,
+            },
+            ttl_offset: None,
+        }
+    }
+
+    fn key(name: &str) -> AnswerKey {
+        (Bytes::from(name.to_string()), None)
+    }
+
+    #[test]
+    fn hits_take_the_query_id_and_flags() {
+        let cache = AnswerCache::new(4);
+        let now = Instant::now();
+        let response = vec![0x00, 0x07, 0x85, 0x00, 0xaa];
+        let ttl = Duration::from_secs(60);
+        cache.insert(key("web"), cached(response), 1, ttl, now);
+
+        let hit = cache
+            .get(&key("web"), &[0x12, 0x34, 0x00, 0x10], 1, now)
+            .expect("cached");
+        assert_eq!(hit.response, [0x12, 0x34, 0x84, 0x10, 0xaa]);
+        assert_eq!(&*hit.resolved.key, "web");
+        assert!(cache.get(&key("WEB"), &[0; 4], 1, now).is_none());
+    }
+
+    #[test]
+    fn entries_expire_and_go_stale_on_reload() {
+        let cache = AnswerCache::new(4);
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        cache.insert(key("web"), cached(vec![0; 12]), 1, ttl, now);
+        assert!(cache.get(&key("web"), &[0; 12], 1, now + ttl).is_none());
+
+        cache.insert(key("web"), cached(vec![0; 12]), 1, ttl, now);
+        assert!(cache.get(&key("web"), &[0; 12], 2, now).is_none());
+        assert!(cache.get(&key("web"), &[0; 12], 1, now).is_none());
+    }
+
+    #[test]
+    fn full_cache_evicts_the_least_recently_used() {
+        let cache = AnswerCache::new(2);
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        cache.insert(key("a"), cached(vec![0; 12]), 1, ttl, now);
+        cache.insert(key("b"), cached(vec![0; 12]), 1, ttl, now);
+        assert!(cache.get(&key("a"), &[0; 12], 1, now).is_some());
+        cache.insert(key("c"), cached(vec![0; 12]), 1, ttl, now);
+
+        assert!(cache.get(&key("a"), &[0; 12], 1, now).is_some());
+        assert!(cache.get(&key("b"), &[0; 12], 1, now).is_none());
+        assert!(cache.get(&key("c"), &[0; 12], 1, now).is_some());
+    }
+
+    #[test]
+    fn sharded_caches_stay_within_their_size() {
+        let cache = AnswerCache::new(4096);
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        let keys: Vec<_> = (0..5000).map(|i| key(&format!("host-{i}"))).collect();
+        for key in &keys {
+            cache.insert(key.clone(), cached(vec![0; 12]), 1, ttl, now);
+        }
+        let kept = keys
+            .iter()
+            .filter(|key| cache.get(key, &[0; 12], 1, now).is_some())
+            .count();
+        assert!((3500..=4096).contains(&kept), "{kept} responses kept");
+    }
+
+    #[test]
+    fn zero_size_caches_nothing() {
+        let cache = AnswerCache::new(0);
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        cache.insert(key("web"), cached(vec![0; 12]), 1, ttl, now);
+        assert!(cache.get(&key("web"), &[0; 12], 1, now).is_none());
+    }
+}
@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Append-only audit log of admin API actions.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::{IoContext, Result};
+
+/// Entries kept in memory for `GET /dns/audit`.
+const RECENT_CAPACITY: usize = 1000;
+
+/// One admin action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_unix: u64,
+    /// Who performed the action, as established by authentication.
+    pub principal: String,
+    /// Action name, e.g. `reload` or `metrics.reset`.
+    pub action: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+impl AuditEntry {
+    pub fn new(principal: &str, action: &str, before: Value, after: Value) -> Self {
+        Self {
+            timestamp_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            principal: principal.to_string(),
+            action: action.to_string(),
+            before,
+            after,
+        }
+    }
+}
+
+/// Audit log: JSON lines appended to a file (if configured), plus the most
+/// recent entries in memory.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    recent: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Open (or create) the log file, loading its tail into memory.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut recent = VecDeque::with_capacity(RECENT_CAPACITY);
+        if let Ok(existing) = File::open(path) {
+            for line in BufReader::new(existing).lines() {
+                let line = line.io_err(|| format!("reading {}", path.display()))?;
+                match serde_json::from_str(&line) {
+                    Ok(entry) => push_bounded(&mut recent, entry),
+                    Err(e) => warn!(
+                        "skipping unreadable audit line in {}: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .io_err(|| format!("opening audit log {}", path.display()))?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            recent: Mutex::new(recent),
+        })
+    }
+
+    /// Append an entry. A failed file write is logged, not propagated, so an
+    /// admin action that already happened is never reported as failed.
+    pub fn record(&self, entry: AuditEntry) {
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            let written = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(file, "{line}"));
+            if let Err(e) = written {
+                warn!("failed to write audit entry: {:#}", e);
+            }
+        }
+        push_bounded(
+            &mut self.recent.lock().unwrap_or_else(|e| e.into_inner()),
+            entry,
+        );
+    }
+
+    /// Most recent entries, newest last.
+    pub fn recent(&self, limit: usize) -> Vec<AuditEntry> {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let skip = recent.len().saturating_sub(limit);
+        recent.iter().skip(skip).cloned().collect()
+    }
+}
+
+fn push_bounded(recent: &mut VecDeque<AuditEntry>, entry: AuditEntry) {
+    if recent.len() == RECENT_CAPACITY {
+        recent.pop_front();
+    }
+    recent.push_back(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn entries_survive_reopen() {
+        let path = std::env::temp_dir().join(format!("hesiod-audit-{}.jsonl", std::process::id()));
+        std::fs::remove_file(&path).ok();
+
+        let log = AuditLog::open(&path).expect("TODO: handle error");
+        log.record(AuditEntry::new(
+            "admin",
+            "drain.start",
+            json!(false),
+            json!(true),
+        ));
+        log.record(AuditEntry::new(
+            "admin",
+            "drain.end",
+            json!(true),
+            json!(false),
+        ));
+        drop(log);
+
+        let reopened = AuditLog::open(&path).expect("TODO: handle error");
+        std::fs::remove_file(&path).ok();
+        let entries = reopened.recent(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].action, "drain.end");
+        assert_eq!(reopened.recent(1)[0].action, "drain.end");
+    }
+
+    #[test]
+    fn memory_only_log_keeps_entries() {
+        let log = AuditLog::default();
+        log.record(AuditEntry::new(
+            "admin",
+            "metrics.reset",
+            json!({}),
+            json!({}),
+        ));
+        assert_eq!(log.recent(10).len(), 1);
+    }
+}
@@ -0,0 +1,528 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Synchronous Hesiod lookups, for callers without a tokio runtime such as
+//! NSS-style plugins and small non-async tools.
+//!
+//! [`BlockingHesiodClient`] uses the naming, server and TLS settings of a
+//! [`HesiodClient`], but does its I/O with `std::net` sockets on the calling
+//! thread. Enabled by the `blocking` feature.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hickory_proto::op::Message;
+use hickory_proto::rr::DNSClass;
+use tokio_rustls::rustls;
+
+use crate::cache::{CacheStats, response_ttl};
+use crate::client::{
+    Answer, HesiodClient, RetryPolicy, TraceStep, Transport, all_failed, answer_txts,
+    bind_query_socket, check_reply_id, parse_response,
+};
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::hesiod_conf::HesiodConf;
+use crate::records::{
+    FilsysRecord, GroupRecord, HesiodRecord, MapType, PasswdRecord, ServiceRecord,
+};
+
+/// Blocking counterpart of [`HesiodClient`], with the same builder methods.
+#[derive(Clone)]
+pub struct BlockingHesiodClient {
+    inner: HesiodClient,
+}
+
+impl From<HesiodClient> for BlockingHesiodClient {
+    fn from(inner: HesiodClient) -> Self {
+        Self { inner }
+    }
+}
+
+impl BlockingHesiodClient {
+    /// See [`HesiodClient::new`].
+    pub fn new(servers: Vec<String>, naming: HesiodConf) -> Result<Self> {
+        Ok(HesiodClient::new(servers, naming)?.into())
+    }
+
+    pub fn with_port(self, port: u16) -> Self {
+        self.inner.with_port(port).into()
+    }
+
+    pub fn with_class(self, class: DNSClass) -> Self {
+        self.inner.with_class(class).into()
+    }
+
+    pub fn with_transport(self, transport: Transport) -> Self {
+        self.inner.with_transport(transport).into()
+    }
+
+    pub fn with_retries(self, retry: RetryPolicy) -> Self {
+        self.inner.with_retries(retry).into()
+    }
+
+    pub fn with_rotation(self, rotate: bool) -> Self {
+        self.inner.with_rotation(rotate).into()
+    }
+
+    pub fn with_system_fallback(self, fallback: bool) -> Self {
+        self.inner.with_system_fallback(fallback).into()
+    }
+
+    pub fn with_trace(self, hook: impl Fn(&TraceStep) + Send + Sync + 'static) -> Self {
+        self.inner.with_trace(hook).into()
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.inner.with_timeout(timeout).into()
+    }
+
+    pub fn with_connect_timeout(self, timeout: Duration) -> Self {
+        self.inner.with_connect_timeout(timeout).into()
+    }
+
+    pub fn with_tls_config(self, tls: Arc<rustls::ClientConfig>) -> Result<Self> {
+        Ok(self.inner.with_tls_config(tls)?.into())
+    }
+
+    pub fn with_tls_server_name(self, name: &str) -> Self {
+        self.inner.with_tls_server_name(name).into()
+    }
+
+    pub fn with_cache(self, max_entries: usize) -> Self {
+        self.inner.with_cache(max_entries).into()
+    }
+
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.inner.cache_stats()
+    }
+
+    pub fn naming(&self) -> &HesiodConf {
+        self.inner.naming()
+    }
+
+    pub fn server_addrs(&self) -> Vec<String> {
+        self.inner.server_addrs()
+    }
+
+    /// See [`HesiodClient::lookup`].
+    pub fn lookup(&self, key: &str, map_type: MapType) -> Result<Answer> {
+        let inner = &self.inner;
+        if let Some(answer) = inner.cached(key, map_type) {
+            inner.trace(|| TraceStep::Cached {
+                qname: inner.naming().query_name(key, map_type),
+            });
+            return Ok(answer);
+        }
+        let (answer, ttl) = match self.query_servers(key, map_type) {
+            Err(e) if inner.system_fallback => {
+                tracing::debug!("{:#}; trying the system resolver", e);
+                inner.trace(|| TraceStep::Fallback {
+                    error: format!("{e:#}"),
+                });
+                Self::from(inner.system_resolver()?)
+                    .query_servers(key, map_type)
+                    .map_err(|fallback| {
+                        HesiodError::dns(format!("{e:#}; system resolver: {fallback:#}"))
+                    })?
+            }
+            result => result?,
+        };
+        inner.remember(key, map_type, &answer, ttl);
+        Ok(answer)
+    }
+
+    fn query_servers(&self, key: &str, map_type: MapType) -> Result<(Answer, Duration)> {
+        let inner = &self.inner;
+        let wire = inner.build_query(key, map_type)?;
+        inner.trace(|| TraceStep::Query {
+            qname: inner.naming().query_name(key, map_type),
+            class: inner.class(),
+            bytes: wire.len(),
+        });
+        let addrs = inner.attempt_order();
+        let retry = inner.retry;
+
+        let mut failures = Vec::new();
+        for round in 0..=retry.retries {
+            if round > 0 {
+                let backoff = retry.backoff(round);
+                inner.trace(|| TraceStep::Retry { round, backoff });
+                std::thread::sleep(backoff);
+            }
+            failures.clear();
+            for addr in &addrs {
+                inner.trace(|| TraceStep::Send {
+                    server: addr.clone(),
+                    transport: inner.transport,
+                });
+                let started = Instant::now();
+                match self.query_server(&wire, addr) {
+                    Ok((txts, ttl)) => {
+                        let server = addr.clone();
+                        return Ok((Answer { server, txts }, ttl));
+                    }
+                    Err(e) => {
+                        tracing::debug!("{} failed: {:#}", addr, e);
+                        inner.trace(|| TraceStep::Failed {
+                            server: addr.clone(),
+                            elapsed: started.elapsed(),
+                            error: format!("{e:#}"),
+                        });
+                        failures.push(format!("{addr}: {e:#}"));
+                    }
+                }
+            }
+        }
+        Err(HesiodError::dns(all_failed(retry.retries, &failures)))
+    }
+
+    fn query_server(&self, wire: &[u8], addr: &str) -> Result<(Vec<String>, Duration)> {
+        let started = Instant::now();
+        let raw = self.exchange_raw(wire, addr)?;
+        let response = check_reply_id(wire, parse_response(&raw)?)?;
+        self.inner.trace_response(addr, &raw, &response, started);
+        Ok((answer_txts(&response)?, response_ttl(&response)))
+    }
+
+    pub fn resolve(&self, key: &str, map_type: MapType) -> Result<Vec<HesiodRecord>> {
+        self.lookup(key, map_type)?.records(map_type)
+    }
+
+    /// See [`HesiodClient::resolve_all`]; the lookups run on scoped threads.
+    pub fn resolve_all(&self, key: &str) -> Result<HashMap<MapType, Vec<HesiodRecord>>> {
+        std::thread::scope(|scope| {
+            let lookups: Vec<_> = MapType::ALL
+                .into_iter()
+                .map(|map_type| (map_type, scope.spawn(move || self.resolve(key, map_type))))
+                .collect();
+            let mut all = HashMap::new();
+            for (map_type, lookup) in lookups {
+                let records = lookup
+                    .join()
+                    .map_err(|_| HesiodError::dns("lookup thread panicked"))?;
+                all.insert(
+                    map_type,
+                    records.dns_err(|| format!("{key}.{}", map_type.label()))?,
+                );
+            }
+            Ok(all)
+        })
+    }
+
+    pub fn resolve_passwd(&self, username: &str) -> Result<Option<PasswdRecord>> {
+        Ok(self
+            .resolve(username, MapType::Passwd)?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Passwd(user) => Some(user),
+                _ => None,
+            }))
+    }
+
+    pub fn resolve_group(&self, name: &str) -> Result<Option<GroupRecord>> {
+        Ok(self
+            .resolve(name, MapType::Group)?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Group(group) => Some(group),
+                _ => None,
+            }))
+    }
+
+    pub fn resolve_service(&self, name: &str) -> Result<Option<ServiceRecord>> {
+        Ok(self
+            .resolve(name, MapType::Service)?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Service(service) => Some(service),
+                _ => None,
+            }))
+    }
+
+    pub fn resolve_filsys(&self, name: &str) -> Result<Vec<FilsysRecord>> {
+        Ok(self
+            .resolve(name, MapType::Filsys)?
+            .into_iter()
+            .filter_map(|record| match record {
+                HesiodRecord::Filsys(fs) => Some(fs),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// See [`HesiodClient::exchange`].
+    pub fn exchange(&self, wire: &[u8], addr: &str) -> Result<Message> {
+        let raw = self.exchange_raw(wire, addr)?;
+        check_reply_id(wire, parse_response(&raw)?)
+    }
+
+    fn exchange_raw(&self, wire: &[u8], addr: &str) -> Result<Vec<u8>> {
+        if wire.len() < 12 {
+            return Err(HesiodError::dns("query is shorter than a DNS header"));
+        }
+        let transport = self.inner.transport;
+        if transport == Transport::Https {
+            return self.exchange_https(wire, addr);
+        }
+        let target = addr
+            .to_socket_addrs()
+            .io_err(|| format!("resolving {addr}"))?
+            .next()
+            .dns_err(|| format!("{addr} did not resolve"))?;
+        let raw = match transport {
+            Transport::Udp => self.exchange_udp(wire, target)?,
+            Transport::Tls => self.exchange_tls(wire, addr, target)?,
+            _ => self.exchange_tcp(wire, target)?,
+        };
+        if transport == Transport::Udp && parse_response(&raw)?.truncated() {
+            tracing::debug!("truncated response from {}, retrying over TCP", addr);
+            return self.exchange_tcp(wire, target);
+        }
+        Ok(raw)
+    }
+
+    fn exchange_udp(&self, wire: &[u8], target: SocketAddr) -> Result<Vec<u8>> {
+        let sock = bind_query_socket(target).io_err(|| "binding a query socket")?;
+        sock.connect(target)
+            .and_then(|()| sock.send(wire))
+            .io_err(|| format!("sending to {target}"))?;
+
+        let deadline = Instant::now() + self.inner.timeout;
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(HesiodError::dns("DNS query timed out"));
+            }
+            sock.set_read_timeout(Some(remaining))
+                .io_err(|| "setting the socket timeout")?;
+            let len = sock.recv(&mut buf).map_err(timeout_error)?;
+            if buf[..len].starts_with(&wire[..2]) {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            tracing::debug!("ignoring datagram with another query ID from {}", target);
+        }
+    }
+
+    fn exchange_tcp(&self, wire: &[u8], target: SocketAddr) -> Result<Vec<u8>> {
+        exchange_stream(self.connect(target)?, wire)
+    }
+
+    fn exchange_tls(&self, wire: &[u8], addr: &str, target: SocketAddr) -> Result<Vec<u8>> {
+        let server_name = self.inner.tls_server_name(addr)?;
+        let session = rustls::ClientConnection::new(Arc::clone(&self.inner.tls), server_name)
+            .dns_err(|| format!("starting TLS with {addr}"))?;
+        let stream = rustls::StreamOwned::new(session, self.connect(target)?);
+        exchange_stream(stream, wire)
+    }
+
+    /// DoH over a fresh HTTP/1.1 connection per query.
+    fn exchange_https(&self, wire: &[u8], url: &str) -> Result<Vec<u8>> {
+        let rest = url
+            .strip_prefix("https://")
+            .config_err(|| format!("{url} is not an https URL"))?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let path = if path.is_empty() { "/" } else { path };
+        let addr =
+            if authority.starts_with('[') && authority.ends_with(']') || !authority.contains(':') {
+                format!("{authority}:443")
+            } else {
+                authority.to_string()
+            };
+        let target = addr
+            .to_socket_addrs()
+            .io_err(|| format!("resolving {addr}"))?
+            .next()
+            .dns_err(|| format!("{addr} did not resolve"))?;
+
+        let mut tls = (*self.inner.tls).clone();
+        tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let server_name = self.inner.tls_server_name(&addr)?;
+        let session = rustls::ClientConnection::new(Arc::new(tls), server_name)
+            .dns_err(|| format!("starting TLS with {addr}"))?;
+        let mut stream = rustls::StreamOwned::new(session, self.connect(target)?);
+
+        let head = format!(
+            "POST {path} HTTP/1.1\r\nHost: {authority}\r\n\
+             Content-Type: application/dns-message\r\nAccept: application/dns-message\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            wire.len()
+        );
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|()| stream.write_all(wire))
+            .and_then(|()| stream.flush())
+            .map_err(timeout_error)?;
+
+        let mut raw = Vec::new();
+        match stream.read_to_end(&mut raw) {
+            Ok(_) => {}
+            // Servers often close without close_notify once the body is sent.
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !raw.is_empty() => {}
+            Err(e) => return Err(timeout_error(e)),
+        }
+        http_body(&raw)
+    }
+
+    /// TCP connection with the client's connect timeout, and its query
+    /// timeout on each read and write.
+    fn connect(&self, target: SocketAddr) -> Result<TcpStream> {
+        let stream = TcpStream::connect_timeout(&target, self.inner.connect_timeout())
+            .map_err(timeout_error)?;
+        let timeout = self.inner.timeout;
+        stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|()| stream.set_write_timeout(Some(timeout)))
+            .io_err(|| "setting the socket timeouts")?;
+        Ok(stream)
+    }
+}
+
+/// Report socket timeouts the way the async client does.
+fn timeout_error(e: std::io::Error) -> HesiodError {
+    match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+            HesiodError::dns("DNS query timed out")
+        }
+        _ => HesiodError::Io {
+            message: "exchanging the query".into(),
+            source: e,
+        },
+    }
+}
+
+/// Write one length-prefixed query and read one length-prefixed response.
+fn exchange_stream<S: Read + Write>(mut stream: S, wire: &[u8]) -> Result<Vec<u8>> {
+    let len = u16::try_from(wire.len()).dns_err(|| "query too large for TCP")?;
+    stream
+        .write_all(&len.to_be_bytes())
+        .and_then(|()| stream.write_all(wire))
+        .and_then(|()| stream.flush())
+        .map_err(timeout_error)?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).map_err(timeout_error)?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf).map_err(timeout_error)?;
+    Ok(buf)
+}
+
+/// Body of a complete HTTP/1.1 response, which must have status 200. Both
+/// `Content-Length` and chunked bodies are accepted.
+fn http_body(raw: &[u8]) -> Result<Vec<u8>> {
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .dns_err(|| "truncated HTTP response")?;
+    let head = std::str::from_utf8(&raw[..split]).dns_err(|| "invalid HTTP response head")?;
+    let mut body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some("200") => {}
+        _ => return Err(HesiodError::dns(format!("HTTP server returned {status:?}"))),
+    }
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            let len: usize = value.parse().dns_err(|| "invalid Content-Length")?;
+            body = body.get(..len).dns_err(|| "truncated HTTP body")?;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+    if !chunked {
+        return Ok(body.to_vec());
+    }
+
+    let mut out = Vec::new();
+    loop {
+        let end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .dns_err(|| "truncated HTTP chunk")?;
+        let size = std::str::from_utf8(&body[..end]).dns_err(|| "invalid HTTP chunk size")?;
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+            .dns_err(|| "invalid HTTP chunk size")?;
+        if size == 0 {
+            return Ok(out);
+        }
+        let chunk = body
+            .get(end + 2..end + 2 + size)
+            .dns_err(|| "truncated HTTP chunk")?;
+        out.extend_from_slice(chunk);
+        body = body.get(end + 4 + size..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::MessageType;
+    use hickory_proto::rr::rdata::TXT;
+    use hickory_proto::rr::{RData, Record};
+    use std::net::UdpSocket;
+
+    #[test]
+    fn cached_udp_lookup_without_runtime() {
+        let server = UdpSocket::bind("127.0.0.1:0").expect("TODO: handle error");
+        let addr = server.local_addr().expect("TODO: handle error");
+        let responder = std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            let (len, peer) = server.recv_from(&mut buf).expect("TODO: handle error");
+            let query = Message::from_vec(&buf[..len]).expect("TODO: handle error");
+            let mut response = Message::new();
+            response.set_id(query.id());
+            response.set_message_type(MessageType::Response);
+            response.add_answer(Record::from_rdata(
+                query.queries()[0].name().clone(),
+                300,
+                RData::TXT(TXT::new(vec![
+                    "alice:*:1000:1000::/home/alice:/bin/sh".into(),
+                ])),
+            ));
+            let wire = response.to_vec().expect("TODO: handle error");
+            server.send_to(&wire, peer).expect("TODO: handle error");
+        });
+
+        let naming = HesiodConf {
+            lhs: ".ns".into(),
+            rhs: ".example.com".into(),
+            ..Default::default()
+        };
+        let client = BlockingHesiodClient::new(vec![addr.to_string()], naming)
+            .expect("TODO: handle error")
+            .with_timeout(Duration::from_secs(2))
+            .with_cache(16);
+        for _ in 0..2 {
+            let user = client
+                .resolve_passwd("alice")
+                .expect("TODO: handle error")
+                .expect("TODO: handle error");
+            assert_eq!(user.uid, 1000);
+        }
+        responder.join().expect("TODO: handle error");
+        assert_eq!(client.cache_stats().hits, 1);
+    }
+
+    #[test]
+    fn http_bodies() {
+        let plain = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabcdef";
+        assert_eq!(http_body(plain).expect("TODO: handle error"), b"abc");
+        let chunked =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n1\r\nc\r\n0\r\n\r\n";
+        assert_eq!(http_body(chunked).expect("TODO: handle error"), b"abc");
+        assert!(http_body(b"HTTP/1.1 415 Unsupported Media Type\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MPL-2.0
+//! What this build of the library is: version, commit, build time and
+//! enabled features, as served by `GET /dns/version`.
+
+/// Crate version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Abbreviated git commit the crate was built from, or `unknown` outside a
+/// git checkout.
+pub const GIT_COMMIT: &str = env!("HESIOD_GIT_COMMIT");
+
+/// Build time in seconds since the Unix epoch (`SOURCE_DATE_EPOCH` if set).
+pub fn build_timestamp() -> u64 {
+    env!("HESIOD_BUILD_TIMESTAMP").parse().unwrap_or_default()
+}
+
+/// Cargo features the crate was built with.
+pub fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "net") {
+        features.push("net");
+    }
+    if cfg!(feature = "blocking") {
+        features.push("blocking");
+    }
+    features
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Client-side cache of lookup answers, honouring record TTLs.
+//!
+//! Empty answers (NXDOMAIN or no TXT records) are cached too, for the
+//! negative TTL of the SOA in the authority section (RFC 2308) or
+//! [`NEGATIVE_TTL`] when the server sends none.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hickory_proto::op::Message;
+use hickory_proto::rr::RData;
+use serde::Serialize;
+
+use crate::client::Answer;
+
+/// How long an empty answer without an SOA is cached.
+pub const NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// Counters of a client's cache since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Lookups answered from the cache, including negative hits.
+    pub hits: u64,
+    /// Hits on cached empty answers.
+    pub negative_hits: u64,
+    /// Lookups that went to a server.
+    pub misses: u64,
+    /// Answers currently held, some possibly expired.
+    pub entries: usize,
+}
+
+/// Answers keyed by query name and class.
+pub(crate) struct ResponseCache {
+    max_entries: usize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<(String, u16), (Answer, Instant)>,
+    stats: CacheStats,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// The unexpired answer for `key`, counting a hit or miss.
+    pub(crate) fn get(&self, key: &(String, u16), now: Instant) -> Option<Answer> {
+        let mut inner = self.lock();
+        match inner.entries.get(key) {
+            Some((answer, expires)) if *expires > now => {
+                let answer = answer.clone();
+                inner.stats.hits += 1;
+                if answer.txts.is_empty() {
+                    inner.stats.negative_hits += 1;
+                }
+                Some(answer)
+            }
+            _ => {
+                inner.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Keep `answer` for `ttl`. When full, expired entries are dropped
+    /// first, then those closest to expiring.
+    pub(crate) fn insert(&self, key: (String, u16), answer: Answer, ttl: Duration, now: Instant) {
+        if ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let mut inner = self.lock();
+        if inner.entries.len() >= self.max_entries && !inner.entries.contains_key(&key) {
+            inner.entries.retain(|_, (_, expires)| *expires > now);
+            while inner.entries.len() >= self.max_entries {
+                let Some(soonest) = inner
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, expires))| *expires)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                inner.entries.remove(&soonest);
+            }
+        }
+        inner.entries.insert(key, (answer, now + ttl));
+    }
+
+    pub(crate) fn flush(&self) {
+        self.lock().entries.clear();
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            entries: inner.entries.len(),
+            ..inner.stats
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// How long `response` may be cached: the lowest TXT TTL of a positive
+/// answer, or the SOA negative TTL of an empty one.
+pub(crate) fn response_ttl(response: &Message) -> Duration {
+    let lowest = response
+        .answers()
+        .iter()
+        .filter(|record| matches!(record.data(), RData::TXT(_)))
+        .map(|record| record.ttl())
+        .min();
+    if let Some(ttl) = lowest {
+        return Duration::from_secs(ttl.into());
+    }
+    response
+        .name_servers()
+        .iter()
+        .find_map(|record| match record.data() {
+            RData::SOA(soa) => Some(record.ttl().min(soa.minimum())),
+            _ => None,
+        })
+        .map_or(NEGATIVE_TTL, |ttl| Duration::from_secs(ttl.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::rr::rdata::{SOA, TXT};
+    use hickory_proto::rr::{Name, Record};
+
+    fn answer(txts: &[&str]) -> Answer {
+        Answer {
+            server: "127.0.0.1:5353".into(),
+            txts: txts.iter().map(|txt| txt.to_string()).collect(),
+        }
+    }
+
+    fn key(name: &str) -> (String, u16) {
+        (name.to_string(), 4)
+    }
+
+    #[test]
+    fn expires_and_counts() {
+        let cache = ResponseCache::new(8);
+        let now = Instant::now();
+        cache.insert(key("a"), answer(&["x"]), Duration::from_secs(10), now);
+        cache.insert(key("b"), answer(&[]), Duration::from_secs(10), now);
+        cache.insert(key("c"), answer(&["y"]), Duration::ZERO, now);
+
+        assert_eq!(cache.get(&key("a"), now), Some(answer(&["x"])));
+        assert_eq!(cache.get(&key("b"), now), Some(answer(&[])));
+        assert_eq!(cache.get(&key("c"), now), None);
+        assert_eq!(cache.get(&key("a"), now + Duration::from_secs(10)), None);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                negative_hits: 1,
+                misses: 2,
+                entries: 2,
+            }
+        );
+        cache.flush();
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn evicts_soonest_expiring() {
+        let cache = ResponseCache::new(2);
+        let now = Instant::now();
+        cache.insert(key("a"), answer(&["a"]), Duration::from_secs(30), now);
+        cache.insert(key("b"), answer(&["b"]), Duration::from_secs(5), now);
+        cache.insert(key("c"), answer(&["c"]), Duration::from_secs(20), now);
+        assert!(cache.get(&key("a"), now).is_some());
+        assert!(cache.get(&key("b"), now).is_none());
+        assert!(cache.get(&key("c"), now).is_some());
+    }
+
+    #[test]
+    fn ttls_from_answers_and_soa() {
+        let name: Name = "alice.passwd.ns.example.com."
+            .parse()
+            .expect("TODO: handle error");
+        let mut positive = Message::new();
+        for ttl in [300, 120] {
+            positive.add_answer(Record::from_rdata(
+                name.clone(),
+                ttl,
+                RData::TXT(TXT::new(vec!["x".into()])),
+            ));
+        }
+        assert_eq!(response_ttl(&positive), Duration::from_secs(120));
+
+        let mut negative = Message::new();
+        assert_eq!(response_ttl(&negative), NEGATIVE_TTL);
+        let soa = SOA::new(name.clone(), name.clone(), 1, 3600, 900, 604800, 30);
+        negative.add_name_server(Record::from_rdata(name, 600, RData::SOA(soa)));
+        assert_eq!(response_ttl(&negative), Duration::from_secs(30));
+    }
+}
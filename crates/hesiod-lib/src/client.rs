@@ -0,0 +1,1045 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Hesiod lookups for programs that resolve records themselves instead of
+//! shelling out to `hesinfo lookup`.
+//!
+//! Names are built from a [`HesiodConf`], and the configured servers are
+//! tried in order over UDP (falling back to TCP on truncation), TCP,
+//! DNS-over-TLS or DNS-over-HTTPS. When every server fails, a
+//! [`RetryPolicy`] can send further rounds after a backoff, and the host's
+//! resolvers can be asked in class IN as a last resort.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use hickory_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_proto::rr::record_data::RData;
+use hickory_proto::rr::{DNSClass, RecordType};
+use tokio_rustls::rustls;
+
+use crate::cache::{CacheStats, ResponseCache, response_ttl};
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::hesiod_conf::{self, HesiodConf};
+use crate::naming::to_bind_name;
+use crate::records::{
+    FilsysRecord, GroupRecord, HesiodRecord, MapType, PasswdRecord, ServiceRecord,
+};
+
+/// How long each server gets to answer before the next one is tried, and
+/// the default limit on connecting to it.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Extra rounds over the server list after all servers failed, waiting an
+/// exponentially growing, jittered backoff before each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Rounds after the first; 0 disables retries.
+    pub retries: u32,
+    /// Backoff before the first retry round, doubled for each further one.
+    pub initial_backoff: Duration,
+    /// Cap on the backoff before jitter.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `retries` extra rounds with the default backoff.
+    pub fn retries(retries: u32) -> Self {
+        Self {
+            retries,
+            ..Self::default()
+        }
+    }
+
+    /// Wait before retry round `round` (1 for the first retry): the capped
+    /// exponential backoff, scaled by a random factor in [0.5, 1] so clients
+    /// that failed together don't retry in lockstep.
+    pub fn backoff(&self, round: u32) -> Duration {
+        let exponent = round.saturating_sub(1).min(31);
+        let base = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        base.mul_f64(rand::random_range(0.5..=1.0))
+    }
+}
+
+/// Wire transport for lookups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    Tls,
+    Https,
+}
+
+impl Transport {
+    pub fn label(self) -> &'static str {
+        match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+            Transport::Tls => "tls",
+            Transport::Https => "https",
+        }
+    }
+
+    /// Port used for servers given without one.
+    pub fn default_port(self) -> u16 {
+        match self {
+            Transport::Udp | Transport::Tcp => 5353,
+            Transport::Tls => 853,
+            Transport::Https => 443,
+        }
+    }
+}
+
+/// One step of a lookup, reported to a [`HesiodClient::with_trace`] hook.
+#[derive(Debug, Clone)]
+pub enum TraceStep {
+    /// Answered from the cache; no query was sent.
+    Cached { qname: String },
+    /// Query built, `bytes` long on the wire.
+    Query {
+        qname: String,
+        class: DNSClass,
+        bytes: usize,
+    },
+    /// Attempt against one server starting.
+    Send {
+        server: String,
+        transport: Transport,
+    },
+    /// Reply from `server`, with its TXT strings before parsing.
+    Response {
+        server: String,
+        rcode: ResponseCode,
+        bytes: usize,
+        elapsed: Duration,
+        txts: Vec<String>,
+    },
+    /// Attempt failed: transport error, timeout, SERVFAIL or REFUSED.
+    Failed {
+        server: String,
+        elapsed: Duration,
+        error: String,
+    },
+    /// Waiting before retry round `round`.
+    Retry { round: u32, backoff: Duration },
+    /// Every server failed; the system resolvers are asked next.
+    Fallback { error: String },
+}
+
+/// Callback receiving [`TraceStep`]s.
+pub type TraceHook = Arc<dyn Fn(&TraceStep) + Send + Sync>;
+
+/// TXT strings one server returned for a lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    /// Server (`host:port`, or the URL for DoH) that answered.
+    pub server: String,
+    pub txts: Vec<String>,
+}
+
+impl Answer {
+    /// The TXT strings parsed as `map_type` records.
+    pub fn records(&self, map_type: MapType) -> Result<Vec<HesiodRecord>> {
+        self.txts
+            .iter()
+            .map(|txt| HesiodRecord::from_txt(map_type, txt))
+            .collect()
+    }
+}
+
+/// Resolver for Hesiod records. Cheap to clone; clones share the TLS and
+/// HTTP client state and the cache.
+#[derive(Clone)]
+pub struct HesiodClient {
+    servers: Vec<String>,
+    port: Option<u16>,
+    naming: HesiodConf,
+    class: DNSClass,
+    pub(crate) transport: Transport,
+    pub(crate) timeout: Duration,
+    connect_timeout: Option<Duration>,
+    pub(crate) tls: Arc<rustls::ClientConfig>,
+    tls_server_name: Option<String>,
+    https: reqwest::Client,
+    cache: Option<Arc<ResponseCache>>,
+    pub(crate) retry: RetryPolicy,
+    rotate: bool,
+    next_server: Arc<AtomicUsize>,
+    pub(crate) system_fallback: bool,
+    recursion_desired: bool,
+    trace: Option<TraceHook>,
+}
+
+impl HesiodClient {
+    /// Client querying `servers` (`host`, `host:port` or, for DoH, a URL) in
+    /// order, in class HS over UDP, with names built from `naming`.
+    pub fn new(servers: Vec<String>, naming: HesiodConf) -> Result<Self> {
+        let tls = tls_config(None, false)?;
+        Ok(Self {
+            servers,
+            port: None,
+            naming,
+            class: DNSClass::HS,
+            transport: Transport::Udp,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: None,
+            https: https_client(&tls)?,
+            tls,
+            tls_server_name: None,
+            cache: None,
+            retry: RetryPolicy::default(),
+            rotate: false,
+            next_server: Arc::new(AtomicUsize::new(rand::random::<u32>() as usize)),
+            system_fallback: false,
+            recursion_desired: false,
+            trace: None,
+        })
+    }
+
+    /// Port for servers given without one, instead of the transport default.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn with_class(mut self, class: DNSClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn with_retries(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Start each lookup at the server after the one the previous lookup
+    /// started at, spreading load like resolv.conf's `options rotate`. The
+    /// first lookup starts at a random server, so short-lived processes
+    /// spread too.
+    pub fn with_rotation(mut self, rotate: bool) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    /// When every server fails, ask the resolvers in
+    /// [`hesiod_conf::RESOLV_CONF`] in class IN instead, as some libhesiod
+    /// builds do. They must serve or forward the Hesiod domain in IN.
+    pub fn with_system_fallback(mut self, fallback: bool) -> Self {
+        self.system_fallback = fallback;
+        self
+    }
+
+    /// Call `hook` with each step of every lookup, for debugging.
+    pub fn with_trace(mut self, hook: impl Fn(&TraceStep) + Send + Sync + 'static) -> Self {
+        self.trace = Some(Arc::new(hook));
+        self
+    }
+
+    /// Report the step built by `step` to the trace hook, if there is one.
+    pub(crate) fn trace(&self, step: impl FnOnce() -> TraceStep) {
+        if let Some(hook) = &self.trace {
+            hook(&step());
+        }
+    }
+
+    /// How long each server gets to answer, per attempt. For TCP and DoT
+    /// this starts once the connection is up.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limit on setting up a TCP connection, including the TLS handshake
+    /// for DoT; defaults to the query timeout. DoH requests get the sum of
+    /// both as one limit.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout.unwrap_or(self.timeout)
+    }
+
+    /// TLS settings for DoT and DoH, e.g. from [`tls_config`].
+    pub fn with_tls_config(mut self, tls: Arc<rustls::ClientConfig>) -> Result<Self> {
+        self.https = https_client(&tls)?;
+        self.tls = tls;
+        Ok(self)
+    }
+
+    /// Name DoT certificates are verified against, instead of the server host.
+    pub fn with_tls_server_name(mut self, name: &str) -> Self {
+        self.tls_server_name = Some(name.to_string());
+        self
+    }
+
+    /// Keep up to `max_entries` answers, positive and negative, for their
+    /// TTL (see [`crate::cache`]).
+    pub fn with_cache(mut self, max_entries: usize) -> Self {
+        self.cache = Some(Arc::new(ResponseCache::new(max_entries)));
+        self
+    }
+
+    /// Drop every cached answer.
+    pub fn flush(&self) {
+        if let Some(cache) = &self.cache {
+            cache.flush();
+        }
+    }
+
+    /// Cache counters; all zero without a cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache
+            .as_ref()
+            .map_or_else(CacheStats::default, |cache| cache.stats())
+    }
+
+    pub fn naming(&self) -> &HesiodConf {
+        &self.naming
+    }
+
+    pub fn class(&self) -> DNSClass {
+        self.class
+    }
+
+    /// Addresses queried, in order: `host:port`, or URLs for DoH.
+    pub fn server_addrs(&self) -> Vec<String> {
+        let port = self.port.unwrap_or(self.transport.default_port());
+        self.servers
+            .iter()
+            .map(|server| match self.transport {
+                Transport::Https if server.starts_with("https://") => server.clone(),
+                Transport::Https => {
+                    format!("https://{}/dns-query", with_default_port(server, port))
+                }
+                _ => with_default_port(server, port),
+            })
+            .collect()
+    }
+
+    /// [`HesiodClient::server_addrs`] in the order the next lookup tries
+    /// them, advancing the rotation if it is enabled.
+    pub(crate) fn attempt_order(&self) -> Vec<String> {
+        let mut addrs = self.server_addrs();
+        if self.rotate && !addrs.is_empty() {
+            let start = self.next_server.fetch_add(1, Ordering::Relaxed) % addrs.len();
+            addrs.rotate_left(start);
+        }
+        addrs
+    }
+
+    /// Wire-format TXT query for `key` in `map_type`.
+    pub fn build_query(&self, key: &str, map_type: MapType) -> Result<Vec<u8>> {
+        let name = to_bind_name(key, map_type, &self.naming.lhs, &self.naming.rhs)?;
+
+        let mut query = Query::new();
+        query.set_name(name);
+        query.set_query_type(RecordType::TXT);
+        query.set_query_class(self.class);
+
+        let mut msg = Message::new();
+        msg.set_id(query_id());
+        msg.set_message_type(MessageType::Query);
+        msg.set_op_code(OpCode::Query);
+        msg.set_recursion_desired(self.recursion_desired);
+        msg.add_query(query);
+        msg.to_vec().dns_err(|| "encoding DNS query")
+    }
+
+    /// Query the servers in order for `key` in `map_type`, moving on to the
+    /// next one on timeout, transport error, SERVFAIL or REFUSED, and
+    /// retrying per the [`RetryPolicy`] once all have failed. With a cache,
+    /// unexpired answers are returned without a query.
+    pub async fn lookup(&self, key: &str, map_type: MapType) -> Result<Answer> {
+        if let Some(answer) = self.cached(key, map_type) {
+            self.trace(|| TraceStep::Cached {
+                qname: self.naming.query_name(key, map_type),
+            });
+            return Ok(answer);
+        }
+        let (answer, ttl) = match self.query_servers(key, map_type).await {
+            Err(e) if self.system_fallback => {
+                tracing::debug!("{:#}; trying the system resolver", e);
+                self.trace(|| TraceStep::Fallback {
+                    error: format!("{e:#}"),
+                });
+                self.system_resolver()?
+                    .query_servers(key, map_type)
+                    .await
+                    .map_err(|fallback| {
+                        HesiodError::dns(format!("{e:#}; system resolver: {fallback:#}"))
+                    })?
+            }
+            result => result?,
+        };
+        self.remember(key, map_type, &answer, ttl);
+        Ok(answer)
+    }
+
+    /// The retry rounds of [`HesiodClient::lookup`], without the cache and
+    /// fallback. Returns the answer and how long it may be cached.
+    async fn query_servers(&self, key: &str, map_type: MapType) -> Result<(Answer, Duration)> {
+        let wire = self.build_query(key, map_type)?;
+        self.trace(|| TraceStep::Query {
+            qname: self.naming.query_name(key, map_type),
+            class: self.class,
+            bytes: wire.len(),
+        });
+        let addrs = self.attempt_order();
+
+        let mut failures = Vec::new();
+        for round in 0..=self.retry.retries {
+            if round > 0 {
+                let backoff = self.retry.backoff(round);
+                tracing::debug!("retry {} in {:?}", round, backoff);
+                self.trace(|| TraceStep::Retry { round, backoff });
+                tokio::time::sleep(backoff).await;
+            }
+            failures.clear();
+            for addr in &addrs {
+                self.trace(|| TraceStep::Send {
+                    server: addr.clone(),
+                    transport: self.transport,
+                });
+                let started = Instant::now();
+                match self.query_server(&wire, addr).await {
+                    Ok((txts, ttl)) => {
+                        let server = addr.clone();
+                        return Ok((Answer { server, txts }, ttl));
+                    }
+                    Err(e) => {
+                        tracing::debug!("{} failed: {:#}", addr, e);
+                        self.trace(|| TraceStep::Failed {
+                            server: addr.clone(),
+                            elapsed: started.elapsed(),
+                            error: format!("{e:#}"),
+                        });
+                        failures.push(format!("{addr}: {e:#}"));
+                    }
+                }
+            }
+        }
+        Err(HesiodError::dns(all_failed(self.retry.retries, &failures)))
+    }
+
+    /// Client asking the system resolvers over UDP in class IN, with
+    /// recursion desired, sharing this client's naming and timeouts.
+    pub(crate) fn system_resolver(&self) -> Result<HesiodClient> {
+        let servers = hesiod_conf::system_nameservers();
+        if servers.is_empty() {
+            return Err(HesiodError::config(format!(
+                "no nameserver in {}",
+                hesiod_conf::RESOLV_CONF
+            )));
+        }
+        Ok(Self {
+            servers,
+            port: Some(53),
+            class: DNSClass::IN,
+            transport: Transport::Udp,
+            retry: RetryPolicy::default(),
+            rotate: false,
+            system_fallback: false,
+            recursion_desired: true,
+            ..self.clone()
+        })
+    }
+
+    /// Records for `key` in `map_type`; empty if there are none.
+    pub async fn resolve(&self, key: &str, map_type: MapType) -> Result<Vec<HesiodRecord>> {
+        self.lookup(key, map_type).await?.records(map_type)
+    }
+
+    /// Records of `key` in every map type, looked up concurrently. Map types
+    /// without records have an empty list; any failed lookup fails the whole.
+    pub async fn resolve_all(&self, key: &str) -> Result<HashMap<MapType, Vec<HesiodRecord>>> {
+        let mut lookups = tokio::task::JoinSet::new();
+        for map_type in MapType::ALL {
+            let (client, key) = (self.clone(), key.to_string());
+            lookups.spawn(async move { (map_type, client.resolve(&key, map_type).await) });
+        }
+        let mut all = HashMap::new();
+        while let Some(joined) = lookups.join_next().await {
+            let (map_type, records) = joined.dns_err(|| "lookup task failed")?;
+            all.insert(
+                map_type,
+                records.dns_err(|| format!("{key}.{}", map_type.label()))?,
+            );
+        }
+        Ok(all)
+    }
+
+    /// The passwd entry of `username`, if there is one.
+    pub async fn resolve_passwd(&self, username: &str) -> Result<Option<PasswdRecord>> {
+        Ok(self
+            .resolve(username, MapType::Passwd)
+            .await?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Passwd(user) => Some(user),
+                _ => None,
+            }))
+    }
+
+    /// The group entry of `name`, if there is one.
+    pub async fn resolve_group(&self, name: &str) -> Result<Option<GroupRecord>> {
+        Ok(self
+            .resolve(name, MapType::Group)
+            .await?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Group(group) => Some(group),
+                _ => None,
+            }))
+    }
+
+    /// The service entry of `name`, if there is one.
+    pub async fn resolve_service(&self, name: &str) -> Result<Option<ServiceRecord>> {
+        Ok(self
+            .resolve(name, MapType::Service)
+            .await?
+            .into_iter()
+            .find_map(|record| match record {
+                HesiodRecord::Service(service) => Some(service),
+                _ => None,
+            }))
+    }
+
+    /// Filesystems of `name`; Hesiod allows several, in preference order.
+    pub async fn resolve_filsys(&self, name: &str) -> Result<Vec<FilsysRecord>> {
+        Ok(self
+            .resolve(name, MapType::Filsys)
+            .await?
+            .into_iter()
+            .filter_map(|record| match record {
+                HesiodRecord::Filsys(fs) => Some(fs),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Unexpired cached answer for `key` in `map_type`.
+    pub(crate) fn cached(&self, key: &str, map_type: MapType) -> Option<Answer> {
+        let cache = self.cache.as_ref()?;
+        cache.get(&self.cache_key(key, map_type), Instant::now())
+    }
+
+    /// Cache `answer` for `ttl`, if there is a cache.
+    pub(crate) fn remember(&self, key: &str, map_type: MapType, answer: &Answer, ttl: Duration) {
+        if let Some(cache) = &self.cache {
+            let cache_key = self.cache_key(key, map_type);
+            cache.insert(cache_key, answer.clone(), ttl, Instant::now());
+        }
+    }
+
+    fn cache_key(&self, key: &str, map_type: MapType) -> (String, u16) {
+        (
+            self.naming.query_name(key, map_type).to_lowercase(),
+            self.class.into(),
+        )
+    }
+
+    /// Send one query to one server and collect the TXT strings of the
+    /// answer, with how long they may be cached.
+    async fn query_server(&self, wire: &[u8], addr: &str) -> Result<(Vec<String>, Duration)> {
+        let started = Instant::now();
+        let raw = self.exchange_raw(wire, addr).await?;
+        let response = check_reply_id(wire, parse_response(&raw)?)?;
+        self.trace_response(addr, &raw, &response, started);
+        Ok((answer_txts(&response)?, response_ttl(&response)))
+    }
+
+    /// Report a reply that arrived `started` ago to the trace hook.
+    pub(crate) fn trace_response(
+        &self,
+        addr: &str,
+        raw: &[u8],
+        response: &Message,
+        started: Instant,
+    ) {
+        self.trace(|| TraceStep::Response {
+            server: addr.to_string(),
+            rcode: response.response_code(),
+            bytes: raw.len(),
+            elapsed: started.elapsed(),
+            txts: txt_strings(response),
+        });
+    }
+
+    /// Send `wire` to `addr` (one of [`HesiodClient::server_addrs`]) and
+    /// parse the reply. A truncated UDP response is retried over TCP.
+    pub async fn exchange(&self, wire: &[u8], addr: &str) -> Result<Message> {
+        let raw = self.exchange_raw(wire, addr).await?;
+        check_reply_id(wire, parse_response(&raw)?)
+    }
+
+    /// [`HesiodClient::exchange`] without parsing the reply.
+    async fn exchange_raw(&self, wire: &[u8], addr: &str) -> Result<Vec<u8>> {
+        if wire.len() < 12 {
+            return Err(HesiodError::dns("query is shorter than a DNS header"));
+        }
+        if self.transport == Transport::Https {
+            return self.exchange_https(wire, addr).await;
+        }
+        let target = tokio::net::lookup_host(addr)
+            .await
+            .io_err(|| format!("resolving {addr}"))?
+            .next()
+            .dns_err(|| format!("{addr} did not resolve"))?;
+        let raw = match self.transport {
+            Transport::Udp => self.exchange_udp(wire, target).await?,
+            Transport::Tls => self.exchange_tls(wire, addr, target).await?,
+            _ => self.exchange_tcp(wire, target).await?,
+        };
+        if self.transport == Transport::Udp && parse_response(&raw)?.truncated() {
+            tracing::debug!("truncated response from {}, retrying over TCP", addr);
+            return self.exchange_tcp(wire, target).await;
+        }
+        Ok(raw)
+    }
+
+    /// One datagram out, one datagram back. The socket is connected to
+    /// `target`, so datagrams from elsewhere never arrive; ones carrying
+    /// another query ID are ignored.
+    async fn exchange_udp(&self, wire: &[u8], target: std::net::SocketAddr) -> Result<Vec<u8>> {
+        let sock = bind_query_socket(target).io_err(|| "binding a UDP query socket")?;
+        let send = async {
+            sock.set_nonblocking(true)?;
+            let sock = tokio::net::UdpSocket::from_std(sock)?;
+            sock.connect(target).await?;
+            sock.send(wire).await?;
+            Ok(sock)
+        };
+        let sock = send.await.io_err(|| "sending the query")?;
+
+        let receive = async {
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let len = sock.recv(&mut buf).await?;
+                if buf[..len].starts_with(&wire[..2]) {
+                    buf.truncate(len);
+                    return Ok(buf);
+                }
+                tracing::debug!("ignoring datagram with another query ID from {}", target);
+            }
+        };
+        tokio::time::timeout(self.timeout, receive)
+            .await
+            .dns_err(|| "DNS query timed out")?
+            .io_err(|| "receiving the reply")
+    }
+
+    /// DNS over TCP: each message is prefixed with its length as a big-endian u16.
+    async fn exchange_tcp(&self, wire: &[u8], target: std::net::SocketAddr) -> Result<Vec<u8>> {
+        let stream = tokio::time::timeout(
+            self.connect_timeout(),
+            tokio::net::TcpStream::connect(target),
+        )
+        .await
+        .dns_err(|| "connecting timed out")?
+        .io_err(|| format!("connecting to {target}"))?;
+        tokio::time::timeout(self.timeout, exchange_stream(stream, wire))
+            .await
+            .dns_err(|| "DNS query timed out")?
+            .io_err(|| "exchanging the query")
+    }
+
+    /// Name the DoT certificate of `addr` is verified against.
+    pub(crate) fn tls_server_name(
+        &self,
+        addr: &str,
+    ) -> Result<rustls::pki_types::ServerName<'static>> {
+        let host = match &self.tls_server_name {
+            Some(name) => name.clone(),
+            None => addr
+                .rsplit_once(':')
+                .map_or(addr, |(host, _)| host)
+                .trim_matches(['[', ']'])
+                .to_string(),
+        };
+        rustls::pki_types::ServerName::try_from(host).config_err(|| "invalid TLS server name")
+    }
+
+    /// DNS over TLS (RFC 7858): TCP framing inside a TLS session.
+    async fn exchange_tls(
+        &self,
+        wire: &[u8],
+        addr: &str,
+        target: std::net::SocketAddr,
+    ) -> Result<Vec<u8>> {
+        let server_name = self.tls_server_name(addr)?;
+        let connector = tokio_rustls::TlsConnector::from(Arc::clone(&self.tls));
+
+        let connect = async {
+            let tcp = tokio::net::TcpStream::connect(target).await?;
+            connector.connect(server_name, tcp).await
+        };
+        let stream = tokio::time::timeout(self.connect_timeout(), connect)
+            .await
+            .dns_err(|| "connecting timed out")?
+            .io_err(|| format!("connecting to {target}"))?;
+        tokio::time::timeout(self.timeout, exchange_stream(stream, wire))
+            .await
+            .dns_err(|| "DNS query timed out")?
+            .io_err(|| "exchanging the query")
+    }
+
+    /// DNS over HTTPS (RFC 8484): the wire query POSTed as `application/dns-message`.
+    async fn exchange_https(&self, wire: &[u8], url: &str) -> Result<Vec<u8>> {
+        const DNS_MESSAGE: &str = "application/dns-message";
+
+        let response = self
+            .https
+            .post(url)
+            .timeout(self.connect_timeout() + self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .body(wire.to_vec())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .dns_err(|| "sending the DoH request")?;
+        let body = response
+            .bytes()
+            .await
+            .dns_err(|| "reading the DoH response")?;
+        Ok(body.to_vec())
+    }
+}
+
+/// Error message for a lookup where every attempt failed; `failures` are
+/// those of the last round.
+pub(crate) fn all_failed(retries: u32, failures: &[String]) -> String {
+    let rounds = match retries {
+        0 => String::new(),
+        n => format!(" after {} rounds", n + 1),
+    };
+    format!("all servers failed{rounds} ({})", failures.join("; "))
+}
+
+/// Parse a reply off the wire.
+pub(crate) fn parse_response(raw: &[u8]) -> Result<Message> {
+    Message::from_vec(raw).dns_err(|| "parsing DNS response")
+}
+
+/// TXT strings in the answer section of `response`. SERVFAIL and REFUSED
+/// are errors, so the next server gets a chance.
+pub(crate) fn answer_txts(response: &Message) -> Result<Vec<String>> {
+    match response.response_code() {
+        ResponseCode::ServFail | ResponseCode::Refused => {
+            return Err(HesiodError::dns(format!(
+                "server returned {}",
+                response.response_code()
+            )));
+        }
+        _ => {}
+    }
+    Ok(txt_strings(response))
+}
+
+/// Every TXT string in the answer section of `response`.
+fn txt_strings(response: &Message) -> Vec<String> {
+    let mut txts = Vec::new();
+    for answer in response.answers() {
+        if let RData::TXT(txt) = answer.data() {
+            for s in txt.iter() {
+                txts.push(std::str::from_utf8(s).unwrap_or("<binary>").to_string());
+            }
+        }
+    }
+    txts
+}
+
+/// rustls client config trusting the webpki roots plus `ca_file`, or
+/// nothing at all with `insecure`.
+pub fn tls_config(ca_file: Option<&Path>, insecure: bool) -> Result<Arc<rustls::ClientConfig>> {
+    use rustls::pki_types::CertificateDer;
+    use rustls::pki_types::pem::PemObject;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .config_err(|| "setting up TLS")?;
+    let config = if insecure {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        if let Some(path) = ca_file {
+            let reading = || format!("reading {}", path.display());
+            for cert in CertificateDer::pem_file_iter(path).config_err(reading)? {
+                roots
+                    .add(cert.config_err(reading)?)
+                    .config_err(|| format!("adding a CA certificate from {}", path.display()))?;
+            }
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    Ok(Arc::new(config))
+}
+
+/// HTTP client for DoH using `tls`, speaking HTTP/1.1.
+fn https_client(tls: &rustls::ClientConfig) -> Result<reqwest::Client> {
+    let mut http_tls = tls.clone();
+    http_tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+    reqwest::Client::builder()
+        .use_preconfigured_tls(http_tls)
+        .build()
+        .config_err(|| "building the DoH client")
+}
+
+/// Append `port` to `server` unless it already names one. Bare IPv6
+/// addresses are bracketed.
+fn with_default_port(server: &str, port: u16) -> String {
+    use std::net::{IpAddr, SocketAddr};
+
+    if server.parse::<SocketAddr>().is_ok() {
+        server.to_string()
+    } else if let Ok(ip) = server.parse::<IpAddr>() {
+        SocketAddr::new(ip, port).to_string()
+    } else if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:{}", server, port)
+    }
+}
+
+/// Write one length-prefixed query and read one length-prefixed response.
+async fn exchange_stream<S>(mut stream: S, wire: &[u8]) -> std::io::Result<Vec<u8>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let len = u16::try_from(wire.len()).map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "query too large for TCP")
+    })?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(wire).await?;
+    stream.flush().await?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Query ID for a new message, from the thread-local CSPRNG so off-path
+/// attackers can't predict it.
+fn query_id() -> u16 {
+    rand::random()
+}
+
+/// Lowest source port picked for UDP queries.
+const MIN_SOURCE_PORT: u16 = 1024;
+
+/// Random source ports tried before leaving the choice to the OS.
+const SOURCE_PORT_ATTEMPTS: usize = 16;
+
+/// Unconnected UDP socket for one query to `target`, on a source port we
+/// pick at random rather than trusting the OS allocator, which may hand
+/// out ports sequentially.
+pub(crate) fn bind_query_socket(
+    target: std::net::SocketAddr,
+) -> std::io::Result<std::net::UdpSocket> {
+    use std::net::{Ipv4Addr, Ipv6Addr, UdpSocket};
+
+    let ip = if target.is_ipv6() {
+        Ipv6Addr::UNSPECIFIED.into()
+    } else {
+        Ipv4Addr::UNSPECIFIED.into()
+    };
+    for _ in 0..SOURCE_PORT_ATTEMPTS {
+        let port = rand::random_range(MIN_SOURCE_PORT..=u16::MAX);
+        match UdpSocket::bind(std::net::SocketAddr::new(ip, port)) {
+            Ok(sock) => return Ok(sock),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    UdpSocket::bind(std::net::SocketAddr::new(ip, 0))
+}
+
+/// `response` if it carries the ID of the query in `wire`.
+pub(crate) fn check_reply_id(wire: &[u8], response: Message) -> Result<Message> {
+    let id = u16::from_be_bytes([wire[0], wire[1]]);
+    if response.id() != id {
+        return Err(HesiodError::dns(format!(
+            "response ID {} does not match query ID {}",
+            response.id(),
+            id
+        )));
+    }
+    Ok(response)
+}
+
+/// Certificate verifier for insecure mode: any chain is accepted, but
+/// handshake signatures are still checked.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<rustls::crypto::CryptoProvider>);
+
+impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(servers: &[&str]) -> HesiodClient {
+        let naming = HesiodConf {
+            lhs: ".ns".into(),
+            rhs: ".example.com".into(),
+            ..Default::default()
+        };
+        HesiodClient::new(servers.iter().map(|s| s.to_string()).collect(), naming)
+            .expect("TODO: handle error")
+    }
+
+    #[test]
+    fn server_addrs_follow_transport() {
+        let udp = client(&["localhost", "10.0.0.1:53", "::1"]);
+        assert_eq!(
+            udp.server_addrs(),
+            ["localhost:5353", "10.0.0.1:53", "[::1]:5353"]
+        );
+        let doh =
+            client(&["dns.example", "https://doh.example/q"]).with_transport(Transport::Https);
+        assert_eq!(
+            doh.server_addrs(),
+            ["https://dns.example:443/dns-query", "https://doh.example/q"]
+        );
+    }
+
+    #[test]
+    fn rotation_and_backoff() {
+        let rotating = client(&["a", "b", "c"]).with_rotation(true);
+        let order = rotating.attempt_order();
+        let start = ["a:5353", "b:5353", "c:5353"]
+            .iter()
+            .position(|addr| *addr == order[0])
+            .expect("TODO: handle error");
+        for step in 1..4 {
+            let expected = ["a:5353", "b:5353", "c:5353"][(start + step) % 3];
+            assert_eq!(rotating.clone().attempt_order()[0], expected);
+        }
+        assert_eq!(client(&["a", "b"]).attempt_order()[0], "a:5353");
+
+        let retry = RetryPolicy {
+            retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        for (round, base) in [(1, 100), (2, 200), (3, 300), (5, 300)] {
+            let backoff = retry.backoff(round);
+            let base = Duration::from_millis(base);
+            assert!(backoff >= base / 2 && backoff <= base, "{backoff:?}");
+        }
+    }
+
+    #[test]
+    fn query_ids_and_source_ports_vary() {
+        let client = client(&["localhost"]);
+        let ids: std::collections::HashSet<u16> = (0..32)
+            .map(|_| {
+                let wire = client
+                    .build_query("alice", MapType::Passwd)
+                    .expect("TODO: handle error");
+                u16::from_be_bytes([wire[0], wire[1]])
+            })
+            .collect();
+        assert!(ids.len() > 16);
+
+        let target = "127.0.0.1:53".parse().expect("TODO: handle error");
+        let ports: std::collections::HashSet<u16> = (0..8)
+            .map(|_| {
+                let sock = bind_query_socket(target).expect("TODO: handle error");
+                sock.local_addr().expect("TODO: handle error").port()
+            })
+            .collect();
+        assert!(ports.len() > 1 && ports.iter().all(|&port| port >= MIN_SOURCE_PORT));
+    }
+
+    #[test]
+    fn query_uses_naming_and_class() {
+        let wire = client(&["localhost"])
+            .with_class(DNSClass::IN)
+            .build_query("alice", MapType::Passwd)
+            .expect("TODO: handle error");
+        let msg = Message::from_vec(&wire).expect("TODO: handle error");
+        let query = &msg.queries()[0];
+        assert_eq!(query.name().to_string(), "alice.passwd.ns.example.com.");
+        assert_eq!(query.query_class(), DNSClass::IN);
+        assert_eq!(query.query_type(), RecordType::TXT);
+    }
+}
@@ -1,11 +1,18 @@
 // SPDX-License-Identifier: MPL-2.0
 //! Configuration loading from JSON (produced by `nickel export`).
 
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::records::MapType;
+use crate::secret::Secret;
+
 /// Top-level Hesiod configuration matching the Nickel schema.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HesiodConfig {
@@ -14,6 +21,11 @@ pub struct HesiodConfig {
     pub rhs: String,
     #[serde(default = "default_ttl")]
     pub ttl: u32,
+    /// How long resolvers may cache a missing name, as the SOA minimum of
+    /// negative answers; defaults to `soa.minimum`, then `ttl`. Keep it short
+    /// where names are looked up just before they are provisioned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_ttl: Option<u32>,
     #[serde(default = "default_dns_port")]
     pub dns_port: u16,
     #[serde(default = "default_http_port")]
@@ -24,6 +36,26 @@ pub struct HesiodConfig {
     pub users: Vec<UserEntry>,
     #[serde(default)]
     pub groups: Vec<GroupEntry>,
+    #[serde(default)]
+    pub filesystems: Vec<FilsysEntry>,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub soa: SoaConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Further zones served by the same process, e.g. `lab.internal`
+    /// alongside a top-level `corp.internal`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ZoneConfig>,
 }
 
 fn default_ttl() -> u32 {
@@ -36,6 +68,50 @@ fn default_http_port() -> u16 {
     8080
 }
 
+/// One extra zone in [`HesiodConfig::zones`]: its own naming, records and
+/// SOA, served with the top-level config's server settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneConfig {
+    pub domain: String,
+    pub lhs: String,
+    pub rhs: String,
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+    /// As [`HesiodConfig::negative_ttl`], for this zone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_ttl: Option<u32>,
+    #[serde(default)]
+    pub services: Vec<ServiceEntry>,
+    #[serde(default)]
+    pub users: Vec<UserEntry>,
+    #[serde(default)]
+    pub groups: Vec<GroupEntry>,
+    #[serde(default)]
+    pub filesystems: Vec<FilsysEntry>,
+    #[serde(default)]
+    pub soa: SoaConfig,
+    /// Bearer token for this zone's `/dns/zones/<domain>/` endpoints, so a
+    /// team can manage its zone without the global admin token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<Secret>,
+}
+
+impl ZoneConfig {
+    /// A standalone config for this zone, as if it were the only one.
+    pub fn to_config(&self) -> HesiodConfig {
+        HesiodConfig {
+            ttl: self.ttl,
+            negative_ttl: self.negative_ttl,
+            services: self.services.clone(),
+            users: self.users.clone(),
+            groups: self.groups.clone(),
+            filesystems: self.filesystems.clone(),
+            soa: self.soa.clone(),
+            ..HesiodConfig::new(&self.domain, &self.lhs, &self.rhs)
+        }
+    }
+}
+
 /// Service entry from config.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceEntry {
@@ -44,6 +120,12 @@ pub struct ServiceEntry {
     pub port: u16,
     #[serde(default = "default_protocol")]
     pub protocol: String,
+    /// IP address of `host`, published as a PTR record in the reverse zone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<IpAddr>,
+    /// Free-form labels, e.g. for grouping hosts in an Ansible inventory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 fn default_protocol() -> String {
@@ -76,17 +158,583 @@ pub struct GroupEntry {
     pub members: Vec<String>,
 }
 
+/// Filesystem entry from config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilsysEntry {
+    pub name: String,
+    pub fs_type: String,
+    pub mount_path: String,
+    pub source: String,
+    #[serde(default = "default_mount_mode")]
+    pub mode: String,
+}
+
+fn default_mount_mode() -> String {
+    "rw".into()
+}
+
+/// Optional metric push settings, for environments without a scraping Prometheus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// StatsD endpoint (`host:port`) that receives UDP metric lines.
+    #[serde(default)]
+    pub statsd: Option<String>,
+    /// OpenMetrics push target, e.g. a Pushgateway job URL.
+    #[serde(default)]
+    pub push_url: Option<Secret>,
+    #[serde(default = "default_push_interval_secs")]
+    pub push_interval_secs: u64,
+    #[serde(default = "default_metrics_prefix")]
+    pub prefix: String,
+    /// File the long-term counters are checkpointed to and restored from.
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+    #[serde(default = "default_checkpoint_interval_secs")]
+    pub checkpoint_interval_secs: u64,
+}
+
+fn default_push_interval_secs() -> u64 {
+    10
+}
+fn default_metrics_prefix() -> String {
+    "hesiod".into()
+}
+fn default_checkpoint_interval_secs() -> u64 {
+    60
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            statsd: None,
+            push_url: None,
+            push_interval_secs: default_push_interval_secs(),
+            prefix: default_metrics_prefix(),
+            state_file: None,
+            checkpoint_interval_secs: default_checkpoint_interval_secs(),
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// Whether any push target is configured.
+    pub fn push_enabled(&self) -> bool {
+        self.statsd.is_some() || self.push_url.is_some()
+    }
+}
+
+/// Admin API settings for the mutating HTTP endpoints.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminConfig {
+    /// Bearer token required by admin endpoints, inline or as a
+    /// [secret reference](crate::secret). Unset disables them.
+    #[serde(default)]
+    pub token: Option<Secret>,
+    /// Append-only JSON-lines file recording every admin action.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+}
+
+/// Health, metrics and admin HTTP server settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. `127.0.0.1` or a management VLAN address
+    /// to keep admin endpoints off the DNS-facing interfaces.
+    #[serde(default = "default_http_bind")]
+    pub bind: IpAddr,
+    /// Listen on TCP at `bind`; turn off to serve only on `unix_socket`.
+    #[serde(default = "default_true")]
+    pub tcp: bool,
+    /// Also serve the same endpoints on a unix domain socket, for local
+    /// tooling. It is cleartext and not rate limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<UnixSocketConfig>,
+    /// Serve HTTPS with this certificate instead of cleartext HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Expect a PROXY protocol v2 header on HTTP connections from
+    /// `proxy_trusted` and take the client address from it; see
+    /// [`crate::proxy_protocol`]. DNS is served over UDP only, so it
+    /// applies to no DNS listener.
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Load balancers whose PROXY protocol headers are believed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_trusted: Vec<Cidr>,
+    /// Per-client request limits; unset serves every request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+fn default_http_bind() -> IpAddr {
+    IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: default_http_bind(),
+            tcp: true,
+            unix_socket: None,
+            tls: None,
+            proxy_protocol: false,
+            proxy_trusted: Vec::new(),
+            rate_limit: None,
+        }
+    }
+}
+
+/// Token-bucket limits per client address on the HTTP API. Reads (`GET`,
+/// `HEAD`) and everything else draw from separate buckets, so polling
+/// can't use up a client's allowance for admin actions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Sustained read requests per second.
+    #[serde(default = "default_read_per_second")]
+    pub read_per_second: f64,
+    /// Reads allowed in a burst above the sustained rate.
+    #[serde(default = "default_read_burst")]
+    pub read_burst: u32,
+    /// Sustained mutating requests per second.
+    #[serde(default = "default_write_per_second")]
+    pub write_per_second: f64,
+    /// Mutating requests allowed in a burst.
+    #[serde(default = "default_write_burst")]
+    pub write_burst: u32,
+}
+
+fn default_read_per_second() -> f64 {
+    20.0
+}
+
+fn default_read_burst() -> u32 {
+    40
+}
+
+fn default_write_per_second() -> f64 {
+    1.0
+}
+
+fn default_write_burst() -> u32 {
+    5
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            read_per_second: default_read_per_second(),
+            read_burst: default_read_burst(),
+            write_per_second: default_write_per_second(),
+            write_burst: default_write_burst(),
+        }
+    }
+}
+
+/// A unix domain socket for the HTTP endpoints. Access is controlled by
+/// the socket file's permissions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnixSocketConfig {
+    /// Socket path; a stale socket left there is replaced.
+    pub path: PathBuf,
+    /// Permissions of the socket file.
+    #[serde(default)]
+    pub mode: FileMode,
+}
+
+/// Unix permission bits, written in octal such as `"0660"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FileMode(pub u32);
+
+impl Default for FileMode {
+    /// Owner and group may connect.
+    fn default() -> Self {
+        Self(0o660)
+    }
+}
+
+impl FromStr for FileMode {
+    type Err = HesiodError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match u32::from_str_radix(s, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(Self(mode)),
+            _ => Err(HesiodError::config(format!(
+                "invalid file mode {s:?}, expected octal like \"0660\""
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for FileMode {
+    type Error = HesiodError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<FileMode> for String {
+    fn from(mode: FileMode) -> Self {
+        mode.to_string()
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+/// PEM files for a TLS listener.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PKCS#8, PKCS#1 or SEC1 private key for the leaf.
+    pub key: PathBuf,
+}
+
+/// A network in CIDR notation, such as `10.0.0.0/8` or `fd00::/8`; a bare
+/// address is a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `addr` is in the network. IPv4 clients on a dual-stack
+    /// socket, seen as IPv4-mapped IPv6 addresses, match IPv4 networks.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = HesiodError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || format!("invalid network {s:?}");
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr.parse().config_err(invalid)?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().config_err(invalid)?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(HesiodError::config(invalid()));
+        }
+        Ok(Self { network, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = HesiodError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// DNS server concurrency and socket tuning: the socket reader hands
+/// datagrams to `workers` tasks through a queue of `queue_depth` entries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Tasks answering queries; at least one runs.
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Datagrams waiting for a worker; at least one is held.
+    #[serde(default = "default_queue_depth")]
+    pub queue_depth: usize,
+    /// What the reader does with a datagram when the queue is full.
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// `SO_RCVBUF` for the UDP socket in bytes; unset keeps the OS default.
+    /// The kernel may cap it (`net.core.rmem_max` on Linux).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_buffer: Option<usize>,
+    /// `SO_SNDBUF` for the UDP socket in bytes; unset keeps the OS default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_buffer: Option<usize>,
+    /// Tokio worker threads for `hesinfo serve`; unset uses one per CPU core.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_threads: Option<usize>,
+    /// Run `hesinfo serve` on tokio's single-threaded runtime, for small
+    /// appliances. Overrides `runtime_threads`.
+    #[serde(default)]
+    pub current_thread: bool,
+    /// What to do with a query holding more than one question.
+    #[serde(default)]
+    pub multi_question: MultiQuestionPolicy,
+    /// Encoded responses kept for repeated questions; 0 turns the cache off.
+    #[serde(default = "default_answer_cache")]
+    pub answer_cache: usize,
+    /// Spread the TTLs of answers randomly within ±this percent (at most
+    /// 100), so clients that cache a popular record at the same time don't
+    /// refresh it in step; 0 serves TTLs as configured.
+    #[serde(default)]
+    pub ttl_jitter: u8,
+    /// Extra listeners each serving a subset of the records, chosen by tag.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<ViewConfig>,
+    /// Networks allowed to query each map type, e.g. `passwd` and `group`
+    /// only from trusted subnets. Maps not listed answer everyone; other
+    /// clients of a listed map get REFUSED, and the HTTP record, lookup,
+    /// search, PowerDNS and CoreDNS endpoints withhold it from them too.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub map_acl: BTreeMap<MapType, Vec<Cidr>>,
+}
+
+fn default_workers() -> usize {
+    4
+}
+fn default_queue_depth() -> usize {
+    1024
+}
+fn default_answer_cache() -> usize {
+    1024
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            workers: default_workers(),
+            queue_depth: default_queue_depth(),
+            overflow: OverflowPolicy::default(),
+            recv_buffer: None,
+            send_buffer: None,
+            runtime_threads: None,
+            current_thread: false,
+            multi_question: MultiQuestionPolicy::default(),
+            answer_cache: default_answer_cache(),
+            ttl_jitter: 0,
+            views: Vec::new(),
+            map_acl: BTreeMap::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Whether `client` may query `map_type`. An unknown client may only
+    /// query maps without an ACL.
+    pub fn map_allows(&self, map_type: MapType, client: Option<IpAddr>) -> bool {
+        match (self.map_acl.get(&map_type), client) {
+            (None, _) => true,
+            (Some(networks), Some(client)) => networks.iter().any(|net| net.contains(client)),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// A UDP listener answering only for records carrying at least one of
+/// `tags`, such as a public-facing port exposing `public` services while
+/// the main listener serves everything. Untagged records are hidden, and so
+/// are services known only to the registry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewConfig {
+    /// Shown in logs and startup output.
+    pub name: String,
+    pub port: u16,
+    pub tags: Vec<String>,
+}
+
+/// Handling of queries arriving while the ingress queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Drop the new datagram and count it; the client retries.
+    #[default]
+    Drop,
+    /// Stop reading until a worker frees a slot, leaving datagrams to queue
+    /// (and eventually drop) in the kernel socket buffer.
+    Block,
+}
+
+/// Handling of queries with more than one question, which no resolver sends
+/// and which DNS gives no way to answer unambiguously.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiQuestionPolicy {
+    /// Refuse the query with FORMERR.
+    #[default]
+    FormErr,
+    /// Answer the first question and ignore the rest.
+    First,
+}
+
+/// Webhooks notified after the served zone changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// URLs, as secrets since they often embed a token.
+    #[serde(default)]
+    pub webhooks: Vec<Secret>,
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    5
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            timeout_secs: default_webhook_timeout_secs(),
+        }
+    }
+}
+
+/// Periodic resync with the config source, so changes are picked up even
+/// when nothing calls `/dns/reload`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Seconds between resyncs; unset leaves reloads to the admin API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    /// Spread each wait randomly within ±this percent (at most 100), so a
+    /// fleet started together doesn't hit the source in step.
+    #[serde(default)]
+    pub jitter: u8,
+}
+
+/// SOA and NS records of generated zone files. Unset names default to
+/// `ns.<origin>` and `hostmaster.<origin>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoaConfig {
+    /// Primary nameserver.
+    #[serde(default)]
+    pub mname: Option<String>,
+    /// Responsible mailbox, as `user@host` or in dotted zone file form.
+    #[serde(default)]
+    pub rname: Option<String>,
+    /// NS records; defaults to just the primary.
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    #[serde(default = "default_soa_refresh")]
+    pub refresh: u32,
+    #[serde(default = "default_soa_retry")]
+    pub retry: u32,
+    #[serde(default = "default_soa_expire")]
+    pub expire: u32,
+    /// Negative-caching TTL, superseded by `negative_ttl`; defaults to the
+    /// zone TTL.
+    #[serde(default)]
+    pub minimum: Option<u32>,
+}
+
+fn default_soa_refresh() -> u32 {
+    3600
+}
+fn default_soa_retry() -> u32 {
+    900
+}
+fn default_soa_expire() -> u32 {
+    604_800
+}
+
+impl Default for SoaConfig {
+    fn default() -> Self {
+        Self {
+            mname: None,
+            rname: None,
+            nameservers: Vec::new(),
+            refresh: default_soa_refresh(),
+            retry: default_soa_retry(),
+            expire: default_soa_expire(),
+            minimum: None,
+        }
+    }
+}
+
 impl HesiodConfig {
+    /// Empty config for `domain` with every optional setting at its default.
+    pub fn new(domain: &str, lhs: &str, rhs: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            lhs: lhs.to_string(),
+            rhs: rhs.to_string(),
+            ttl: default_ttl(),
+            negative_ttl: None,
+            dns_port: default_dns_port(),
+            http_port: default_http_port(),
+            services: Vec::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            filesystems: Vec::new(),
+            metrics: MetricsConfig::default(),
+            admin: AdminConfig::default(),
+            notify: NotifyConfig::default(),
+            sync: SyncConfig::default(),
+            soa: SoaConfig::default(),
+            server: ServerConfig::default(),
+            http: HttpConfig::default(),
+            zones: Vec::new(),
+        }
+    }
+
     /// Load configuration from a JSON file (output of `nickel export`).
     pub fn from_file(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
-            .with_context(|| format!("reading config from {}", path.display()))?;
+            .io_err(|| format!("reading config from {}", path.display()))?;
         Self::from_json(&content)
     }
 
     /// Parse configuration from a JSON string.
     pub fn from_json(json: &str) -> Result<Self> {
-        serde_json::from_str(json).context("parsing Hesiod config JSON")
+        serde_json::from_str(json).config_err(|| "parsing Hesiod config JSON")
+    }
+
+    /// Resolve every secret given by reference, which parsing leaves
+    /// unresolved. Blocking: it reads files and may run `age` or `sops`.
+    pub fn resolve_secrets(&mut self) -> Result<()> {
+        let zone_tokens = self
+            .zones
+            .iter_mut()
+            .filter_map(|zone| zone.admin_token.as_mut());
+        self.admin
+            .token
+            .iter_mut()
+            .chain(self.metrics.push_url.iter_mut())
+            .chain(self.notify.webhooks.iter_mut())
+            .chain(zone_tokens)
+            .try_for_each(Secret::resolve)
     }
 }
 
@@ -134,4 +782,52 @@ fn parse_full_config() {
         assert_eq!(config.groups.len(), 1);
         assert_eq!(config.users[0].shell, "/bin/zsh");
     }
+
+    #[test]
+    fn parse_metrics_section() {
+        let json = r#"{
+            "domain": "example.internal",
+            "lhs": ".ns",
+            "rhs": ".example.internal",
+            "metrics": {"statsd": "127.0.0.1:8125", "push_interval_secs": 30}
+        }"#;
+        let config = HesiodConfig::from_json(json).expect("TODO: handle error");
+        assert_eq!(config.metrics.statsd.as_deref(), Some("127.0.0.1:8125"));
+        assert_eq!(config.metrics.push_interval_secs, 30);
+        assert_eq!(config.metrics.prefix, "hesiod");
+        assert!(config.metrics.push_enabled());
+    }
+
+    #[test]
+    fn parse_http_section() {
+        let json = r#"{
+            "domain": "example.internal",
+            "lhs": ".ns",
+            "rhs": ".example.internal",
+            "http": {"bind": "::1"}
+        }"#;
+        let config = HesiodConfig::from_json(json).expect("TODO: handle error");
+        assert_eq!(config.http.bind, IpAddr::V6(std::net::Ipv6Addr::LOCALHOST));
+        assert!(config.http.tls.is_none());
+        assert!(HttpConfig::default().bind.is_unspecified());
+        assert!(config.http.rate_limit.is_none());
+        assert!(config.http.tcp);
+
+        let json = r#"{
+            "domain": "example.internal",
+            "lhs": ".ns",
+            "rhs": ".example.internal",
+            "http": {"tcp": false, "unix_socket": {"path": "/run/hesiod/admin.sock"}}
+        }"#;
+        let config = HesiodConfig::from_json(json).expect("TODO: handle error");
+        assert!(!config.http.tcp);
+        let socket = config.http.unix_socket.expect("TODO: handle error");
+        assert_eq!(socket.mode, FileMode(0o660));
+        assert_eq!(
+            "0600".parse::<FileMode>().expect("TODO: handle error").0,
+            0o600
+        );
+        assert!("rw-rw----".parse::<FileMode>().is_err());
+        assert_eq!(FileMode(0o640).to_string(), "0640");
+    }
 }
@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: MPL-2.0
+//! In-place edits of a JSON config file: add, replace and remove entries
+//! while keeping key order and indentation.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::HesiodConfig;
+use crate::error::{Context, HesiodError, Result};
+use crate::records::MapType;
+use crate::zone::HesiodZone;
+
+/// A config JSON document being edited.
+#[derive(Debug, Clone)]
+pub struct ConfigDocument {
+    root: Value,
+    /// Indent unit, or `None` for a single-line document.
+    indent: Option<String>,
+    trailing_newline: bool,
+}
+
+/// Config array and key field holding each map's entries.
+fn section(map_type: MapType) -> (&'static str, &'static str) {
+    match map_type {
+        MapType::Passwd => ("users", "username"),
+        MapType::Group => ("groups", "name"),
+        MapType::Service => ("services", "name"),
+        MapType::Filsys => ("filesystems", "name"),
+    }
+}
+
+impl ConfigDocument {
+    /// Parse config text, remembering its indentation for [`ConfigDocument::render`].
+    pub fn parse(content: &str) -> Result<Self> {
+        let root: Value = serde_json::from_str(content).config_err(|| "parsing config JSON")?;
+        if !root.is_object() {
+            return Err(HesiodError::config("config must be a JSON object"));
+        }
+        let indent = content.trim().contains('\n').then(|| {
+            content
+                .lines()
+                .nth(1)
+                .map(|line| {
+                    line.chars()
+                        .take_while(|c| *c == ' ' || *c == '\t')
+                        .collect::<String>()
+                })
+                .filter(|indent| !indent.is_empty())
+                .unwrap_or_else(|| "  ".into())
+        });
+        Ok(Self {
+            root,
+            indent,
+            trailing_newline: content.ends_with('\n'),
+        })
+    }
+
+    /// Add `entry` (an entry struct such as `UserEntry`) to the map's section.
+    /// An existing entry with the same key is an error unless `replace` is set,
+    /// in which case it is overwritten in place.
+    pub fn add<T: Serialize>(&mut self, map_type: MapType, entry: &T, replace: bool) -> Result<()> {
+        let (array, key_field) = section(map_type);
+        let entry = serde_json::to_value(entry).config_err(|| "serializing entry")?;
+        let key = entry
+            .get(key_field)
+            .and_then(Value::as_str)
+            .config_err(|| format!("entry has no {key_field}"))?
+            .to_string();
+
+        let entries = self.entries_mut(array)?;
+        match position(entries, key_field, &key) {
+            Some(index) if replace => entries[index] = entry,
+            Some(_) => {
+                return Err(HesiodError::config(format!(
+                    "{} {:?} already exists",
+                    map_type.label(),
+                    key
+                )));
+            }
+            None => entries.push(entry),
+        }
+        self.validate()
+    }
+
+    /// Remove the entry keyed `key` from the map's section.
+    pub fn remove(&mut self, map_type: MapType, key: &str) -> Result<()> {
+        let (array, key_field) = section(map_type);
+        let entries = self.entries_mut(array)?;
+        let index = position(entries, key_field, key)
+            .config_err(|| format!("{} {:?} not found", map_type.label(), key))?;
+        entries.remove(index);
+        self.validate()
+    }
+
+    /// Serialize with the original indentation (or lack of it) and trailing newline.
+    pub fn render(&self) -> Result<String> {
+        let mut text = match &self.indent {
+            Some(indent) => {
+                let mut out = Vec::new();
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+                let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+                self.root
+                    .serialize(&mut serializer)
+                    .config_err(|| "serializing config")?;
+                String::from_utf8(out).config_err(|| "serializing config")?
+            }
+            None => serde_json::to_string(&self.root).config_err(|| "serializing config")?,
+        };
+        if self.trailing_newline {
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    fn entries_mut(&mut self, array: &str) -> Result<&mut Vec<Value>> {
+        self.root
+            .as_object_mut()
+            .expect("root checked to be an object")
+            .entry(array)
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .config_err(|| format!("{array} is not an array"))
+    }
+
+    /// The edited document must still be a loadable config.
+    fn validate(&self) -> Result<()> {
+        let config: HesiodConfig =
+            serde_json::from_value(self.root.clone()).config_err(|| "edited config is invalid")?;
+        HesiodZone::from_config(&config)?;
+        Ok(())
+    }
+}
+
+fn position(entries: &[Value], key_field: &str, key: &str) -> Option<usize> {
+    entries
+        .iter()
+        .position(|e| e.get(key_field).and_then(Value::as_str) == Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GroupEntry;
+
+    const CONFIG: &str = r#"{
+    "domain": "test.internal",
+    "lhs": ".ns",
+    "rhs": ".test.internal",
+    "groups": [
+        {
+            "name": "ops",
+            "gid": 1001,
+            "members": []
+        }
+    ]
+}
+"#;
+
+    fn ops(gid: u32) -> GroupEntry {
+        GroupEntry {
+            name: "ops".into(),
+            gid,
+            members: vec![],
+        }
+    }
+
+    #[test]
+    fn add_keeps_order_and_indent() {
+        let mut doc = ConfigDocument::parse(CONFIG).expect("TODO: handle error");
+        assert!(doc.add(MapType::Group, &ops(1002), false).is_err());
+
+        doc.add(MapType::Group, &ops(1002), true)
+            .expect("TODO: handle error");
+        let text = doc.render().expect("TODO: handle error");
+        assert_eq!(text, CONFIG.replace("1001", "1002"));
+    }
+
+    #[test]
+    fn remove_missing_entry_fails() {
+        let mut doc = ConfigDocument::parse(CONFIG).expect("TODO: handle error");
+        assert!(doc.remove(MapType::Passwd, "alice").is_err());
+        doc.remove(MapType::Group, "ops")
+            .expect("TODO: handle error");
+        assert!(
+            doc.render()
+                .expect("TODO: handle error")
+                .contains("\"groups\": []")
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: MPL-2.0
+//! The `coredns.dns.DnsService` gRPC service used by CoreDNS's `grpc`
+//! plugin, so CoreDNS can forward the Hesiod zone here and cache in front:
+//!
+//! ```text
+//! example.com {
+//!     cache
+//!     grpc . hesiod-host:8080
+//! }
+//! ```
+//!
+//! It is served on the HTTP port over cleartext HTTP/2. `Query` takes and
+//! returns a `DnsPacket { bytes msg = 1; }` holding a DNS wire message, which
+//! is answered exactly as a UDP query would be, `server.map_acl` included.
+
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::Response;
+use axum::routing::post;
+use http_body::Frame;
+
+use crate::error::{Context as _, HesiodError, Result};
+use crate::health::ClientAddr;
+use crate::server::{DnsServerState, handle_query_in_view};
+
+/// gRPC status codes sent in `grpc-status`.
+const STATUS_OK: &str = "0";
+const STATUS_INVALID_ARGUMENT: &str = "3";
+const STATUS_UNIMPLEMENTED: &str = "12";
+
+/// Route for merging into the HTTP router.
+pub fn coredns_router() -> Router<Arc<DnsServerState>> {
+    Router::new().route("/coredns.dns.DnsService/Query", post(query))
+}
+
+/// `POST /coredns.dns.DnsService/Query` - One DNS message in, one out.
+async fn query(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    body: Bytes,
+) -> Response {
+    if body.first() == Some(&1) {
+        return failure(
+            STATUS_UNIMPLEMENTED,
+            "compressed messages are not supported",
+        );
+    }
+    let request = match unframe(&body).and_then(decode_packet) {
+        Ok(msg) => msg,
+        Err(e) => return failure(STATUS_INVALID_ARGUMENT, &format!("{e:#}")),
+    };
+    let client = client.map(|ClientAddr(addr)| addr.ip());
+    let response = handle_query_in_view(&body.slice_ref(request), &state, None, client);
+    state.query_count.inc();
+    match response {
+        Ok(msg) => {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static(STATUS_OK));
+            grpc_response(Body::new(UnaryBody {
+                message: Some(frame(&encode_packet(&msg))),
+                trailers: Some(trailers),
+            }))
+        }
+        Err(e) => failure(STATUS_INVALID_ARGUMENT, &format!("{e:#}")),
+    }
+}
+
+fn grpc_response(body: Body) -> Response {
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    response
+}
+
+/// A trailers-only response carrying an error status.
+fn failure(status: &'static str, message: &str) -> Response {
+    let mut response = grpc_response(Body::empty());
+    let headers = response.headers_mut();
+    headers.insert("grpc-status", HeaderValue::from_static(status));
+    if let Ok(message) = HeaderValue::from_str(&message.replace(['\r', '\n'], " ")) {
+        headers.insert("grpc-message", message);
+    }
+    response
+}
+
+/// The message of a single gRPC length-prefixed frame.
+fn unframe(body: &[u8]) -> Result<&[u8]> {
+    if body.len() < 5 {
+        return Err(HesiodError::dns("truncated gRPC frame"));
+    }
+    if body[0] != 0 {
+        return Err(HesiodError::dns("invalid gRPC compression flag"));
+    }
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    body.get(5..5 + len).dns_err(|| "truncated gRPC message")
+}
+
+fn frame(message: &[u8]) -> Bytes {
+    let mut out = Vec::with_capacity(5 + message.len());
+    out.push(0);
+    out.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    out.extend_from_slice(message);
+    out.into()
+}
+
+/// The `msg` field of a protobuf `DnsPacket`; other fields are skipped.
+fn decode_packet(mut buf: &[u8]) -> Result<&[u8]> {
+    let mut msg: &[u8] = &[];
+    while !buf.is_empty() {
+        let key = read_varint(&mut buf)?;
+        match key & 7 {
+            0 => {
+                read_varint(&mut buf)?;
+            }
+            1 | 5 => {
+                let width = if key & 7 == 1 { 8 } else { 4 };
+                buf = buf.get(width..).dns_err(|| "truncated protobuf field")?;
+            }
+            2 => {
+                let len = usize::try_from(read_varint(&mut buf)?)
+                    .dns_err(|| "protobuf field too long")?;
+                let (field, rest) = buf
+                    .split_at_checked(len)
+                    .dns_err(|| "truncated protobuf field")?;
+                if key >> 3 == 1 {
+                    msg = field;
+                }
+                buf = rest;
+            }
+            wire_type => {
+                return Err(HesiodError::dns(format!(
+                    "unsupported protobuf wire type {wire_type}"
+                )));
+            }
+        }
+    }
+    Ok(msg)
+}
+
+fn encode_packet(msg: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x0a];
+    let mut len = msg.len() as u64;
+    while len >= 0x80 {
+        out.push((len as u8) | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(msg);
+    out
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().dns_err(|| "truncated protobuf varint")?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(HesiodError::dns("protobuf varint too long"))
+}
+
+/// A unary response body: one message frame, then the status trailers.
+struct UnaryBody {
+    message: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl http_body::Body for UnaryBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(message) = self.message.take() {
+            return Poll::Ready(Some(Ok(Frame::data(message))));
+        }
+        Poll::Ready(self.trailers.take().map(|t| Ok(Frame::trailers(t))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum::http::Request;
+    use hickory_proto::op::{Message, Query, ResponseCode};
+    use hickory_proto::rr::{DNSClass, RecordType};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use crate::config::ServerConfig;
+    use crate::health::health_router;
+    use crate::records::{HesiodRecord, MapType, ServiceRecord};
+    use crate::zone::HesiodZone;
+
+    fn router() -> Router {
+        router_with(ServerConfig::default())
+    }
+
+    fn router_with(server: ServerConfig) -> Router {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "web",
+            HesiodRecord::Service(ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        health_router(Arc::new(DnsServerState::new(zone).with_server(server)))
+    }
+
+    fn call(body: Vec<u8>) -> Request<Body> {
+        Request::post("/coredns.dns.DnsService/Query")
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .body(Body::from(body))
+            .expect("TODO: handle error")
+    }
+
+    /// The answer `router` gives to a query for the `web` service.
+    async fn ask(router: Router) -> Message {
+        let mut query = Query::new();
+        query.set_name(
+            "web.service.ns.test.internal."
+                .parse()
+                .expect("TODO: handle error"),
+        );
+        query.set_query_type(RecordType::TXT);
+        query.set_query_class(DNSClass::IN);
+        let mut request = Message::new();
+        request.set_id(9);
+        request.add_query(query);
+        let wire = request.to_vec().expect("TODO: handle error");
+
+        let response = router
+            .oneshot(call(frame(&encode_packet(&wire)).to_vec()))
+            .await
+            .expect("TODO: handle error");
+        let collected = response
+            .into_body()
+            .collect()
+            .await
+            .expect("TODO: handle error");
+        let trailers = collected.trailers().cloned().expect("TODO: handle error");
+        assert_eq!(trailers["grpc-status"], "0");
+
+        let body = collected.to_bytes();
+        let msg =
+            decode_packet(unframe(&body).expect("TODO: handle error")).expect("TODO: handle error");
+        Message::from_vec(msg).expect("TODO: handle error")
+    }
+
+    #[tokio::test]
+    async fn query_answers_over_grpc() {
+        let answer = ask(router()).await;
+        assert_eq!(answer.id(), 9);
+        assert_eq!(answer.answers().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn map_acls_apply_to_the_grpc_client() {
+        let trusted = "10.0.0.0/8".parse().expect("TODO: handle error");
+        let router = router_with(ServerConfig {
+            map_acl: [(MapType::Service, vec![trusted])].into(),
+            ..ServerConfig::default()
+        });
+        let from = |client: &str| {
+            let client = ClientAddr(client.parse().expect("TODO: handle error"));
+            router.clone().layer(MockConnectInfo(client))
+        };
+        assert_eq!(ask(from("10.1.2.3:5000")).await.answers().len(), 1);
+        let refused = ask(from("192.0.2.1:5000")).await;
+        assert_eq!(refused.response_code(), ResponseCode::Refused);
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_fail_with_status() {
+        let response = router()
+            .oneshot(call(vec![0, 0, 0, 0, 9, 1]))
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.headers()["grpc-status"], STATUS_INVALID_ARGUMENT);
+
+        let response = router()
+            .oneshot(call(frame(&[0x0a, 0x02, 0xde, 0xad]).to_vec()))
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.headers()["grpc-status"], STATUS_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn packet_round_trips() {
+        let msg = vec![7u8; 300];
+        assert_eq!(
+            decode_packet(&encode_packet(&msg)).expect("TODO: handle error"),
+            msg
+        );
+        // An unknown varint field before `msg` is skipped.
+        assert_eq!(
+            decode_packet(&[0x10, 0x01, 0x0a, 0x01, 0x2a]).expect("TODO: handle error"),
+            [0x2a]
+        );
+        assert!(decode_packet(&[0x0a, 0x05, 0x00]).is_err());
+    }
+}
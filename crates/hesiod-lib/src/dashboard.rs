@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Embedded operator dashboard at `/dns/ui`.
+//!
+//! A single static page, compiled into the binary, that polls the JSON
+//! endpoints already on the HTTP port: `/dns/health` for the zone summary,
+//! `/dns/metrics/history` for the query rate graph, `/dns/queries` for recent
+//! queries and `/dns/lookup` for its lookup form. It needs no admin token and
+//! shows nothing those endpoints don't already.
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+
+use crate::server::DnsServerState;
+
+/// The dashboard page.
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// Route for merging into the HTTP router.
+pub fn dashboard_router() -> Router<Arc<DnsServerState>> {
+    Router::new().route("/dns/ui", get(dashboard))
+}
+
+/// `GET /dns/ui` - The dashboard page.
+async fn dashboard() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        DASHBOARD_HTML,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::health::health_router;
+    use crate::zone::HesiodZone;
+
+    #[tokio::test]
+    async fn serves_page() {
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        let response = health_router(Arc::new(DnsServerState::new(zone)))
+            .oneshot(
+                Request::get("/dns/ui")
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let page = std::str::from_utf8(&body).expect("TODO: handle error");
+        for endpoint in [
+            "/dns/health",
+            "/dns/metrics/history",
+            "/dns/queries",
+            "/dns/lookup",
+        ] {
+            assert!(page.contains(endpoint), "page does not use {endpoint}");
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MPL-2.0
+//! The library's error type.
+//!
+//! Every fallible function returns [`HesiodError`], so embedders can match
+//! on the kind of failure instead of its message. The message says what was
+//! being done; the cause, if any, is the error's
+//! [`source`](std::error::Error::source). Like `anyhow`, the alternate form
+//! (`{:#}`) prints the whole chain:
+//!
+//! ```text
+//! parsing zones/lab.zone: line 4: TXT for web: invalid service record port: ...
+//! ```
+
+use std::error::Error as _;
+use std::fmt;
+
+use crate::records::MapType;
+
+/// A boxed underlying cause.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// `Result` with [`HesiodError`] as the default error.
+pub type Result<T, E = HesiodError> = std::result::Result<T, E>;
+
+/// What went wrong, by kind.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum HesiodError {
+    /// A config, `hesiod.conf`, TLS or import input is invalid.
+    Config {
+        message: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// A field of a Hesiod record doesn't parse.
+    RecordParse {
+        map: MapType,
+        /// The field at fault, or `fields` when the count is wrong.
+        field: &'static str,
+        message: String,
+    },
+    /// A zone, zone file or zone reload is inconsistent.
+    Zone {
+        message: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// A DNS exchange, message or name failed.
+    Dns {
+        message: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// Reading or writing a file or socket failed.
+    Io {
+        message: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl HesiodError {
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn record_parse(map: MapType, field: &'static str, message: impl Into<String>) -> Self {
+        Self::RecordParse {
+            map,
+            field,
+            message: message.into(),
+        }
+    }
+
+    pub fn zone(message: impl Into<String>) -> Self {
+        Self::Zone {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn dns(message: impl Into<String>) -> Self {
+        Self::Dns {
+            message: message.into(),
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for HesiodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config { message, .. }
+            | Self::Zone { message, .. }
+            | Self::Dns { message, .. }
+            | Self::Io { message, .. } => f.write_str(message)?,
+            Self::RecordParse {
+                map,
+                field,
+                message,
+            } => write!(f, "invalid {map} record {field}: {message}")?,
+        }
+        if f.alternate() {
+            let mut source = self.source();
+            while let Some(cause) = source {
+                write!(f, ": {cause}")?;
+                source = cause.source();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Attach a message to a failure, making it a [`HesiodError`] of the chosen
+/// kind with the failure as its source.
+pub(crate) trait Context<T> {
+    fn config_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T>;
+    fn zone_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T>;
+    #[cfg(feature = "net")]
+    fn dns_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T>;
+}
+
+impl<T, E: Into<BoxError>> Context<T> for std::result::Result<T, E> {
+    fn config_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|e| HesiodError::Config {
+            message: message().into(),
+            source: Some(e.into()),
+        })
+    }
+
+    fn zone_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|e| HesiodError::Zone {
+            message: message().into(),
+            source: Some(e.into()),
+        })
+    }
+
+    #[cfg(feature = "net")]
+    fn dns_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|e| HesiodError::Dns {
+            message: message().into(),
+            source: Some(e.into()),
+        })
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn config_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.ok_or_else(|| HesiodError::config(message()))
+    }
+
+    fn zone_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.ok_or_else(|| HesiodError::zone(message()))
+    }
+
+    #[cfg(feature = "net")]
+    fn dns_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.ok_or_else(|| HesiodError::dns(message()))
+    }
+}
+
+/// Attach a message to an I/O failure.
+pub(crate) trait IoContext<T> {
+    fn io_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T>;
+}
+
+impl<T> IoContext<T> for std::io::Result<T> {
+    fn io_err<M: Into<String>>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|source| HesiodError::Io {
+            message: message().into(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_display_prints_the_chain() {
+        let inner: Result<u16> = "x"
+            .parse::<u16>()
+            .map_err(|e| HesiodError::record_parse(MapType::Service, "port", e.to_string()));
+        let err = inner
+            .zone_err(|| "line 4: TXT for web")
+            .expect_err("bad port");
+        assert_eq!(err.to_string(), "line 4: TXT for web");
+        assert_eq!(
+            format!("{err:#}"),
+            "line 4: TXT for web: invalid service record port: \
+             invalid digit found in string"
+        );
+        assert!(matches!(err, HesiodError::Zone { .. }));
+    }
+}
@@ -0,0 +1,464 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Rendering of zone records as flat files for hosts without Hesiod NSS.
+//!
+//! Output matches what `getent passwd`/`getent group` print on a host that
+//! resolves through Hesiod: password fields are `*` and entries are ordered by
+//! numeric id, then name. Filsys records become autofs maps, and users and
+//! groups can also be written as LDIF for seeding an LDAP directory, and
+//! service hosts as an Ansible inventory.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use serde_json::{Map, Value, json};
+
+use crate::config::HesiodConfig;
+use crate::records::{GroupRecord, HesiodRecord, MapType, PasswdRecord};
+use crate::zone::HesiodZone;
+
+/// `/etc/passwd`-format text of every passwd record.
+pub fn passwd_file(zone: &HesiodZone) -> String {
+    lines(users(zone).iter().map(|user| user.to_txt()))
+}
+
+/// `/etc/group`-format text of every group record.
+pub fn group_file(zone: &HesiodZone) -> String {
+    lines(groups(zone).iter().map(|group| group.to_txt()))
+}
+
+/// LDIF adding `ou=People` and `ou=Group` under `base_dn` (which must
+/// already exist), then a posixAccount per user and a posixGroup per group.
+/// `base_dn` defaults to the zone domain as `dc=` components.
+pub fn ldif(zone: &HesiodZone, base_dn: Option<&str>) -> String {
+    let base_dn = base_dn.map_or_else(|| domain_dn(&zone.domain), str::to_string);
+    let people = format!("ou=People,{base_dn}");
+    let group_ou = format!("ou=Group,{base_dn}");
+
+    let mut entries = Vec::new();
+    for (ou, dn) in [("People", &people), ("Group", &group_ou)] {
+        entries.push(ldif_entry(
+            dn,
+            &[
+                ("objectClass", "top"),
+                ("objectClass", "organizationalUnit"),
+                ("ou", ou),
+            ],
+        ));
+    }
+    for user in users(zone) {
+        let (uid, gid) = (user.uid.to_string(), user.gid.to_string());
+        let cn = if user.gecos.is_empty() {
+            &user.username
+        } else {
+            user.gecos.split(',').next().unwrap_or_default()
+        };
+        let mut attrs = vec![
+            ("objectClass", "top"),
+            ("objectClass", "account"),
+            ("objectClass", "posixAccount"),
+            ("uid", user.username.as_str()),
+            ("cn", cn),
+            ("uidNumber", &uid),
+            ("gidNumber", &gid),
+            ("homeDirectory", &user.home),
+            ("loginShell", &user.shell),
+        ];
+        if !user.gecos.is_empty() {
+            attrs.push(("gecos", &user.gecos));
+        }
+        let dn = format!("uid={},{people}", escape_dn_value(&user.username));
+        entries.push(ldif_entry(&dn, &attrs));
+    }
+    for group in groups(zone) {
+        let gid = group.gid.to_string();
+        let mut attrs = vec![
+            ("objectClass", "top"),
+            ("objectClass", "posixGroup"),
+            ("cn", group.name.as_str()),
+            ("gidNumber", &gid),
+        ];
+        attrs.extend(group.members.iter().map(|m| ("memberUid", m.as_str())));
+        let dn = format!("cn={},{group_ou}", escape_dn_value(&group.name));
+        entries.push(ldif_entry(&dn, &attrs));
+    }
+    format!("version: 1\n\n{}", entries.join("\n"))
+}
+
+/// Ansible inventory, in the YAML inventory plugin's layout (JSON is valid
+/// YAML), of the hosts in service records. Each host is in a group named
+/// after each of its services and, when `config` is given, each tag of its
+/// service entries. Host vars list its `hesiod_services`, plus
+/// `ansible_host` when an entry has an address.
+pub fn ansible_inventory(zone: &HesiodZone, config: Option<&HesiodConfig>) -> Value {
+    let mut hosts: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    let mut groups: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for entry in zone.snapshot(Some(MapType::Service)).records {
+        let HesiodRecord::Service(service) = &entry.record else {
+            continue;
+        };
+        let host = service.host.trim_end_matches('.').to_string();
+        let vars = hosts.entry(host.clone()).or_default();
+        let services = vars.entry("hesiod_services").or_insert_with(|| json!([]));
+        if let Value::Array(services) = services {
+            services.push(json!({
+                "name": entry.name,
+                "port": service.port,
+                "protocol": service.protocol,
+            }));
+        }
+        groups
+            .entry(group_name(&entry.name))
+            .or_default()
+            .insert(host);
+    }
+
+    for entry in config.map_or(&[][..], |config| &config.services) {
+        let host = entry.host.trim_end_matches('.');
+        let Some(vars) = hosts.get_mut(host) else {
+            continue;
+        };
+        if let Some(address) = entry.address {
+            vars.insert("ansible_host".into(), json!(address.to_string()));
+        }
+        for tag in &entry.tags {
+            groups
+                .entry(group_name(tag))
+                .or_default()
+                .insert(host.to_string());
+        }
+    }
+
+    let children: Map<String, Value> = groups
+        .into_iter()
+        .map(|(group, members)| {
+            let members: Map<String, Value> =
+                members.into_iter().map(|host| (host, json!({}))).collect();
+            (group, json!({ "hosts": members }))
+        })
+        .collect();
+    json!({ "all": { "hosts": hosts, "children": children } })
+}
+
+/// `name` as an Ansible group name: letters, digits and underscores, not
+/// starting with a digit.
+fn group_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+fn users(zone: &HesiodZone) -> Vec<&PasswdRecord> {
+    let mut users: Vec<&PasswdRecord> = zone
+        .records()
+        .filter_map(|(_, record)| match record {
+            HesiodRecord::Passwd(user) => Some(user),
+            _ => None,
+        })
+        .collect();
+    users.sort_by(|a, b| a.uid.cmp(&b.uid).then_with(|| a.username.cmp(&b.username)));
+    users
+}
+
+fn groups(zone: &HesiodZone) -> Vec<&GroupRecord> {
+    let mut groups: Vec<&GroupRecord> = zone
+        .records()
+        .filter_map(|(_, record)| match record {
+            HesiodRecord::Group(group) => Some(group),
+            _ => None,
+        })
+        .collect();
+    groups.sort_by(|a, b| a.gid.cmp(&b.gid).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
+
+/// `example.com` as `dc=example,dc=com`.
+fn domain_dn(domain: &str) -> String {
+    domain
+        .trim_matches('.')
+        .split('.')
+        .map(|label| format!("dc={}", escape_dn_value(label)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// One LDIF record, blank-line terminated.
+fn ldif_entry(dn: &str, attrs: &[(&str, &str)]) -> String {
+    let mut out = ldif_line("dn", dn);
+    for (name, value) in attrs {
+        out.push_str(&ldif_line(name, value));
+    }
+    out
+}
+
+/// `name: value`, or `name:: <base64>` when the value isn't a safe
+/// string under RFC 2849.
+fn ldif_line(name: &str, value: &str) -> String {
+    let safe = value
+        .bytes()
+        .all(|b| b.is_ascii() && b != b'\0' && b != b'\n' && b != b'\r')
+        && !value.starts_with([' ', ':', '<'])
+        && !value.ends_with(' ');
+    if safe {
+        format!("{name}: {value}\n")
+    } else {
+        format!("{name}:: {}\n", BASE64.encode(value))
+    }
+}
+
+/// Escape an RDN attribute value (RFC 4514).
+fn escape_dn_value(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let edge = i == 0 && (c == ' ' || c == '#') || i == last && c == ' ';
+        if edge || matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// One autofs map file: its name and contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutofsFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// autofs maps for every filsys record: an `auto.master` followed by one
+/// indirect map per parent directory (`/home/alice` goes into `auto.home`
+/// under key `alice`). Mounts directly under `/` go into the direct map
+/// `auto.direct`. `map_dir` is where `auto.master` expects the maps.
+pub fn autofs_files(zone: &HesiodZone, map_dir: &str) -> Vec<AutofsFile> {
+    // mount point -> (map name, entries)
+    let mut maps: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+    for (_, record) in zone.records() {
+        let HesiodRecord::Filsys(fs) = record else {
+            continue;
+        };
+        let path = Path::new(&fs.mount_path);
+        let parent = path
+            .parent()
+            .and_then(Path::to_str)
+            .unwrap_or("/")
+            .trim_end_matches('/');
+        let (mount_point, map_name, key) = if parent.is_empty() {
+            (
+                "/-".to_string(),
+                "auto.direct".to_string(),
+                fs.mount_path.clone(),
+            )
+        } else {
+            let key = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let map_name = format!("auto.{}", parent.trim_start_matches('/').replace('/', "_"));
+            (parent.to_string(), map_name, key)
+        };
+        maps.entry(mount_point)
+            .or_insert_with(|| (map_name, Vec::new()))
+            .1
+            .push(format!(
+                "{key}\t-fstype={},{}\t{}",
+                fs.fs_type, fs.mode, fs.source
+            ));
+    }
+
+    let map_dir = map_dir.trim_end_matches('/');
+    let master = lines(
+        maps.iter()
+            .map(|(mount_point, (name, _))| format!("{mount_point}\t{map_dir}/{name}")),
+    );
+    let mut files = vec![AutofsFile {
+        name: "auto.master".into(),
+        content: master,
+    }];
+    for (name, mut entries) in maps.into_values() {
+        entries.sort();
+        files.push(AutofsFile {
+            name,
+            content: lines(entries.into_iter()),
+        });
+    }
+    files
+}
+
+fn lines(entries: impl Iterator<Item = String>) -> String {
+    entries.map(|line| line + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::FilsysRecord;
+
+    fn user(name: &str, uid: u32) -> HesiodRecord {
+        HesiodRecord::Passwd(PasswdRecord {
+            username: name.into(),
+            uid,
+            gid: 100,
+            gecos: name.to_uppercase(),
+            home: format!("/home/{name}"),
+            shell: "/bin/sh".into(),
+        })
+    }
+
+    #[test]
+    fn files_are_ordered_by_id() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record("zed", user("zed", 1000));
+        zone.add_record("bob", user("bob", 1001));
+        zone.add_record("amy", user("amy", 1001));
+        zone.add_record(
+            "staff",
+            HesiodRecord::Group(GroupRecord {
+                name: "staff".into(),
+                gid: 100,
+                members: vec!["amy".into(), "bob".into()],
+            }),
+        );
+
+        assert_eq!(
+            passwd_file(&zone),
+            "zed:*:1000:100:ZED:/home/zed:/bin/sh\n\
+             amy:*:1001:100:AMY:/home/amy:/bin/sh\n\
+             bob:*:1001:100:BOB:/home/bob:/bin/sh\n"
+        );
+        assert_eq!(group_file(&zone), "staff:*:100:amy,bob\n");
+    }
+
+    #[test]
+    fn ldif_entries() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record("amy", user("amy", 1001));
+        zone.add_record(
+            "staff",
+            HesiodRecord::Group(GroupRecord {
+                name: "staff".into(),
+                gid: 100,
+                members: vec!["amy".into()],
+            }),
+        );
+
+        let text = ldif(&zone, None);
+        assert!(text.starts_with("version: 1\n\ndn: ou=People,dc=test,dc=internal\n"));
+        assert!(text.contains(
+            "dn: uid=amy,ou=People,dc=test,dc=internal\n\
+             objectClass: top\n\
+             objectClass: account\n\
+             objectClass: posixAccount\n\
+             uid: amy\n\
+             cn: AMY\n\
+             uidNumber: 1001\n\
+             gidNumber: 100\n\
+             homeDirectory: /home/amy\n\
+             loginShell: /bin/sh\n\
+             gecos: AMY\n"
+        ));
+        assert!(text.ends_with(
+            "dn: cn=staff,ou=Group,dc=test,dc=internal\n\
+             objectClass: top\n\
+             objectClass: posixGroup\n\
+             cn: staff\n\
+             gidNumber: 100\n\
+             memberUid: amy\n"
+        ));
+        assert!(ldif(&zone, Some("o=corp")).contains("dn: uid=amy,ou=People,o=corp\n"));
+
+        assert_eq!(ldif_line("gecos", "Zoë"), "gecos:: Wm/Dqw==\n");
+        assert_eq!(escape_dn_value("a,b "), "a\\,b\\ ");
+    }
+
+    #[test]
+    fn ansible_groups_by_service_and_tag() {
+        use crate::config::ServiceEntry;
+
+        let mut config = HesiodConfig::new("test.internal", ".ns", ".test.internal");
+        for (name, host, tags) in [
+            ("web", "app1.test.internal", vec!["frontend"]),
+            ("api-v2", "app1.test.internal", vec![]),
+            ("db", "db1.test.internal", vec!["backend", "frontend"]),
+        ] {
+            config.services.push(ServiceEntry {
+                name: name.into(),
+                host: host.into(),
+                port: 443,
+                protocol: "tcp".into(),
+                address: (host == "db1.test.internal")
+                    .then(|| "10.0.0.5".parse().expect("TODO: handle error")),
+                tags: tags.into_iter().map(String::from).collect(),
+            });
+        }
+        let zone = HesiodZone::from_config(&config).expect("TODO: handle error");
+
+        let inventory = ansible_inventory(&zone, Some(&config));
+        let all = &inventory["all"];
+        assert_eq!(
+            all["hosts"]["db1.test.internal"]["ansible_host"],
+            "10.0.0.5"
+        );
+        assert_eq!(
+            all["hosts"]["app1.test.internal"]["hesiod_services"][0],
+            json!({ "name": "api-v2", "port": 443, "protocol": "tcp" })
+        );
+        let groups: Vec<_> = all["children"]
+            .as_object()
+            .expect("TODO: handle error")
+            .keys()
+            .collect();
+        assert_eq!(groups, ["api_v2", "backend", "db", "frontend", "web"]);
+        assert_eq!(
+            all["children"]["frontend"]["hosts"],
+            json!({ "app1.test.internal": {}, "db1.test.internal": {} })
+        );
+
+        // Without the config there are no tags or addresses.
+        let bare = ansible_inventory(&zone, None);
+        assert!(bare["all"]["children"]["frontend"].is_null());
+    }
+
+    #[test]
+    fn autofs_maps_group_by_parent() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        for (name, path) in [
+            ("bob", "/home/bob"),
+            ("alice", "/home/alice"),
+            ("data", "/data"),
+        ] {
+            zone.add_record(
+                name,
+                HesiodRecord::Filsys(FilsysRecord {
+                    fs_type: "nfs".into(),
+                    mount_path: path.into(),
+                    source: format!("nfs:/export{path}"),
+                    mode: "rw".into(),
+                }),
+            );
+        }
+
+        let files = autofs_files(&zone, "/etc/");
+        let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["auto.master", "auto.direct", "auto.home"]);
+        assert_eq!(
+            files[0].content,
+            "/-\t/etc/auto.direct\n/home\t/etc/auto.home\n"
+        );
+        assert_eq!(
+            files[2].content,
+            "alice\t-fstype=nfs,rw\tnfs:/export/home/alice\n\
+             bob\t-fstype=nfs,rw\tnfs:/export/home/bob\n"
+        );
+    }
+}
@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Zone output for DNS servers that don't read BIND zone files, and for
+//! resolvers carrying a static copy of the records.
+//!
+//! These are all class IN, so clients must query IN (`classes=IN` in
+//! hesiod.conf).
+
+use serde_json::json;
+
+use crate::idn::key_to_ascii;
+use crate::records::{HesiodRecord, MapType};
+use crate::zone::HesiodZone;
+
+/// Lowest TTL Cloudflare accepts outside enterprise plans.
+const CLOUDFLARE_MIN_TTL: u32 = 60;
+
+impl HesiodZone {
+    /// tinydns-data lines: a `Z` line for the SOA, `&` lines for the NS
+    /// records and one `'` line per TXT record.
+    pub fn to_tinydns(&self, serial: u32) -> String {
+        let origin = self.origin();
+        let (mname, rname) = self.soa_names();
+        let (mname, rname) = (mname.trim_end_matches('.'), rname.trim_end_matches('.'));
+
+        let mut out = format!(
+            "# Hesiod data for {}\n# Generated by hesiod-dns-map\n",
+            self.domain
+        );
+        out.push_str(&format!(
+            "Z{origin}:{mname}:{rname}:{serial}:{}:{}:{}:{}:{}\n",
+            self.soa.refresh,
+            self.soa.retry,
+            self.soa.expire,
+            self.soa_minimum(),
+            self.ttl
+        ));
+        if self.soa.nameservers.is_empty() {
+            out.push_str(&format!("&{origin}::{mname}:{}\n", self.ttl));
+        }
+        for ns in &self.soa.nameservers {
+            out.push_str(&format!(
+                "&{origin}::{}:{}\n",
+                ns.trim_end_matches('.'),
+                self.ttl
+            ));
+        }
+        for entry in self.snapshot(None).records {
+            out.push_str(&format!(
+                "'{}:{}:{}\n",
+                self.fqdn(&entry.name, entry.record.map_type()),
+                tinydns_escape(&entry.record.to_txt()),
+                self.ttl
+            ));
+        }
+        out
+    }
+
+    /// dnsmasq config lines: one `txt-record=` per record plus a
+    /// `srv-host=` (`_name._protocol.<origin>`) per service record. dnsmasq
+    /// has no per-record TTL, so set `local-ttl` to match the zone.
+    pub fn to_dnsmasq(&self) -> String {
+        let mut out = format!(
+            "# Hesiod data for {}\n# Generated by hesiod-dns-map\n",
+            self.domain
+        );
+        let mut srv_hosts = Vec::new();
+        for entry in self.snapshot(None).records {
+            out.push_str(&format!(
+                "txt-record={},\"{}\"\n",
+                self.fqdn(&entry.name, entry.record.map_type()),
+                dnsmasq_escape(&entry.record.to_txt())
+            ));
+            if let HesiodRecord::Service(service) = &entry.record {
+                srv_hosts.push(format!(
+                    "srv-host=_{}._{}.{},{},{}\n",
+                    entry.name,
+                    service.protocol,
+                    self.origin(),
+                    service.host.trim_end_matches('.'),
+                    service.port
+                ));
+            }
+        }
+        out.extend(srv_hosts);
+        out
+    }
+
+    /// unbound.conf `server:` clause: a transparent `local-zone` for the
+    /// Hesiod names and a `local-data` per record. Other names under the
+    /// zone still resolve normally.
+    pub fn to_unbound(&self) -> String {
+        let hesiod_zone = match self.lhs.trim_matches('.') {
+            "" => self.origin().to_string(),
+            lhs => format!("{lhs}.{}", self.origin()),
+        };
+        let mut out = format!(
+            "# Hesiod data for {}\n# Generated by hesiod-dns-map\nserver:\n    local-zone: \"{hesiod_zone}.\" transparent\n",
+            self.domain
+        );
+        for entry in self.snapshot(None).records {
+            // The value is single-quoted for unbound, so `'` needs a DNS escape.
+            out.push_str(&format!(
+                "    local-data: '{}. {} IN TXT {}'\n",
+                self.fqdn(&entry.name, entry.record.map_type()),
+                self.ttl,
+                quote_txt(&entry.record.to_txt()).replace('\'', "\\039")
+            ));
+        }
+        out
+    }
+
+    /// Class IN TXT records with absolute owner names and no SOA, for
+    /// `$INCLUDE` into a zone a resolver already serves.
+    pub fn to_bind_include(&self) -> String {
+        let mut out = format!(
+            "; Hesiod data for {}\n; Generated by hesiod-dns-map\n",
+            self.domain
+        );
+        for entry in self.snapshot(None).records {
+            out.push_str(&format!(
+                "{}.\t{} IN TXT {}\n",
+                self.fqdn(&entry.name, entry.record.map_type()),
+                self.ttl,
+                quote_txt(&entry.record.to_txt())
+            ));
+        }
+        out
+    }
+
+    /// Route53 `change-resource-record-sets` change batch, one `UPSERT` of a
+    /// TXT record set per record:
+    ///
+    /// ```text
+    /// aws route53 change-resource-record-sets --hosted-zone-id Z123 \
+    ///     --change-batch file://hesiod-route53.json
+    /// ```
+    ///
+    /// Route53 takes at most 1000 changes per batch, so larger zones have to
+    /// be split before submitting.
+    pub fn to_route53(&self) -> String {
+        let changes: Vec<_> = self
+            .snapshot(None)
+            .records
+            .iter()
+            .map(|entry| {
+                json!({
+                    "Action": "UPSERT",
+                    "ResourceRecordSet": {
+                        "Name": format!("{}.", self.fqdn(&entry.name, entry.record.map_type())),
+                        "Type": "TXT",
+                        "TTL": self.ttl,
+                        "ResourceRecords": [{ "Value": quote_txt(&entry.record.to_txt()) }],
+                    },
+                })
+            })
+            .collect();
+        let batch = json!({
+            "Comment": format!("Hesiod data for {}, generated by hesiod-dns-map", self.domain),
+            "Changes": changes,
+        });
+        serde_json::to_string_pretty(&batch).unwrap_or_default() + "\n"
+    }
+
+    /// Zone file for Cloudflare's DNS record import: `$ORIGIN`, `$TTL` and
+    /// class IN TXT records, with no SOA or NS since Cloudflare keeps its
+    /// own. Cloudflare's shortest TTL is 60 seconds, so shorter ones are
+    /// raised to that.
+    pub fn to_cloudflare(&self) -> String {
+        let ttl = self.ttl.max(CLOUDFLARE_MIN_TTL);
+        let mut out = format!(
+            "; Hesiod data for {}\n; Generated by hesiod-dns-map\n$ORIGIN {}.\n$TTL {ttl}\n",
+            self.domain,
+            self.origin()
+        );
+        for entry in self.snapshot(None).records {
+            out.push_str(&format!(
+                "{}.\t{ttl}\tIN\tTXT\t{}\n",
+                self.fqdn(&entry.name, entry.record.map_type()),
+                quote_txt(&entry.record.to_txt())
+            ));
+        }
+        out
+    }
+
+    /// Fully qualified owner name of a record, without the trailing dot.
+    fn fqdn(&self, name: &str, map_type: MapType) -> String {
+        format!(
+            "{}.{}{}.{}",
+            key_to_ascii(name),
+            map_type.label(),
+            self.lhs,
+            self.origin()
+        )
+    }
+}
+
+/// Serial of the `Z` line in tinydns data written by
+/// [`HesiodZone::to_tinydns`].
+pub fn tinydns_serial(data: &str) -> Option<u32> {
+    data.lines()
+        .find_map(|line| line.strip_prefix('Z'))
+        .and_then(|soa| soa.split(':').nth(3)?.parse().ok())
+}
+
+/// TXT data in zone file syntax: quoted and escaped, split into strings of
+/// at most 255 bytes.
+pub(crate) fn quote_txt(txt: &str) -> String {
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    for c in txt.chars() {
+        if current.len() + c.len_utf8() > 255 {
+            strings.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    strings.push(current);
+    strings
+        .iter()
+        .map(|s| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Octal-escape bytes tinydns-data treats specially (`:`, `\`) and anything
+/// outside printable ASCII.
+fn tinydns_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte == b':' || byte == b'\\' || !(0x20..0x7f).contains(&byte) {
+            out.push_str(&format!("\\{byte:03o}"));
+        } else {
+            out.push(char::from(byte));
+        }
+    }
+    out
+}
+
+/// Backslash-escape quotes and backslashes for a quoted dnsmasq value.
+fn dnsmasq_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::{GroupRecord, ServiceRecord};
+
+    fn sample_zone() -> HesiodZone {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "web",
+            HesiodRecord::Service(ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        zone
+    }
+
+    #[test]
+    fn tinydns_lines() {
+        let data = sample_zone().to_tinydns(7);
+        assert_eq!(tinydns_serial(&data), Some(7));
+        assert!(data.contains(
+            "Ztest.internal:ns.test.internal:hostmaster.test.internal:7:3600:900:604800:300:300\n"
+        ));
+        assert!(data.contains("&test.internal::ns.test.internal:300\n"));
+        assert!(data.ends_with("'web.service.ns.test.internal:web.svc\\072443\\072tcp:300\n"));
+    }
+
+    #[test]
+    fn dnsmasq_lines() {
+        let mut zone = sample_zone();
+        zone.add_record(
+            "ops",
+            HesiodRecord::Group(GroupRecord {
+                name: "ops".into(),
+                gid: 10,
+                members: vec!["alice".into()],
+            }),
+        );
+        let config = zone.to_dnsmasq();
+        assert!(config.contains("txt-record=ops.group.ns.test.internal,\"ops:*:10:alice\"\n"));
+        assert!(config.contains("txt-record=web.service.ns.test.internal,\"web.svc:443:tcp\"\n"));
+        assert!(config.ends_with("srv-host=_web._tcp.test.internal,web.svc,443\n"));
+        assert_eq!(dnsmasq_escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+
+    #[test]
+    fn unbound_and_include_lines() {
+        let mut zone = sample_zone();
+        zone.add_record(
+            "o'neil",
+            HesiodRecord::Group(GroupRecord {
+                name: "o'neil".into(),
+                gid: 11,
+                members: vec![],
+            }),
+        );
+        let unbound = zone.to_unbound();
+        assert!(unbound.contains("server:\n    local-zone: \"ns.test.internal.\" transparent\n"));
+        assert!(unbound.contains(
+            "    local-data: 'web.service.ns.test.internal. 300 IN TXT \"web.svc:443:tcp\"'\n"
+        ));
+        assert!(unbound.contains("IN TXT \"o\\039neil:*:11:\"'\n"));
+
+        let include = zone.to_bind_include();
+        assert!(
+            include.contains("web.service.ns.test.internal.\t300 IN TXT \"web.svc:443:tcp\"\n")
+        );
+        assert!(!include.contains("SOA"));
+    }
+
+    #[test]
+    fn route53_and_cloudflare_records() {
+        let mut zone = sample_zone();
+        zone.ttl = 30;
+        let batch: serde_json::Value =
+            serde_json::from_str(&zone.to_route53()).expect("TODO: handle error");
+        assert_eq!(batch["Changes"][0]["Action"], "UPSERT");
+        let set = &batch["Changes"][0]["ResourceRecordSet"];
+        assert_eq!(set["Name"], "web.service.ns.test.internal.");
+        assert_eq!(set["TTL"], 30);
+        assert_eq!(set["ResourceRecords"][0]["Value"], "\"web.svc:443:tcp\"");
+
+        let cloudflare = zone.to_cloudflare();
+        assert!(cloudflare.contains("$ORIGIN test.internal.\n$TTL 60\n"));
+        assert!(
+            cloudflare
+                .ends_with("web.service.ns.test.internal.\t60\tIN\tTXT\t\"web.svc:443:tcp\"\n")
+        );
+        assert!(!cloudflare.contains("SOA"));
+    }
+
+    #[test]
+    fn long_txt_is_split() {
+        let txt = format!("{}\"", "a".repeat(300));
+        assert_eq!(
+            quote_txt(&txt),
+            format!("\"{}\" \"{}\\\"\"", "a".repeat(255), "a".repeat(45))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Configs kept in a git repository, for GitOps-style zone management: the
+//! server clones the repository, pulls it on each reload or resync and
+//! serves the config file at the fetched commit, which `/dns/version`
+//! reports. The repository's history is then the audit trail of the zone.
+//!
+//! Runs the `git` command, so whatever credentials it is set up with (SSH
+//! keys, credential helpers) apply. It is never asked to prompt.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::process::Command;
+use tracing::info;
+
+use crate::config::HesiodConfig;
+use crate::error::{HesiodError, IoContext, Result};
+use crate::signature::TrustedKeys;
+use crate::source::read_config;
+
+/// A config file in a git repository, checked out to a local directory.
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    repo: String,
+    /// Unset follows the remote's default branch.
+    branch: Option<String>,
+    /// The config file, relative to the repository root.
+    path: PathBuf,
+    /// Working copy the server owns; local changes in it are discarded.
+    checkout: PathBuf,
+    commit: Arc<Mutex<Option<String>>>,
+}
+
+impl GitSource {
+    /// The config at `path` in `repo` (a URL or local path), on `branch`,
+    /// checked out in `checkout`.
+    pub fn new(repo: &str, branch: Option<&str>, path: &Path, checkout: &Path) -> Self {
+        Self {
+            repo: repo.to_string(),
+            branch: branch.map(String::from),
+            path: path.to_path_buf(),
+            checkout: checkout.to_path_buf(),
+            commit: Arc::default(),
+        }
+    }
+
+    /// The commit last checked out.
+    pub fn commit(&self) -> Option<String> {
+        self.commit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Clone or pull the repository and read the config at its head,
+    /// checked against its signature there when there are `keys`.
+    pub(crate) async fn fetch(&self, keys: Option<&TrustedKeys>) -> Result<HesiodConfig> {
+        let checkout = self.checkout.to_string_lossy().into_owned();
+        if self.checkout.join(".git").is_dir() {
+            let branch = self.branch.as_deref().unwrap_or("HEAD");
+            git(&[
+                "-C", &checkout, "fetch", "--quiet", "--depth", "1", "origin", branch,
+            ])
+            .await?;
+            git(&["-C", &checkout, "reset", "--quiet", "--hard", "FETCH_HEAD"]).await?;
+        } else {
+            let mut args = vec!["clone", "--quiet", "--depth", "1"];
+            if let Some(branch) = &self.branch {
+                args.extend(["--branch", branch]);
+            }
+            args.extend(["--", &self.repo, &checkout]);
+            git(&args).await?;
+        }
+        let commit = git(&["-C", &checkout, "rev-parse", "HEAD"]).await?;
+        let config = read_config(&self.checkout.join(&self.path), keys)?;
+        let previous = self
+            .commit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(commit.clone());
+        if previous.as_ref() != Some(&commit) {
+            info!("checked out {} at {}", self.repo, commit);
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for GitSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "git:{}", self.repo)?;
+        if let Some(branch) = &self.branch {
+            write!(f, "#{branch}")?;
+        }
+        write!(f, ":{}", self.path.display())
+    }
+}
+
+/// Sources are the same if they read the same file of the same branch.
+impl PartialEq for GitSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.repo == other.repo
+            && self.branch == other.branch
+            && self.path == other.path
+            && self.checkout == other.checkout
+    }
+}
+
+impl Eq for GitSource {}
+
+/// Run `git` with `args` and return its trimmed standard output.
+async fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .io_err(|| "running git")?;
+    if !output.status.success() {
+        return Err(HesiodError::config(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commit `config` as `hesiod.json` in the repository at `repo`.
+    async fn commit(repo: &str, config: &str) -> String {
+        std::fs::write(Path::new(repo).join("hesiod.json"), config).expect("TODO: handle error");
+        let identity = "-c user.name=hesiod -c user.email=hesiod@test.internal";
+        let commit = format!("-C {repo} {identity} commit --quiet -m update");
+        for args in [format!("-C {repo} add hesiod.json"), commit] {
+            let args: Vec<_> = args.split(' ').collect();
+            git(&args).await.expect("TODO: handle error");
+        }
+        git(&["-C", repo, "rev-parse", "HEAD"])
+            .await
+            .expect("TODO: handle error")
+    }
+
+    #[tokio::test]
+    async fn follows_new_commits() {
+        let root = std::env::temp_dir().join(format!("hesiod-git-{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let (repo, checkout) = (root.join("repo"), root.join("checkout"));
+        std::fs::create_dir_all(&repo).expect("TODO: handle error");
+        let repo_path = repo.to_string_lossy().into_owned();
+        git(&["init", "--quiet", &repo_path])
+            .await
+            .expect("TODO: handle error");
+
+        let zone = r#"{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal"#;
+        let first = commit(&repo_path, &format!("{zone}\"}}")).await;
+        let source = GitSource::new(&repo_path, None, Path::new("hesiod.json"), &checkout);
+        let config = source.fetch(None).await.expect("TODO: handle error");
+        assert!(config.services.is_empty());
+        assert_eq!(source.commit(), Some(first));
+
+        let services = r#"", "services": [{"name": "web", "host": "web.svc", "port": 443}]}"#;
+        let second = commit(&repo_path, &format!("{zone}{services}")).await;
+        let config = source.fetch(None).await.expect("TODO: handle error");
+        assert_eq!(config.services.len(), 1);
+        assert_eq!(source.commit(), Some(second));
+        assert_eq!(source.to_string(), format!("git:{repo_path}:hesiod.json"));
+
+        let missing = GitSource::new(&repo_path, None, Path::new("nope.json"), &root.join("b"));
+        assert!(missing.fetch(None).await.is_err());
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -1,74 +1,1656 @@
 // SPDX-License-Identifier: MPL-2.0
 //! HTTP health and metrics endpoints using Axum (port 8080).
 
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::Router;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, FromRequestParts, OptionalFromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::Json;
 use axum::routing::{get, post};
+use axum::serve::IncomingStream;
+use serde::Deserialize;
 use serde_json::{Value, json};
+use tokio::sync::watch;
 use tracing::info;
 
+use crate::audit::AuditEntry;
+use crate::build_info;
+use crate::config::{AdminConfig, HttpConfig, UnixSocketConfig};
+use crate::error::{HesiodError, IoContext, Result};
+use crate::http_tls::{self, TlsListener};
+use crate::metrics::MetricsSnapshot;
+use crate::proxy_protocol::ProxyListener;
+use crate::rate_limit::{self, RateLimiter};
+use crate::records::{MapType, ServiceRecord};
+use crate::registry::Registered;
+use crate::search::{self, RecordPattern};
+use crate::secret::Secret;
 use crate::server::DnsServerState;
+use crate::source::{reload_one_zone, reload_zone};
+use crate::zone::{HesiodZone, ZoneSet};
 
 /// Build the Axum router for health/metrics endpoints.
 pub fn health_router(state: Arc<DnsServerState>) -> Router {
     Router::new()
         .route("/dns/health", get(health_check))
+        .route("/dns/ready", get(readiness))
+        .route("/dns/drain", post(start_drain).delete(end_drain))
         .route("/dns/metrics", get(metrics))
+        .route("/dns/metrics/reset", post(reset_metrics))
+        .route("/dns/metrics/history", get(metrics_history))
+        .route("/dns/queries", get(recent_queries))
+        .route("/dns/lookup", get(lookup))
+        .route("/dns/register", get(registrations).post(register))
         .route("/dns/reload", post(reload))
+        .route("/dns/sync", post(sync))
+        .route("/dns/zone/checksum", get(zone_checksum))
+        .route("/dns/version", get(version))
+        .route("/dns/records", get(records))
+        .route("/dns/records/search", get(search_records))
+        .route("/dns/audit", get(audit_log))
+        .route("/dns/zones/{domain}/records", get(zone_records))
+        .route("/dns/zones/{domain}/metrics", get(zone_metrics))
+        .route("/dns/zones/{domain}/reload", post(zone_reload))
+        .nest("/dns/pdns", crate::powerdns::powerdns_router())
+        .merge(crate::coredns::coredns_router())
+        .merge(crate::dashboard::dashboard_router())
         .with_state(state)
 }
 
 /// `GET /dns/health` - Returns server status, zone record count, and uptime.
+/// Reports "degraded" while the last sync with the config source has failed
+/// and the previously loaded zone is being served.
 async fn health_check(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
     let uptime = state.start_time.elapsed();
+    let zone = state.zone();
+    let sync = state.sync_status();
     Json(json!({
-        "status": "healthy",
-        "zone_records": state.zone.record_count(),
-        "domain": state.zone.domain,
+        "status": if sync.is_degraded() { "degraded" } else { "healthy" },
+        "draining": state.is_draining(),
+        "zone_records": zone.record_count(),
+        "domain": zone.domain,
+        "zone_checksum": zone.checksum(),
         "uptime_seconds": uptime.as_secs(),
+        "source": state.source.as_ref().map(|s| s.to_string()),
+        "sync": sync,
     }))
 }
 
+/// `GET /dns/ready` - 200 when accepting traffic, 503 while draining.
+async fn readiness(State(state): State<Arc<DnsServerState>>) -> (StatusCode, Json<Value>) {
+    if state.is_draining() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "ready": false, "reason": "draining" })),
+        )
+    } else {
+        (StatusCode::OK, Json(json!({ "ready": true })))
+    }
+}
+
+/// `POST /dns/drain` - Enter drain mode (admin token required).
+async fn start_drain(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let principal = match authorize(&headers, &state.admin) {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection,
+    };
+    let before = state.is_draining();
+    state.start_drain();
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "drain.start",
+        json!({ "draining": before }),
+        json!({ "draining": true }),
+    ));
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": "draining",
+            "grace_seconds": state.drain_grace.as_secs(),
+        })),
+    )
+}
+
+/// `DELETE /dns/drain` - Leave drain mode (admin token required).
+async fn end_drain(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let principal = match authorize(&headers, &state.admin) {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection,
+    };
+    let before = state.is_draining();
+    state.end_drain();
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "drain.end",
+        json!({ "draining": before }),
+        json!({ "draining": false }),
+    ));
+    (StatusCode::OK, Json(json!({ "status": "ready" })))
+}
+
 /// `GET /dns/metrics` - Returns query count and performance metrics.
-async fn metrics(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
-    let query_count = state.query_count.load(std::sync::atomic::Ordering::Relaxed);
-    let uptime = state.start_time.elapsed().as_secs();
-    let qps = if uptime > 0 {
-        query_count as f64 / uptime as f64
+async fn metrics(State(state): State<Arc<DnsServerState>>) -> Json<MetricsSnapshot> {
+    Json(MetricsSnapshot::capture(&state))
+}
+
+/// `GET /dns/zone/checksum` - Content hash of the served zone, for drift detection.
+async fn zone_checksum(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
+    let zone = state.zone();
+    Json(json!({
+        "domain": zone.domain,
+        "checksum": zone.checksum(),
+        "zone_records": zone.record_count(),
+    }))
+}
+
+/// `GET /dns/version` - Build identity, the checksum of every served zone
+/// and, for a git config source, the commit served, for auditing what a
+/// fleet is running.
+async fn version(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
+    let zones: Vec<_> = state
+        .zones()
+        .iter()
+        .map(|zone| json!({ "domain": zone.domain, "checksum": zone.checksum() }))
+        .collect();
+    Json(json!({
+        "version": build_info::VERSION,
+        "git_commit": build_info::GIT_COMMIT,
+        "build_timestamp_unix": build_info::build_timestamp(),
+        "features": build_info::features(),
+        "config_commit": state.sync_status().commit,
+        "zone_serial": state.zone_serial(),
+        "zones": zones,
+    }))
+}
+
+/// `GET /dns/records?map=passwd` - Full record set, optionally one map only.
+/// Maps whose `server.map_acl` doesn't allow the client are left out.
+async fn records(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    Query(params): Query<RecordsParams>,
+) -> (StatusCode, Json<Value>) {
+    let map = match params.map.as_deref().map(str::parse::<MapType>).transpose() {
+        Ok(map) => map,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": e.to_string() })),
+            );
+        }
+    };
+    if let Some(map) = map
+        && let Err(rejection) = allow_map(&state, map, client)
+    {
+        return rejection;
+    }
+    let mut snapshot = state.zone().snapshot(map);
+    snapshot
+        .records
+        .retain(|entry| map_allowed(&state, entry.record.map_type(), client));
+    (StatusCode::OK, Json(json!(snapshot)))
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordsParams {
+    map: Option<String>,
+}
+
+/// Matches returned by `/dns/records/search` when no `limit` is given.
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+/// Most matches `/dns/records/search` returns, whatever `limit` says.
+const MAX_SEARCH_LIMIT: usize = 1000;
+
+/// `GET /dns/records/search?pattern=web*&map=service&zone=<domain>` - Records
+/// whose key or any field matches a glob, or an anchored regex with
+/// `regex=true`, in the maps the client may see. Searches the primary zone
+/// unless `zone` names another, which takes its zone token or the admin
+/// token.
+async fn search_records(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    headers: HeaderMap,
+    Query(params): Query<SearchParams>,
+) -> (StatusCode, Json<Value>) {
+    let bad_request = |e: HesiodError| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("{e:#}") })),
+        )
+    };
+    let map = match params.map.as_deref().map(str::parse::<MapType>).transpose() {
+        Ok(map) => map,
+        Err(e) => return bad_request(e),
+    };
+    if let Some(map) = map
+        && let Err(rejection) = allow_map(&state, map, client)
+    {
+        return rejection;
+    }
+    let maps: Vec<MapType> = MapType::ALL
+        .into_iter()
+        .filter(|m| map.is_none_or(|map| map == *m) && map_allowed(&state, *m, client))
+        .collect();
+    let pattern = if params.regex {
+        RecordPattern::regex(&params.pattern)
     } else {
-        0.0
+        RecordPattern::glob(&params.pattern)
+    };
+    let pattern = match pattern {
+        Ok(pattern) => pattern,
+        Err(e) => return bad_request(e),
+    };
+    let zone = match readable_zone(&headers, &state, params.zone.as_deref()) {
+        Ok(zone) => zone,
+        Err(rejection) => return rejection,
     };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .min(MAX_SEARCH_LIMIT);
+    let results = search::search(&zone, &pattern, &maps, limit);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "domain": zone.domain,
+            "total": results.total,
+            "truncated": results.total > results.matches.len(),
+            "matches": results.matches,
+        })),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    pattern: String,
+    #[serde(default)]
+    regex: bool,
+    map: Option<String>,
+    zone: Option<String>,
+    limit: Option<usize>,
+}
+
+/// `GET /dns/metrics/history` - Query rate samples, oldest first.
+async fn metrics_history(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
     Json(json!({
-        "query_count": query_count,
-        "uptime_seconds": uptime,
-        "queries_per_second": qps,
-        "zone_records": state.zone.record_count(),
+        "interval_seconds": crate::metrics::HISTORY_INTERVAL.as_secs(),
+        "samples": state.history.samples(),
     }))
 }
 
-/// `POST /dns/reload` - Placeholder for zone reload (returns acknowledgement).
-async fn reload(State(_state): State<Arc<DnsServerState>>) -> (StatusCode, Json<Value>) {
-    // In a full implementation this would re-read the config and rebuild the zone.
-    // For now it acknowledges the request.
-    info!("zone reload requested");
+/// `GET /dns/queries?limit=N` - Recently answered queries, newest last: in
+/// every zone with the admin token, in its own zone with a zone token, and
+/// in the primary zone otherwise. Maps whose `server.map_acl` doesn't allow
+/// the client are left out.
+async fn recent_queries(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    headers: HeaderMap,
+    Query(params): Query<LimitParams>,
+) -> Json<Value> {
+    let zone = authorize(&headers, &state.admin).is_err().then(|| {
+        let zones = state.zones();
+        token_zone(&headers, &zones).map_or_else(
+            || zones.primary().domain.clone(),
+            |zone| zone.domain.clone(),
+        )
+    });
+    let queries = state
+        .recent_queries
+        .recent_where(params.limit.unwrap_or(50), |query| {
+            zone.as_deref()
+                .is_none_or(|zone| query.zone.eq_ignore_ascii_case(zone))
+                && map_allowed(&state, query.map, client)
+        });
+    Json(json!({ "queries": queries }))
+}
+
+/// `GET /dns/lookup?map=passwd&name=alice&zone=<domain>` - One record as
+/// JSON, from the primary zone unless `zone` names another, which takes its
+/// zone token or the admin token. Forbidden for maps whose `server.map_acl`
+/// doesn't allow the client.
+async fn lookup(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    headers: HeaderMap,
+    Query(params): Query<LookupParams>,
+) -> (StatusCode, Json<Value>) {
+    let map = match params.map.parse::<MapType>() {
+        Ok(map) => map,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": e.to_string() })),
+            );
+        }
+    };
+    if let Err(rejection) = allow_map(&state, map, client) {
+        return rejection;
+    }
+    let zone = match readable_zone(&headers, &state, params.zone.as_deref()) {
+        Ok(zone) => zone,
+        Err(rejection) => return rejection,
+    };
+    match zone.lookup(&params.name, map) {
+        Some(record) => (
+            StatusCode::OK,
+            Json(json!({
+                "domain": zone.domain,
+                "name": params.name,
+                "map": map,
+                "txt": record.to_txt(),
+                "record": record,
+            })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": format!("no {map} record for {} in {}", params.name, zone.domain),
+            })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupParams {
+    map: String,
+    name: String,
+    zone: Option<String>,
+}
+
+/// `POST /dns/metrics/reset` - Zeroes the query counters (admin token required).
+async fn reset_metrics(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let principal = match authorize(&headers, &state.admin) {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection,
+    };
+    let before = MetricsSnapshot::capture(&state);
+    state.reset_counters();
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "metrics.reset",
+        json!({ "query_count": before.query_count, "map_queries": before.map_queries }),
+        json!({ "query_count": 0 }),
+    ));
+    info!("query counters reset via admin API");
+    (StatusCode::OK, Json(json!({ "status": "reset" })))
+}
+
+/// Record count and checksum, for audit before/after values.
+fn zone_summary(state: &DnsServerState) -> Value {
+    let zone = state.zone();
+    json!({ "zone_records": zone.record_count(), "checksum": zone.checksum() })
+}
+
+/// `GET /dns/audit?limit=N` - Recent admin actions, newest last (admin token required).
+async fn audit_log(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+    Query(params): Query<LimitParams>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(rejection) = authorize(&headers, &state.admin) {
+        return rejection;
+    }
+    let entries = state.audit.recent(params.limit.unwrap_or(100));
+    (StatusCode::OK, Json(json!({ "entries": entries })))
+}
+
+#[derive(Debug, Deserialize)]
+struct LimitParams {
+    limit: Option<usize>,
+}
+
+/// `GET /dns/zones/<domain>/records?map=passwd` - One zone's records (zone or
+/// admin token required).
+async fn zone_records(
+    State(state): State<Arc<DnsServerState>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<RecordsParams>,
+) -> (StatusCode, Json<Value>) {
+    let zone = match authorize_zone(&headers, &state, &domain) {
+        Ok((zone, _)) => zone,
+        Err(rejection) => return rejection,
+    };
+    match params.map.as_deref().map(str::parse::<MapType>).transpose() {
+        Ok(map) => (StatusCode::OK, Json(json!(zone.snapshot(map)))),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e.to_string() })),
+        ),
+    }
+}
+
+/// `GET /dns/zones/<domain>/metrics` - Query counts for one zone (zone or
+/// admin token required).
+async fn zone_metrics(
+    State(state): State<Arc<DnsServerState>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let zone = match authorize_zone(&headers, &state, &domain) {
+        Ok((zone, _)) => zone,
+        Err(rejection) => return rejection,
+    };
+    let counters = state.zone_counters(&zone.domain);
+    let map_queries: BTreeMap<_, _> = MapType::ALL
+        .iter()
+        .zip(&counters.map_queries)
+        .map(|(map_type, counter)| (map_type.label(), counter.get()))
+        .collect();
     (
         StatusCode::OK,
         Json(json!({
-            "status": "acknowledged",
-            "message": "zone reload is not yet implemented in this version",
+            "domain": zone.domain,
+            "query_count": counters.queries.get(),
+            "map_queries": map_queries,
+            "zone_records": zone.record_count(),
+            "zone_checksum": zone.checksum(),
         })),
     )
 }
 
-/// Start the HTTP health server on the given port.
-pub async fn run_health_server(state: Arc<DnsServerState>, port: u16) -> anyhow::Result<()> {
-    let app = health_router(state);
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    info!("Health/metrics HTTP server listening on port {}", port);
-    axum::serve(listener, app).await?;
+/// `POST /dns/zones/<domain>/reload` - Re-reads the config source and swaps
+/// in just this zone (zone or admin token required).
+async fn zone_reload(
+    State(state): State<Arc<DnsServerState>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let (zone, principal) = match authorize_zone(&headers, &state, &domain) {
+        Ok(authorized) => authorized,
+        Err(rejection) => return rejection,
+    };
+    if state.source.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({ "status": "error", "message": "server has no config source" })),
+        );
+    }
+    info!("reload of zone {} requested by {}", zone.domain, principal);
+    let result = reload_one_zone(&state, &zone.domain).await;
+    let after = match (&result, state.zones().get(&zone.domain)) {
+        (Ok(_), Some(new)) => {
+            json!({ "zone_records": new.record_count(), "checksum": new.checksum() })
+        }
+        (Ok(_), None) => json!({}),
+        (Err(e), _) => json!({ "error": format!("{e:#}") }),
+    };
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "zone.reload",
+        json!({
+            "domain": zone.domain,
+            "zone_records": zone.record_count(),
+            "checksum": zone.checksum(),
+        }),
+        after,
+    ));
+    match result {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(json!({ "status": "reloaded", "domain": zone.domain, "zone_records": count })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": format!("{e:#}") })),
+        ),
+    }
+}
+
+/// `GET /dns/register` - Live service registrations: every zone's with the
+/// admin token, only its own zone's with a zone token.
+async fn registrations(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let mut services = state.registry.services(Instant::now());
+    if authorize(&headers, &state.admin).is_err() {
+        let zones = state.zones();
+        let Some(zone) = token_zone(&headers, &zones) else {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "missing or invalid zone or admin token" })),
+            );
+        };
+        services.retain(|service| service.domain.eq_ignore_ascii_case(&zone.domain));
+    }
+    (StatusCode::OK, Json(json!({ "services": services })))
+}
+
+/// `POST /dns/register` - Serve a service record until `ttl` seconds pass
+/// without another registration; `ttl` 0 withdraws it (zone or admin token
+/// required). See [`crate::registry`].
+async fn register(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+    Json(request): Json<RegisterRequest>,
+) -> (StatusCode, Json<Value>) {
+    let domain = request
+        .zone
+        .clone()
+        .unwrap_or_else(|| state.zone().domain.clone());
+    let (zone, principal) = match authorize_zone(&headers, &state, &domain) {
+        Ok(authorized) => authorized,
+        Err(rejection) => return rejection,
+    };
+    let bad_request =
+        |message: String| (StatusCode::BAD_REQUEST, Json(json!({ "error": message })));
+    if let Err(e) =
+        crate::naming::to_bind_name(&request.name, MapType::Service, &zone.lhs, &zone.rhs)
+    {
+        return bad_request(format!("{e:#}"));
+    }
+    if request.host.is_empty() || request.host.contains(':') || request.protocol.contains(':') {
+        return bad_request("host must be non-empty and host and protocol free of ':'".into());
+    }
+    if zone.lookup(&request.name, MapType::Service).is_some() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("{} is a configured service in {}", request.name, zone.domain),
+            })),
+        );
+    }
+
+    let record = ServiceRecord {
+        host: request.host,
+        port: request.port,
+        protocol: request.protocol,
+    };
+    let ttl = Duration::from_secs(request.ttl).min(crate::registry::MAX_TTL);
+    let outcome = state.registry.register(
+        &zone.domain,
+        &request.name,
+        record.clone(),
+        ttl,
+        Instant::now(),
+    );
+    // Renewals are routine and would drown the audit log.
+    let (status, action) = match outcome {
+        Registered::Added => ("registered", Some("service.register")),
+        Registered::Renewed => ("renewed", None),
+        Registered::Withdrawn => ("withdrawn", Some("service.withdraw")),
+    };
+    if let Some(action) = action {
+        info!(
+            "service {} in {} {} by {}",
+            request.name, zone.domain, status, principal
+        );
+        state.audit.record(AuditEntry::new(
+            &principal,
+            action,
+            json!({ "domain": zone.domain, "name": request.name }),
+            json!({ "record": record, "ttl": ttl.as_secs() }),
+        ));
+    }
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": status,
+            "domain": zone.domain,
+            "name": request.name,
+            "ttl": ttl.as_secs(),
+        })),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterRequest {
+    name: String,
+    host: String,
+    port: u16,
+    #[serde(default = "default_protocol")]
+    protocol: String,
+    /// Seconds until the registration lapses.
+    ttl: u64,
+    /// Zone to register in; the primary one if unset.
+    zone: Option<String>,
+}
+
+fn default_protocol() -> String {
+    "tcp".to_string()
+}
+
+/// Principal established by the shared admin token.
+const ADMIN_PRINCIPAL: &str = "admin";
+
+/// Check the `Authorization: Bearer <token>` header against the admin token,
+/// returning the authenticated principal.
+fn authorize(
+    headers: &HeaderMap,
+    admin: &AdminConfig,
+) -> Result<String, (StatusCode, Json<Value>)> {
+    let Some(expected) = admin.token.as_ref().and_then(Secret::expose) else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "admin API disabled: no admin token configured" })),
+        ));
+    };
+    match bearer_token(headers) {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+            Ok(ADMIN_PRINCIPAL.to_string())
+        }
+        _ => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "missing or invalid admin token" })),
+        )),
+    }
+}
+
+/// The zone for `domain` if the request carries its zone token or the admin
+/// token, with the principal: `zone:<domain>` or the admin principal. Zone
+/// tokens only open their own zone.
+fn authorize_zone(
+    headers: &HeaderMap,
+    state: &DnsServerState,
+    domain: &str,
+) -> Result<(Arc<HesiodZone>, String), (StatusCode, Json<Value>)> {
+    let zones = state.zones();
+    let Some(zone) = zones.get(domain).cloned() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("no zone {domain}") })),
+        ));
+    };
+    let admin = match authorize(headers, &state.admin) {
+        Ok(principal) => return Ok((zone, principal)),
+        Err(rejection) => rejection,
+    };
+    let Some(expected) = zones.admin_token(&zone.domain) else {
+        return Err(admin);
+    };
+    match bearer_token(headers) {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+            let principal = format!("zone:{}", zone.domain);
+            Ok((zone, principal))
+        }
+        _ => Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "missing or invalid zone token" })),
+        )),
+    }
+}
+
+/// The zone whose zone token the request carries, if any.
+fn token_zone<'a>(headers: &HeaderMap, zones: &'a ZoneSet) -> Option<&'a HesiodZone> {
+    let token = bearer_token(headers)?;
+    zones.iter().find(|zone| {
+        zones
+            .admin_token(&zone.domain)
+            .is_some_and(|expected| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+    })
+}
+
+/// The zone a read endpoint's `zone` parameter names. The primary zone is
+/// open to anyone; any other takes its zone token or the admin token.
+fn readable_zone(
+    headers: &HeaderMap,
+    state: &DnsServerState,
+    domain: Option<&str>,
+) -> Result<Arc<HesiodZone>, (StatusCode, Json<Value>)> {
+    let zones = state.zones();
+    let primary = zones.primary();
+    let Some(domain) = domain else {
+        return Ok(Arc::clone(primary));
+    };
+    match zones.get(domain) {
+        Some(zone) if Arc::ptr_eq(zone, primary) => Ok(Arc::clone(zone)),
+        _ => authorize_zone(headers, state, domain).map(|(zone, _)| zone),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Byte comparison whose timing does not depend on where the inputs differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `POST /dns/reload` - Re-reads the config source and swaps in the new zone
+/// (admin token required). On failure the previous zone keeps being served.
+async fn reload(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let principal = match authorize(&headers, &state.admin) {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection,
+    };
+    info!("zone reload requested by {}", principal);
+    if state.source.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({ "status": "error", "message": "server has no config source" })),
+        );
+    }
+    let before = zone_summary(&state);
+    let result = reload_zone(&state).await;
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "reload",
+        before,
+        match &result {
+            Ok(_) => zone_summary(&state),
+            Err(e) => json!({ "error": format!("{e:#}") }),
+        },
+    ));
+    match result {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(json!({ "status": "reloaded", "zone_records": count })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": format!("{e:#}") })),
+        ),
+    }
+}
+
+/// `POST /dns/sync` - Resyncs with the config source now, as the periodic
+/// resync does, and returns the sync status with its success and failure
+/// counts (admin token required).
+async fn sync(
+    State(state): State<Arc<DnsServerState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let principal = match authorize(&headers, &state.admin) {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection,
+    };
+    info!("resync requested by {}", principal);
+    if state.source.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({ "status": "error", "message": "server has no config source" })),
+        );
+    }
+    let before = zone_summary(&state);
+    let result = reload_zone(&state).await;
+    state.audit.record(AuditEntry::new(
+        &principal,
+        "sync",
+        before,
+        match &result {
+            Ok(_) => zone_summary(&state),
+            Err(e) => json!({ "error": format!("{e:#}") }),
+        },
+    ));
+    let sync = state.sync_status();
+    match result {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(json!({ "status": "synced", "zone_records": count, "sync": sync })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": format!("{e:#}"), "sync": sync })),
+        ),
+    }
+}
+
+/// Address of the client on the other end of an HTTP connection, as the
+/// listener reports it: after the PROXY protocol header where there is one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub SocketAddr);
+
+// One impl per listener: a blanket one over `Listener` would overlap
+// axum's own for `TapIo`.
+macro_rules! client_addr_from {
+    ($($listener:ty),*) => {$(
+        impl Connected<IncomingStream<'_, $listener>> for ClientAddr {
+            fn connect_info(stream: IncomingStream<'_, $listener>) -> Self {
+                Self(*stream.remote_addr())
+            }
+        }
+    )*};
+}
+
+client_addr_from!(tokio::net::TcpListener, ProxyListener, TlsListener);
+
+/// Unix socket connections have no address, so `Option<ClientAddr>` is
+/// `None` there.
+impl<S: Send + Sync> OptionalFromRequestParts<S> for ClientAddr {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> std::result::Result<Option<Self>, Self::Rejection> {
+        let connect_info = ConnectInfo::<ClientAddr>::from_request_parts(parts, state).await;
+        Ok(connect_info.ok().map(|ConnectInfo(client)| client))
+    }
+}
+
+/// Whether `server.map_acl` lets `client` see `map`'s records; an unknown
+/// client only sees maps without an ACL.
+pub(crate) fn map_allowed(
+    state: &DnsServerState,
+    map: MapType,
+    client: Option<ClientAddr>,
+) -> bool {
+    let ip: Option<IpAddr> = client.map(|ClientAddr(addr)| addr.ip());
+    state.server.map_allows(map, ip)
+}
+
+/// [`map_allowed`], counting and rejecting a refused request.
+fn allow_map(
+    state: &DnsServerState,
+    map: MapType,
+    client: Option<ClientAddr>,
+) -> std::result::Result<(), (StatusCode, Json<Value>)> {
+    if map_allowed(state, map, client) {
+        return Ok(());
+    }
+    state.map_refusals(map).inc();
+    Err((
+        StatusCode::FORBIDDEN,
+        Json(json!({ "error": format!("the {map} map is not served to this client") })),
+    ))
+}
+
+/// Start the HTTP health server on `http.bind` and the given port, over
+/// HTTPS when `http.tls` is set and rate limited when `http.rate_limit` is,
+/// and on `http.unix_socket` if set. Once `shutdown` completes no new
+/// connections are accepted and the server returns when the open ones are
+/// done.
+pub async fn run_health_server(
+    state: Arc<DnsServerState>,
+    port: u16,
+    http: &HttpConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let router = health_router(state);
+    let (stop, stopped) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown.await;
+        stop.send_replace(true);
+    });
+    let shutdown = move || {
+        let mut stopped = stopped.clone();
+        async move {
+            stopped.wait_for(|stopped| *stopped).await.ok();
+        }
+    };
+    match (http.tcp, &http.unix_socket) {
+        (true, None) => serve_tcp(router, port, http, shutdown()).await,
+        (false, Some(socket)) => serve_unix(router, socket, shutdown()).await,
+        (true, Some(socket)) => {
+            tokio::try_join!(
+                serve_tcp(router.clone(), port, http, shutdown()),
+                serve_unix(router, socket, shutdown()),
+            )?;
+            Ok(())
+        }
+        (false, None) => Err(HesiodError::config(
+            "http.tcp is off but no http.unix_socket is set",
+        )),
+    }
+}
+
+async fn serve_tcp(
+    mut app: Router,
+    port: u16,
+    http: &HttpConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    if let Some(limits) = &http.rate_limit {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::new(RateLimiter::new(limits)),
+            rate_limit::limit,
+        ));
+    }
+    let app = app.into_make_service_with_connect_info::<ClientAddr>();
+    let listener = tokio::net::TcpListener::bind((http.bind, port))
+        .await
+        .io_err(|| format!("binding HTTP port {port}"))?;
+    let addr = listener
+        .local_addr()
+        .io_err(|| "reading the HTTP listener address")?;
+    let proxied = if http.proxy_protocol {
+        " behind PROXY protocol"
+    } else {
+        ""
+    };
+    match (&http.tls, http.proxy_protocol) {
+        (Some(tls), proxy) => {
+            let config = http_tls::server_config(tls)?;
+            let listener = if proxy {
+                ProxyListener::new(listener, http.proxy_trusted.clone())
+                    .and_then(|listener| TlsListener::new(listener, config))
+            } else {
+                TlsListener::new(listener, config)
+            }
+            .io_err(|| "setting up the HTTPS listener")?;
+            info!("Health/metrics HTTPS server listening on {addr}{proxied}");
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .io_err(|| "serving HTTPS")?;
+        }
+        (None, true) => {
+            info!("Health/metrics HTTP server listening on {addr}{proxied}");
+            let listener = ProxyListener::new(listener, http.proxy_trusted.clone())
+                .io_err(|| "setting up the PROXY protocol listener")?;
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .io_err(|| "serving HTTP")?;
+        }
+        (None, false) => {
+            info!("Health/metrics HTTP server listening on {addr}");
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .io_err(|| "serving HTTP")?;
+        }
+    }
     Ok(())
 }
+
+/// Serve `router` on the unix socket, replacing a stale socket file and
+/// removing it again on shutdown.
+#[cfg(unix)]
+async fn serve_unix(
+    router: Router,
+    socket: &UnixSocketConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let path = &socket.path;
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .io_err(|| format!("removing stale socket {}", path.display()))?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .io_err(|| format!("binding unix socket {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(socket.mode.0))
+        .io_err(|| format!("setting permissions of {}", path.display()))?;
+    info!(
+        "Health/metrics HTTP server listening on unix socket {} (mode {})",
+        path.display(),
+        socket.mode
+    );
+    let served = axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await;
+    std::fs::remove_file(path).ok();
+    served.io_err(|| format!("serving on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(
+    _router: Router,
+    _socket: &UnixSocketConfig,
+    _shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    Err(HesiodError::config(
+        "http.unix_socket is only supported on unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::zone::HesiodZone;
+
+    fn state(token: Option<&str>) -> Arc<DnsServerState> {
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        Arc::new(DnsServerState::new(zone).with_admin(AdminConfig {
+            token: token.map(Secret::from),
+            ..Default::default()
+        }))
+    }
+
+    async fn post(state: Arc<DnsServerState>, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut req = Request::post(uri);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        health_router(state)
+            .oneshot(req.body(Body::empty()).expect("TODO: handle error"))
+            .await
+            .expect("TODO: handle error")
+            .status()
+    }
+
+    #[tokio::test]
+    async fn reset_requires_token() {
+        let state = state(Some("s3cret"));
+        state.query_count.add(5);
+
+        let status = post(Arc::clone(&state), "/dns/metrics/reset", Some("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(state.query_count.get(), 5);
+
+        let status = post(Arc::clone(&state), "/dns/metrics/reset", Some("s3cret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state.query_count.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_flips_readiness() {
+        let state = state(Some("s3cret"));
+        let ready = |state: Arc<DnsServerState>| async move {
+            health_router(state)
+                .oneshot(
+                    Request::get("/dns/ready")
+                        .body(Body::empty())
+                        .expect("TODO: handle error"),
+                )
+                .await
+                .expect("TODO: handle error")
+                .status()
+        };
+        assert_eq!(ready(Arc::clone(&state)).await, StatusCode::OK);
+
+        let status = post(Arc::clone(&state), "/dns/drain", Some("s3cret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            ready(Arc::clone(&state)).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let audit = state.audit.recent(10);
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].principal, "admin");
+        assert_eq!(audit[0].action, "drain.start");
+    }
+
+    #[tokio::test]
+    async fn records_filters_by_map() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "web",
+            crate::records::HesiodRecord::Service(crate::records::ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        let router = health_router(Arc::new(DnsServerState::new(zone)));
+        let get = |uri: &str| {
+            router.clone().oneshot(
+                Request::get(uri)
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+        };
+
+        let response = get("/dns/records?map=service")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let snapshot: crate::zone::ZoneSnapshot =
+            serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(snapshot.records.len(), 1);
+
+        let response = get("/dns/records?map=bogus")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn map_acls_hide_records_from_other_networks() {
+        use axum::extract::connect_info::MockConnectInfo;
+
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "alice",
+            crate::records::HesiodRecord::Passwd(crate::records::PasswdRecord {
+                username: "alice".into(),
+                uid: 1000,
+                gid: 1000,
+                gecos: "Alice".into(),
+                home: "/home/alice".into(),
+                shell: "/bin/bash".into(),
+            }),
+        );
+        let trusted = "10.0.0.0/8".parse().expect("TODO: handle error");
+        let state = Arc::new(
+            DnsServerState::new(zone).with_server(crate::config::ServerConfig {
+                map_acl: [(MapType::Passwd, vec![trusted])].into(),
+                ..Default::default()
+            }),
+        );
+        let get = |uri: &str, client: &str| {
+            let client = ClientAddr(client.parse().expect("TODO: handle error"));
+            health_router(Arc::clone(&state))
+                .layer(MockConnectInfo(client))
+                .oneshot(
+                    Request::get(uri)
+                        .body(Body::empty())
+                        .expect("TODO: handle error"),
+                )
+        };
+        let records = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("TODO: handle error");
+            let snapshot: crate::zone::ZoneSnapshot =
+                serde_json::from_slice(&body).expect("TODO: handle error");
+            snapshot.records.len()
+        };
+
+        let outside = "192.0.2.1:5000";
+        let response = get("/dns/records", outside)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(records(response).await, 0);
+        for uri in [
+            "/dns/records?map=passwd",
+            "/dns/lookup?map=passwd&name=alice",
+            "/dns/records/search?pattern=*&map=passwd",
+        ] {
+            let response = get(uri, outside).await.expect("TODO: handle error");
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+        }
+        let response = get("/dns/pdns/list/1/test.internal", outside)
+            .await
+            .expect("TODO: handle error");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        assert!(!String::from_utf8_lossy(&body).contains("alice"));
+        state.recent_queries.record(crate::metrics::RecentQuery {
+            timestamp_unix: 0,
+            name: "alice".into(),
+            map: MapType::Passwd,
+            zone: "test.internal".into(),
+            found: true,
+        });
+        let queried = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("TODO: handle error");
+            let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+            value["queries"].as_array().map_or(0, Vec::len)
+        };
+        let response = get("/dns/queries", outside)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(queried(response).await, 0);
+
+        let inside = "10.1.2.3:5000";
+        let response = get("/dns/queries", inside)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(queried(response).await, 1);
+        let response = get("/dns/records", inside)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(records(response).await, 1);
+        let response = get("/dns/lookup?map=passwd&name=alice", inside)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn version_reports_build_and_zone_checksums() {
+        let state = state(None);
+        let checksum = state.zone().checksum().to_string();
+        let response = health_router(state)
+            .oneshot(
+                Request::get("/dns/version")
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(value["version"], env!("CARGO_PKG_VERSION"));
+        assert!(
+            !value["git_commit"]
+                .as_str()
+                .expect("TODO: handle error")
+                .is_empty()
+        );
+        assert!(value["build_timestamp_unix"].as_u64().is_some());
+        assert!(
+            value["features"]
+                .as_array()
+                .expect("TODO: handle error")
+                .contains(&json!("net"))
+        );
+        assert_eq!(value["zones"][0]["domain"], "test.internal");
+        assert_eq!(value["zones"][0]["checksum"], checksum);
+    }
+
+    #[tokio::test]
+    async fn lookup_returns_one_record() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "web",
+            crate::records::HesiodRecord::Service(crate::records::ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        let router = health_router(Arc::new(DnsServerState::new(zone)));
+        let get = |uri: &str| {
+            router.clone().oneshot(
+                Request::get(uri)
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+        };
+
+        let response = get("/dns/lookup?map=service&name=web")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(value["txt"], "web.svc:443:tcp");
+        assert_eq!(value["record"]["port"], 443);
+
+        let missing = get("/dns/lookup?map=passwd&name=web")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        let bad_map = get("/dns/lookup?map=bogus&name=web")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(bad_map.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn search_finds_records_by_pattern() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        for name in ["web-1", "web-2", "db"] {
+            zone.add_record(
+                name,
+                crate::records::HesiodRecord::Service(crate::records::ServiceRecord {
+                    host: format!("{name}.svc"),
+                    port: 443,
+                    protocol: "tcp".into(),
+                }),
+            );
+        }
+        let router = health_router(Arc::new(DnsServerState::new(zone)));
+        let get = |uri: &str| {
+            router.clone().oneshot(
+                Request::get(uri)
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+        };
+
+        let response = get("/dns/records/search?pattern=web*&map=service&limit=1")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(value["total"], 2);
+        assert_eq!(value["truncated"], true);
+        assert_eq!(value["matches"][0]["name"], "web-1");
+        assert_eq!(value["matches"][0]["txt"], "web-1.svc:443:tcp");
+
+        let regex = get("/dns/records/search?pattern=db%5C.svc&regex=true")
+            .await
+            .expect("TODO: handle error");
+        let body = axum::body::to_bytes(regex.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(value["matches"][0]["name"], "db");
+
+        let bad = get("/dns/records/search?pattern=(&regex=true")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(bad.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn register_serves_until_withdrawn() {
+        let state = state(Some("s3cret"));
+        let call = |body: Value, token: Option<&str>| {
+            let mut req =
+                Request::post("/dns/register").header(header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            health_router(Arc::clone(&state)).oneshot(
+                req.body(Body::from(body.to_string()))
+                    .expect("TODO: handle error"),
+            )
+        };
+        let web = json!({ "name": "web", "host": "10.0.0.5", "port": 8443, "ttl": 60 });
+
+        let response = call(web.clone(), None).await.expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = call(web.clone(), Some("s3cret"))
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let (record, _) = state
+            .registry
+            .lookup("test.internal", "web", Instant::now())
+            .expect("registered");
+        assert_eq!(record.to_txt(), "10.0.0.5:8443:tcp");
+
+        let renewal = call(web, Some("s3cret")).await.expect("TODO: handle error");
+        assert_eq!(renewal.status(), StatusCode::OK);
+        let bad = json!({ "name": "bad..name", "host": "h", "port": 1, "ttl": 60 });
+        let response = call(bad, Some("s3cret")).await.expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let withdraw = json!({ "name": "web", "host": "10.0.0.5", "port": 8443, "ttl": 0 });
+        let response = call(withdraw, Some("s3cret"))
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.registry.services(Instant::now()).is_empty());
+
+        let actions: Vec<_> = state
+            .audit
+            .recent(10)
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(actions, ["service.register", "service.withdraw"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_on_unix_socket_only() {
+        use std::os::unix::fs::PermissionsExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("hesiod-http-{}.sock", std::process::id()));
+        let http = HttpConfig {
+            tcp: false,
+            unix_socket: Some(UnixSocketConfig {
+                path: path.clone(),
+                mode: crate::config::FileMode(0o600),
+            }),
+            ..Default::default()
+        };
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            run_health_server(state(None), 0, &http, async {
+                stopped.await.ok();
+            })
+            .await
+        });
+        while !path.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mode = std::fs::metadata(&path)
+            .expect("TODO: handle error")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut stream = tokio::net::UnixStream::connect(&path)
+            .await
+            .expect("TODO: handle error");
+        stream
+            .write_all(b"GET /dns/ready HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("TODO: handle error");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("TODO: handle error");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+        stop.send(()).ok();
+        server
+            .await
+            .expect("TODO: handle error")
+            .expect("TODO: handle error");
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn reset_disabled_without_token() {
+        let status = post(state(None), "/dns/metrics/reset", Some("anything")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn reload_requires_token() {
+        let status = post(state(None), "/dns/reload", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let state = state(Some("s3cret"));
+        let status = post(Arc::clone(&state), "/dns/reload", Some("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(state.audit.recent(10).is_empty());
+
+        // Authenticated, but this state has no config source to reload.
+        let status = post(Arc::clone(&state), "/dns/reload", Some("s3cret")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn sync_reports_its_outcome() {
+        let path = std::env::temp_dir().join(format!("hesiod-sync-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"domain": "test.internal", "lhs": ".ns", "rhs": ".test.internal",
+                "services": [{"name": "web", "host": "web.svc", "port": 443}]}"#,
+        )
+        .expect("TODO: handle error");
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        let admin = AdminConfig {
+            token: Some("s3cret".into()),
+            ..Default::default()
+        };
+        let state = Arc::new(
+            DnsServerState::new(zone)
+                .with_admin(admin)
+                .with_source(crate::source::ConfigSource::File(path.clone())),
+        );
+
+        let status = post(Arc::clone(&state), "/dns/sync", Some("s3cret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state.zone().record_count(), 1);
+
+        std::fs::write(&path, "not json").expect("TODO: handle error");
+        let status = post(Arc::clone(&state), "/dns/sync", Some("s3cret")).await;
+        std::fs::remove_file(&path).ok();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let sync = state.sync_status();
+        assert_eq!((sync.successes, sync.failures), (2, 1));
+        assert_eq!(state.audit.recent(10).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn zone_tokens_open_only_their_zone() {
+        let config = |lab_groups: &str| {
+            format!(
+                r#"{{"domain": "corp.internal", "lhs": ".ns", "rhs": ".corp.internal",
+                    "zones": [{{"domain": "lab.internal", "lhs": ".ns", "rhs": ".lab.internal",
+                                "admin_token": "lab-token", "groups": [{lab_groups}]}}]}}"#
+            )
+        };
+        let path = std::env::temp_dir().join(format!("hesiod-zones-{}.json", std::process::id()));
+        std::fs::write(&path, config(r#"{"name": "lab", "gid": 50}"#)).expect("TODO: handle error");
+        let zones = crate::zone::ZoneSet::from_config(
+            &crate::config::HesiodConfig::from_file(&path).expect("TODO: handle error"),
+        )
+        .expect("TODO: handle error");
+        let state = Arc::new(
+            DnsServerState::with_zones(zones)
+                .with_admin(AdminConfig {
+                    token: Some("s3cret".into()),
+                    ..Default::default()
+                })
+                .with_source(crate::source::ConfigSource::File(path.clone())),
+        );
+        state.zone_counters("lab.internal").queries.add(3);
+
+        let call = |method: &str, uri: &str, token: &str| {
+            health_router(Arc::clone(&state)).oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+        };
+        let response = call("GET", "/dns/zones/lab.internal/metrics", "lab-token")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        let metrics: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+        assert_eq!(metrics["query_count"], 3);
+
+        let status = |response: Result<axum::response::Response, _>| {
+            response.expect("TODO: handle error").status()
+        };
+        let corp = call("GET", "/dns/zones/corp.internal/records", "lab-token").await;
+        assert_eq!(status(corp), StatusCode::UNAUTHORIZED);
+        let corp = call("GET", "/dns/zones/corp.internal/records", "s3cret").await;
+        assert_eq!(status(corp), StatusCode::OK);
+        let missing = call("GET", "/dns/zones/nope.internal/metrics", "s3cret").await;
+        assert_eq!(status(missing), StatusCode::NOT_FOUND);
+
+        let search = "/dns/records/search?pattern=*&zone=lab.internal";
+        let guessed = call("GET", search, "guess").await;
+        assert_eq!(status(guessed), StatusCode::UNAUTHORIZED);
+        let lab = call("GET", search, "lab-token").await;
+        assert_eq!(status(lab), StatusCode::OK);
+        let primary = "/dns/records/search?pattern=*&zone=corp.internal";
+        assert_eq!(status(call("GET", primary, "guess").await), StatusCode::OK);
+        let lookup = "/dns/lookup?map=group&name=lab&zone=lab.internal";
+        let guessed = call("GET", lookup, "guess").await;
+        assert_eq!(status(guessed), StatusCode::UNAUTHORIZED);
+        let lab = call("GET", lookup, "lab-token").await;
+        assert_eq!(status(lab), StatusCode::OK);
+
+        for domain in ["corp.internal", "lab.internal"] {
+            let record = crate::records::ServiceRecord {
+                host: "10.0.0.5".into(),
+                port: 80,
+                protocol: "tcp".into(),
+            };
+            let ttl = Duration::from_secs(60);
+            state
+                .registry
+                .register(domain, "web", record, ttl, Instant::now());
+        }
+        let registered = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("TODO: handle error");
+            let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+            value["services"]
+                .as_array()
+                .expect("TODO: handle error")
+                .iter()
+                .map(|service| service["domain"].to_string())
+                .collect::<Vec<_>>()
+        };
+        let lab = call("GET", "/dns/register", "lab-token")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(registered(lab).await, [r#""lab.internal""#]);
+        let admin = call("GET", "/dns/register", "s3cret")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(registered(admin).await.len(), 2);
+        let guessed = call("GET", "/dns/register", "guess").await;
+        assert_eq!(status(guessed), StatusCode::UNAUTHORIZED);
+
+        for zone in ["corp.internal", "lab.internal"] {
+            state.recent_queries.record(crate::metrics::RecentQuery {
+                timestamp_unix: 0,
+                name: "alice".into(),
+                map: MapType::Passwd,
+                zone: zone.into(),
+                found: true,
+            });
+        }
+        let queried = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("TODO: handle error");
+            let value: Value = serde_json::from_slice(&body).expect("TODO: handle error");
+            value["queries"]
+                .as_array()
+                .expect("TODO: handle error")
+                .iter()
+                .map(|query| query["zone"].to_string())
+                .collect::<Vec<_>>()
+        };
+        let lab = call("GET", "/dns/queries", "lab-token")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(queried(lab).await, [r#""lab.internal""#]);
+        let anyone = call("GET", "/dns/queries", "guess")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(queried(anyone).await, [r#""corp.internal""#]);
+        let admin = call("GET", "/dns/queries", "s3cret")
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(queried(admin).await.len(), 2);
+
+        std::fs::write(
+            &path,
+            config(r#"{"name": "lab", "gid": 50}, {"name": "qa", "gid": 51}"#),
+        )
+        .expect("TODO: handle error");
+        let reload = call("POST", "/dns/zones/lab.internal/reload", "lab-token").await;
+        std::fs::remove_file(&path).ok();
+        assert_eq!(status(reload), StatusCode::OK);
+        let zones = state.zones();
+        assert_eq!(
+            zones.get("lab.internal").map(|zone| zone.record_count()),
+            Some(2)
+        );
+        assert_eq!(state.audit.recent(1)[0].principal, "zone:lab.internal");
+    }
+}
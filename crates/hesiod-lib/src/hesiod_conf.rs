@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Client-side name construction settings, read the way libhesiod does:
+//! `/etc/hesiod.conf` first, then the `HES_DOMAIN` environment variable.
+
+use std::path::Path;
+
+use crate::error::{Context, IoContext, Result};
+use crate::idn::key_to_ascii;
+use crate::records::MapType;
+
+/// Default location of the system Hesiod configuration.
+pub const DEFAULT_PATH: &str = "/etc/hesiod.conf";
+
+/// Where the host's DNS resolvers are configured.
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Left- and right-hand sides used to build `<key>.<map><lhs><rhs>` names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HesiodConf {
+    pub lhs: String,
+    pub rhs: String,
+    /// Query classes in preference order, e.g. `["IN", "HS"]`.
+    pub classes: Vec<String>,
+}
+
+impl Default for HesiodConf {
+    fn default() -> Self {
+        Self {
+            lhs: ".ns".into(),
+            rhs: String::new(),
+            classes: vec!["IN".into(), "HS".into()],
+        }
+    }
+}
+
+impl HesiodConf {
+    /// Parse `key=value` lines (`lhs`, `rhs`, `classes`); `#` starts a comment.
+    /// Unknown keys are ignored, as libhesiod does.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut conf = Self::default();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .config_err(|| format!("line {}: expected key=value", line_no + 1))?;
+            let value = value.trim();
+            match key.trim() {
+                "lhs" => conf.set_lhs(value),
+                "rhs" => conf.set_rhs(value),
+                "classes" => {
+                    conf.classes = value
+                        .split(',')
+                        .map(|c| c.trim().to_ascii_uppercase())
+                        .filter(|c| !c.is_empty())
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        Ok(conf)
+    }
+
+    /// Read `path`; a missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                Self::parse(&content).config_err(|| format!("parsing {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).io_err(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Load the system configuration and apply `HES_DOMAIN`, which overrides `rhs`.
+    pub fn from_system() -> Result<Self> {
+        let mut conf = Self::load(Path::new(DEFAULT_PATH))?;
+        if let Ok(domain) = std::env::var("HES_DOMAIN")
+            && !domain.is_empty()
+        {
+            conf.set_rhs(&domain);
+        }
+        Ok(conf)
+    }
+
+    /// Set the left-hand side; a leading dot is added if missing.
+    pub fn set_lhs(&mut self, lhs: &str) {
+        self.lhs = dotted(lhs);
+    }
+
+    /// Set the right-hand side; a leading dot is added if missing.
+    pub fn set_rhs(&mut self, rhs: &str) {
+        self.rhs = dotted(rhs);
+    }
+
+    /// `key=value` text that [`HesiodConf::parse`] and libhesiod read back.
+    pub fn to_conf(&self) -> String {
+        format!(
+            "lhs={}\nrhs={}\nclasses={}\n",
+            self.lhs,
+            self.rhs,
+            self.classes.join(",")
+        )
+    }
+
+    /// Fully qualified query name, e.g. `alice.passwd.ns.example.com`.
+    pub fn query_name(&self, key: &str, map_type: MapType) -> String {
+        format!(
+            "{}.{}{}{}",
+            key_to_ascii(key),
+            map_type.label(),
+            self.lhs,
+            self.rhs
+        )
+    }
+}
+
+/// `nameserver` addresses in resolv.conf-format `content`, in order.
+pub fn parse_nameservers(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(address)) => Some(address.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The host's resolvers from [`RESOLV_CONF`]; empty if it can't be read.
+pub fn system_nameservers() -> Vec<String> {
+    parse_nameservers(&std::fs::read_to_string(RESOLV_CONF).unwrap_or_default())
+}
+
+/// Normalise a name component to carry a leading dot.
+fn dotted(value: &str) -> String {
+    if value.is_empty() || value.starts_with('.') {
+        value.to_string()
+    } else {
+        format!(".{value}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nameservers_from_resolv_conf() {
+        let content = "# generated\nsearch example.com\nnameserver 10.0.0.53\n\
+                       ;nameserver 10.0.0.1\nnameserver   ::1  \noptions rotate\n";
+        assert_eq!(parse_nameservers(content), ["10.0.0.53", "::1"]);
+    }
+
+    #[test]
+    fn parse_classic_config() {
+        let conf = HesiodConf::parse(
+            "# site config\nlhs=.ns\nrhs=example.com\nclasses=in, hs\nunknown=1\n",
+        )
+        .expect("TODO: handle error");
+        assert_eq!(conf.lhs, ".ns");
+        assert_eq!(conf.rhs, ".example.com");
+        assert_eq!(conf.classes, ["IN", "HS"]);
+        assert_eq!(
+            conf.query_name("alice", MapType::Passwd),
+            "alice.passwd.ns.example.com"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_line() {
+        assert!(HesiodConf::parse("rhs .example.com").is_err());
+    }
+}
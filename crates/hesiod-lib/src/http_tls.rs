@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: MPL-2.0
+//! HTTPS for the health, metrics and admin server, so bearer tokens and
+//! admin requests aren't sent in cleartext. Enabled by `http.tls` in the
+//! config:
+//!
+//! ```json
+//! "http": { "tls": { "cert": "/etc/hesiod/tls.pem", "key": "/etc/hesiod/tls.key" } }
+//! ```
+//!
+//! Both HTTP/2 and HTTP/1.1 are offered over ALPN, so the CoreDNS gRPC
+//! service keeps working. The files are read once at startup.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::serve::Listener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+use tokio_rustls::server::TlsStream;
+use tracing::debug;
+
+use crate::config::TlsConfig;
+use crate::error::{Context, HesiodError, Result};
+
+/// Time a client gets to finish the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaken connections waiting for the HTTP server to pick them up.
+const PENDING_CONNECTIONS: usize = 64;
+
+/// rustls server config for the certificate and key in `tls`.
+pub fn server_config(tls: &TlsConfig) -> Result<Arc<rustls::ServerConfig>> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_file_iter(&tls.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .config_err(|| format!("reading {}", tls.cert.display()))?;
+    if certs.is_empty() {
+        return Err(HesiodError::config(format!(
+            "no certificates in {}",
+            tls.cert.display()
+        )));
+    }
+    let key = PrivateKeyDer::from_pem_file(&tls.key)
+        .config_err(|| format!("reading {}", tls.key.display()))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .config_err(|| "setting up TLS")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .config_err(|| format!("loading the key in {}", tls.key.display()))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// A listener for `axum::serve` yielding TLS streams. Handshakes run in
+/// their own tasks, so a slow client doesn't hold up the others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    /// TLS over the connections `listener` accepts, from a
+    /// [`tokio::net::TcpListener`] or a
+    /// [`ProxyListener`](crate::proxy_protocol::ProxyListener).
+    pub fn new<L>(listener: L, config: Arc<rustls::ServerConfig>) -> io::Result<Self>
+    where
+        L: Listener<Io = TcpStream, Addr = SocketAddr>,
+    {
+        let local_addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel(PENDING_CONNECTIONS);
+        tokio::spawn(accept_loop(listener, TlsAcceptor::from(config), tx));
+        Ok(Self {
+            local_addr,
+            incoming,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(connection) => connection,
+            // The accept loop only stops once this receiver is gone.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Accept TCP connections until the [`TlsListener`] is dropped.
+async fn accept_loop<L: Listener<Io = TcpStream, Addr = SocketAddr>>(
+    mut listener: L,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = Listener::accept(&mut listener) => accepted,
+            () = tx.closed() => return,
+        };
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = tx.send((stream, peer)).await;
+                }
+                Ok(Err(e)) => debug!("TLS handshake with {peer} failed: {e}"),
+                Err(_) => debug!("TLS handshake with {peer} timed out"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    use crate::client;
+    use crate::health::health_router;
+    use crate::server::DnsServerState;
+    use crate::zone::HesiodZone;
+
+    fn test_tls() -> TlsConfig {
+        let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        TlsConfig {
+            cert: data.join("localhost.pem"),
+            key: data.join("localhost.key"),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_health_over_https() {
+        let tls = test_tls();
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        let app = health_router(Arc::new(DnsServerState::new(zone)));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let listener = TlsListener::new(listener, server_config(&tls).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        let addr = listener.local_addr;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client_tls =
+            (*client::tls_config(Some(&tls.cert), false).expect("TODO: handle error")).clone();
+        client_tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let stream = TcpStream::connect(addr).await.expect("TODO: handle error");
+        let mut stream = TlsConnector::from(Arc::new(client_tls))
+            .connect(
+                ServerName::try_from("localhost").expect("TODO: handle error"),
+                stream,
+            )
+            .await
+            .expect("TODO: handle error");
+        stream
+            .write_all(b"GET /dns/health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("TODO: handle error");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("TODO: handle error");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    }
+
+    #[test]
+    fn missing_or_mismatched_files_fail() {
+        let tls = test_tls();
+        let missing = TlsConfig {
+            cert: tls.cert.with_file_name("missing.pem"),
+            key: tls.key.clone(),
+        };
+        assert!(server_config(&missing).is_err());
+        let swapped = TlsConfig {
+            cert: tls.key.clone(),
+            key: tls.cert.clone(),
+        };
+        assert!(server_config(&swapped).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Internationalized record keys. A key such as `josé` is kept in Unicode,
+//! normalized to NFC, and goes on the wire as its punycode form
+//! (`xn--jos-dma`), which is what standards-compliant clients send
+//! (RFC 3492, RFC 5891).
+//!
+//! Conversion is label by label and only touches labels with non-ASCII
+//! characters, so ASCII keys keep their case and characters such as `_`.
+
+use std::borrow::Cow;
+
+use icu_normalizer::ComposingNormalizerBorrowed;
+
+/// Prefix of a punycode label.
+const ACE_PREFIX: &str = "xn--";
+
+/// `key` in Normalization Form C, the form keys are stored and matched in.
+pub fn normalize_key(key: &str) -> Cow<'_, str> {
+    if key.is_ascii() {
+        return Cow::Borrowed(key);
+    }
+    ComposingNormalizerBorrowed::new_nfc().normalize(key)
+}
+
+/// `key` case-folded and in NFC, so names that differ only in case or
+/// normalization form, as different client stacks produce them, match.
+pub fn fold_key(key: &str) -> Cow<'_, str> {
+    if key.is_ascii() {
+        if key.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Cow::Owned(key.to_ascii_lowercase());
+        }
+        return Cow::Borrowed(key);
+    }
+    let lower = normalize_key(key).to_lowercase();
+    Cow::Owned(normalize_key(&lower).into_owned())
+}
+
+/// `key` as it appears in a DNS name: each non-ASCII label of its NFC form
+/// in punycode.
+pub fn key_to_ascii(key: &str) -> Cow<'_, str> {
+    if key.is_ascii() {
+        return Cow::Borrowed(key);
+    }
+    let labels: Vec<String> = normalize_key(key)
+        .split('.')
+        .map(|label| {
+            if label.is_ascii() {
+                return label.to_string();
+            }
+            idna::punycode::encode_str(label)
+                .map_or_else(|| label.to_string(), |ace| format!("{ACE_PREFIX}{ace}"))
+        })
+        .collect();
+    Cow::Owned(labels.join("."))
+}
+
+/// `key` as taken from a DNS name, with punycode labels decoded, in NFC.
+/// Labels that aren't valid punycode are kept as they are.
+pub fn key_from_ascii(key: &str) -> Cow<'_, str> {
+    let is_ace = |label: &str| {
+        label
+            .get(..ACE_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(ACE_PREFIX))
+    };
+    if !key.split('.').any(is_ace) {
+        return normalize_key(key);
+    }
+    let labels: Vec<String> = key
+        .split('.')
+        .map(|label| {
+            is_ace(label)
+                .then(|| idna::punycode::decode_to_string(&label[ACE_PREFIX.len()..]))
+                .flatten()
+                .unwrap_or_else(|| label.to_string())
+        })
+        .collect();
+    Cow::Owned(normalize_key(&labels.join(".")).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unicode_keys_round_trip_through_punycode() {
+        assert_eq!(key_to_ascii("josé"), "xn--jos-dma");
+        assert_eq!(key_from_ascii("xn--jos-dma"), "josé");
+        assert_eq!(key_from_ascii("XN--jos-dma"), "josé");
+        assert_eq!(key_to_ascii("web.müller"), "web.xn--mller-kva");
+        assert_eq!(key_from_ascii("web.xn--mller-kva"), "web.müller");
+    }
+
+    #[test]
+    fn keys_are_stored_in_nfc() {
+        let decomposed = "jose\u{301}";
+        assert_eq!(normalize_key(decomposed), "josé");
+        assert_eq!(key_to_ascii(decomposed), "xn--jos-dma");
+    }
+
+    #[test]
+    fn folding_ignores_case_and_normalization() {
+        assert_eq!(fold_key("Alice"), "alice");
+        assert!(matches!(fold_key("alice"), Cow::Borrowed("alice")));
+        assert_eq!(fold_key("JOSE\u{301}"), fold_key("josé"));
+        assert_eq!(fold_key("MÜLLER"), "müller");
+    }
+
+    #[test]
+    fn ascii_keys_are_untouched() {
+        assert!(matches!(
+            key_to_ascii("Svc_A.v2"),
+            Cow::Borrowed("Svc_A.v2")
+        ));
+        assert!(matches!(
+            key_from_ascii("Svc_A.v2"),
+            Cow::Borrowed("Svc_A.v2")
+        ));
+        assert_eq!(key_from_ascii("xn--not valid!"), "xn--not valid!");
+    }
+}
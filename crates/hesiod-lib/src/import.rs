@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Conversion of flat `/etc/passwd`, `/etc/group` and `/etc/services` files
+//! (or the equivalent `ypcat` output) into config entries.
+
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::config::{GroupEntry, ServiceEntry, UserEntry};
+use crate::error::{Context, HesiodError, Result};
+use crate::records::MapType;
+
+/// Which entries to keep. Unset ranges and an empty shell list allow everything.
+#[derive(Debug, Clone, Default)]
+pub struct ImportFilter {
+    pub uid_range: Option<RangeInclusive<u32>>,
+    pub gid_range: Option<RangeInclusive<u32>>,
+    /// Login shells users must have to be imported.
+    pub shells: Vec<String>,
+}
+
+impl ImportFilter {
+    fn keeps_user(&self, user: &UserEntry) -> bool {
+        self.uid_range
+            .as_ref()
+            .is_none_or(|r| r.contains(&user.uid))
+            && (self.shells.is_empty() || self.shells.contains(&user.shell))
+    }
+
+    fn keeps_group(&self, group: &GroupEntry) -> bool {
+        self.gid_range
+            .as_ref()
+            .is_none_or(|r| r.contains(&group.gid))
+    }
+}
+
+/// Parse an inclusive range written `low-high`, `low-` or `-high`.
+pub fn parse_range(text: &str) -> Result<RangeInclusive<u32>> {
+    let (low, high) = text
+        .split_once('-')
+        .config_err(|| format!("range {text:?} must look like low-high"))?;
+    let low = if low.is_empty() {
+        0
+    } else {
+        low.parse().config_err(|| "invalid range start")?
+    };
+    let high = if high.is_empty() {
+        u32::MAX
+    } else {
+        high.parse().config_err(|| "invalid range end")?
+    };
+    if low > high {
+        return Err(HesiodError::config(format!("range {text:?} is empty")));
+    }
+    Ok(low..=high)
+}
+
+/// Parse one field of the entry on `line_no`.
+fn field<T: FromStr>(map: MapType, name: &'static str, line_no: usize, text: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    text.parse()
+        .map_err(|e| HesiodError::record_parse(map, name, format!("line {line_no}: {e}")))
+}
+
+/// Data lines of a flat file: comments, blanks and NIS `+`/`-` inclusion
+/// lines are skipped, and a leading `ypcat -k` key column is dropped.
+fn data_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content.lines().enumerate().filter_map(|(index, line)| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', '+', '-']) {
+            return None;
+        }
+        let line = match line.split_once(char::is_whitespace) {
+            Some((key, rest)) if !key.contains(':') && rest.contains(':') => rest.trim_start(),
+            _ => line,
+        };
+        Some((index + 1, line))
+    })
+}
+
+/// Users from passwd-format content.
+pub fn parse_passwd(content: &str, filter: &ImportFilter) -> Result<Vec<UserEntry>> {
+    let mut users = Vec::new();
+    for (line_no, line) in data_lines(content) {
+        let fields: Vec<&str> = line.split(':').collect();
+        let [username, _password, uid, gid, gecos, home, shell] = fields[..] else {
+            return Err(HesiodError::record_parse(
+                MapType::Passwd,
+                "fields",
+                format!("line {line_no}: expected 7, got {}", fields.len()),
+            ));
+        };
+        let user = UserEntry {
+            username: username.to_string(),
+            uid: field(MapType::Passwd, "uid", line_no, uid)?,
+            gid: field(MapType::Passwd, "gid", line_no, gid)?,
+            gecos: gecos.to_string(),
+            home: home.to_string(),
+            shell: shell.to_string(),
+        };
+        if filter.keeps_user(&user) {
+            users.push(user);
+        }
+    }
+    Ok(users)
+}
+
+/// Groups from group-format content.
+pub fn parse_group(content: &str, filter: &ImportFilter) -> Result<Vec<GroupEntry>> {
+    let mut groups = Vec::new();
+    for (line_no, line) in data_lines(content) {
+        let fields: Vec<&str> = line.split(':').collect();
+        let [name, _password, gid, members] = fields[..] else {
+            return Err(HesiodError::record_parse(
+                MapType::Group,
+                "fields",
+                format!("line {line_no}: expected 4, got {}", fields.len()),
+            ));
+        };
+        let group = GroupEntry {
+            name: name.to_string(),
+            gid: field(MapType::Group, "gid", line_no, gid)?,
+            members: members
+                .split(',')
+                .filter(|m| !m.is_empty())
+                .map(String::from)
+                .collect(),
+        };
+        if filter.keeps_group(&group) {
+            groups.push(group);
+        }
+    }
+    Ok(groups)
+}
+
+/// Services from services-format content (`name port/proto [aliases]`).
+/// The file has no host column, so every service is pointed at `host`.
+/// A name listed for several protocols keeps its first entry.
+pub fn parse_services(content: &str, host: &str) -> Result<Vec<ServiceEntry>> {
+    let mut services: Vec<ServiceEntry> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(port_proto)) = (fields.next(), fields.next()) else {
+            return Err(HesiodError::record_parse(
+                MapType::Service,
+                "fields",
+                format!("line {line_no}: expected `name port/proto`"),
+            ));
+        };
+        let (port, protocol) = port_proto.split_once('/').ok_or_else(|| {
+            HesiodError::record_parse(
+                MapType::Service,
+                "port",
+                format!("line {line_no}: expected port/proto"),
+            )
+        })?;
+        if services.iter().any(|s| s.name == name) {
+            continue;
+        }
+        services.push(ServiceEntry {
+            name: name.to_string(),
+            host: host.to_string(),
+            port: field(MapType::Service, "port", line_no, port)?,
+            protocol: protocol.to_string(),
+            address: None,
+            tags: Vec::new(),
+        });
+    }
+    Ok(services)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwd_filters_and_ypcat_keys() {
+        let content = "# local\n\
+                       root:x:0:0:root:/root:/bin/bash\n\
+                       alice alice:x:1000:1000:Alice:/home/alice:/bin/zsh\n\
+                       svc:x:1001:1001::/var/svc:/usr/sbin/nologin\n\
+                       +@netgroup\n";
+        let filter = ImportFilter {
+            uid_range: Some(parse_range("1000-").expect("TODO: handle error")),
+            shells: vec!["/bin/zsh".into()],
+            ..Default::default()
+        };
+        let users = parse_passwd(content, &filter).expect("TODO: handle error");
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, "alice");
+        assert_eq!(users[0].gecos, "Alice");
+    }
+
+    #[test]
+    fn group_and_services() {
+        let groups = parse_group(
+            "ops:x:1001:alice,bob\nnone:x:1002:\n",
+            &ImportFilter::default(),
+        )
+        .expect("TODO: handle error");
+        assert_eq!(groups[0].members, ["alice", "bob"]);
+        assert!(groups[1].members.is_empty());
+
+        let services = parse_services(
+            "http 80/tcp www # web\nhttp 80/udp\nntp 123/udp\n",
+            "gw.internal",
+        )
+        .expect("TODO: handle error");
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[1].protocol, "udp");
+        assert_eq!(services[1].host, "gw.internal");
+    }
+
+    #[test]
+    fn rejects_bad_lines_and_ranges() {
+        assert!(parse_passwd("alice:x:1000\n", &ImportFilter::default()).is_err());
+        assert!(parse_range("10-1").is_err());
+    }
+}
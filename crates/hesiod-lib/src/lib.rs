@@ -3,10 +3,70 @@
 //!
 //! Provides HS-class TXT record management, a lightweight UDP DNS server,
 //! and HTTP health/metrics endpoints for FlatRacoon network stack integration.
+//!
+//! The server, client and HTTP modules need the default `net` feature.
+//! Without it the record, config, zone and lint code still builds, including
+//! for `wasm32-unknown-unknown`.
 
 #![forbid(unsafe_code)]
+#[cfg(feature = "net")]
+pub mod answer_cache;
+pub mod audit;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod build_info;
+#[cfg(feature = "net")]
+pub mod cache;
+#[cfg(feature = "net")]
+pub mod client;
 pub mod config;
+pub mod config_edit;
+#[cfg(feature = "net")]
+pub mod coredns;
+#[cfg(feature = "net")]
+pub mod dashboard;
+pub mod error;
+pub mod export;
+pub mod formats;
+#[cfg(feature = "net")]
+pub mod git;
+#[cfg(feature = "net")]
 pub mod health;
+pub mod hesiod_conf;
+#[cfg(feature = "net")]
+pub mod http_tls;
+pub mod idn;
+pub mod import;
+pub mod lint;
+#[cfg(feature = "net")]
+pub mod metrics;
+#[cfg(feature = "net")]
+pub mod naming;
+#[cfg(feature = "net")]
+pub mod notify;
+pub mod nss;
+#[cfg(feature = "net")]
+pub mod powerdns;
+#[cfg(feature = "net")]
+pub mod proxy_protocol;
+#[cfg(feature = "net")]
+pub mod rate_limit;
 pub mod records;
+#[cfg(feature = "net")]
+pub mod registry;
+pub mod reverse;
+#[cfg(feature = "net")]
+pub mod s3;
+#[cfg(feature = "net")]
+pub mod search;
+pub mod secret;
+#[cfg(feature = "net")]
 pub mod server;
+#[cfg(feature = "net")]
+pub mod signature;
+#[cfg(feature = "net")]
+pub mod source;
 pub mod zone;
+pub mod zonefile;
+
+pub use error::{HesiodError, Result};
@@ -0,0 +1,616 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Cross-record consistency checks over a zone or config.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::config::{HesiodConfig, SoaConfig};
+use crate::records::{HesiodRecord, MapType};
+use crate::zone::HesiodZone;
+use crate::zonefile::{ZoneFile, ZoneFileEntry};
+
+/// TTLs below this make resolvers re-query constantly.
+const MIN_SENSIBLE_TTL: u32 = 60;
+/// TTLs above this (one week) make changes take too long to propagate.
+const MAX_SENSIBLE_TTL: u32 = 604_800;
+/// How long [`check_service_hosts`] waits for each host to resolve.
+#[cfg(feature = "net")]
+const RESOLVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How serious a finding is. Ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// One problem found by a check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    /// File the finding is in, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// 1-based line in `file`, for findings about one record of a zone file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `duplicate-uid`.
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub map: Option<MapType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Self {
+            file: None,
+            line: None,
+            severity,
+            code,
+            map: None,
+            key: None,
+            message,
+        }
+    }
+
+    fn at(mut self, map: MapType, key: &str) -> Self {
+        self.map = Some(map);
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// Point the finding at the zone file line `entry` was read from.
+    fn located(mut self, entry: &ZoneFileEntry) -> Self {
+        self.file = entry.file.as_ref().map(|f| f.display().to_string());
+        self.line = Some(entry.line);
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}:{line}: ")?,
+            (Some(file), None) => write!(f, "{file}: ")?,
+            (None, Some(line)) => write!(f, "line {line}: ")?,
+            (None, None) => {}
+        }
+        write!(f, "{}[{}]", self.severity, self.code)?;
+        if let (Some(map), Some(key)) = (self.map, &self.key) {
+            write!(f, " {}/{}", map.label(), key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Run every check over `zone`. Findings are ordered most severe first.
+pub fn lint_zone(zone: &HesiodZone) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = ttl_finding(zone.ttl).into_iter().collect();
+    findings.extend(soa_timer_findings(&zone.soa));
+
+    let mut users = BTreeSet::new();
+    let mut uids: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+    let mut gids: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+    for (name, record) in zone.records() {
+        match record {
+            HesiodRecord::Passwd(user) => {
+                users.insert(user.username.as_str());
+                uids.entry(user.uid).or_default().push(name);
+            }
+            HesiodRecord::Group(group) => gids.entry(group.gid).or_default().push(name),
+            _ => {}
+        }
+    }
+
+    for (uid, mut names) in uids.into_iter().filter(|(_, n)| n.len() > 1) {
+        names.sort_unstable();
+        for name in &names {
+            findings.push(
+                Finding::new(
+                    Severity::Error,
+                    "duplicate-uid",
+                    format!("uid {} is shared by {}", uid, names.join(", ")),
+                )
+                .at(MapType::Passwd, name),
+            );
+        }
+    }
+    for (gid, names) in gids.iter().filter(|(_, n)| n.len() > 1) {
+        let mut names = names.clone();
+        names.sort_unstable();
+        for name in &names {
+            findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    "duplicate-gid",
+                    format!("gid {} is shared by groups {}", gid, names.join(", ")),
+                )
+                .at(MapType::Group, name),
+            );
+        }
+    }
+
+    for (name, record) in zone.records() {
+        match record {
+            HesiodRecord::Passwd(user) => {
+                if !user.home.starts_with('/') {
+                    findings.push(
+                        Finding::new(
+                            Severity::Error,
+                            "relative-home",
+                            format!("home directory {:?} is not an absolute path", user.home),
+                        )
+                        .at(MapType::Passwd, name),
+                    );
+                }
+                if !user.shell.starts_with('/') || user.shell.contains(char::is_whitespace) {
+                    findings.push(
+                        Finding::new(
+                            Severity::Error,
+                            "invalid-shell",
+                            format!("shell {:?} is not an absolute path", user.shell),
+                        )
+                        .at(MapType::Passwd, name),
+                    );
+                }
+                if !gids.contains_key(&user.gid) {
+                    findings.push(
+                        Finding::new(
+                            Severity::Info,
+                            "unknown-primary-gid",
+                            format!("primary gid {} has no group record", user.gid),
+                        )
+                        .at(MapType::Passwd, name),
+                    );
+                }
+            }
+            HesiodRecord::Group(group) => {
+                for member in group.members.iter().filter(|m| !users.contains(m.as_str())) {
+                    findings.push(
+                        Finding::new(
+                            Severity::Warning,
+                            "dangling-member",
+                            format!("member {member:?} has no passwd record"),
+                        )
+                        .at(MapType::Group, name),
+                    );
+                }
+            }
+            HesiodRecord::Filsys(fs) => {
+                if !fs.mount_path.starts_with('/') {
+                    findings.push(
+                        Finding::new(
+                            Severity::Error,
+                            "relative-mount",
+                            format!("mount path {:?} is not an absolute path", fs.mount_path),
+                        )
+                        .at(MapType::Filsys, name),
+                    );
+                }
+            }
+            HesiodRecord::Service(svc) => {
+                if svc.port == 0 {
+                    findings.push(
+                        Finding::new(Severity::Error, "zero-port", "port 0 is not usable".into())
+                            .at(MapType::Service, name),
+                    );
+                }
+            }
+        }
+    }
+
+    sort(&mut findings);
+    findings
+}
+
+/// [`lint_zone`] plus checks only visible before config entries are merged
+/// into a zone, such as entries that silently replace earlier ones.
+pub fn lint_config(config: &HesiodConfig) -> crate::error::Result<Vec<Finding>> {
+    let mut findings = lint_zone(&HesiodZone::from_config(config)?);
+
+    let names = [
+        (
+            MapType::Service,
+            config
+                .services
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        (
+            MapType::Passwd,
+            config.users.iter().map(|u| u.username.as_str()).collect(),
+        ),
+        (
+            MapType::Group,
+            config.groups.iter().map(|g| g.name.as_str()).collect(),
+        ),
+    ];
+    for (map, names) in names {
+        let mut seen = BTreeSet::new();
+        for name in names {
+            if !seen.insert(name) {
+                findings.push(
+                    Finding::new(
+                        Severity::Error,
+                        "duplicate-key",
+                        "defined more than once; only the last entry is served".into(),
+                    )
+                    .at(map, name),
+                );
+            }
+        }
+    }
+
+    sort(&mut findings);
+    Ok(findings)
+}
+
+/// An error for every zone file record that isn't valid for its map.
+pub fn invalid_records(file: &ZoneFile) -> Vec<Finding> {
+    file.entries
+        .iter()
+        .filter_map(|entry| {
+            let e = HesiodRecord::from_txt(entry.map_type, &entry.txt).err()?;
+            Some(
+                Finding::new(
+                    Severity::Error,
+                    "invalid-record",
+                    format!("invalid {} record: {e}", entry.map_type.label()),
+                )
+                .at(entry.map_type, &entry.key)
+                .located(entry),
+            )
+        })
+        .collect()
+}
+
+/// [`lint_zone`] over the valid records of a zone file, plus checks only
+/// visible in the file itself: keys defined on several lines and record
+/// TTLs that differ from the zone's. Findings about a record point at the
+/// line it was read from (the first one, for duplicated keys).
+pub fn lint_zone_file(file: &ZoneFile) -> Vec<Finding> {
+    let zone = file.valid_zone();
+
+    let mut entries: BTreeMap<(MapType, String), Vec<&ZoneFileEntry>> = BTreeMap::new();
+    for entry in &file.entries {
+        entries
+            .entry((entry.map_type, entry.key.clone()))
+            .or_default()
+            .push(entry);
+    }
+
+    let mut findings: Vec<Finding> = lint_zone(&zone)
+        .into_iter()
+        .map(|finding| {
+            let entry = match (finding.map, &finding.key) {
+                (Some(map), Some(key)) => entries.get(&(map, key.clone())),
+                _ => None,
+            };
+            match entry.and_then(|e| e.first()) {
+                Some(entry) => finding.located(entry),
+                None => finding,
+            }
+        })
+        .collect();
+    for entry in &file.entries {
+        if entry.ttl != zone.ttl
+            && let Some(finding) = ttl_finding(entry.ttl)
+        {
+            findings.push(finding.at(entry.map_type, &entry.key).located(entry));
+        }
+    }
+    for ((map, key), entries) in entries.into_iter().filter(|(_, e)| e.len() > 1) {
+        let locations: Vec<String> = entries.iter().map(|e| e.location()).collect();
+        findings.push(
+            Finding::new(
+                Severity::Error,
+                "duplicate-key",
+                format!(
+                    "defined at {}; resolvers may get any of them",
+                    locations.join(", ")
+                ),
+            )
+            .at(map, &key)
+            .located(entries[0]),
+        );
+    }
+
+    sort(&mut findings);
+    findings
+}
+
+/// A warning for every service whose host doesn't resolve through the local
+/// resolver.
+#[cfg(feature = "net")]
+pub async fn check_service_hosts(zone: &HesiodZone) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (name, record) in zone.records() {
+        let HesiodRecord::Service(svc) = record else {
+            continue;
+        };
+        let lookup = tokio::net::lookup_host((svc.host.as_str(), svc.port));
+        let problem = match tokio::time::timeout(RESOLVE_TIMEOUT, lookup).await {
+            Ok(Ok(mut addrs)) => match addrs.next() {
+                Some(_) => continue,
+                None => "has no addresses".to_string(),
+            },
+            Ok(Err(e)) => format!("does not resolve: {e}"),
+            Err(_) => format!("did not resolve within {}s", RESOLVE_TIMEOUT.as_secs()),
+        };
+        findings.push(
+            Finding::new(
+                Severity::Warning,
+                "unresolvable-host",
+                format!("host {:?} {problem}", svc.host),
+            )
+            .at(MapType::Service, name),
+        );
+    }
+    sort(&mut findings);
+    findings
+}
+
+/// Findings as a SARIF 2.1.0 log, for code scanning tools and editors.
+pub fn sarif_report(findings: &[Finding]) -> serde_json::Value {
+    let rules: BTreeSet<&str> = findings.iter().map(|f| f.code).collect();
+    let results: Vec<_> = findings
+        .iter()
+        .map(|finding| {
+            let mut result = serde_json::json!({
+                "ruleId": finding.code,
+                "level": match finding.severity {
+                    Severity::Info => "note",
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                },
+                "message": { "text": finding.message },
+            });
+            let mut location = serde_json::Map::new();
+            if let Some(file) = &finding.file {
+                let mut physical = serde_json::json!({ "artifactLocation": { "uri": file } });
+                if let Some(line) = finding.line {
+                    physical["region"] = serde_json::json!({ "startLine": line });
+                }
+                location.insert("physicalLocation".into(), physical);
+            }
+            if let (Some(map), Some(key)) = (finding.map, &finding.key) {
+                location.insert(
+                    "logicalLocations".into(),
+                    serde_json::json!([{
+                        "name": key,
+                        "fullyQualifiedName": format!("{}/{}", map.label(), key),
+                    }]),
+                );
+            }
+            if !location.is_empty() {
+                result["locations"] = serde_json::json!([location]);
+            }
+            result
+        })
+        .collect();
+
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "hesiod-dns-map",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn ttl_finding(ttl: u32) -> Option<Finding> {
+    if ttl == 0 {
+        Some(Finding::new(
+            Severity::Error,
+            "zero-ttl",
+            "ttl 0 disables caching entirely".into(),
+        ))
+    } else if ttl < MIN_SENSIBLE_TTL {
+        Some(Finding::new(
+            Severity::Warning,
+            "low-ttl",
+            format!("ttl {ttl}s is below {MIN_SENSIBLE_TTL}s and will cause heavy query load"),
+        ))
+    } else if ttl > MAX_SENSIBLE_TTL {
+        Some(Finding::new(
+            Severity::Warning,
+            "high-ttl",
+            format!("ttl {ttl}s exceeds one week; changes will propagate slowly"),
+        ))
+    } else {
+        None
+    }
+}
+
+/// SOA timers a secondary can't follow sensibly (RFC 1912 section 2.2).
+fn soa_timer_findings(soa: &SoaConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if soa.retry >= soa.refresh {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "soa-retry",
+            format!(
+                "soa retry {}s is not below refresh {}s",
+                soa.retry, soa.refresh
+            ),
+        ));
+    }
+    if u64::from(soa.expire) <= u64::from(soa.refresh) + u64::from(soa.retry) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "soa-expire",
+            format!(
+                "soa expire {}s leaves secondaries no time to retry after refresh {}s",
+                soa.expire, soa.refresh
+            ),
+        ));
+    }
+    findings
+}
+
+fn sort(findings: &mut [Finding]) {
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| a.map.cmp(&b.map))
+            .then_with(|| a.key.cmp(&b.key))
+            .then_with(|| a.code.cmp(b.code))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::{GroupRecord, PasswdRecord};
+
+    fn user(name: &str, uid: u32, shell: &str) -> HesiodRecord {
+        HesiodRecord::Passwd(PasswdRecord {
+            username: name.into(),
+            uid,
+            gid: 100,
+            gecos: String::new(),
+            home: format!("/home/{name}"),
+            shell: shell.into(),
+        })
+    }
+
+    #[test]
+    fn finds_cross_record_problems() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 30);
+        zone.add_record("alice", user("alice", 1000, "/bin/bash"));
+        zone.add_record("bob", user("bob", 1000, "bash"));
+        zone.add_record(
+            "staff",
+            HesiodRecord::Group(GroupRecord {
+                name: "staff".into(),
+                gid: 100,
+                members: vec!["alice".into(), "carol".into()],
+            }),
+        );
+
+        let codes: Vec<_> = lint_zone(&zone).iter().map(|f| f.code).collect();
+        assert_eq!(
+            codes,
+            [
+                "duplicate-uid",
+                "duplicate-uid",
+                "invalid-shell",
+                "low-ttl",
+                "dangling-member"
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_zone_has_no_findings() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record("alice", user("alice", 1000, "/bin/bash"));
+        zone.add_record(
+            "staff",
+            HesiodRecord::Group(GroupRecord {
+                name: "staff".into(),
+                gid: 100,
+                members: vec!["alice".into()],
+            }),
+        );
+        assert!(lint_zone(&zone).is_empty());
+    }
+
+    #[test]
+    fn soa_timers_a_secondary_cannot_follow() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.soa = SoaConfig {
+            refresh: 600,
+            retry: 600,
+            expire: 1200,
+            ..SoaConfig::default()
+        };
+        let codes: Vec<_> = lint_zone(&zone).iter().map(|f| f.code).collect();
+        assert_eq!(codes, ["soa-expire", "soa-retry"]);
+    }
+
+    #[test]
+    fn zone_file_duplicates_and_ttls() {
+        let file = ZoneFile::parse(
+            "$ORIGIN test.internal.\n\
+             alice.passwd.ns 300 HS TXT \"alice:*:1000:100::/home/alice:/bin/sh\"\n\
+             alice.passwd.ns 300 HS TXT \"alice:*:1001:100::/home/alice:/bin/sh\"\n\
+             web.service.ns 0 HS TXT \"web.svc:80:tcp\"\n\
+             bad.group.ns 300 HS TXT \"nope\"\n",
+        )
+        .expect("TODO: handle error");
+        let findings = lint_zone_file(&file);
+        let codes: Vec<_> = findings
+            .iter()
+            .map(|f| (f.code, f.key.as_deref()))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("duplicate-key", Some("alice")),
+                ("zero-ttl", Some("web")),
+                ("unknown-primary-gid", Some("alice")),
+            ]
+        );
+        assert!(findings[0].message.contains("line 2, line 3"));
+        assert_eq!(findings[0].line, Some(2));
+        assert_eq!(findings[1].line, Some(4));
+        assert!(
+            findings[0]
+                .to_string()
+                .starts_with("line 2: error[duplicate-key]")
+        );
+
+        let invalid = invalid_records(&file);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].line, Some(5));
+    }
+
+    #[test]
+    fn sarif_has_locations() {
+        let mut finding =
+            Finding::new(Severity::Warning, "low-ttl", "too low".into()).at(MapType::Passwd, "bob");
+        finding.file = Some("zone.db".into());
+        finding.line = Some(7);
+        let sarif = sarif_report(&[finding]);
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["level"], "warning");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startLine"],
+            7
+        );
+        assert_eq!(
+            result["locations"][0]["logicalLocations"][0]["fullyQualifiedName"],
+            "passwd/bob"
+        );
+        assert_eq!(
+            sarif["runs"][0]["tool"]["driver"]["rules"][0]["id"],
+            "low-ttl"
+        );
+    }
+}
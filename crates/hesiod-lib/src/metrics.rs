@@ -0,0 +1,753 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Metric snapshots and optional periodic push to StatsD or OpenMetrics targets.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::MetricsConfig;
+use crate::error::{Context, IoContext, Result};
+use crate::records::MapType;
+use crate::secret::{Secret, redact_url};
+use crate::server::DnsServerState;
+
+// ---------------------------------------------------------------------------
+// ShardedCounter
+// ---------------------------------------------------------------------------
+
+/// Monotonic counter spread over cache-line-padded shards. Each thread writes
+/// to its own shard, so concurrent workers don't contend on one cache line;
+/// reads sum all shards.
+#[derive(Debug)]
+pub struct ShardedCounter {
+    shards: Box<[Shard]>,
+}
+
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+/// Round-robin source of per-thread shard slots.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_SLOT: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+impl ShardedCounter {
+    /// One shard per available CPU.
+    pub fn new() -> Self {
+        let shards = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(shards)
+    }
+
+    pub fn with_shards(count: usize) -> Self {
+        Self {
+            shards: (0..count.max(1)).map(|_| Shard::default()).collect(),
+        }
+    }
+
+    pub fn add(&self, n: u64) {
+        let slot = SHARD_SLOT.with(|slot| *slot) % self.shards.len();
+        self.shards[slot].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Sum across all shards.
+    pub fn get(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn reset(&self) {
+        for shard in self.shards.iter() {
+            shard.0.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for ShardedCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Query counters for one zone of a multi-zone server, reported only to
+/// that zone's admins.
+#[derive(Debug, Default)]
+pub struct ZoneCounters {
+    /// Questions for names in the zone.
+    pub queries: ShardedCounter,
+    /// Per map type, indexed by [`MapType::ALL`] order.
+    pub map_queries: [ShardedCounter; 4],
+}
+
+impl ZoneCounters {
+    pub fn reset(&self) {
+        self.queries.reset();
+        self.map_queries.iter().for_each(ShardedCounter::reset);
+    }
+}
+
+/// Counters for packets that were dropped or could not be answered.
+#[derive(Debug, Default)]
+pub struct ErrorCounters {
+    /// Datagrams `Message::from_vec` could not parse.
+    pub malformed_packets: ShardedCounter,
+    /// Datagrams larger than the receive or query size limit, dropped
+    /// unparsed.
+    pub oversized_packets: ShardedCounter,
+    /// Queries with more questions than the limit, refused with FORMERR.
+    pub excess_questions: ShardedCounter,
+    /// Packets with the QR bit set, i.e. responses, dropped unanswered.
+    pub response_packets: ShardedCounter,
+    /// Queries answered despite bytes after the last record.
+    pub trailing_data: ShardedCounter,
+    /// Responses `send_to` failed to deliver.
+    pub send_failures: ShardedCounter,
+    /// Times the DNS receive loop was restarted after a socket error.
+    pub server_restarts: ShardedCounter,
+    /// Queries dropped because the ingress queue was full.
+    pub dropped_queries: ShardedCounter,
+}
+
+impl ErrorCounters {
+    /// Name/counter pairs, used for rendering and reset.
+    pub fn entries(&self) -> [(&'static str, &ShardedCounter); 8] {
+        [
+            ("malformed_packets", &self.malformed_packets),
+            ("oversized_packets", &self.oversized_packets),
+            ("excess_questions", &self.excess_questions),
+            ("response_packets", &self.response_packets),
+            ("trailing_data", &self.trailing_data),
+            ("send_failures", &self.send_failures),
+            ("server_restarts", &self.server_restarts),
+            ("dropped_queries", &self.dropped_queries),
+        ]
+    }
+
+    pub fn reset(&self) {
+        self.entries()
+            .iter()
+            .for_each(|(_, counter)| counter.reset());
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Snapshots and push
+// ---------------------------------------------------------------------------
+
+/// Point-in-time view of the server counters.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub query_count: u64,
+    pub uptime_seconds: u64,
+    pub queries_per_second: f64,
+    pub zone_records: usize,
+    pub map_queries: BTreeMap<MapType, u64>,
+    /// Queries refused by the map ACLs.
+    pub map_refusals: BTreeMap<MapType, u64>,
+    pub errors: BTreeMap<&'static str, u64>,
+    /// Successful and failed loads from the config source.
+    pub sync_successes: u64,
+    pub sync_failures: u64,
+}
+
+impl MetricsSnapshot {
+    /// Read the current counters from the server state.
+    pub fn capture(state: &DnsServerState) -> Self {
+        let query_count = state.query_count.get();
+        let uptime_seconds = state.start_time.elapsed().as_secs();
+        let counting_seconds = state.counters_elapsed().as_secs();
+        let queries_per_second = if counting_seconds > 0 {
+            query_count as f64 / counting_seconds as f64
+        } else {
+            0.0
+        };
+        let map_queries = MapType::ALL
+            .iter()
+            .map(|mt| (*mt, state.map_queries(*mt).get()))
+            .collect();
+        let map_refusals = MapType::ALL
+            .iter()
+            .map(|mt| (*mt, state.map_refusals(*mt).get()))
+            .collect();
+        let errors = state
+            .errors
+            .entries()
+            .iter()
+            .map(|(name, counter)| (*name, counter.get()))
+            .collect();
+        let sync = state.sync_status();
+        Self {
+            query_count,
+            uptime_seconds,
+            queries_per_second,
+            zone_records: state.zones().record_count(),
+            map_queries,
+            map_refusals,
+            errors,
+            sync_successes: sync.successes,
+            sync_failures: sync.failures,
+        }
+    }
+
+    /// StatsD lines. `queries_delta` is the query count since the previous push,
+    /// so the counter can be sent as a StatsD `c` increment.
+    pub fn to_statsd(&self, prefix: &str, queries_delta: u64) -> String {
+        let mut out = format!("{prefix}.queries:{queries_delta}|c\n");
+        out.push_str(&format!(
+            "{prefix}.uptime_seconds:{}|g\n",
+            self.uptime_seconds
+        ));
+        out.push_str(&format!("{prefix}.zone_records:{}|g\n", self.zone_records));
+        for (map_type, count) in &self.map_queries {
+            out.push_str(&format!("{prefix}.map_queries.{map_type}:{count}|g\n"));
+        }
+        for (map_type, count) in &self.map_refusals {
+            out.push_str(&format!("{prefix}.map_refusals.{map_type}:{count}|g\n"));
+        }
+        for (name, count) in &self.errors {
+            out.push_str(&format!("{prefix}.errors.{name}:{count}|g\n"));
+        }
+        out.push_str(&format!(
+            "{prefix}.sync.successes:{}|g\n",
+            self.sync_successes
+        ));
+        out.push_str(&format!(
+            "{prefix}.sync.failures:{}|g\n",
+            self.sync_failures
+        ));
+        out
+    }
+
+    /// OpenMetrics text exposition, terminated by `# EOF`.
+    pub fn to_openmetrics(&self, prefix: &str) -> String {
+        let mut out = String::with_capacity(512);
+        out.push_str(&format!("# TYPE {prefix}_queries counter\n"));
+        out.push_str(&format!("{prefix}_queries_total {}\n", self.query_count));
+        out.push_str(&format!("# TYPE {prefix}_map_queries counter\n"));
+        for (map_type, count) in &self.map_queries {
+            out.push_str(&format!(
+                "{prefix}_map_queries_total{{map=\"{map_type}\"}} {count}\n"
+            ));
+        }
+        out.push_str(&format!("# TYPE {prefix}_map_refusals counter\n"));
+        for (map_type, count) in &self.map_refusals {
+            out.push_str(&format!(
+                "{prefix}_map_refusals_total{{map=\"{map_type}\"}} {count}\n"
+            ));
+        }
+        for (name, count) in &self.errors {
+            out.push_str(&format!("# TYPE {prefix}_{name} counter\n"));
+            out.push_str(&format!("{prefix}_{name}_total {count}\n"));
+        }
+        out.push_str(&format!("# TYPE {prefix}_sync_successes counter\n"));
+        out.push_str(&format!(
+            "{prefix}_sync_successes_total {}\n",
+            self.sync_successes
+        ));
+        out.push_str(&format!("# TYPE {prefix}_sync_failures counter\n"));
+        out.push_str(&format!(
+            "{prefix}_sync_failures_total {}\n",
+            self.sync_failures
+        ));
+        out.push_str(&format!("# TYPE {prefix}_uptime_seconds gauge\n"));
+        out.push_str(&format!(
+            "{prefix}_uptime_seconds {}\n",
+            self.uptime_seconds
+        ));
+        out.push_str(&format!("# TYPE {prefix}_zone_records gauge\n"));
+        out.push_str(&format!("{prefix}_zone_records {}\n", self.zone_records));
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+// ---------------------------------------------------------------------------
+// History
+// ---------------------------------------------------------------------------
+
+/// How often [`spawn_metrics_history`] samples the query counter.
+pub const HISTORY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Samples kept: one hour at [`HISTORY_INTERVAL`].
+const HISTORY_CAPACITY: usize = 360;
+
+/// Queries kept by [`RecentQueries`].
+const RECENT_QUERIES_CAPACITY: usize = 50;
+
+/// One point of the query rate history.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistorySample {
+    pub timestamp_unix: u64,
+    pub query_count: u64,
+    /// Rate since the previous sample; 0 for the first one.
+    pub queries_per_second: f64,
+}
+
+/// Bounded in-memory query rate history, as served by
+/// `GET /dns/metrics/history`.
+#[derive(Debug, Default)]
+pub struct MetricsHistory {
+    samples: Mutex<VecDeque<HistorySample>>,
+}
+
+impl MetricsHistory {
+    /// Append a sample of `query_count` taken at `timestamp_unix`. A counter
+    /// that went backwards (a reset) restarts the rate at zero.
+    pub fn record(&self, timestamp_unix: u64, query_count: u64) {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        let queries_per_second = match samples.back() {
+            Some(last) if timestamp_unix > last.timestamp_unix => {
+                query_count.saturating_sub(last.query_count) as f64
+                    / (timestamp_unix - last.timestamp_unix) as f64
+            }
+            _ => 0.0,
+        };
+        if samples.len() == HISTORY_CAPACITY {
+            samples.pop_front();
+        }
+        samples.push_back(HistorySample {
+            timestamp_unix,
+            query_count,
+            queries_per_second,
+        });
+    }
+
+    /// Every kept sample, oldest first.
+    pub fn samples(&self) -> Vec<HistorySample> {
+        let samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        samples.iter().cloned().collect()
+    }
+}
+
+/// One answered question, as shown on the dashboard.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentQuery {
+    pub timestamp_unix: u64,
+    /// Key looked up, e.g. `alice`.
+    pub name: Arc<str>,
+    pub map: MapType,
+    /// Zone the query went to.
+    pub zone: Arc<str>,
+    /// Whether a record was found.
+    pub found: bool,
+}
+
+/// The last few queries the server resolved to a zone. Recording skips a
+/// query rather than wait when another worker holds the lock, so the
+/// answer path never blocks on it.
+#[derive(Debug, Default)]
+pub struct RecentQueries {
+    queries: Mutex<VecDeque<RecentQuery>>,
+}
+
+impl RecentQueries {
+    pub fn record(&self, query: RecentQuery) {
+        let Ok(mut queries) = self.queries.try_lock() else {
+            return;
+        };
+        if queries.len() == RECENT_QUERIES_CAPACITY {
+            queries.pop_front();
+        }
+        queries.push_back(query);
+    }
+
+    /// Most recent queries, newest last.
+    pub fn recent(&self, limit: usize) -> Vec<RecentQuery> {
+        self.recent_where(limit, |_| true)
+    }
+
+    /// Most recent queries that `keep` accepts, newest last.
+    pub fn recent_where(
+        &self,
+        limit: usize,
+        keep: impl Fn(&RecentQuery) -> bool,
+    ) -> Vec<RecentQuery> {
+        let queries = self.queries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut recent: Vec<_> = queries
+            .iter()
+            .rev()
+            .filter(|query| keep(query))
+            .take(limit)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Spawn the task sampling the query counter into the state's history.
+pub fn spawn_metrics_history(state: Arc<DnsServerState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HISTORY_INTERVAL);
+        loop {
+            interval.tick().await;
+            state.history.record(unix_now(), state.query_count.get());
+        }
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Persistence
+// ---------------------------------------------------------------------------
+
+/// Long-term counters checkpointed to disk so restarts don't zero them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersistedStats {
+    pub query_count: u64,
+    #[serde(default)]
+    pub map_queries: BTreeMap<MapType, u64>,
+}
+
+impl PersistedStats {
+    /// Current counter values from the server state.
+    pub fn capture(state: &DnsServerState) -> Self {
+        let snapshot = MetricsSnapshot::capture(state);
+        Self {
+            query_count: snapshot.query_count,
+            map_queries: snapshot.map_queries,
+        }
+    }
+
+    /// Load a state file. A missing file yields zeroed stats.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .config_err(|| format!("parsing stats file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).io_err(|| format!("reading stats file {}", path.display())),
+        }
+    }
+
+    /// Write the state file atomically (temp file + rename).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let json = serde_json::to_vec_pretty(self).config_err(|| "serializing stats")?;
+        std::fs::write(&tmp, json).io_err(|| format!("writing stats file {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .io_err(|| format!("renaming stats file to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Add these totals onto the live counters.
+    pub fn restore_into(&self, state: &DnsServerState) {
+        state.query_count.add(self.query_count);
+        for (map_type, count) in &self.map_queries {
+            state.map_queries(*map_type).add(*count);
+        }
+    }
+}
+
+/// Restore stats from the configured state file and spawn the checkpoint task.
+pub fn spawn_stats_checkpoint(
+    state: Arc<DnsServerState>,
+    config: &MetricsConfig,
+) -> Result<Option<JoinHandle<()>>> {
+    let Some(path) = config.state_file.clone() else {
+        return Ok(None);
+    };
+    let persisted = PersistedStats::load(&path)?;
+    persisted.restore_into(&state);
+    info!(
+        "restored {} queries from {}, checkpointing every {}s",
+        persisted.query_count,
+        path.display(),
+        config.checkpoint_interval_secs
+    );
+
+    let period = Duration::from_secs(config.checkpoint_interval_secs.max(1));
+    Ok(Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = PersistedStats::capture(&state).save(&path) {
+                warn!("stats checkpoint failed: {:#}", e);
+            }
+        }
+    })))
+}
+
+/// Spawn the periodic push task if any target is configured.
+pub fn spawn_metrics_push(
+    state: Arc<DnsServerState>,
+    config: MetricsConfig,
+) -> Option<JoinHandle<()>> {
+    if !config.push_enabled() {
+        return None;
+    }
+    info!(
+        "pushing metrics every {}s (statsd: {:?}, push_url: {:?})",
+        config.push_interval_secs, config.statsd, config.push_url
+    );
+
+    Some(tokio::spawn(async move {
+        // A push still hanging when the next is due is given up.
+        let period = Duration::from_secs(config.push_interval_secs.max(1));
+        let http = reqwest::Client::builder()
+            .timeout(period)
+            .build()
+            .unwrap_or_default();
+        let mut interval = tokio::time::interval(period);
+        let mut last_queries = state.query_count.get();
+        loop {
+            interval.tick().await;
+            let snapshot = MetricsSnapshot::capture(&state);
+
+            if let Some(target) = &config.statsd {
+                let delta = queries_since(last_queries, snapshot.query_count);
+                if let Err(e) =
+                    push_statsd(target, &snapshot.to_statsd(&config.prefix, delta)).await
+                {
+                    warn!("statsd push to {} failed: {:#}", target, e);
+                }
+            }
+            if let Some(url) = config.push_url.as_ref().and_then(Secret::expose)
+                && let Err(e) =
+                    push_openmetrics(&http, url, &snapshot.to_openmetrics(&config.prefix)).await
+            {
+                warn!(
+                    "metrics push to {} failed: {:#}",
+                    redact_url(url),
+                    e.without_url()
+                );
+            }
+            last_queries = snapshot.query_count;
+        }
+    }))
+}
+
+/// Queries counted since a push that saw `previous`. A counter that went
+/// backwards was reset, so everything it holds is new.
+fn queries_since(previous: u64, current: u64) -> u64 {
+    if current >= previous {
+        current - previous
+    } else {
+        current
+    }
+}
+
+/// Send `payload` to `target` from a socket of the same address family.
+async fn push_statsd(target: &str, payload: &str) -> std::io::Result<()> {
+    let target = tokio::net::lookup_host(target)
+        .await?
+        .next()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{target} has no address"),
+            )
+        })?;
+    let local: SocketAddr = if target.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.send_to(payload.as_bytes(), target).await?;
+    Ok(())
+}
+
+async fn push_openmetrics(http: &reqwest::Client, url: &str, body: &str) -> reqwest::Result<()> {
+    http.post(url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )
+        .body(body.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::HesiodZone;
+
+    fn state() -> DnsServerState {
+        DnsServerState::new(HesiodZone::new("t.internal", ".ns", ".t.internal", 300))
+    }
+
+    fn snapshot() -> MetricsSnapshot {
+        let state = state();
+        state.query_count.add(42);
+        state.map_queries(MapType::Passwd).add(40);
+        state.map_refusals(MapType::Group).add(3);
+        state.errors.malformed_packets.add(2);
+        MetricsSnapshot::capture(&state)
+    }
+
+    #[test]
+    fn sharded_counter_sums_across_threads() {
+        let counter = Arc::new(ShardedCounter::with_shards(4));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                std::thread::spawn(move || (0..1000).for_each(|_| counter.inc()))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("TODO: handle error");
+        }
+        assert_eq!(counter.get(), 8000);
+        counter.reset();
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn statsd_lines() {
+        let lines = snapshot().to_statsd("hesiod", 7);
+        assert!(lines.contains("hesiod.queries:7|c\n"));
+        assert!(lines.contains("hesiod.zone_records:0|g\n"));
+    }
+
+    #[test]
+    fn openmetrics_text() {
+        let text = snapshot().to_openmetrics("hesiod");
+        assert!(text.contains("hesiod_queries_total 42\n"));
+        assert!(text.contains("hesiod_map_queries_total{map=\"passwd\"} 40\n"));
+        assert!(text.contains("hesiod_map_refusals_total{map=\"group\"} 3\n"));
+        assert!(text.contains("hesiod_malformed_packets_total 2\n"));
+        assert!(text.contains("hesiod_sync_failures_total 0\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn history_rates_between_samples() {
+        let history = MetricsHistory::default();
+        history.record(100, 50);
+        history.record(110, 250);
+        history.record(120, 10);
+        let samples = history.samples();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].queries_per_second, 0.0);
+        assert_eq!(samples[1].queries_per_second, 20.0);
+        // The counter was reset between the last two samples.
+        assert_eq!(samples[2].queries_per_second, 0.0);
+
+        for t in 0..HISTORY_CAPACITY as u64 {
+            history.record(200 + t, t);
+        }
+        assert_eq!(history.samples().len(), HISTORY_CAPACITY);
+    }
+
+    #[test]
+    fn recent_queries_are_bounded() {
+        let recent = RecentQueries::default();
+        for i in 0..RECENT_QUERIES_CAPACITY + 5 {
+            recent.record(RecentQuery {
+                timestamp_unix: 0,
+                name: format!("user{i}").into(),
+                map: MapType::Passwd,
+                zone: "t.internal".into(),
+                found: true,
+            });
+        }
+        let all = recent.recent(usize::MAX);
+        assert_eq!(all.len(), RECENT_QUERIES_CAPACITY);
+        assert_eq!(&*recent.recent(1)[0].name, "user54");
+    }
+
+    #[test]
+    fn persisted_stats_round_trip() {
+        let path = std::env::temp_dir().join(format!("hesiod-stats-{}.json", std::process::id()));
+        let source = state();
+        source.query_count.add(10);
+        source.map_queries(MapType::Group).add(3);
+        PersistedStats::capture(&source)
+            .save(&path)
+            .expect("TODO: handle error");
+
+        let restored = state();
+        restored.query_count.add(1);
+        PersistedStats::load(&path)
+            .expect("TODO: handle error")
+            .restore_into(&restored);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(restored.query_count.get(), 11);
+        assert_eq!(restored.map_queries(MapType::Group).get(), 3);
+    }
+
+    #[test]
+    fn missing_stats_file_is_empty() {
+        let stats = PersistedStats::load(Path::new("/nonexistent/hesiod-stats.json"))
+            .expect("TODO: handle error");
+        assert_eq!(stats, PersistedStats::default());
+    }
+
+    #[test]
+    fn push_deltas_restart_after_a_reset() {
+        assert_eq!(queries_since(100, 130), 30);
+        assert_eq!(queries_since(100, 100), 0);
+        assert_eq!(queries_since(100, 7), 7);
+    }
+
+    #[tokio::test]
+    async fn statsd_pushes_reach_ipv6_targets() {
+        let Ok(collector) = UdpSocket::bind("[::1]:0").await else {
+            return; // no IPv6 loopback here
+        };
+        let target = collector.local_addr().expect("TODO: handle error");
+        push_statsd(&target.to_string(), "hesiod.queries:1|c")
+            .await
+            .expect("TODO: handle error");
+        let mut buf = [0u8; 64];
+        let len = collector.recv(&mut buf).await.expect("TODO: handle error");
+        assert_eq!(&buf[..len], b"hesiod.queries:1|c");
+    }
+
+    #[tokio::test]
+    async fn failed_pushes_keep_their_url_out_of_the_logs() {
+        let (logs, _guard) = crate::secret::capture_logs();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("TODO: handle error");
+        let addr = listener.local_addr().expect("TODO: handle error");
+        drop(listener);
+
+        let config = MetricsConfig {
+            push_url: Some(format!("http://push:pw-s3cret@{addr}/metrics?token=tok-s3cret").into()),
+            push_interval_secs: 60,
+            ..Default::default()
+        };
+        let push = spawn_metrics_push(Arc::new(state()), config).expect("push enabled");
+        let logged =
+            || String::from_utf8_lossy(&logs.lock().expect("TODO: handle error")).into_owned();
+        for _ in 0..200 {
+            if logged().contains("failed") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        push.abort();
+        let logged = logged();
+        assert!(
+            logged.contains(&format!("metrics push to http://{addr} failed")),
+            "{logged}"
+        );
+        assert!(!logged.contains("s3cret"), "{logged}");
+    }
+}
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Hesiod owner names, `<key>.<map><lhs><rhs>` (e.g.
+//! `alice.passwd.ns.example.com`), built and taken apart the way the server
+//! does.
+//!
+//! `lhs` and `rhs` are used verbatim and normally carry their leading dots,
+//! as in the config and [`crate::hesiod_conf::HesiodConf`]. Unicode keys are
+//! in punycode on the wire; see [`crate::idn`].
+
+use hickory_proto::rr::Name;
+
+use crate::error::{Context, Result};
+use crate::idn::{key_from_ascii, key_to_ascii};
+use crate::records::MapType;
+
+/// Fully qualified DNS name of `key` in `map_type`.
+pub fn to_bind_name(key: &str, map_type: MapType, lhs: &str, rhs: &str) -> Result<Name> {
+    let name = format!("{}.{}{}{}.", key_to_ascii(key), map_type.label(), lhs, rhs);
+    Name::from_ascii(&name).dns_err(|| format!("invalid DNS name {name:?}"))
+}
+
+/// Record key and map type of `name`, or `None` if it isn't a Hesiod name
+/// under `lhs` and `rhs`. The key may itself contain dots. The suffix and
+/// map match in any case; the key is as sent, with punycode labels decoded,
+/// for [`crate::zone::HesiodZone::lookup`] to fold.
+pub fn from_bind_name(name: &Name, lhs: &str, rhs: &str) -> Option<(String, MapType)> {
+    // The wire form: `to_string` would decode punycode labels itself.
+    let name_str = name.to_ascii();
+    let (key, map_type) = split_ascii_name(&name_str, lhs, rhs)?;
+    Some((key_from_ascii(key).into_owned(), map_type))
+}
+
+/// [`from_bind_name`] on the ASCII form of a name, leaving the key in
+/// punycode, so one rendering of the name can be tried against many zones.
+pub(crate) fn split_ascii_name<'a>(
+    name: &'a str,
+    lhs: &str,
+    rhs: &str,
+) -> Option<(&'a str, MapType)> {
+    // Remove trailing dot if present
+    let name = name.strip_suffix('.').unwrap_or(name);
+
+    // Strip e.g. ".ns.flatracoon.internal" to get "<key>.<map_type>"
+    let split = name.len().checked_sub(lhs.len() + rhs.len())?;
+    let (prefix, tail) = (name.get(..split)?, name.get(split..)?);
+    let (tail_lhs, tail_rhs) = (tail.get(..lhs.len())?, tail.get(lhs.len()..)?);
+    if !tail_lhs.eq_ignore_ascii_case(lhs) || !tail_rhs.eq_ignore_ascii_case(rhs) {
+        return None;
+    }
+
+    let (key, map_label) = prefix.rsplit_once('.')?;
+    let map_type: MapType = map_label.parse().ok()?;
+
+    Some((key, map_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let name = to_bind_name("alice", MapType::Passwd, ".ns", ".example.com")
+            .expect("TODO: handle error");
+        assert_eq!(name.to_string(), "alice.passwd.ns.example.com.");
+        assert_eq!(
+            from_bind_name(&name, ".ns", ".example.com"),
+            Some(("alice".into(), MapType::Passwd))
+        );
+
+        let dotted = to_bind_name("web.v2", MapType::Service, ".ns", ".example.com")
+            .expect("TODO: handle error");
+        assert_eq!(
+            from_bind_name(&dotted, ".ns", ".example.com"),
+            Some(("web.v2".into(), MapType::Service))
+        );
+
+        let unicode = to_bind_name("jose\u{301}", MapType::Passwd, ".ns", ".example.com")
+            .expect("TODO: handle error");
+        assert_eq!(unicode.to_ascii(), "xn--jos-dma.passwd.ns.example.com.");
+        assert_eq!(
+            from_bind_name(&unicode, ".ns", ".example.com"),
+            Some(("josé".into(), MapType::Passwd))
+        );
+    }
+
+    #[test]
+    fn suffix_and_map_match_in_any_case() {
+        // As sent on the wire; parsing would lowercase it.
+        let name = Name::from_ascii("AliCe.PassWD.NS.Example.COM.").expect("TODO: handle error");
+        assert_eq!(
+            from_bind_name(&name, ".ns", ".example.com"),
+            Some(("AliCe".into(), MapType::Passwd))
+        );
+    }
+
+    #[test]
+    fn rejects_foreign_names() {
+        let name: Name = "alice.passwd.ns.other.com"
+            .parse()
+            .expect("TODO: handle error");
+        assert_eq!(from_bind_name(&name, ".ns", ".example.com"), None);
+        let name: Name = "alice.shadow.ns.example.com"
+            .parse()
+            .expect("TODO: handle error");
+        assert_eq!(from_bind_name(&name, ".ns", ".example.com"), None);
+        assert!(to_bind_name("bad..key", MapType::Passwd, ".ns", ".example.com").is_err());
+    }
+}
@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Webhook notifications sent after the served zone changes.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::config::NotifyConfig;
+use crate::secret::{Secret, redact_url};
+use crate::zone::ZoneDiff;
+
+/// Summary POSTed to each webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ZoneChange {
+    pub domain: String,
+    /// SOA serial of the zones the change was applied in.
+    pub serial: u64,
+    pub checksum: String,
+    pub previous_checksum: String,
+    pub zone_records: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+impl ZoneChange {
+    pub fn counts_from(&mut self, diff: &ZoneDiff) {
+        self.added = diff.added.len();
+        self.removed = diff.removed.len();
+        self.changed = diff.changed.len();
+    }
+}
+
+/// Fire-and-forget webhook sender. Webhook URLs stay [`Secret`]s and are
+/// only logged redacted.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    urls: Vec<Secret>,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(config: &NotifyConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+            .build()
+            .unwrap_or_default();
+        Self {
+            urls: config.webhooks.clone(),
+            client,
+        }
+    }
+
+    /// POST `change` to every webhook in the background. Failures are logged;
+    /// they never hold up or fail the change itself.
+    pub fn notify(&self, change: &ZoneChange) {
+        for url in self.urls.iter().filter_map(Secret::expose) {
+            let request = self.client.post(url).json(change);
+            let url = redact_url(url);
+            tokio::spawn(async move {
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => debug!("notified webhook {}", url),
+                    Err(e) => warn!("webhook {} failed: {}", url, e.without_url()),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn posts_change_summary() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let url = format!(
+            "http://{}/hook",
+            listener.local_addr().expect("TODO: handle error")
+        );
+
+        let notifier = Notifier::new(&NotifyConfig {
+            webhooks: vec![url.into()],
+            ..Default::default()
+        });
+        notifier.notify(&ZoneChange {
+            domain: "t.internal".into(),
+            serial: 2,
+            checksum: "sha256:new".into(),
+            previous_checksum: "sha256:old".into(),
+            zone_records: 3,
+            added: 1,
+            removed: 0,
+            changed: 0,
+        });
+
+        let (mut conn, _) = listener.accept().await.expect("TODO: handle error");
+        let mut buf = vec![0u8; 4096];
+        let n = conn.read(&mut buf).await.expect("TODO: handle error");
+        conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .await
+            .expect("TODO: handle error");
+        let request = String::from_utf8_lossy(&buf[..n]);
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.contains("\"serial\":2"));
+    }
+
+    #[tokio::test]
+    async fn failed_webhooks_keep_their_url_out_of_the_logs() {
+        let (logs, _guard) = crate::secret::capture_logs();
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let addr = listener.local_addr().expect("TODO: handle error");
+        drop(listener);
+
+        let url = format!("http://hook:pw-s3cret@{addr}/hook?token=tok-s3cret");
+        let notifier = Notifier::new(&NotifyConfig {
+            webhooks: vec![url.into()],
+            ..Default::default()
+        });
+        notifier.notify(&ZoneChange {
+            domain: "t.internal".into(),
+            serial: 2,
+            checksum: "sha256:new".into(),
+            previous_checksum: "sha256:old".into(),
+            zone_records: 3,
+            added: 1,
+            removed: 0,
+            changed: 0,
+        });
+
+        let logged =
+            || String::from_utf8_lossy(&logs.lock().expect("TODO: handle error")).into_owned();
+        for _ in 0..200 {
+            if logged().contains("failed") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let logged = logged();
+        assert!(
+            logged.contains(&format!("webhook http://{addr} failed")),
+            "{logged}"
+        );
+        assert!(!logged.contains("s3cret"), "{logged}");
+    }
+}
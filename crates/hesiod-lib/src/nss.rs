@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Client host configuration for glibc's `nss_hesiod`, so `getpwnam` and
+//! `getgrnam` resolve through this server.
+//!
+//! libhesiod queries through the host's resolver, so `/etc/resolv.conf` must
+//! point at something answering for the zone on port 53: this server, or a
+//! resolver forwarding the domain to it. nss_hesiod also looks up `uid`,
+//! `gid` and `grplist` names for `getpwuid`, `getgrgid` and `initgroups`;
+//! those aren't served, so such lookups fall through to the next source.
+
+use crate::hesiod_conf::HesiodConf;
+use crate::zone::HesiodZone;
+
+/// nsswitch.conf lines adding Hesiod after local files. Service records
+/// aren't in the format nss_hesiod reads, so `services` is left alone.
+pub const NSSWITCH_LINES: &str = "passwd: files hesiod\ngroup: files hesiod\n";
+
+/// `/etc/hesiod.conf` that names records the way `zone` serves them. IN is
+/// tried before HS since many recursive resolvers refuse class HS.
+pub fn hesiod_conf(zone: &HesiodZone) -> String {
+    let mut conf = HesiodConf::default();
+    conf.set_lhs(&zone.lhs);
+    conf.set_rhs(&zone.rhs);
+    format!(
+        "# Hesiod client settings for {}\n# Generated by hesiod-dns-map\n{}",
+        zone.domain,
+        conf.to_conf()
+    )
+}
+
+/// The [`NSSWITCH_LINES`] to merge into `/etc/nsswitch.conf`.
+pub fn nsswitch_conf(zone: &HesiodZone) -> String {
+    format!(
+        "# Merge into /etc/nsswitch.conf to resolve users and groups from {}\n{NSSWITCH_LINES}",
+        zone.domain
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::MapType;
+
+    #[test]
+    fn hesiod_conf_round_trips() {
+        let zone = HesiodZone::new("test.internal", "ns", ".test.internal", 300);
+        let text = hesiod_conf(&zone);
+        assert!(text.ends_with("lhs=.ns\nrhs=.test.internal\nclasses=IN,HS\n"));
+
+        let conf = HesiodConf::parse(&text).expect("TODO: handle error");
+        assert_eq!(
+            conf.query_name("alice", MapType::Passwd),
+            "alice.passwd.ns.test.internal"
+        );
+        assert!(nsswitch_conf(&zone).ends_with(NSSWITCH_LINES));
+    }
+}
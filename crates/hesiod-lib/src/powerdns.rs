@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: MPL-2.0
+//! PowerDNS remote backend (HTTP connector) endpoints, so a PowerDNS
+//! authoritative server can serve the zone straight from this daemon:
+//!
+//! ```text
+//! launch=remote
+//! remote-connection-string=http:url=http://hesiod-host:8080/dns/pdns
+//! ```
+//!
+//! PowerDNS only serves class IN, so clients must query IN (`classes=IN` in
+//! hesiod.conf). Records are answered from the live zone, with an SOA and NS
+//! at the origin; the SOA serial is the server's zone serial, the time of
+//! the last change. Maps with a `server.map_acl` are only served to
+//! the PowerDNS hosts it allows.
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use axum::routing::get;
+use hickory_proto::rr::Name;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::formats::quote_txt;
+use crate::health::{ClientAddr, map_allowed};
+use crate::idn::key_to_ascii;
+use crate::naming::from_bind_name;
+use crate::server::DnsServerState;
+use crate::zone::HesiodZone;
+
+/// Domain id reported for the single zone.
+const DOMAIN_ID: u32 = 1;
+
+/// One record in a `lookup` or `list` result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ResourceRecord {
+    qname: String,
+    qtype: &'static str,
+    content: String,
+    ttl: u32,
+    auth: bool,
+    domain_id: u32,
+}
+
+/// Routes for nesting under `/dns/pdns`. Methods not listed answer
+/// `{"result": false}`, which PowerDNS takes as "not supported".
+pub fn powerdns_router() -> Router<Arc<DnsServerState>> {
+    Router::new()
+        .route("/lookup/{qname}/{qtype}", get(lookup))
+        .route("/list/{id}/{zonename}", get(list))
+        .route("/getDomainInfo/{zonename}", get(domain_info))
+        .route("/getAllDomains", get(all_domains))
+        .route("/getAllDomainMetadata/{zonename}", get(all_metadata))
+        .route("/getDomainMetadata/{zonename}/{kind}", get(metadata))
+        .fallback(unsupported)
+}
+
+/// `GET /dns/pdns/lookup/<qname>/<qtype>` - Records at `qname`, `ANY` for all types.
+async fn lookup(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    Path((qname, qtype)): Path<(String, String)>,
+) -> (StatusCode, Json<Value>) {
+    if state.drain_expired() {
+        return draining();
+    }
+    state.query_count.inc();
+    let records: Vec<_> = records_at(&state, &qname, client)
+        .into_iter()
+        .filter(|rr| qtype.eq_ignore_ascii_case("ANY") || qtype.eq_ignore_ascii_case(rr.qtype))
+        .collect();
+    if records.is_empty() {
+        (StatusCode::OK, Json(json!({ "result": false })))
+    } else {
+        (StatusCode::OK, Json(json!({ "result": records })))
+    }
+}
+
+/// `GET /dns/pdns/list/<id>/<zonename>` - Every record of the zone, for AXFR.
+async fn list(
+    State(state): State<Arc<DnsServerState>>,
+    client: Option<ClientAddr>,
+    Path((_, zonename)): Path<(String, String)>,
+) -> (StatusCode, Json<Value>) {
+    if state.drain_expired() {
+        return draining();
+    }
+    let zone = state.zone();
+    if !is_origin(&zone, &zonename) {
+        return (StatusCode::OK, Json(json!({ "result": false })));
+    }
+    let mut records = apex_records(&zone, state.zone_serial());
+    for entry in zone.snapshot(None).records {
+        let map_type = entry.record.map_type();
+        if !map_allowed(&state, map_type, client) {
+            continue;
+        }
+        records.push(txt_record(
+            &zone,
+            format!(
+                "{}.{}{}.{}.",
+                key_to_ascii(&entry.name),
+                map_type.label(),
+                zone.lhs,
+                zone.origin()
+            ),
+            &entry.record.to_txt(),
+        ));
+    }
+    (StatusCode::OK, Json(json!({ "result": records })))
+}
+
+/// `GET /dns/pdns/getDomainInfo/<zonename>`
+async fn domain_info(
+    State(state): State<Arc<DnsServerState>>,
+    Path(zonename): Path<String>,
+) -> Json<Value> {
+    let zone = state.zone();
+    if !is_origin(&zone, &zonename) {
+        return Json(json!({ "result": false }));
+    }
+    Json(json!({ "result": domain(&zone, state.zone_serial()) }))
+}
+
+/// `GET /dns/pdns/getAllDomains`
+async fn all_domains(State(state): State<Arc<DnsServerState>>) -> Json<Value> {
+    Json(json!({ "result": [domain(&state.zone(), state.zone_serial())] }))
+}
+
+/// `GET /dns/pdns/getAllDomainMetadata/<zonename>` - None is kept.
+async fn all_metadata() -> Json<Value> {
+    Json(json!({ "result": {} }))
+}
+
+/// `GET /dns/pdns/getDomainMetadata/<zonename>/<kind>` - None is kept.
+async fn metadata() -> Json<Value> {
+    Json(json!({ "result": [] }))
+}
+
+async fn unsupported() -> Json<Value> {
+    Json(json!({ "result": false }))
+}
+
+/// A failed call, so PowerDNS answers SERVFAIL like the UDP server does.
+fn draining() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "result": false, "log": ["server is draining"] })),
+    )
+}
+
+/// Records of every type at `qname` for `client`, counting the map of
+/// Hesiod names.
+fn records_at(
+    state: &DnsServerState,
+    qname: &str,
+    client: Option<ClientAddr>,
+) -> Vec<ResourceRecord> {
+    let zone = &state.zone();
+    if is_origin(zone, qname) {
+        return apex_records(zone, state.zone_serial());
+    }
+    let Ok(name) = Name::from_ascii(qname) else {
+        return Vec::new();
+    };
+    let Some((key, map_type)) = from_bind_name(&name, &zone.lhs, &zone.rhs) else {
+        return Vec::new();
+    };
+    if !map_allowed(state, map_type, client) {
+        state.map_refusals(map_type).inc();
+        return Vec::new();
+    }
+    state.map_queries(map_type).inc();
+    zone.lookup(&key, map_type)
+        .map(|record| vec![txt_record(zone, absolute(qname), &record.to_txt())])
+        .unwrap_or_default()
+}
+
+/// SOA and NS records at the zone origin.
+fn apex_records(zone: &HesiodZone, serial: u64) -> Vec<ResourceRecord> {
+    let origin = format!("{}.", zone.origin());
+    let (mname, rname) = zone.soa_names();
+    let soa = format!(
+        "{mname} {rname} {} {} {} {} {}",
+        soa_serial(serial),
+        zone.soa.refresh,
+        zone.soa.retry,
+        zone.soa.expire,
+        zone.soa_minimum()
+    );
+    let nameservers = if zone.soa.nameservers.is_empty() {
+        vec![mname]
+    } else {
+        zone.soa.nameservers.iter().map(|ns| absolute(ns)).collect()
+    };
+    let mut records = vec![record(zone, origin.clone(), "SOA", soa)];
+    for ns in nameservers {
+        records.push(record(zone, origin.clone(), "NS", ns));
+    }
+    records
+}
+
+fn txt_record(zone: &HesiodZone, qname: String, txt: &str) -> ResourceRecord {
+    record(zone, qname, "TXT", quote_txt(txt))
+}
+
+fn record(
+    zone: &HesiodZone,
+    qname: String,
+    qtype: &'static str,
+    content: String,
+) -> ResourceRecord {
+    ResourceRecord {
+        qname,
+        qtype,
+        content,
+        ttl: zone.ttl,
+        auth: true,
+        domain_id: DOMAIN_ID,
+    }
+}
+
+fn domain(zone: &HesiodZone, serial: u64) -> Value {
+    json!({
+        "id": DOMAIN_ID,
+        "zone": format!("{}.", zone.origin()),
+        "kind": "native",
+        "serial": soa_serial(serial),
+    })
+}
+
+fn soa_serial(serial: u64) -> u32 {
+    u32::try_from(serial).unwrap_or(u32::MAX)
+}
+
+fn is_origin(zone: &HesiodZone, name: &str) -> bool {
+    name.trim_end_matches('.')
+        .eq_ignore_ascii_case(zone.origin())
+}
+
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::health::health_router;
+    use crate::records::{HesiodRecord, ServiceRecord};
+
+    async fn get(uri: &str) -> Value {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        zone.add_record(
+            "web",
+            HesiodRecord::Service(ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        let response = health_router(Arc::new(DnsServerState::new(zone)))
+            .oneshot(
+                Request::get(uri)
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("TODO: handle error");
+        serde_json::from_slice(&body).expect("TODO: handle error")
+    }
+
+    #[tokio::test]
+    async fn lookup_answers_txt_and_apex() {
+        let txt = get("/dns/pdns/lookup/web.service.ns.test.internal./ANY").await;
+        assert_eq!(txt["result"][0]["qtype"], "TXT");
+        assert_eq!(txt["result"][0]["content"], "\"web.svc:443:tcp\"");
+
+        let soa = get("/dns/pdns/lookup/test.internal./SOA").await;
+        assert_eq!(soa["result"].as_array().map(Vec::len), Some(1));
+        let content = soa["result"][0]["content"].as_str().unwrap_or_default();
+        let fields: Vec<_> = content.split(' ').collect();
+        assert_eq!(
+            fields[..2],
+            ["ns.test.internal.", "hostmaster.test.internal."]
+        );
+        assert_eq!(fields[3..], ["3600", "900", "604800", "300"]);
+        let serial: u32 = fields[2].parse().expect("TODO: handle error");
+        assert!(serial > 1_700_000_000, "serial {serial} is a Unix time");
+
+        let missing = get("/dns/pdns/lookup/nobody.passwd.ns.test.internal./ANY").await;
+        assert_eq!(missing["result"], false);
+        assert_eq!(get("/dns/pdns/initialize").await["result"], false);
+    }
+
+    #[tokio::test]
+    async fn list_covers_the_zone() {
+        let list = get("/dns/pdns/list/1/test.internal.").await;
+        let types: Vec<_> = list["result"]
+            .as_array()
+            .expect("TODO: handle error")
+            .iter()
+            .map(|rr| rr["qtype"].as_str().unwrap_or_default().to_string())
+            .collect();
+        assert_eq!(types, ["SOA", "NS", "TXT"]);
+        assert_eq!(list["result"][2]["qname"], "web.service.ns.test.internal.");
+        assert_eq!(
+            get("/dns/pdns/list/1/other.internal.").await["result"],
+            false
+        );
+    }
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MPL-2.0
+//! PROXY protocol v2 on the HTTP listener, for running behind an L4 load
+//! balancer. With `http.proxy_protocol` set, connections from the
+//! `http.proxy_trusted` networks must start with a v2 header, and the
+//! client address in it stands in for the connection's own wherever the
+//! HTTP server looks at the client:
+//!
+//! ```json
+//! "http": { "proxy_protocol": true, "proxy_trusted": ["10.0.0.0/24"] }
+//! ```
+//!
+//! Headers are only read from those networks. A client that reaches the
+//! listener directly keeps the address it connects from, so it can't claim
+//! to be loopback or sit inside an ACL; a header it sends is left in the
+//! stream, where it fails as HTTP. Connections from a trusted peer without
+//! a valid header are closed. `LOCAL` headers, sent by balancers for their
+//! own health checks, keep the connection's address.
+//!
+//! Only the HTTP listener reads these headers. PROXY protocol is usually
+//! put in front of DNS over TCP and DoT, but DNS is only served over UDP
+//! here, so there is no DNS listener for it: DNS map ACLs and query metrics
+//! keep seeing the datagram's source address.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::serve::Listener;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::config::Cidr;
+
+/// Time a client gets to send its header.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections past their header waiting for the HTTP server to pick them up.
+const PENDING_CONNECTIONS: usize = 64;
+
+/// First 12 bytes of every v2 header.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the v2 header at the start of `stream`, returning the client
+/// address it carries, or `None` for a `LOCAL` header or an address family
+/// other than TCP over IPv4 or IPv6.
+pub async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).await?;
+    if header[..12] != SIGNATURE || header[12] >> 4 != 2 {
+        return Err(invalid("not a PROXY protocol v2 header"));
+    }
+    let mut body = vec![0; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+    stream.read_exact(&mut body).await?;
+    match header[12] & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unknown PROXY protocol command")),
+    }
+    // Source address, destination address, source port, destination port,
+    // then any TLVs, which are skipped.
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    let client = match header[13] {
+        0x11 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            SocketAddr::new(ip.into(), port(8))
+        }
+        0x21 if body.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            SocketAddr::new(Ipv6Addr::from(octets).into(), port(32))
+        }
+        0x11 | 0x21 => return Err(invalid("truncated PROXY protocol addresses")),
+        _ => return Ok(None),
+    };
+    Ok(Some(client))
+}
+
+/// A listener for `axum::serve` yielding connections past their PROXY
+/// protocol header, with the client address it named, and connections from
+/// untrusted peers as they are. Headers are read in their own tasks, so a
+/// slow client doesn't hold up the others.
+pub struct ProxyListener {
+    local_addr: SocketAddr,
+    incoming: mpsc::Receiver<(TcpStream, SocketAddr)>,
+}
+
+impl ProxyListener {
+    /// Read headers on connections from the `trusted` networks, of which
+    /// there must be at least one.
+    pub fn new(listener: TcpListener, trusted: Vec<Cidr>) -> io::Result<Self> {
+        if trusted.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "http.proxy_protocol needs the balancers in http.proxy_trusted",
+            ));
+        }
+        let local_addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel(PENDING_CONNECTIONS);
+        tokio::spawn(accept_loop(listener, trusted, tx));
+        Ok(Self {
+            local_addr,
+            incoming,
+        })
+    }
+}
+
+impl Listener for ProxyListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(connection) => connection,
+            // The accept loop only stops once this receiver is gone.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Accept TCP connections until the [`ProxyListener`] is dropped.
+async fn accept_loop(
+    mut listener: TcpListener,
+    trusted: Vec<Cidr>,
+    tx: mpsc::Sender<(TcpStream, SocketAddr)>,
+) {
+    loop {
+        let (mut stream, peer) = tokio::select! {
+            accepted = Listener::accept(&mut listener) => accepted,
+            () = tx.closed() => return,
+        };
+        let proxied = trusted.iter().any(|net| net.contains(peer.ip()));
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if !proxied {
+                let _ = tx.send((stream, peer)).await;
+                return;
+            }
+            match tokio::time::timeout(HEADER_TIMEOUT, read_header(&mut stream)).await {
+                Ok(Ok(client)) => {
+                    let _ = tx.send((stream, client.unwrap_or(peer))).await;
+                }
+                Ok(Err(e)) => debug!("PROXY protocol header from {peer} rejected: {e}"),
+                Err(_) => debug!("PROXY protocol header from {peer} timed out"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    /// A v2 header with `command`, `family` and `addresses`.
+    fn header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+        header.extend([0x20 | command, family]);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn headers_name_the_client() {
+        let v4 = [192, 0, 2, 7, 198, 51, 100, 1, 0x30, 0x39, 0, 80, 0xee, 0, 0];
+        let mut read = &header(1, 0x11, &v4)[..];
+        let client = read_header(&mut read).await.expect("TODO: handle error");
+        assert_eq!(
+            client,
+            Some("192.0.2.7:12345".parse().expect("TODO: handle error"))
+        );
+
+        let mut v6 = [0u8; 36];
+        v6[..16].copy_from_slice(
+            &"2001:db8::1"
+                .parse::<Ipv6Addr>()
+                .expect("TODO: handle error")
+                .octets(),
+        );
+        v6[32..34].copy_from_slice(&443u16.to_be_bytes());
+        let mut read = &header(1, 0x21, &v6)[..];
+        let client = read_header(&mut read).await.expect("TODO: handle error");
+        assert_eq!(
+            client,
+            Some("[2001:db8::1]:443".parse().expect("TODO: handle error"))
+        );
+
+        let mut local = &header(0, 0, &[])[..];
+        assert_eq!(
+            read_header(&mut local).await.expect("TODO: handle error"),
+            None
+        );
+        for bad in [
+            b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+            header(1, 0x11, &v4[..8]),
+        ] {
+            assert!(read_header(&mut &bad[..]).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn listener_yields_the_proxied_address() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let trusted = vec!["127.0.0.0/8".parse().expect("TODO: handle error")];
+        let mut listener = ProxyListener::new(listener, trusted).expect("TODO: handle error");
+        let mut stream = TcpStream::connect(listener.local_addr)
+            .await
+            .expect("TODO: handle error");
+        let v4 = [192, 0, 2, 7, 127, 0, 0, 1, 0x30, 0x39, 0, 80];
+        stream
+            .write_all(&header(1, 0x11, &v4))
+            .await
+            .expect("TODO: handle error");
+        stream.write_all(b"ping").await.expect("TODO: handle error");
+
+        let (mut accepted, client) = listener.accept().await;
+        assert_eq!(
+            client,
+            "192.0.2.7:12345".parse().expect("TODO: handle error")
+        );
+        let mut payload = [0u8; 4];
+        accepted
+            .read_exact(&mut payload)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(&payload, b"ping");
+    }
+
+    #[tokio::test]
+    async fn untrusted_peers_keep_their_own_address() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let trusted = vec!["192.0.2.0/24".parse().expect("TODO: handle error")];
+        let mut listener = ProxyListener::new(listener, trusted).expect("TODO: handle error");
+        let mut stream = TcpStream::connect(listener.local_addr)
+            .await
+            .expect("TODO: handle error");
+        // A header claiming the connection comes from [::1]:12345.
+        let mut v6 = [0u8; 36];
+        v6[15] = 1;
+        v6[31] = 1;
+        v6[32..34].copy_from_slice(&12345u16.to_be_bytes());
+        let sent = header(1, 0x21, &v6);
+        stream.write_all(&sent).await.expect("TODO: handle error");
+
+        let (mut accepted, client) = listener.accept().await;
+        assert_eq!(client, stream.local_addr().expect("TODO: handle error"));
+        let mut received = vec![0; sent.len()];
+        accepted
+            .read_exact(&mut received)
+            .await
+            .expect("TODO: handle error");
+        assert_eq!(received, sent);
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        assert!(ProxyListener::new(listener, Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Per-client token-bucket rate limiting for the HTTP API, so a runaway
+//! dashboard or script polling `/dns/records` can't take CPU from the DNS
+//! workers. Enabled by `http.rate_limit`:
+//!
+//! ```json
+//! "http": { "rate_limit": { "read_per_second": 20, "read_burst": 40,
+//!                           "write_per_second": 1, "write_burst": 5 } }
+//! ```
+//!
+//! Clients are told apart by IP address, the one from the PROXY protocol
+//! header when `http.proxy_protocol` is on. A request over its client's
+//! limit gets `429 Too Many Requests` with a `Retry-After` header.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde_json::json;
+use tracing::debug;
+
+use crate::config::RateLimitConfig;
+use crate::health::ClientAddr;
+
+/// Client buckets kept before refilled ones are forgotten.
+const MAX_TRACKED: usize = 4096;
+
+/// Which allowance a request draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Read,
+    Write,
+}
+
+impl Class {
+    fn of(method: &Method) -> Self {
+        if method == Method::GET || method == Method::HEAD {
+            Self::Read
+        } else {
+            Self::Write
+        }
+    }
+}
+
+/// Sustained rate and burst size of one bucket.
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    per_second: f64,
+    burst: f64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Tokens available at `now`.
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst);
+        self.updated = now;
+    }
+}
+
+/// Token buckets by client address and request class.
+#[derive(Debug)]
+pub struct RateLimiter {
+    read: Limit,
+    write: Limit,
+    buckets: Mutex<HashMap<(IpAddr, Class), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let limit = |per_second: f64, burst: u32| Limit {
+            per_second: per_second.max(f64::MIN_POSITIVE),
+            burst: f64::from(burst.max(1)),
+        };
+        Self {
+            read: limit(config.read_per_second, config.read_burst),
+            write: limit(config.write_per_second, config.write_burst),
+            buckets: Mutex::default(),
+        }
+    }
+
+    fn limit(&self, class: Class) -> Limit {
+        match class {
+            Class::Read => self.read,
+            Class::Write => self.write,
+        }
+    }
+
+    /// Take a token for `client` at `now`, or say how long until one is free.
+    fn acquire(&self, client: IpAddr, class: Class, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(class);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED && !buckets.contains_key(&(client, class)) {
+            self.forget_full(&mut buckets, now);
+        }
+        let bucket = buckets.entry((client, class)).or_insert(Bucket {
+            tokens: limit.burst,
+            updated: now,
+        });
+        bucket.refill(limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.per_second,
+            ))
+        }
+    }
+
+    /// Drop buckets that have refilled, which behave the same as new ones.
+    fn forget_full(&self, buckets: &mut HashMap<(IpAddr, Class), Bucket>, now: Instant) {
+        buckets.retain(|(_, class), bucket| {
+            let limit = self.limit(*class);
+            bucket.refill(limit, now);
+            bucket.tokens < limit.burst
+        });
+    }
+}
+
+/// Middleware rejecting requests over their client's limit.
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(ClientAddr(client)): ConnectInfo<ClientAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let class = Class::of(request.method());
+    let client = client.ip().to_canonical();
+    match limiter.acquire(client, class, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            debug!(
+                "rate limiting {} {} from {}",
+                request.method(),
+                request.uri(),
+                client
+            );
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({ "error": "rate limit exceeded" })),
+            )
+                .into_response();
+            let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
+    use tower::ServiceExt;
+
+    use crate::health::health_router;
+    use crate::server::DnsServerState;
+    use crate::zone::HesiodZone;
+
+    fn limiter(read_burst: u32) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            read_per_second: 1.0,
+            read_burst,
+            write_per_second: 1.0,
+            write_burst: 1,
+        })
+    }
+
+    #[test]
+    fn bucket_refills_at_the_sustained_rate() {
+        let limiter = limiter(2);
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let start = Instant::now();
+        assert!(limiter.acquire(client, Class::Read, start).is_ok());
+        assert!(limiter.acquire(client, Class::Read, start).is_ok());
+        let wait = limiter
+            .acquire(client, Class::Read, start)
+            .expect_err("burst used up");
+        assert_eq!(wait, Duration::from_secs(1));
+
+        // Writes and other clients have their own buckets.
+        assert!(limiter.acquire(client, Class::Write, start).is_ok());
+        assert!(
+            limiter
+                .acquire(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), Class::Read, start)
+                .is_ok()
+        );
+
+        let later = start + Duration::from_millis(1500);
+        assert!(limiter.acquire(client, Class::Read, later).is_ok());
+        assert!(limiter.acquire(client, Class::Read, later).is_err());
+    }
+
+    #[test]
+    fn refilled_buckets_are_forgotten() {
+        let limiter = limiter(1);
+        let start = Instant::now();
+        for i in 0..MAX_TRACKED as u32 {
+            let client = IpAddr::V4(Ipv4Addr::from(i));
+            limiter
+                .acquire(client, Class::Read, start)
+                .expect("TODO: handle error");
+        }
+        let later = start + Duration::from_secs(2);
+        limiter
+            .acquire(IpAddr::V4(Ipv4Addr::LOCALHOST), Class::Read, later)
+            .expect("TODO: handle error");
+        assert_eq!(limiter.buckets.lock().expect("TODO: handle error").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn over_limit_requests_get_429() {
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        let client = SocketAddr::from((Ipv4Addr::new(192, 0, 2, 7), 40000));
+        let router = health_router(Arc::new(DnsServerState::new(zone)))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(limiter(1)),
+                limit,
+            ))
+            .layer(MockConnectInfo(ClientAddr(client)));
+        let get = || {
+            router.clone().oneshot(
+                Request::get("/dns/health")
+                    .body(Body::empty())
+                    .expect("TODO: handle error"),
+            )
+        };
+
+        let first = get().await.expect("TODO: handle error");
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = get().await.expect("TODO: handle error");
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(second.headers()[header::RETRY_AFTER], "1");
+    }
+}
@@ -4,11 +4,12 @@
 
 use std::fmt;
 
-use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 
+use crate::error::{HesiodError, Result};
+
 /// Map types corresponding to Hesiod naming conventions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MapType {
     Passwd,
@@ -18,6 +19,19 @@ pub enum MapType {
 }
 
 impl MapType {
+    /// Every map type, in declaration order.
+    pub const ALL: [MapType; 4] = [
+        MapType::Passwd,
+        MapType::Group,
+        MapType::Service,
+        MapType::Filsys,
+    ];
+
+    /// Position in [`MapType::ALL`], for per-map arrays.
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
     /// DNS label used in zone names (e.g. `admin.passwd.ns`).
     pub fn label(&self) -> &'static str {
         match self {
@@ -36,7 +50,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 }
 
 impl std::str::FromStr for MapType {
-    type Err = anyhow::Error;
+    type Err = HesiodError;
 
     fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
@@ -44,11 +58,29 @@ fn from_str(s: &str) -> Result<Self> {
             "group" => Ok(MapType::Group),
             "service" => Ok(MapType::Service),
             "filsys" => Ok(MapType::Filsys),
-            other => bail!("unknown map type: {other}"),
+            other => Err(HesiodError::config(format!("unknown map type: {other}"))),
         }
     }
 }
 
+/// Error for a record with the wrong number of fields.
+fn field_count(map: MapType, expected: usize, separator: &str, got: usize) -> HesiodError {
+    HesiodError::record_parse(
+        map,
+        "fields",
+        format!("expected {expected} {separator}-separated fields, got {got}"),
+    )
+}
+
+/// Parse one numeric field of a record.
+fn parse_field<T: std::str::FromStr>(map: MapType, field: &'static str, text: &str) -> Result<T>
+where
+    T::Err: fmt::Display,
+{
+    text.parse()
+        .map_err(|e| HesiodError::record_parse(map, field, format!("{e}")))
+}
+
 // ---------------------------------------------------------------------------
 // PasswdRecord
 // ---------------------------------------------------------------------------
@@ -75,16 +107,13 @@ pub fn to_txt(&self) -> String {
     pub fn from_txt(txt: &str) -> Result<Self> {
         let parts: Vec<&str> = txt.splitn(7, ':').collect();
         if parts.len() != 7 {
-            bail!(
-                "passwd record requires 7 colon-separated fields, got {}",
-                parts.len()
-            );
+            return Err(field_count(MapType::Passwd, 7, "colon", parts.len()));
         }
         Ok(Self {
             username: parts[0].to_string(),
             // parts[1] is the password placeholder (always "*")
-            uid: parts[2].parse().context("invalid uid")?,
-            gid: parts[3].parse().context("invalid gid")?,
+            uid: parse_field(MapType::Passwd, "uid", parts[2])?,
+            gid: parse_field(MapType::Passwd, "gid", parts[3])?,
             gecos: parts[4].to_string(),
             home: parts[5].to_string(),
             shell: parts[6].to_string(),
@@ -118,10 +147,7 @@ pub fn to_txt(&self) -> String {
     pub fn from_txt(txt: &str) -> Result<Self> {
         let parts: Vec<&str> = txt.splitn(4, ':').collect();
         if parts.len() != 4 {
-            bail!(
-                "group record requires 4 colon-separated fields, got {}",
-                parts.len()
-            );
+            return Err(field_count(MapType::Group, 4, "colon", parts.len()));
         }
         let members = if parts[3].is_empty() {
             Vec::new()
@@ -131,7 +157,7 @@ pub fn from_txt(txt: &str) -> Result<Self> {
         Ok(Self {
             name: parts[0].to_string(),
             // parts[1] is the password placeholder (always "*")
-            gid: parts[2].parse().context("invalid gid")?,
+            gid: parse_field(MapType::Group, "gid", parts[2])?,
             members,
         })
     }
@@ -163,14 +189,11 @@ pub fn to_txt(&self) -> String {
     pub fn from_txt(txt: &str) -> Result<Self> {
         let parts: Vec<&str> = txt.splitn(3, ':').collect();
         if parts.len() != 3 {
-            bail!(
-                "service record requires 3 colon-separated fields, got {}",
-                parts.len()
-            );
+            return Err(field_count(MapType::Service, 3, "colon", parts.len()));
         }
         Ok(Self {
             host: parts[0].to_string(),
-            port: parts[1].parse().context("invalid port")?,
+            port: parse_field(MapType::Service, "port", parts[1])?,
             protocol: parts[2].to_string(),
         })
     }
@@ -206,10 +229,7 @@ pub fn to_txt(&self) -> String {
     pub fn from_txt(txt: &str) -> Result<Self> {
         let parts: Vec<&str> = txt.splitn(4, ' ').collect();
         if parts.len() != 4 {
-            bail!(
-                "filsys record requires 4 space-separated fields, got {}",
-                parts.len()
-            );
+            return Err(field_count(MapType::Filsys, 4, "space", parts.len()));
         }
         Ok(Self {
             fs_type: parts[0].to_string(),
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Ephemeral service registrations announced over `POST /dns/register`.
+//!
+//! A service posts its name and location with a TTL and is answered as a
+//! Hesiod service record until the TTL runs out, so it re-registers before
+//! then to stay listed:
+//!
+//! ```json
+//! { "name": "web", "host": "10.0.0.5", "port": 8443, "ttl": 60 }
+//! ```
+//!
+//! Registrations live in memory only and never shadow a service record
+//! from the config. A TTL of 0 withdraws the registration.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::records::ServiceRecord;
+
+/// Longest TTL a registration may ask for.
+pub const MAX_TTL: Duration = Duration::from_secs(86_400);
+
+#[derive(Debug, Clone)]
+struct Registration {
+    record: ServiceRecord,
+    expires: Instant,
+}
+
+/// A live registration, as listed by `GET /dns/register`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegisteredService {
+    pub domain: String,
+    pub name: String,
+    pub record: ServiceRecord,
+    pub expires_in_seconds: u64,
+}
+
+/// What [`ServiceRegistry::register`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registered {
+    /// The name was not registered before.
+    Added,
+    /// An existing registration was renewed or moved.
+    Renewed,
+    /// A TTL of 0 removed the registration (or there was none).
+    Withdrawn,
+}
+
+/// Registered services by lowercased zone domain and service name.
+#[derive(Debug, Default)]
+pub struct ServiceRegistry {
+    services: RwLock<HashMap<(String, String), Registration>>,
+}
+
+impl ServiceRegistry {
+    /// Serve `record` as `name` in the zone for `domain` for `ttl` from
+    /// `now`, capped at [`MAX_TTL`]. Expired registrations are dropped.
+    pub fn register(
+        &self,
+        domain: &str,
+        name: &str,
+        record: ServiceRecord,
+        ttl: Duration,
+        now: Instant,
+    ) -> Registered {
+        let mut services = self.services.write().unwrap_or_else(|e| e.into_inner());
+        services.retain(|_, registration| registration.expires > now);
+        let key = (domain.to_ascii_lowercase(), name.to_string());
+        if ttl.is_zero() {
+            services.remove(&key);
+            return Registered::Withdrawn;
+        }
+        let registration = Registration {
+            record,
+            expires: now + ttl.min(MAX_TTL),
+        };
+        match services.insert(key, registration) {
+            Some(_) => Registered::Renewed,
+            None => Registered::Added,
+        }
+    }
+
+    /// The record registered as `name` in `domain` at `now`, with the time
+    /// it has left.
+    pub fn lookup(
+        &self,
+        domain: &str,
+        name: &str,
+        now: Instant,
+    ) -> Option<(ServiceRecord, Duration)> {
+        let services = self.services.read().unwrap_or_else(|e| e.into_inner());
+        let registration = services.get(&(domain.to_ascii_lowercase(), name.to_string()))?;
+        let left = registration.expires.checked_duration_since(now)?;
+        (!left.is_zero()).then(|| (registration.record.clone(), left))
+    }
+
+    /// Every registration live at `now`, sorted by domain and name.
+    pub fn services(&self, now: Instant) -> Vec<RegisteredService> {
+        let services = self.services.read().unwrap_or_else(|e| e.into_inner());
+        let mut live: Vec<_> = services
+            .iter()
+            .filter(|(_, registration)| registration.expires > now)
+            .map(|((domain, name), registration)| RegisteredService {
+                domain: domain.clone(),
+                name: name.clone(),
+                record: registration.record.clone(),
+                expires_in_seconds: (registration.expires - now).as_secs(),
+            })
+            .collect();
+        live.sort_by(|a, b| (&a.domain, &a.name).cmp(&(&b.domain, &b.name)));
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn web() -> ServiceRecord {
+        ServiceRecord {
+            host: "10.0.0.5".into(),
+            port: 8443,
+            protocol: "tcp".into(),
+        }
+    }
+
+    #[test]
+    fn registrations_expire_without_renewal() {
+        let registry = ServiceRegistry::default();
+        let start = Instant::now();
+        let ttl = Duration::from_secs(30);
+        assert_eq!(
+            registry.register("Lab.Internal", "web", web(), ttl, start),
+            Registered::Added
+        );
+
+        let (record, left) = registry
+            .lookup("lab.internal", "web", start + Duration::from_secs(10))
+            .expect("TODO: handle error");
+        assert_eq!(record, web());
+        assert_eq!(left, Duration::from_secs(20));
+        assert!(registry.lookup("lab.internal", "Web", start).is_none());
+
+        let renewed = start + Duration::from_secs(20);
+        assert_eq!(
+            registry.register("lab.internal", "web", web(), ttl, renewed),
+            Registered::Renewed
+        );
+        assert!(
+            registry
+                .lookup("lab.internal", "web", start + Duration::from_secs(45))
+                .is_some()
+        );
+        assert!(
+            registry
+                .lookup("lab.internal", "web", start + Duration::from_secs(50))
+                .is_none()
+        );
+        assert!(
+            registry
+                .services(start + Duration::from_secs(50))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn zero_ttl_withdraws() {
+        let registry = ServiceRegistry::default();
+        let now = Instant::now();
+        registry.register("lab.internal", "web", web(), MAX_TTL * 2, now);
+        assert_eq!(
+            registry.services(now)[0].expires_in_seconds,
+            MAX_TTL.as_secs()
+        );
+        assert_eq!(
+            registry.register("lab.internal", "web", web(), Duration::ZERO, now),
+            Registered::Withdrawn
+        );
+        assert!(registry.lookup("lab.internal", "web", now).is_none());
+    }
+}
@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: MPL-2.0
+//! PTR reverse zones for service hosts that have an `address` in the config,
+//! generated alongside the forward Hesiod zone so the two stay in sync.
+//!
+//! IPv4 addresses are grouped into `/24` zones (`2.0.192.in-addr.arpa`) and
+//! IPv6 addresses into `/64` zones under `ip6.arpa`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+use crate::error::Result;
+
+use crate::config::HesiodConfig;
+use crate::zone::{HesiodZone, absolute};
+
+/// Reverse zone and owner label (relative to it) of `address`.
+pub fn reverse_name(address: IpAddr) -> (String, String) {
+    match address {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            (format!("{c}.{b}.{a}.in-addr.arpa"), d.to_string())
+        }
+        IpAddr::V6(v6) => {
+            let nibbles: Vec<String> = v6
+                .octets()
+                .iter()
+                .flat_map(|byte| [byte >> 4, byte & 0xf])
+                .rev()
+                .map(|nibble| format!("{nibble:x}"))
+                .collect();
+            let (host, network) = nibbles.split_at(16);
+            (format!("{}.ip6.arpa", network.join(".")), host.join("."))
+        }
+    }
+}
+
+/// Reverse zone files for every service with an address, as
+/// `(zone name, contents)` sorted by zone name. Each shares the forward
+/// zone's SOA settings and `serial`.
+pub fn reverse_zone_files(config: &HesiodConfig, serial: u32) -> Result<Vec<(String, String)>> {
+    let forward = HesiodZone::from_config(config)?;
+
+    let mut zones: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+    for service in &config.services {
+        let Some(address) = service.address else {
+            continue;
+        };
+        let (zone, label) = reverse_name(address);
+        zones
+            .entry(zone)
+            .or_default()
+            .insert((label, absolute(Some(&service.host), "")));
+    }
+
+    Ok(zones
+        .into_iter()
+        .map(|(zone, ptrs)| {
+            let mut out = format!(
+                "; Reverse zone for service hosts of {}\n; Generated by hesiod-dns-map\n\n",
+                forward.domain
+            );
+            out.push_str(&forward.soa_header(&zone, "IN", serial));
+            for (label, host) in ptrs {
+                out.push_str(&format!("{label}\tIN PTR {host}\n"));
+            }
+            (zone, out)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceEntry;
+
+    fn service(name: &str, host: &str, address: Option<&str>) -> ServiceEntry {
+        ServiceEntry {
+            name: name.into(),
+            host: host.into(),
+            port: 443,
+            protocol: "tcp".into(),
+            address: address.map(|a| a.parse().expect("TODO: handle error")),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reverse_names() {
+        assert_eq!(
+            reverse_name("192.0.2.10".parse().expect("TODO: handle error")),
+            ("2.0.192.in-addr.arpa".into(), "10".into())
+        );
+        let (zone, label) = reverse_name("2001:db8::1".parse().expect("TODO: handle error"));
+        assert_eq!(zone, "0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+        assert_eq!(label, "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0");
+    }
+
+    #[test]
+    fn groups_ptrs_by_network() {
+        let mut config = HesiodConfig::new("test.internal", ".ns", ".test.internal");
+        config.services = vec![
+            service("web", "web.test.internal", Some("192.0.2.10")),
+            service("https", "web.test.internal", Some("192.0.2.10")),
+            service("db", "db.test.internal.", Some("192.0.2.20")),
+            service("api", "api.test.internal", None),
+        ];
+
+        let files = reverse_zone_files(&config, 9).expect("TODO: handle error");
+        assert_eq!(files.len(), 1);
+        let (zone, content) = &files[0];
+        assert_eq!(zone, "2.0.192.in-addr.arpa");
+        assert!(content.contains("$ORIGIN 2.0.192.in-addr.arpa.\n"));
+        assert!(content.contains("@ IN SOA ns.test.internal. hostmaster.test.internal. ("));
+        assert!(content.ends_with("10\tIN PTR web.test.internal.\n20\tIN PTR db.test.internal.\n"));
+    }
+}
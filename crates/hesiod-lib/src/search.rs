@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Record search behind `GET /dns/records/search`, for finding entries in
+//! large zones without paging through `/dns/records`.
+//!
+//! A pattern is a shell-style glob (`*` for any run of characters, `?` for
+//! one) or, with `regex=true`, a regular expression. Either must match a
+//! whole string: the record's key or one of its fields, such as a service
+//! host, a home directory or a single group member.
+
+use std::borrow::Cow;
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::error::{Context, HesiodError, Result};
+use crate::records::{HesiodRecord, MapType};
+use crate::zone::HesiodZone;
+
+/// Compiled size a regex may grow to, so a request can't ask for a huge
+/// automaton.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// A compiled glob or anchored regex.
+#[derive(Debug, Clone)]
+pub struct RecordPattern {
+    regex: Regex,
+}
+
+impl RecordPattern {
+    /// A shell-style glob: `*` matches any run, `?` one character.
+    pub fn glob(pattern: &str) -> Result<Self> {
+        let mut regex = String::with_capacity(pattern.len() + 8);
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        Self::regex(&regex)
+    }
+
+    /// A regular expression, anchored at both ends.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        if pattern.is_empty() {
+            return Err(HesiodError::config("empty search pattern"));
+        }
+        let regex = RegexBuilder::new(&format!("^(?:{pattern})$"))
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .config_err(|| format!("invalid pattern {pattern:?}"))?;
+        Ok(Self { regex })
+    }
+
+    /// Whether the pattern matches `name` or any field of `record`.
+    pub fn matches(&self, name: &str, record: &HesiodRecord) -> bool {
+        self.regex.is_match(name)
+            || fields(record)
+                .iter()
+                .any(|field| self.regex.is_match(field))
+    }
+}
+
+/// The searchable fields of a record.
+fn fields(record: &HesiodRecord) -> Vec<Cow<'_, str>> {
+    match record {
+        HesiodRecord::Passwd(r) => vec![
+            r.username.as_str().into(),
+            r.uid.to_string().into(),
+            r.gid.to_string().into(),
+            r.gecos.as_str().into(),
+            r.home.as_str().into(),
+            r.shell.as_str().into(),
+        ],
+        HesiodRecord::Group(r) => {
+            let mut fields = vec![r.name.as_str().into(), r.gid.to_string().into()];
+            fields.extend(r.members.iter().map(|m| Cow::from(m.as_str())));
+            fields
+        }
+        HesiodRecord::Service(r) => vec![
+            r.host.as_str().into(),
+            r.port.to_string().into(),
+            r.protocol.as_str().into(),
+        ],
+        HesiodRecord::Filsys(r) => vec![
+            r.fs_type.as_str().into(),
+            r.mount_path.as_str().into(),
+            r.source.as_str().into(),
+            r.mode.as_str().into(),
+        ],
+    }
+}
+
+/// One record found by [`search`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub name: String,
+    pub map: MapType,
+    pub txt: String,
+    pub record: HesiodRecord,
+}
+
+/// Records matching a pattern, sorted by map type then name.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResults {
+    /// Every matching record, even those past the limit.
+    pub total: usize,
+    /// The first `limit` matches.
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Records in `zone`, in any of `maps`, that `pattern` matches.
+pub fn search(
+    zone: &HesiodZone,
+    pattern: &RecordPattern,
+    maps: &[MapType],
+    limit: usize,
+) -> SearchResults {
+    let mut found: Vec<_> = zone
+        .records()
+        .filter(|(_, record)| maps.contains(&record.map_type()))
+        .filter(|(name, record)| pattern.matches(name, record))
+        .collect();
+    found.sort_by(|(a_name, a), (b_name, b)| (a.map_type(), a_name).cmp(&(b.map_type(), b_name)));
+    let total = found.len();
+    let matches = found
+        .into_iter()
+        .take(limit)
+        .map(|(name, record)| SearchMatch {
+            name: name.to_string(),
+            map: record.map_type(),
+            txt: record.to_txt(),
+            record: record.clone(),
+        })
+        .collect();
+    SearchResults { total, matches }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::{GroupRecord, ServiceRecord};
+
+    fn zone() -> HesiodZone {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        for (name, host) in [("web", "web1.svc"), ("web-2", "web2.svc"), ("db", "db.svc")] {
+            zone.add_record(
+                name,
+                HesiodRecord::Service(ServiceRecord {
+                    host: host.into(),
+                    port: 443,
+                    protocol: "tcp".into(),
+                }),
+            );
+        }
+        zone.add_record(
+            "webadmins",
+            HesiodRecord::Group(GroupRecord {
+                name: "webadmins".into(),
+                gid: 2000,
+                members: vec!["alice".into(), "bob".into()],
+            }),
+        );
+        zone
+    }
+
+    fn names(results: &SearchResults) -> Vec<&str> {
+        results.matches.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn glob_matches_keys_and_fields() {
+        let zone = zone();
+        let web = RecordPattern::glob("web*").expect("TODO: handle error");
+        assert_eq!(
+            names(&search(&zone, &web, &MapType::ALL, 10)),
+            ["webadmins", "web", "web-2"]
+        );
+        assert_eq!(
+            names(&search(&zone, &web, &[MapType::Service], 10)),
+            ["web", "web-2"]
+        );
+
+        let member = RecordPattern::glob("bo?").expect("TODO: handle error");
+        assert_eq!(
+            names(&search(&zone, &member, &MapType::ALL, 10)),
+            ["webadmins"]
+        );
+        let host = RecordPattern::glob("db.svc").expect("TODO: handle error");
+        assert_eq!(names(&search(&zone, &host, &MapType::ALL, 10)), ["db"]);
+        // Globs match whole strings and `.` is literal.
+        let partial = RecordPattern::glob("db.s").expect("TODO: handle error");
+        assert_eq!(search(&zone, &partial, &MapType::ALL, 10).total, 0);
+    }
+
+    #[test]
+    fn regex_is_anchored() {
+        let zone = zone();
+        let pattern = RecordPattern::regex("web(-[0-9]+)?").expect("TODO: handle error");
+        assert_eq!(
+            names(&search(&zone, &pattern, &MapType::ALL, 10)),
+            ["web", "web-2"]
+        );
+        let unanchored = RecordPattern::regex("eb").expect("TODO: handle error");
+        assert_eq!(search(&zone, &unanchored, &MapType::ALL, 10).total, 0);
+        assert!(RecordPattern::regex("(").is_err());
+        assert!(RecordPattern::glob("").is_err());
+    }
+
+    #[test]
+    fn limit_keeps_total() {
+        let zone = zone();
+        let all = RecordPattern::glob("*").expect("TODO: handle error");
+        let results = search(&zone, &all, &MapType::ALL, 2);
+        assert_eq!(results.total, 4);
+        assert_eq!(results.matches.len(), 2);
+    }
+}
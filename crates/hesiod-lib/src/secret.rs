@@ -0,0 +1,390 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Credentials in the config (admin tokens, push and webhook URLs), given
+//! inline or as a reference, so the JSON and wherever it is kept needn't
+//! hold them in plaintext:
+//!
+//! - `{"env": "HESIOD_ADMIN_TOKEN"}`: an environment variable
+//! - `{"file": "/run/secrets/admin-token"}`: a file, such as a mounted
+//!   container secret
+//! - `{"age": "-----BEGIN AGE ENCRYPTED FILE-----\n..."}`: an armored age
+//!   ciphertext, decrypted by `age` with the identity file named by
+//!   `HESIOD_AGE_IDENTITY`
+//! - `{"sops": {"file": "secrets.enc.json", "key": "admin.token"}}`: a
+//!   value in a SOPS-encrypted file, decrypted by `sops` with whatever keys
+//!   it is set up with
+//!
+//! Parsing a config leaves references unresolved, so linting or generating
+//! from it needs none of the credentials; the server resolves them with
+//! [`Secret::resolve`] when it loads the config, and an unresolved secret
+//! exposes nothing. Values read from files or decrypted lose their trailing
+//! newline. They are left out of `Debug` output, and a config serialized
+//! again keeps the reference, not the value. URLs kept as secrets are
+//! logged through [`redact_url`].
+
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Context, HesiodError, IoContext, Result};
+
+/// Environment variable naming the age identity file secrets are decrypted
+/// with.
+pub const AGE_IDENTITY_VAR: &str = "HESIOD_AGE_IDENTITY";
+
+/// A credential from the config.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SecretSpec", into = "SecretSpec")]
+pub struct Secret {
+    spec: SecretSpec,
+    value: Option<String>,
+}
+
+/// How a secret is written in the config.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SecretSpec {
+    Inline(String),
+    Reference(SecretRef),
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+enum SecretRef {
+    Env(String),
+    File(PathBuf),
+    Age(String),
+    Sops { file: PathBuf, key: String },
+}
+
+impl Secret {
+    /// The secret's value, or `None` while a reference is unresolved.
+    pub fn expose(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// Resolve a reference: read the variable or file, or decrypt it.
+    /// Blocking, as it may run `age` or `sops`; inline and already resolved
+    /// secrets are left as they are.
+    pub fn resolve(&mut self) -> Result<()> {
+        self.resolve_with(&Decrypters::default())
+    }
+
+    fn resolve_with(&mut self, decrypters: &Decrypters) -> Result<()> {
+        if let (SecretSpec::Reference(reference), None) = (&self.spec, &self.value) {
+            self.value = Some(reference.resolve(decrypters)?);
+        }
+        Ok(())
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self {
+            spec: SecretSpec::Inline(value.clone()),
+            value: Some(value),
+        }
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl From<SecretSpec> for Secret {
+    fn from(spec: SecretSpec) -> Self {
+        let value = match &spec {
+            SecretSpec::Inline(value) => Some(value.clone()),
+            SecretSpec::Reference(_) => None,
+        };
+        Self { spec, value }
+    }
+}
+
+impl From<Secret> for SecretSpec {
+    fn from(secret: Secret) -> Self {
+        secret.spec
+    }
+}
+
+/// `url` cut down to `scheme://host[:port]` for logs, as push and webhook
+/// URLs tend to carry tokens in their userinfo, path or query.
+pub fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return "<redacted>".to_string();
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    format!("{scheme}://{host}")
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.spec {
+            SecretSpec::Inline(_) => f.write_str("Secret(inline)"),
+            SecretSpec::Reference(SecretRef::Env(name)) => write!(f, "Secret(env {name})"),
+            SecretSpec::Reference(SecretRef::File(path)) => {
+                write!(f, "Secret(file {})", path.display())
+            }
+            SecretSpec::Reference(SecretRef::Age(_)) => f.write_str("Secret(age)"),
+            SecretSpec::Reference(SecretRef::Sops { file, key }) => {
+                write!(f, "Secret(sops {} {key})", file.display())
+            }
+        }
+    }
+}
+
+/// The programs referenced secrets are decrypted with, and the age identity
+/// file.
+struct Decrypters {
+    age: String,
+    sops: String,
+    age_identity: Option<String>,
+}
+
+impl Default for Decrypters {
+    fn default() -> Self {
+        Self {
+            age: "age".to_string(),
+            sops: "sops".to_string(),
+            age_identity: std::env::var(AGE_IDENTITY_VAR).ok(),
+        }
+    }
+}
+
+impl SecretRef {
+    fn resolve(&self, decrypters: &Decrypters) -> Result<String> {
+        let value = match self {
+            SecretRef::Env(name) => {
+                return std::env::var(name)
+                    .config_err(|| format!("reading secret from environment variable {name}"));
+            }
+            SecretRef::File(path) => std::fs::read_to_string(path)
+                .io_err(|| format!("reading secret from {}", path.display()))?,
+            SecretRef::Age(ciphertext) => {
+                let identity = decrypters.age_identity.as_deref().ok_or_else(|| {
+                    HesiodError::config(format!(
+                        "{AGE_IDENTITY_VAR} must name an age identity file to decrypt secrets"
+                    ))
+                })?;
+                let args = ["--decrypt", "--identity", identity];
+                run(&decrypters.age, &args, Some(ciphertext))
+                    .config_err(|| "decrypting age secret")?
+            }
+            SecretRef::Sops { file, key } => {
+                let path: String = key.split('.').map(|part| format!("[{part:?}]")).collect();
+                let file_arg = file.to_string_lossy();
+                let args = ["--decrypt", "--extract", &path, &file_arg];
+                run(&decrypters.sops, &args, None)
+                    .config_err(|| format!("decrypting {key} from {}", file.display()))?
+            }
+        };
+        Ok(value.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// Run `program` with `args`, feeding it `input`, and return its standard
+/// output.
+fn run(program: &str, args: &[&str], input: Option<&str>) -> Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .io_err(|| format!("running {program}"))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .io_err(|| format!("writing to {program}"))?;
+    }
+    let output = child
+        .wait_with_output()
+        .io_err(|| format!("running {program}"))?;
+    if !output.status.success() {
+        return Err(HesiodError::config(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout).config_err(|| format!("{program} output is not UTF-8"))
+}
+
+/// Log lines written on this thread while the guard is held, for checking
+/// that secrets stay out of the logs.
+#[cfg(test)]
+pub(crate) fn capture_logs() -> (
+    std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    tracing::subscriber::DefaultGuard,
+) {
+    use std::sync::{Arc, Mutex};
+
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut logs = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            logs.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let writer = Arc::clone(&logs);
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || Capture(Arc::clone(&writer)))
+        .finish();
+    (logs, tracing::subscriber::set_default(subscriber))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> std::result::Result<Secret, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    fn resolved(json: &str) -> Result<String> {
+        let mut secret = parse(json).expect("TODO: handle error");
+        secret.resolve()?;
+        Ok(secret.expose().expect("TODO: handle error").to_string())
+    }
+
+    #[test]
+    fn inline_and_env_secrets() {
+        let inline = parse(r#""s3cret""#).expect("TODO: handle error");
+        assert_eq!(inline.expose(), Some("s3cret"));
+        assert_eq!(format!("{inline:?}"), "Secret(inline)");
+
+        let path = std::env::var("PATH").expect("TODO: handle error");
+        let mut secret = parse(r#"{"env": "PATH"}"#).expect("TODO: handle error");
+        assert_eq!(secret.expose(), None);
+        secret.resolve().expect("TODO: handle error");
+        assert_eq!(secret.expose(), Some(path.as_str()));
+        assert_eq!(format!("{secret:?}"), "Secret(env PATH)");
+        assert_eq!(
+            serde_json::to_string(&secret).expect("TODO: handle error"),
+            r#"{"env":"PATH"}"#
+        );
+
+        // Parsing never reads the reference; resolving does.
+        let err = resolved(r#"{"env": "HESIOD_TEST_SECRET_UNSET"}"#).expect_err("unset");
+        assert!(err.to_string().contains("HESIOD_TEST_SECRET_UNSET"));
+    }
+
+    #[test]
+    fn urls_are_redacted_to_scheme_and_host() {
+        assert_eq!(
+            redact_url("https://user:pw@hooks.example.com:8443/t/abc?token=x#y"),
+            "https://hooks.example.com:8443"
+        );
+        assert_eq!(
+            redact_url("http://push.example.com?k=1"),
+            "http://push.example.com"
+        );
+        assert_eq!(redact_url("not a url s3cret"), "<redacted>");
+    }
+
+    #[test]
+    fn file_secrets_drop_the_trailing_newline() {
+        let path = std::env::temp_dir().join(format!("hesiod-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").expect("TODO: handle error");
+        let json = serde_json::json!({ "file": path });
+        let mut secret: Secret = serde_json::from_value(json.clone()).expect("TODO: handle error");
+        secret.resolve().expect("TODO: handle error");
+        assert_eq!(secret.expose(), Some("from-file"));
+        assert_eq!(
+            serde_json::to_value(&secret).expect("TODO: handle error"),
+            json
+        );
+        std::fs::remove_file(&path).ok();
+
+        let mut missing: Secret = serde_json::from_value(json).expect("TODO: handle error");
+        assert!(missing.resolve().is_err());
+        assert!(parse(r#"{"vault": "kv/hesiod"}"#).is_err());
+    }
+
+    /// A directory holding fake `age` and `sops` scripts that echo their
+    /// arguments and input back, so the tests needn't have either installed.
+    #[cfg(unix)]
+    fn fake_decrypters(name: &str) -> (PathBuf, Decrypters) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("hesiod-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("TODO: handle error");
+        let scripts = [
+            ("age", "#!/bin/sh\nprintf '%s|' \"$@\"\ncat\n"),
+            ("sops", "#!/bin/sh\nprintf '%s|' \"$@\"\necho\n"),
+        ];
+        for (program, script) in scripts {
+            let path = dir.join(program);
+            std::fs::write(&path, script).expect("TODO: handle error");
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .expect("TODO: handle error");
+        }
+        let decrypters = Decrypters {
+            age: dir.join("age").to_string_lossy().into_owned(),
+            sops: dir.join("sops").to_string_lossy().into_owned(),
+            age_identity: Some("/keys/age.txt".to_string()),
+        };
+        (dir, decrypters)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn age_secrets_are_decrypted_with_the_identity() {
+        let (dir, mut decrypters) = fake_decrypters("age");
+        let mut secret = parse(r#"{"age": "ciphertext\n"}"#).expect("TODO: handle error");
+        assert_eq!(format!("{secret:?}"), "Secret(age)");
+        secret
+            .resolve_with(&decrypters)
+            .expect("TODO: handle error");
+        assert_eq!(
+            secret.expose(),
+            Some("--decrypt|--identity|/keys/age.txt|ciphertext")
+        );
+
+        decrypters.age_identity = None;
+        let mut secret = parse(r#"{"age": "ciphertext"}"#).expect("TODO: handle error");
+        let err = secret.resolve_with(&decrypters).expect_err("no identity");
+        assert!(err.to_string().contains(AGE_IDENTITY_VAR));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sops_secrets_extract_the_key() {
+        let (dir, mut decrypters) = fake_decrypters("sops");
+        let json = r#"{"sops": {"file": "secrets.enc.json", "key": "admin.token"}}"#;
+        let mut secret = parse(json).expect("TODO: handle error");
+        assert_eq!(
+            format!("{secret:?}"),
+            "Secret(sops secrets.enc.json admin.token)"
+        );
+        secret
+            .resolve_with(&decrypters)
+            .expect("TODO: handle error");
+        assert_eq!(
+            secret.expose(),
+            Some(r#"--decrypt|--extract|["admin"]["token"]|secrets.enc.json|"#)
+        );
+
+        decrypters.sops = dir.join("missing").to_string_lossy().into_owned();
+        let mut secret = parse(json).expect("TODO: handle error");
+        assert!(secret.resolve_with(&decrypters).is_err());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
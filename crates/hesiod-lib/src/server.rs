@@ -1,83 +1,735 @@
 // SPDX-License-Identifier: MPL-2.0
-//! UDP DNS server handling HS-class TXT queries using hickory-proto.
+//! UDP DNS server handling HS-class TXT queries using hickory-proto. A
+//! reader task queues datagrams for a pool of workers that answer them.
 
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use hickory_proto::op::{Header, Message, OpCode, ResponseCode};
-use hickory_proto::rr::rdata::TXT;
+use bytes::Bytes;
+use hickory_proto::op::{Header, Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_proto::rr::rdata::{SOA, TXT};
 use hickory_proto::rr::record_data::RData;
 use hickory_proto::rr::{DNSClass, Name, Record, RecordType};
+use hickory_proto::serialize::binary::{BinDecodable, BinDecoder, BinEncodable};
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, watch};
+use tokio::task::{JoinHandle, JoinSet};
 use tracing::{debug, error, info, warn};
 
+use crate::answer_cache::{AnswerCache, CachedResponse};
+use crate::audit::AuditLog;
+use crate::config::{AdminConfig, MultiQuestionPolicy, OverflowPolicy, ServerConfig};
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::metrics::{
+    ErrorCounters, MetricsHistory, RecentQueries, RecentQuery, ShardedCounter, ZoneCounters,
+};
+use crate::notify::Notifier;
 use crate::records::MapType;
-use crate::zone::HesiodZone;
+use crate::registry::ServiceRegistry;
+use crate::source::{ConfigSource, SyncStatus};
+use crate::zone::{HesiodZone, ZoneSet};
 
 /// DNS class value for Hesiod (HS = 4).
 const DNS_CLASS_HS: u16 = 4;
 
+/// Largest datagram accepted; anything bigger is counted and dropped.
+const MAX_DATAGRAM: usize = 4096;
+
+/// Largest query [`handle_query`] parses. A question with EDNS options and
+/// padding fits in a fraction of this.
+const MAX_QUERY: usize = 1232;
+
+/// Most questions [`handle_query`] answers in one query; resolvers send one.
+const MAX_QUESTIONS: u16 = 8;
+
+/// A receive loop running this long before failing counts as healthy, so
+/// its failure starts the restart count afresh.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// How often the receive loop is restarted after a fatal socket error,
+/// waiting an exponentially growing backoff before each restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Consecutive restarts before the error is returned; 0 never restarts.
+    pub restarts: u32,
+    /// Backoff before the first restart, doubled for each further one.
+    pub initial_backoff: Duration,
+    /// Cap on the backoff.
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            restarts: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Up to `restarts` consecutive restarts with the default backoff.
+    pub fn restarts(restarts: u32) -> Self {
+        Self {
+            restarts,
+            ..Self::default()
+        }
+    }
+
+    /// Wait before restart `attempt` (1 for the first).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff)
+    }
+}
+
 /// Shared server state.
 pub struct DnsServerState {
-    pub zone: HesiodZone,
-    pub query_count: std::sync::atomic::AtomicU64,
-    pub start_time: std::time::Instant,
+    /// Zones currently being served; swapped wholesale on reload.
+    zones: RwLock<Arc<ZoneSet>>,
+    /// SOA serial, moved on by [`serial_after`] each time a new zone is
+    /// swapped in.
+    zone_serial: AtomicU64,
+    pub notifier: Notifier,
+    /// Where reloads fetch the config from, if anywhere.
+    pub source: Option<ConfigSource>,
+    sync: Mutex<SyncStatus>,
+    pub query_count: ShardedCounter,
+    /// Queries per map type, indexed by [`MapType::ALL`] order.
+    pub map_query_counts: [ShardedCounter; 4],
+    /// Queries refused by `server.map_acl`, per map type in the same order.
+    pub map_refusal_counts: [ShardedCounter; 4],
+    /// Per-zone query counters by lowercased domain, created on first use.
+    zone_counters: RwLock<HashMap<String, Arc<ZoneCounters>>>,
+    pub errors: ErrorCounters,
+    /// Query rate samples for the dashboard graph.
+    pub history: MetricsHistory,
+    /// Last few queries, for the dashboard.
+    pub recent_queries: RecentQueries,
+    /// Services registered over `POST /dns/register`.
+    pub registry: ServiceRegistry,
+    pub start_time: Instant,
+    /// When the counters were last zeroed (initially `start_time`).
+    counters_since: Mutex<Instant>,
+    pub admin: AdminConfig,
+    pub audit: AuditLog,
+    /// When drain (maintenance) mode started, in nanoseconds since
+    /// `start_time`; 0 when not draining. Read on every query, so atomic.
+    draining_since: AtomicU64,
+    /// How long queries keep being answered after a drain starts.
+    pub drain_grace: Duration,
+    /// Address the UDP socket is bound to, once started.
+    dns_addr: OnceLock<SocketAddr>,
+    /// When the receive loop is restarted after a fatal socket error.
+    pub restart_policy: RestartPolicy,
+    /// Worker count and ingress queue settings.
+    pub server: ServerConfig,
+    /// Recent responses, sized by `server.answer_cache`.
+    answers: AnswerCache,
+    /// Set by [`DnsServerState::stop_dns_server`].
+    stopping: watch::Sender<bool>,
+}
+
+impl DnsServerState {
+    /// Fresh state serving `zone`, with counters at zero.
+    pub fn new(zone: HesiodZone) -> Self {
+        Self::with_zones(ZoneSet::new(zone))
+    }
+
+    /// Fresh state serving every zone in `zones`.
+    pub fn with_zones(zones: ZoneSet) -> Self {
+        let now = Instant::now();
+        Self {
+            zones: RwLock::new(Arc::new(zones)),
+            zone_serial: AtomicU64::new(serial_after(0, SystemTime::now())),
+            notifier: Notifier::default(),
+            source: None,
+            sync: Mutex::new(SyncStatus::default()),
+            query_count: ShardedCounter::new(),
+            map_query_counts: Default::default(),
+            map_refusal_counts: Default::default(),
+            zone_counters: RwLock::default(),
+            errors: ErrorCounters::default(),
+            history: MetricsHistory::default(),
+            recent_queries: RecentQueries::default(),
+            registry: ServiceRegistry::default(),
+            start_time: now,
+            counters_since: Mutex::new(now),
+            admin: AdminConfig::default(),
+            audit: AuditLog::default(),
+            draining_since: AtomicU64::new(0),
+            drain_grace: Duration::from_secs(30),
+            dns_addr: OnceLock::new(),
+            restart_policy: RestartPolicy::default(),
+            server: ServerConfig::default(),
+            answers: AnswerCache::new(ServerConfig::default().answer_cache),
+            stopping: watch::Sender::new(false),
+        }
+    }
+
+    /// Set the audit log admin actions are recorded to.
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Set the worker count, ingress queue, multi-question handling and
+    /// answer cache size.
+    pub fn with_server(mut self, server: ServerConfig) -> Self {
+        self.answers = AnswerCache::new(server.answer_cache);
+        self.server = server;
+        self
+    }
+
+    /// Set when the receive loop is restarted after a fatal socket error.
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Set how long queries are still answered after a drain starts.
+    pub fn with_drain_grace(mut self, grace: Duration) -> Self {
+        self.drain_grace = grace;
+        self
+    }
+
+    /// Enter drain mode: readiness fails immediately, queries get SERVFAIL
+    /// once the grace period has passed. Starting an active drain is a no-op.
+    pub fn start_drain(&self) {
+        let now = self.nanos_since_start().max(1);
+        if self
+            .draining_since
+            .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            info!(
+                "entering drain mode ({}s grace)",
+                self.drain_grace.as_secs()
+            );
+        }
+    }
+
+    /// Leave drain mode and resume normal service.
+    pub fn end_drain(&self) {
+        if self.draining_since.swap(0, Ordering::Relaxed) != 0 {
+            info!("leaving drain mode");
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining_since.load(Ordering::Relaxed) != 0
+    }
+
+    /// Stop reading DNS queries, for shutdown. Those already received are
+    /// still answered, then [`DnsServerHandle::wait`] returns.
+    pub fn stop_dns_server(&self) {
+        self.stopping.send_replace(true);
+    }
+
+    /// Draining and past the grace period, so queries should be refused.
+    pub fn drain_expired(&self) -> bool {
+        let since = self.draining_since.load(Ordering::Relaxed);
+        let draining = self.nanos_since_start().saturating_sub(since);
+        since != 0 && u128::from(draining) >= self.drain_grace.as_nanos()
+    }
+
+    fn nanos_since_start(&self) -> u64 {
+        self.start_time.elapsed().as_nanos() as u64
+    }
+
+    /// Set the admin API configuration.
+    pub fn with_admin(mut self, admin: AdminConfig) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    /// Set the config source; the zone passed to `new` counts as its first sync.
+    pub fn with_source(mut self, source: ConfigSource) -> Self {
+        self.update_sync(|sync| sync.record_load(&source));
+        self.source = Some(source);
+        self
+    }
+
+    /// The zone currently being served.
+    pub fn zone(&self) -> Arc<HesiodZone> {
+        Arc::clone(self.zones().primary())
+    }
+
+    /// Every zone being served, the primary one first.
+    pub fn zones(&self) -> Arc<ZoneSet> {
+        Arc::clone(&self.zones.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Set the webhooks notified on zone changes.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Atomically swap in a new primary zone, returning the new serial.
+    pub fn replace_zone(&self, zone: HesiodZone) -> u64 {
+        let mut current = self.zones.write().unwrap_or_else(|e| e.into_inner());
+        *current = Arc::new(current.with_primary(zone));
+        self.next_serial()
+    }
+
+    /// Atomically swap in a new set of zones, returning the new serial.
+    pub fn replace_zones(&self, zones: ZoneSet) -> u64 {
+        *self.zones.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(zones);
+        self.next_serial()
+    }
+
+    /// Serial of the zone currently being served, as the SOA carries it.
+    pub fn zone_serial(&self) -> u64 {
+        self.zone_serial.load(Ordering::Relaxed)
+    }
+
+    fn next_serial(&self) -> u64 {
+        let now = SystemTime::now();
+        let previous = self
+            .zone_serial
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |previous| {
+                Some(serial_after(previous, now))
+            })
+            .unwrap_or_else(|previous| previous);
+        serial_after(previous, now)
+    }
+
+    pub fn sync_status(&self) -> SyncStatus {
+        self.sync.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn update_sync(&self, f: impl FnOnce(&mut SyncStatus)) {
+        f(&mut self.sync.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Query counter for one map type.
+    pub fn map_queries(&self, map_type: MapType) -> &ShardedCounter {
+        &self.map_query_counts[map_type.index()]
+    }
+
+    /// Counter of queries for `map_type` refused by its ACL.
+    pub fn map_refusals(&self, map_type: MapType) -> &ShardedCounter {
+        &self.map_refusal_counts[map_type.index()]
+    }
+
+    /// Query counters of the zone for `domain`.
+    pub fn zone_counters(&self, domain: &str) -> Arc<ZoneCounters> {
+        let key = domain.trim_end_matches('.');
+        let key = if key.bytes().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(key.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(key)
+        };
+        if let Some(counters) = self
+            .zone_counters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key.as_ref())
+        {
+            return Arc::clone(counters);
+        }
+        let mut counters = self
+            .zone_counters
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        Arc::clone(counters.entry(key.into_owned()).or_default())
+    }
+
+    /// Zero all query counters. Uptime is unaffected.
+    pub fn reset_counters(&self) {
+        self.query_count.reset();
+        self.map_query_counts.iter().for_each(ShardedCounter::reset);
+        self.map_refusal_counts
+            .iter()
+            .for_each(ShardedCounter::reset);
+        self.zone_counters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .for_each(|counters| counters.reset());
+        self.errors.reset();
+        *self
+            .counters_since
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Address the DNS server is listening on, with the actual port when
+    /// started on port 0. `None` before [`start_dns_server`] has run.
+    pub fn dns_addr(&self) -> Option<SocketAddr> {
+        self.dns_addr.get().copied()
+    }
+
+    /// Time the counters have been accumulating since start or the last reset.
+    pub fn counters_elapsed(&self) -> Duration {
+        self.counters_since
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed()
+    }
 }
 
-/// Run the Hesiod DNS server on the given port.
+/// Run the Hesiod DNS server on the given port, or an ephemeral one for
+/// port 0; [`DnsServerState::dns_addr`] has the bound address.
 pub async fn run_dns_server(zone: HesiodZone, port: u16) -> Result<Arc<DnsServerState>> {
-    let addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
-    let socket = UdpSocket::bind(addr)
-        .await
-        .with_context(|| format!("binding UDP socket on port {}", port))?;
+    let state = Arc::new(DnsServerState::new(zone));
+    start_dns_server(Arc::clone(&state), port).await?;
+    Ok(state)
+}
+
+/// Bind the UDP socket and spawn the receive loop for an existing state,
+/// supervised under the state's [`RestartPolicy`]. The bound address is also
+/// kept in the state.
+pub async fn start_dns_server(state: Arc<DnsServerState>, port: u16) -> Result<DnsServerHandle> {
+    let (socket, addr) = bind_listener(&state, port)?;
+    if state.dns_addr.set(addr).is_err() {
+        return Err(HesiodError::dns(
+            "DNS server already started for this state",
+        ));
+    }
 
     info!("Hesiod DNS server listening on {}", addr);
+    Ok(spawn_listener(state, socket, addr, None))
+}
+
+/// Bind and spawn the listener for `server.views[view]`, which answers only
+/// for records carrying one of the view's tags. It runs alongside the main
+/// listener, under the same restart policy, and stops with it.
+pub async fn start_view_server(state: Arc<DnsServerState>, view: usize) -> Result<DnsServerHandle> {
+    let Some(config) = state.server.views.get(view) else {
+        return Err(HesiodError::dns(format!("no DNS view {view} configured")));
+    };
+    let (socket, addr) = bind_listener(&state, config.port)?;
+    info!("Hesiod DNS view {} listening on {}", config.name, addr);
+    Ok(spawn_listener(state, socket, addr, Some(view)))
+}
+
+/// A UDP socket on `port` with the configured buffers, and its address.
+fn bind_listener(state: &DnsServerState, port: u16) -> Result<(UdpSocket, SocketAddr)> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let socket =
+        bind_udp(addr, &state.server).io_err(|| format!("binding UDP socket on port {}", port))?;
+    let addr = socket
+        .local_addr()
+        .io_err(|| "reading the DNS socket address")?;
+    let sock = socket2::SockRef::from(&socket);
+    info!(
+        "DNS socket buffers: receive {} bytes, send {} bytes",
+        sock.recv_buffer_size()
+            .io_err(|| "reading the receive buffer size")?,
+        sock.send_buffer_size()
+            .io_err(|| "reading the send buffer size")?
+    );
+    Ok((socket, addr))
+}
+
+/// Spawn the supervised receive loop for `socket`, answering in `view`.
+fn spawn_listener(
+    state: Arc<DnsServerState>,
+    socket: UdpSocket,
+    addr: SocketAddr,
+    view: Option<usize>,
+) -> DnsServerHandle {
+    // Restarts rebind the same address, so an ephemeral port is kept.
+    let mut socket = Some(socket);
+    let loop_state = Arc::clone(&state);
+    let task = tokio::spawn(supervise(state, move || {
+        let socket = socket.take();
+        let state = Arc::clone(&loop_state);
+        async move {
+            let socket = match socket {
+                Some(socket) => socket,
+                None => bind_udp(addr, &state.server)
+                    .io_err(|| format!("rebinding UDP socket on {}", addr))?,
+            };
+            receive_loop(Arc::new(socket), state, view).await
+        }
+    }));
+
+    DnsServerHandle { addr, task }
+}
+
+/// A non-blocking UDP socket on `addr` with the configured buffer sizes.
+/// Sizes the kernel caps below the request are logged.
+fn bind_udp(addr: SocketAddr, server: &ServerConfig) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if let Some(size) = server.recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        check_buffer("receive", size, socket.recv_buffer_size()?, "rmem_max");
+    }
+    if let Some(size) = server.send_buffer {
+        socket.set_send_buffer_size(size)?;
+        check_buffer("send", size, socket.send_buffer_size()?, "wmem_max");
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn check_buffer(kind: &str, requested: usize, effective: usize, sysctl: &str) {
+    // Linux reports double the size set, to cover its bookkeeping.
+    let expected = if cfg!(target_os = "linux") {
+        requested.saturating_mul(2)
+    } else {
+        requested
+    };
+    if effective < expected {
+        warn!(
+            "{kind} buffer capped at {effective} bytes (asked for {requested}); raise net.core.{sysctl}"
+        );
+    }
+}
+
+/// A running DNS server. Dropping the handle leaves the server running.
+pub struct DnsServerHandle {
+    addr: SocketAddr,
+    task: JoinHandle<Result<()>>,
+}
+
+impl DnsServerHandle {
+    /// Address the UDP socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Wait for the server to stop, which it only does on a socket error
+    /// once its restarts are used up, or after [`DnsServerHandle::abort`].
+    pub async fn wait(self) -> Result<()> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Ok(()),
+            Err(e) => Err(HesiodError::dns(format!("DNS server task panicked: {e}"))),
+        }
+    }
 
-    let state = Arc::new(DnsServerState {
-        zone,
-        query_count: std::sync::atomic::AtomicU64::new(0),
-        start_time: std::time::Instant::now(),
-    });
-
-    let state_clone = Arc::clone(&state);
-    tokio::spawn(async move {
-        let mut buf = vec![0u8; 4096];
-        loop {
-            match socket.recv_from(&mut buf).await {
-                Ok((len, src)) => {
-                    let data = buf[..len].to_vec();
-                    let state_inner = Arc::clone(&state_clone);
-                    let socket_ref = &socket;
-                    // Process inline to avoid borrow issues with socket
-                    let response = handle_query(&data, &state_inner);
-                    state_inner
-                        .query_count
-                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    match response {
-                        Ok(resp_bytes) => {
-                            if let Err(e) = socket_ref.send_to(&resp_bytes, src).await {
-                                error!("failed to send response to {}: {}", src, e);
-                            }
-                        }
-                        Err(e) => {
-                            warn!("failed to handle query from {}: {}", src, e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("recv_from error: {}", e);
-                }
+    /// Stop the server.
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+}
+
+/// Run `run` (one receive loop) until it fails more often than the state's
+/// restart policy allows, waiting out the backoff before each restart.
+async fn supervise<F, Fut>(state: Arc<DnsServerState>, mut run: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let policy = state.restart_policy;
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        let Err(e) = run().await else {
+            return Ok(());
+        };
+        if started.elapsed() >= HEALTHY_RUN {
+            restarts = 0;
+        }
+        if restarts >= policy.restarts {
+            error!("DNS server stopped: {e:#}");
+            return Err(e);
+        }
+        restarts += 1;
+        let delay = policy.backoff(restarts);
+        warn!(
+            "DNS server failed: {e:#}; restart {restarts}/{} in {delay:?}",
+            policy.restarts
+        );
+        tokio::time::sleep(delay).await;
+        state.errors.server_restarts.inc();
+    }
+}
+
+/// A received query and where to send the answer.
+type Datagram = (Bytes, SocketAddr);
+
+/// Read datagrams from `socket` into the ingress queue for the workers until
+/// a receive error that isn't transient, or until the server is stopped,
+/// after which the workers answer what is queued and the loop returns.
+async fn receive_loop(
+    socket: Arc<UdpSocket>,
+    state: Arc<DnsServerState>,
+    view: Option<usize>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel::<Datagram>(state.server.queue_depth.max(1));
+    let rx = Arc::new(tokio::sync::Mutex::new(rx));
+    let mut workers = JoinSet::new();
+    for _ in 0..state.server.workers.max(1) {
+        let (socket, state, rx) = (Arc::clone(&socket), Arc::clone(&state), Arc::clone(&rx));
+        workers.spawn(async move {
+            loop {
+                let Some((data, src)) = rx.lock().await.recv().await else {
+                    return;
+                };
+                answer(&socket, &state, view, &data, src).await;
+            }
+        });
+    }
+
+    // One spare byte so an oversized datagram is detectable rather than
+    // silently truncated to exactly MAX_DATAGRAM. Each datagram is copied
+    // out at its own size, so a queued query holds no more than it needs.
+    let mut buf = vec![0u8; MAX_DATAGRAM + 1];
+    let mut stopping = state.stopping.subscribe();
+    loop {
+        let received = tokio::select! {
+            biased;
+            _ = stopping.wait_for(|stopping| *stopping) => break,
+            received = socket.recv_from(&mut buf) => received,
+        };
+        match received {
+            Ok((len, src)) if len > MAX_DATAGRAM => {
+                state.errors.oversized_packets.inc();
+                debug!("dropping oversized datagram from {}", src);
             }
+            Ok((len, src)) => {
+                let datagram = (Bytes::copy_from_slice(&buf[..len]), src);
+                enqueue(&tx, datagram, state.server.overflow, &state).await;
+            }
+            Err(e) if is_transient(&e) => {
+                error!("recv_from error: {}", e);
+            }
+            Err(e) => return Err(e).io_err(|| "receiving on the DNS socket"),
         }
-    });
+    }
 
-    Ok(state)
+    info!("DNS server stopped receiving, answering queued queries");
+    drop(tx);
+    while workers.join_next().await.is_some() {}
+    Ok(())
 }
 
-/// Parse a DNS query and build a response.
-fn handle_query(data: &[u8], state: &DnsServerState) -> Result<Vec<u8>> {
-    let request = Message::from_vec(data).context("parsing DNS query")?;
+/// Queue `datagram` for a worker, dropping it or waiting for room when the
+/// queue is full as `overflow` says.
+async fn enqueue(
+    tx: &mpsc::Sender<Datagram>,
+    datagram: Datagram,
+    overflow: OverflowPolicy,
+    state: &DnsServerState,
+) {
+    let src = datagram.1;
+    let queued = match overflow {
+        OverflowPolicy::Drop => tx.try_send(datagram).is_ok(),
+        OverflowPolicy::Block => tx.send(datagram).await.is_ok(),
+    };
+    if !queued {
+        state.errors.dropped_queries.inc();
+        debug!("ingress queue full, dropping query from {}", src);
+    }
+}
+
+/// Answer one query in `view` and send the response back to `src`.
+async fn answer(
+    socket: &UdpSocket,
+    state: &DnsServerState,
+    view: Option<usize>,
+    data: &Bytes,
+    src: SocketAddr,
+) {
+    let response = handle_query_in_view(data, state, view, Some(src.ip()));
+    state.query_count.inc();
+    match response {
+        Ok(resp_bytes) => {
+            if let Err(e) = socket.send_to(&resp_bytes, src).await {
+                state.errors.send_failures.inc();
+                error!("failed to send response to {}: {}", src, e);
+            }
+        }
+        Err(e) => {
+            warn!("failed to handle query from {}: {}", src, e);
+        }
+    }
+}
+
+/// Receive errors that leave the socket usable, e.g. an ICMP port
+/// unreachable for an earlier reply reported as a connection reset.
+fn is_transient(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        e.kind(),
+        ConnectionReset | ConnectionRefused | Interrupted | WouldBlock | TimedOut
+    )
+}
+
+/// Parse a DNS query and build a response, as the UDP listener does.
+///
+/// Oversized queries and responses are rejected before anything past the
+/// header is parsed, and queries with too many questions get FORMERR. Bytes
+/// after the last record are ignored. Each case has an error counter.
+///
+/// Only one question is answered: a query with several gets FORMERR, or an
+/// answer to its first, as `server.multi_question` says.
+///
+/// The client is unknown, so maps with an ACL in `server.map_acl` are
+/// refused.
+pub fn handle_query(data: &[u8], state: &DnsServerState) -> Result<Vec<u8>> {
+    handle_query_in_view(&Bytes::copy_from_slice(data), state, None, None)
+}
+
+/// [`handle_query`] as the listener for `server.views[view]` answers it for
+/// `client`, seeing only the records carrying one of the view's tags;
+/// `None` sees every record. Questions for a map whose ACL doesn't allow
+/// `client` get REFUSED.
+pub fn handle_query_in_view(
+    data: &Bytes,
+    state: &DnsServerState,
+    view: Option<usize>,
+    client: Option<IpAddr>,
+) -> Result<Vec<u8>> {
+    let tags = match view {
+        Some(view) => match state.server.views.get(view) {
+            Some(config) => Some(config.tags.as_slice()),
+            None => return Err(HesiodError::dns(format!("no DNS view {view} configured"))),
+        },
+        None => None,
+    };
+    if data.len() > MAX_QUERY {
+        state.errors.oversized_packets.inc();
+        return Err(HesiodError::dns(format!(
+            "query of {} bytes is over the {MAX_QUERY}-byte limit",
+            data.len()
+        )));
+    }
+    let header = match Header::read(&mut BinDecoder::new(data)) {
+        Ok(header) => header,
+        Err(e) => {
+            state.errors.malformed_packets.inc();
+            return Err(e).dns_err(|| "parsing DNS query");
+        }
+    };
+    if header.message_type() == MessageType::Response {
+        state.errors.response_packets.inc();
+        return Err(HesiodError::dns("packet is a response, not a query"));
+    }
+    if header.query_count() > MAX_QUESTIONS {
+        state.errors.excess_questions.inc();
+        let mut response = Message::new();
+        response.set_header(Header::response_from_request(&header));
+        response.set_response_code(ResponseCode::FormErr);
+        return response.to_vec().dns_err(|| "encoding DNS response");
+    }
+
+    let mut decoder = BinDecoder::new(data);
+    let request = match Message::read(&mut decoder) {
+        Ok(request) => request,
+        Err(e) => {
+            state.errors.malformed_packets.inc();
+            return Err(e).dns_err(|| "parsing DNS query");
+        }
+    };
+    if !decoder.is_empty() {
+        state.errors.trailing_data.inc();
+        debug!("ignoring {} bytes after the query", decoder.len());
+    }
     let mut response = Message::new();
 
     let mut header = Header::response_from_request(request.header());
@@ -85,79 +737,306 @@ fn handle_query(data: &[u8], state: &DnsServerState) -> Result<Vec<u8>> {
 
     response.set_header(header);
 
-    // Copy the question section
-    for query in request.queries() {
-        response.add_query(query.clone());
-    }
-
     if request.header().op_code() != OpCode::Query {
+        response.add_queries(request.queries().iter().cloned());
         response.set_response_code(ResponseCode::NotImp);
-        return Ok(response.to_vec()?);
+        return response.to_vec().dns_err(|| "encoding DNS response");
     }
 
-    for query in request.queries() {
-        let name = query.name();
-        let qclass_raw: u16 = query.query_class().into();
-        let qtype = query.query_type();
+    let query = match (request.queries(), state.server.multi_question) {
+        ([query], _) | ([query, ..], MultiQuestionPolicy::First) => query,
+        (queries, _) => {
+            debug!("refusing a query with {} questions", queries.len());
+            response.set_response_code(ResponseCode::FormErr);
+            return response.to_vec().dns_err(|| "encoding DNS response");
+        }
+    };
 
-        debug!("query: {} class={} type={:?}", name, qclass_raw, qtype);
+    if state.drain_expired() {
+        response.add_query(query.clone());
+        response.set_response_code(ResponseCode::ServFail);
+        return response.to_vec().dns_err(|| "encoding DNS response");
+    }
 
-        // Only handle HS class (4) or IN class (1) as fallback
-        if qclass_raw != DNS_CLASS_HS && qclass_raw != u16::from(DNSClass::IN) {
-            continue;
-        }
+    // Checked before the answer cache, which is shared by all clients.
+    if !state.server.map_acl.is_empty()
+        && let Some((_, _, map_type)) = state.zones().resolve(query.name())
+        && !state.server.map_allows(map_type, client)
+    {
+        state.map_refusals(map_type).inc();
+        debug!("refusing a {} query from {:?}", map_type, client);
+        response.add_query(query.clone());
+        response.set_response_code(ResponseCode::Refused);
+        return response.to_vec().dns_err(|| "encoding DNS response");
+    }
 
-        // Only handle TXT queries
-        if qtype != RecordType::TXT {
-            continue;
+    // Read before resolving, so a reload racing this query leaves its
+    // response stale rather than cached under the new serial.
+    let serial = state.zone_serial();
+    let key = question_wire(data).map(|question| (question, view));
+    if let Some(key) = &key
+        && let Some(mut cached) = state.answers.get(key, data, serial, Instant::now())
+    {
+        cached.resolved.count(state);
+        if let Some(offset) = cached.ttl_offset {
+            jitter_ttl(&mut cached.response, offset, state.server.ttl_jitter);
         }
+        return Ok(cached.response);
+    }
+    response.add_query(query.clone());
 
-        if let Some(txt_data) = resolve_name(name, &state.zone) {
-            let txt_rdata = TXT::new(vec![txt_data.clone()]);
-            let mut record =
-                Record::from_rdata(name.clone(), state.zone.ttl, RData::TXT(txt_rdata));
-            record.set_dns_class(DNSClass::HS);
+    let answer = answer_question(query, state, serial, tags);
+    if let Some(resolved) = &answer.resolved {
+        resolved.count(state);
+    }
+    let mut rdata_len = None;
+    match answer.record {
+        Some(record) => {
+            // Unlike an SOA's names, TXT data is never compressed, so it is
+            // as long in the response as on its own.
+            if let RData::TXT(txt) = record.data() {
+                rdata_len = txt.to_bytes().ok().map(|rdata| rdata.len());
+            }
             response.add_answer(record);
-        } else {
-            debug!("no record found for {}", name);
         }
+        None => {
+            response.set_response_code(ResponseCode::NXDomain);
+            response.add_name_servers(answer.soa);
+        }
+    }
+
+    let mut bytes = response.to_vec().dns_err(|| "encoding DNS response")?;
+    // The answer is the last record, so its TTL sits just before RDLENGTH
+    // and RDATA.
+    let ttl_offset = rdata_len.and_then(|len| bytes.len().checked_sub(len + 6));
+    if let (Some((question, view)), Some(resolved), Some(ttl)) =
+        (key, answer.resolved, answer.cache_ttl)
+    {
+        // A copy, so the cache doesn't keep whole queries alive.
+        let key = (Bytes::copy_from_slice(&question), view);
+        let cached = CachedResponse {
+            response: bytes.clone(),
+            resolved,
+            ttl_offset,
+        };
+        let ttl = Duration::from_secs(ttl.into());
+        state
+            .answers
+            .insert(key, cached, serial, ttl, Instant::now());
+    }
+    if let Some(offset) = ttl_offset {
+        jitter_ttl(&mut bytes, offset, state.server.ttl_jitter);
+    }
+    Ok(bytes)
+}
+
+/// SOA serial of zones swapped in at `now` after ones at `previous`: the
+/// Unix time in seconds, or `previous + 1` if that is not larger. Unlike a
+/// reload counter it keeps increasing across restarts, so secondaries
+/// never see it go back, and replicas loading a config together agree on
+/// it to within the time between their reloads.
+fn serial_after(previous: u64, now: SystemTime) -> u64 {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    secs.max(previous + 1)
+}
+
+/// The first question of the valid query `data` as sent: its name, type
+/// and class. `None` if the name is compressed, as it then depends on the
+/// rest of the packet.
+fn question_wire(data: &Bytes) -> Option<Bytes> {
+    const HEADER_LEN: usize = 12;
+    let mut end = HEADER_LEN;
+    loop {
+        match usize::from(*data.get(end)?) {
+            0 => break,
+            len @ 1..=63 => end += 1 + len,
+            _ => return None,
+        }
+    }
+    // The root label, type and class.
+    end += 5;
+    (end <= data.len()).then(|| data.slice(HEADER_LEN..end))
+}
+
+/// Move the TTL at `offset` in `response` by a random amount within
+/// ±`percent`% (at most 100), so clients that cached a record together
+/// don't all ask again at the same moment.
+fn jitter_ttl(response: &mut [u8], offset: usize, percent: u8) {
+    if percent == 0 {
+        return;
     }
+    let Some(field) = response.get_mut(offset..offset + 4) else {
+        return;
+    };
+    let ttl = u64::from(u32::from_be_bytes([field[0], field[1], field[2], field[3]]));
+    let spread = ttl * u64::from(percent.min(100)) / 100;
+    let jittered = rand::random_range(ttl - spread..=ttl + spread);
+    let jittered = u32::try_from(jittered).unwrap_or(u32::MAX);
+    field.copy_from_slice(&jittered.to_be_bytes());
+}
+
+/// Where a question that reached a served zone went, for the per-map and
+/// per-zone counters and the recent-query log.
+#[derive(Debug, Clone)]
+pub(crate) struct Resolved {
+    pub(crate) zone: Arc<str>,
+    pub(crate) key: Arc<str>,
+    pub(crate) map_type: MapType,
+    pub(crate) found: bool,
+}
 
-    if response.answers().is_empty() {
-        response.set_response_code(ResponseCode::NXDomain);
+impl Resolved {
+    fn count(&self, state: &DnsServerState) {
+        state.map_queries(self.map_type).inc();
+        let zone_counters = state.zone_counters(&self.zone);
+        zone_counters.queries.inc();
+        zone_counters.map_queries[self.map_type.index()].inc();
+        state.recent_queries.record(RecentQuery {
+            timestamp_unix: crate::metrics::unix_now(),
+            name: Arc::clone(&self.key),
+            map: self.map_type,
+            zone: Arc::clone(&self.zone),
+            found: self.found,
+        });
     }
+}
 
-    Ok(response.to_vec()?)
+/// What [`answer_question`] made of a question.
+#[derive(Default)]
+struct Answer {
+    /// The TXT answer, if a served zone has a record for the question.
+    record: Option<Record>,
+    /// The zone's SOA, for the authority section when there is no record.
+    soa: Option<Record>,
+    /// Set when the question reached a zone.
+    resolved: Option<Resolved>,
+    /// How long the response may be cached, unless it depends on the
+    /// service registry, which changes without a reload.
+    cache_ttl: Option<u32>,
 }
 
-/// Resolve a DNS name against the zone.
-/// Expected format: `<key>.<map_type><lhs><rhs>` e.g. `admin.passwd.ns.flatracoon.internal`
-fn resolve_name(name: &Name, zone: &HesiodZone) -> Option<String> {
-    let name_str = name.to_string();
-    // Remove trailing dot if present
-    let name_str = name_str.strip_suffix('.').unwrap_or(&name_str);
+/// The answer to `query` from the served zones (at `serial`) and
+/// registered services, or only from records carrying one of `tags`.
+fn answer_question(
+    query: &Query,
+    state: &DnsServerState,
+    serial: u64,
+    tags: Option<&[String]>,
+) -> Answer {
+    let name = query.name();
+    let qclass_raw: u16 = query.query_class().into();
+    let qtype = query.query_type();
+
+    debug!("query: {} class={} type={:?}", name, qclass_raw, qtype);
+
+    // Only handle HS class (4) or IN class (1) as fallback
+    if qclass_raw != DNS_CLASS_HS && qclass_raw != u16::from(DNSClass::IN) {
+        return Answer::default();
+    }
+
+    let zones = state.zones();
 
-    // Build the expected suffix: e.g. ".ns.flatracoon.internal"
-    let suffix = format!("{}{}", zone.lhs, zone.rhs);
+    // Secondaries poll the SOA at the origin for the serial and timers.
+    if qtype == RecordType::SOA {
+        let origin = name.to_string();
+        let origin = origin.trim_end_matches('.');
+        return Answer {
+            record: zones
+                .iter()
+                .find(|zone| zone.origin().eq_ignore_ascii_case(origin))
+                .and_then(|zone| zone_soa(zone, serial, query.query_class(), zone.ttl)),
+            ..Answer::default()
+        };
+    }
 
-    // Strip the suffix to get "<key>.<map_type>"
-    let prefix = name_str.strip_suffix(&suffix)?;
+    // Otherwise only handle TXT queries
+    if qtype != RecordType::TXT {
+        return Answer::default();
+    }
 
-    // Split into key and map_type
-    let dot_pos = prefix.rfind('.')?;
-    let key = &prefix[..dot_pos];
-    let map_label = &prefix[dot_pos + 1..];
+    let Some((zone, key, map_type)) = zones.resolve(name) else {
+        debug!("name {} is outside the served zones", name);
+        return Answer::default();
+    };
 
-    let map_type: MapType = map_label.parse().ok()?;
-    let record = zone.lookup(key, map_type)?;
+    // Registered services answer only where the config has no record,
+    // and never for longer than they have left. They carry no tags, so
+    // views never see them.
+    let configured = match tags {
+        Some(tags) => zone.lookup_tagged(&key, map_type, tags),
+        None => zone.lookup(&key, map_type),
+    };
+    let registered = configured.is_none() && tags.is_none() && map_type == MapType::Service;
+    let found = match configured {
+        Some(record) => Some((record.to_txt(), zone.ttl)),
+        None if registered => state
+            .registry
+            .lookup(&zone.domain, &key, Instant::now())
+            .map(|(record, left)| {
+                let left = u32::try_from(left.as_secs().max(1)).unwrap_or(u32::MAX);
+                (record.to_txt(), zone.ttl.min(left))
+            }),
+        None => None,
+    };
+    let mut answer = Answer {
+        record: None,
+        soa: None,
+        resolved: Some(Resolved {
+            zone: zone.domain.as_str().into(),
+            key: key.as_str().into(),
+            map_type,
+            found: found.is_some(),
+        }),
+        cache_ttl: (!registered).then_some(zone.ttl),
+    };
+    let Some((txt, ttl)) = found else {
+        debug!("no record found for {}", name);
+        answer.soa = zone_soa(zone, serial, query.query_class(), zone.soa_minimum());
+        answer.cache_ttl = (!registered).then(|| zone.soa_minimum());
+        return answer;
+    };
+    let txt_rdata = TXT::new(vec![txt]);
+    let mut record = Record::from_rdata(name.clone(), ttl, RData::TXT(txt_rdata));
+    // libhesiod drops answers whose class differs from the query's.
+    record.set_dns_class(query.query_class());
+    answer.record = Some(record);
+    answer
+}
 
-    Some(record.to_txt())
+/// `zone`'s SOA in `class` with `ttl`, its timers from the zone's
+/// [`SoaConfig`](crate::config::SoaConfig). Negative answers carry it with
+/// the SOA minimum as TTL, so resolvers cache them for that long (RFC 2308).
+fn zone_soa(zone: &HesiodZone, serial: u64, class: DNSClass, ttl: u32) -> Option<Record> {
+    let (mname, rname) = zone.soa_names();
+    let origin = Name::from_ascii(format!("{}.", zone.origin())).ok()?;
+    let timer = |secs: u32| i32::try_from(secs).unwrap_or(i32::MAX);
+    let soa = SOA::new(
+        Name::from_ascii(mname).ok()?,
+        Name::from_ascii(rname).ok()?,
+        u32::try_from(serial).unwrap_or(u32::MAX),
+        timer(zone.soa.refresh),
+        timer(zone.soa.retry),
+        timer(zone.soa.expire),
+        zone.soa_minimum(),
+    );
+    let mut record = Record::from_rdata(origin, ttl, RData::SOA(soa));
+    record.set_dns_class(class);
+    Some(record)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::HesiodConfig;
+    use crate::config::{Cidr, HesiodConfig, ViewConfig};
+    use crate::records::HesiodRecord;
+    use hickory_proto::rr::Name;
+    use std::collections::HashSet;
+
+    /// Resolve a DNS name against the zones, dispatching by suffix.
+    fn resolve_name(name: &Name, zones: &ZoneSet) -> Option<String> {
+        let (zone, key, map_type) = zones.resolve(name)?;
+        zone.lookup(&key, map_type).map(|record| record.to_txt())
+    }
 
     fn test_zone() -> HesiodZone {
         let config = HesiodConfig {
@@ -165,6 +1044,7 @@ fn test_zone() -> HesiodZone {
             lhs: ".ns".into(),
             rhs: ".test.internal".into(),
             ttl: 300,
+            negative_ttl: None,
             dns_port: 53,
             http_port: 8080,
             services: vec![crate::config::ServiceEntry {
@@ -172,16 +1052,27 @@ fn test_zone() -> HesiodZone {
                 host: "web.svc".into(),
                 port: 443,
                 protocol: "tcp".into(),
+                address: None,
+                tags: Vec::new(),
             }],
             users: vec![],
             groups: vec![],
+            metrics: Default::default(),
+            admin: Default::default(),
+            notify: Default::default(),
+            sync: Default::default(),
+            filesystems: vec![],
+            soa: Default::default(),
+            server: Default::default(),
+            http: Default::default(),
+            zones: vec![],
         };
         HesiodZone::from_config(&config).expect("TODO: handle error")
     }
 
     #[test]
     fn resolve_service_name() {
-        let zone = test_zone();
+        let zone = ZoneSet::new(test_zone());
         let name: Name = "web.service.ns.test.internal".parse().expect("TODO: handle error");
         let result = resolve_name(&name, &zone);
         assert_eq!(result, Some("web.svc:443:tcp".into()));
@@ -189,15 +1080,534 @@ fn resolve_service_name() {
 
     #[test]
     fn resolve_missing_name() {
-        let zone = test_zone();
+        let zone = ZoneSet::new(test_zone());
         let name: Name = "missing.service.ns.test.internal".parse().expect("TODO: handle error");
         assert!(resolve_name(&name, &zone).is_none());
     }
 
     #[test]
     fn resolve_wrong_suffix() {
-        let zone = test_zone();
+        let zone = ZoneSet::new(test_zone());
         let name: Name = "web.service.ns.other.internal".parse().expect("TODO: handle error");
         assert!(resolve_name(&name, &zone).is_none());
     }
+
+    fn query_bytes(name: &str) -> Vec<u8> {
+        query_bytes_in(name, DNSClass::HS)
+    }
+
+    fn query_bytes_in(name: &str, class: DNSClass) -> Vec<u8> {
+        use hickory_proto::op::Query;
+
+        let mut query = Query::new();
+        query.set_name(name.parse().expect("TODO: handle error"));
+        query.set_query_type(RecordType::TXT);
+        query.set_query_class(class);
+        let mut msg = Message::new();
+        msg.set_id(7);
+        msg.add_query(query);
+        msg.to_vec().expect("TODO: handle error")
+    }
+
+    #[test]
+    fn handle_query_counts_per_map() {
+        let state = DnsServerState::new(test_zone());
+        let resp = handle_query(&query_bytes("web.service.ns.test.internal"), &state)
+            .expect("TODO: handle error");
+        let resp = Message::from_vec(&resp).expect("TODO: handle error");
+        assert_eq!(resp.answers().len(), 1);
+
+        handle_query(&query_bytes("nobody.passwd.ns.test.internal"), &state)
+            .expect("TODO: handle error");
+        assert_eq!(state.map_queries(MapType::Service).get(), 1);
+        assert_eq!(state.map_queries(MapType::Passwd).get(), 1);
+        assert_eq!(state.map_queries(MapType::Group).get(), 0);
+    }
+
+    #[test]
+    fn answers_in_query_class() {
+        let state = DnsServerState::new(test_zone());
+        for class in [DNSClass::HS, DNSClass::IN] {
+            let query = query_bytes_in("web.service.ns.test.internal", class);
+            let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+                .expect("TODO: handle error");
+            assert_eq!(resp.answers()[0].dns_class(), class);
+        }
+    }
+
+    #[test]
+    fn answers_registered_services() {
+        let state = DnsServerState::new(test_zone());
+        let record = crate::records::ServiceRecord {
+            host: "10.0.0.9".into(),
+            port: 9000,
+            protocol: "udp".into(),
+        };
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        state
+            .registry
+            .register("test.internal", "api", record.clone(), ttl, now);
+        // Doesn't shadow the configured record.
+        state
+            .registry
+            .register("test.internal", "web", record, ttl, now);
+
+        let answer = |name: &str| {
+            let resp = Message::from_vec(
+                &handle_query(&query_bytes(name), &state).expect("TODO: handle error"),
+            )
+            .expect("TODO: handle error");
+            resp.answers()
+                .first()
+                .map(|answer| (answer.data().to_string(), answer.ttl()))
+        };
+        let (txt, ttl) = answer("api.service.ns.test.internal").expect("registered");
+        assert_eq!(txt, "10.0.0.9:9000:udp");
+        assert!(ttl <= 60);
+        let (txt, _) = answer("web.service.ns.test.internal").expect("configured");
+        assert_eq!(txt, "web.svc:443:tcp");
+        assert!(answer("api.passwd.ns.test.internal").is_none());
+    }
+
+    #[test]
+    fn malformed_packet_is_counted() {
+        let state = DnsServerState::new(test_zone());
+        assert!(handle_query(&[0xde, 0xad], &state).is_err());
+        assert_eq!(state.errors.malformed_packets.get(), 1);
+    }
+
+    #[test]
+    fn crafted_packets_hit_their_limits() {
+        let state = DnsServerState::new(test_zone());
+        let query = query_bytes("web.service.ns.test.internal");
+
+        let mut padded = query.clone();
+        padded.resize(MAX_QUERY + 1, 0);
+        assert!(handle_query(&padded, &state).is_err());
+        assert_eq!(state.errors.oversized_packets.get(), 1);
+
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        assert!(handle_query(&response, &state).is_err());
+        assert_eq!(state.errors.response_packets.get(), 1);
+
+        let mut questions = query.clone();
+        questions[4..6].copy_from_slice(&(MAX_QUESTIONS + 1).to_be_bytes());
+        let resp = Message::from_vec(&handle_query(&questions, &state).expect("FORMERR"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::FormErr);
+        assert_eq!(resp.id(), 7);
+        assert_eq!(state.errors.excess_questions.get(), 1);
+
+        let mut trailing = query;
+        trailing.extend_from_slice(b"junk");
+        let resp = Message::from_vec(&handle_query(&trailing, &state).expect("answered"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.answers().len(), 1);
+        assert_eq!(state.errors.trailing_data.get(), 1);
+        assert_eq!(state.errors.malformed_packets.get(), 0);
+    }
+
+    #[test]
+    fn multi_question_queries_follow_the_policy() {
+        let mut msg = Message::from_vec(&query_bytes("web.service.ns.test.internal"))
+            .expect("TODO: handle error");
+        let mut second = msg.queries()[0].clone();
+        second.set_name(
+            "nobody.passwd.ns.test.internal."
+                .parse()
+                .expect("TODO: handle error"),
+        );
+        msg.add_query(second);
+        let query = msg.to_vec().expect("TODO: handle error");
+
+        let state = DnsServerState::new(test_zone());
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::FormErr);
+        assert!(resp.answers().is_empty());
+        assert_eq!(state.map_queries(MapType::Service).get(), 0);
+
+        let state = DnsServerState::new(test_zone()).with_server(ServerConfig {
+            multi_question: MultiQuestionPolicy::First,
+            ..ServerConfig::default()
+        });
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::NoError);
+        assert_eq!(resp.queries().len(), 1);
+        assert_eq!(resp.answers().len(), 1);
+        assert_eq!(state.map_queries(MapType::Passwd).get(), 0);
+    }
+
+    #[test]
+    fn negative_answers_carry_the_soa_for_the_negative_ttl() {
+        let mut zone = test_zone();
+        zone.negative_ttl = Some(15);
+        let state = DnsServerState::new(zone);
+        let query = query_bytes("nobody.passwd.ns.test.internal");
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::NXDomain);
+        let soa = &resp.name_servers()[0];
+        assert_eq!(soa.name().to_string(), "test.internal.");
+        assert_eq!(soa.dns_class(), DNSClass::HS);
+        assert_eq!(soa.ttl(), 15);
+        let RData::SOA(soa) = soa.data() else {
+            panic!("not an SOA: {soa:?}");
+        };
+        assert_eq!(soa.minimum(), 15);
+        assert_eq!(soa.mname().to_string(), "ns.test.internal.");
+
+        // Names outside the served zones get no SOA.
+        let query = query_bytes("nobody.passwd.ns.elsewhere.internal");
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert!(resp.name_servers().is_empty());
+    }
+
+    #[test]
+    fn serves_the_soa_at_the_origin() {
+        let mut zone = test_zone();
+        zone.soa = crate::config::SoaConfig {
+            rname: Some("ops@test.internal".into()),
+            refresh: 1800,
+            retry: 300,
+            expire: 1_209_600,
+            minimum: Some(120),
+            ..Default::default()
+        };
+        let state = DnsServerState::new(zone);
+        let mut msg = Message::from_vec(&query_bytes("test.internal")).expect("TODO: handle error");
+        let mut query = msg.queries()[0].clone();
+        query.set_query_type(RecordType::SOA);
+        msg.take_queries();
+        msg.add_query(query);
+        let query = msg.to_vec().expect("TODO: handle error");
+
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::NoError);
+        let answer = &resp.answers()[0];
+        assert_eq!(answer.ttl(), 300);
+        let RData::SOA(soa) = answer.data() else {
+            panic!("not an SOA: {answer:?}");
+        };
+        assert_eq!(soa.rname().to_string(), "ops.test.internal.");
+        assert_eq!(
+            (soa.refresh(), soa.retry(), soa.expire(), soa.minimum()),
+            (1800, 300, 1_209_600, 120)
+        );
+        assert_eq!(u64::from(soa.serial()), state.zone_serial());
+    }
+
+    #[test]
+    fn unicode_keys_answer_their_punycode_names() {
+        let mut zone = test_zone();
+        zone.add_record(
+            "jose\u{301}",
+            crate::records::HesiodRecord::Service(crate::records::ServiceRecord {
+                host: "josé.svc".into(),
+                port: 80,
+                protocol: "tcp".into(),
+            }),
+        );
+        let state = DnsServerState::new(zone);
+        let query = query_bytes("xn--jos-dma.service.ns.test.internal");
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.answers().len(), 1);
+        assert_eq!(
+            resp.answers()[0].name().to_ascii(),
+            "xn--jos-dma.service.ns.test.internal."
+        );
+    }
+
+    #[test]
+    fn repeated_questions_are_answered_from_the_cache() {
+        let state = DnsServerState::new(test_zone());
+        let query = query_bytes("web.service.ns.test.internal");
+        let first = handle_query(&query, &state).expect("TODO: handle error");
+
+        let mut again = query.clone();
+        again[..2].copy_from_slice(&0x4242u16.to_be_bytes());
+        let cached = handle_query(&again, &state).expect("TODO: handle error");
+        assert_eq!(cached[2..], first[2..]);
+        let resp = Message::from_vec(&cached).expect("TODO: handle error");
+        assert_eq!(resp.id(), 0x4242);
+        assert_eq!(resp.answers().len(), 1);
+        assert_eq!(state.map_queries(MapType::Service).get(), 2);
+        assert_eq!(state.zone_counters("test.internal").queries.get(), 2);
+
+        // A reload makes cached responses stale.
+        let empty = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        state.replace_zone(empty);
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::NXDomain);
+
+        // Registered services can go at any time, so aren't cached.
+        let record = crate::records::ServiceRecord {
+            host: "10.0.0.9".into(),
+            port: 9000,
+            protocol: "udp".into(),
+        };
+        let (now, ttl) = (Instant::now(), Duration::from_secs(60));
+        let api = query_bytes("api.service.ns.test.internal");
+        state
+            .registry
+            .register("test.internal", "api", record.clone(), ttl, now);
+        let resp = Message::from_vec(&handle_query(&api, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.answers().len(), 1);
+        state
+            .registry
+            .register("test.internal", "api", record, Duration::ZERO, now);
+        let resp = Message::from_vec(&handle_query(&api, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::NXDomain);
+    }
+
+    #[test]
+    fn views_answer_only_for_tagged_records() {
+        let mut zone = test_zone();
+        let mut api = zone.lookup("web", MapType::Service).expect("web").clone();
+        if let HesiodRecord::Service(service) = &mut api {
+            service.host = "api.svc".into();
+        }
+        zone.add_record("api", api);
+        zone.set_tags("api", MapType::Service, vec!["public".into()]);
+        let view = ViewConfig {
+            name: "public".into(),
+            port: 0,
+            tags: vec!["public".into()],
+        };
+        let state = DnsServerState::new(zone).with_server(ServerConfig {
+            views: vec![view],
+            ..ServerConfig::default()
+        });
+        let ask = |name: &str, view| {
+            let resp = handle_query_in_view(&query_bytes(name).into(), &state, view, None)
+                .expect("TODO: handle error");
+            Message::from_vec(&resp).expect("TODO: handle error")
+        };
+
+        // The main listener answers first, so the view must not reuse its
+        // cached response.
+        let web = "web.service.ns.test.internal";
+        assert_eq!(ask(web, None).answers().len(), 1);
+        assert_eq!(ask(web, Some(0)).response_code(), ResponseCode::NXDomain);
+        let api = "api.service.ns.test.internal";
+        assert_eq!(ask(api, Some(0)).answers().len(), 1);
+        assert!(handle_query_in_view(&query_bytes(web).into(), &state, Some(1), None).is_err());
+    }
+
+    #[test]
+    fn map_acls_refuse_other_networks() {
+        let trusted: Cidr = "10.0.0.0/8".parse().expect("TODO: handle error");
+        let state = DnsServerState::new(test_zone()).with_server(ServerConfig {
+            map_acl: [(MapType::Passwd, vec![trusted])].into(),
+            ..ServerConfig::default()
+        });
+        let ask = |name: &str, client: &str| {
+            let client = client.parse().ok();
+            let resp = handle_query_in_view(&query_bytes(name).into(), &state, None, client)
+                .expect("TODO: handle error");
+            Message::from_vec(&resp)
+                .expect("TODO: handle error")
+                .response_code()
+        };
+
+        let passwd = "nobody.passwd.ns.test.internal";
+        assert_eq!(ask(passwd, "10.1.2.3"), ResponseCode::NXDomain);
+        assert_eq!(ask(passwd, "192.0.2.1"), ResponseCode::Refused);
+        assert_eq!(ask(passwd, "unknown"), ResponseCode::Refused);
+        let web = "web.service.ns.test.internal";
+        assert_eq!(ask(web, "192.0.2.1"), ResponseCode::NoError);
+        assert_eq!(state.map_refusals(MapType::Passwd).get(), 2);
+        assert_eq!(state.map_refusals(MapType::Service).get(), 0);
+    }
+
+    #[test]
+    fn serials_follow_the_clock_across_restarts() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+        assert_eq!(serial_after(0, now), 1_760_000_000);
+        assert_eq!(serial_after(1_760_000_000, now), 1_760_000_001);
+
+        let state = DnsServerState::new(test_zone());
+        let first = state.zone_serial();
+        assert!(first > 1_760_000_000);
+        let restarted = DnsServerState::new(test_zone());
+        assert!(restarted.zone_serial() >= first);
+        let swapped = state.replace_zones(state.zones().as_ref().clone());
+        assert!(swapped > first);
+        assert_eq!(state.zone_serial(), swapped);
+    }
+
+    #[test]
+    fn jitter_spreads_answer_ttls() {
+        let state = DnsServerState::new(test_zone()).with_server(ServerConfig {
+            ttl_jitter: 20,
+            ..ServerConfig::default()
+        });
+        let query = query_bytes("web.service.ns.test.internal");
+        let ttls: HashSet<u32> = (0..50)
+            .map(|_| {
+                let resp =
+                    Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+                        .expect("TODO: handle error");
+                assert_eq!(resp.answers()[0].data().to_string(), "web.svc:443:tcp");
+                resp.answers()[0].ttl()
+            })
+            .collect();
+        assert!(ttls.iter().all(|ttl| (240..=360).contains(ttl)), "{ttls:?}");
+        assert!(ttls.len() > 1);
+    }
+
+    #[test]
+    fn drain_servfails_after_grace() {
+        let state = DnsServerState::new(test_zone()).with_drain_grace(Duration::ZERO);
+        let query = query_bytes("web.service.ns.test.internal");
+
+        state.start_drain();
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.response_code(), ResponseCode::ServFail);
+
+        state.end_drain();
+        let resp = Message::from_vec(&handle_query(&query, &state).expect("TODO: handle error"))
+            .expect("TODO: handle error");
+        assert_eq!(resp.answers().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn supervisor_restarts_then_gives_up() {
+        let state = Arc::new(
+            DnsServerState::new(test_zone()).with_restart_policy(RestartPolicy::restarts(2)),
+        );
+        let mut runs = 0;
+        let result = supervise(Arc::clone(&state), || {
+            runs += 1;
+            async { Err(HesiodError::dns("socket closed")) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(runs, 3);
+        assert_eq!(state.errors.server_restarts.get(), 2);
+
+        let policy = RestartPolicy::restarts(9);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(20), policy.max_backoff);
+    }
+
+    #[tokio::test]
+    async fn handle_reports_the_bound_port() {
+        let state = Arc::new(DnsServerState::new(test_zone()));
+        let handle = start_dns_server(Arc::clone(&state), 0)
+            .await
+            .expect("TODO: handle error");
+        assert_ne!(handle.local_addr().port(), 0);
+        assert_eq!(state.dns_addr(), Some(handle.local_addr()));
+        assert!(start_dns_server(Arc::clone(&state), 0).await.is_err());
+        handle.abort();
+        handle.wait().await.expect("TODO: handle error");
+    }
+
+    #[tokio::test]
+    async fn stopped_server_finishes_and_stops_reading() {
+        let state = Arc::new(DnsServerState::new(test_zone()));
+        let handle = start_dns_server(Arc::clone(&state), 0)
+            .await
+            .expect("TODO: handle error");
+        let client = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let server = SocketAddr::from(([127, 0, 0, 1], handle.local_addr().port()));
+        client
+            .send_to(&query_bytes("web.service.ns.test.internal"), server)
+            .await
+            .expect("TODO: handle error");
+        let mut buf = [0u8; 512];
+        let (len, _) = client.recv_from(&mut buf).await.expect("TODO: handle error");
+        assert!(len > 0);
+
+        state.stop_dns_server();
+        handle.wait().await.expect("TODO: handle error");
+        assert_eq!(state.query_count.get(), 1);
+
+        // Nothing reads the socket any more.
+        client
+            .send_to(&query_bytes("web.service.ns.test.internal"), server)
+            .await
+            .expect("TODO: handle error");
+        let reply = tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buf));
+        assert!(reply.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_or_waits() {
+        let state = DnsServerState::new(test_zone());
+        let src: SocketAddr = "127.0.0.1:9".parse().expect("TODO: handle error");
+        let (tx, mut rx) = mpsc::channel(1);
+
+        enqueue(&tx, (vec![1].into(), src), OverflowPolicy::Drop, &state).await;
+        enqueue(&tx, (vec![2].into(), src), OverflowPolicy::Drop, &state).await;
+        assert_eq!(state.errors.dropped_queries.get(), 1);
+        assert_eq!(rx.recv().await.map(|(data, _)| data), Some(vec![1].into()));
+
+        enqueue(&tx, (vec![3].into(), src), OverflowPolicy::Block, &state).await;
+        let blocked = enqueue(&tx, (vec![4].into(), src), OverflowPolicy::Block, &state);
+        let (_, received) = tokio::join!(blocked, rx.recv());
+        assert_eq!(received.map(|(data, _)| data), Some(vec![3].into()));
+        assert_eq!(rx.recv().await.map(|(data, _)| data), Some(vec![4].into()));
+        assert_eq!(state.errors.dropped_queries.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn socket_buffers_are_applied() {
+        let server = ServerConfig {
+            recv_buffer: Some(256 * 1024),
+            send_buffer: Some(64 * 1024),
+            ..ServerConfig::default()
+        };
+        let socket = bind_udp("127.0.0.1:0".parse().expect("TODO: handle error"), &server)
+            .expect("TODO: handle error");
+        let sock = socket2::SockRef::from(&socket);
+        // Linux doubles the value for bookkeeping, other systems keep it as is.
+        assert!(sock.send_buffer_size().expect("TODO: handle error") >= 64 * 1024);
+        assert!(sock.recv_buffer_size().expect("TODO: handle error") > 0);
+    }
+
+    #[test]
+    fn queries_dispatch_by_zone_suffix() {
+        let config = HesiodConfig::from_json(
+            r#"{
+                "domain": "corp.internal", "lhs": ".ns", "rhs": ".corp.internal",
+                "services": [{"name": "web", "host": "corp.svc", "port": 443}],
+                "zones": [{
+                    "domain": "lab.internal", "lhs": ".ns", "rhs": ".lab.internal", "ttl": 60,
+                    "services": [{"name": "web", "host": "lab.svc", "port": 80}]
+                }]
+            }"#,
+        )
+        .expect("TODO: handle error");
+        let state =
+            DnsServerState::with_zones(ZoneSet::from_config(&config).expect("TODO: handle error"));
+
+        for (name, txt, ttl) in [
+            ("web.service.ns.corp.internal", "corp.svc:443:tcp", 300),
+            ("web.service.ns.lab.internal", "lab.svc:80:tcp", 60),
+        ] {
+            let resp = Message::from_vec(
+                &handle_query(&query_bytes(name), &state).expect("TODO: handle error"),
+            )
+            .expect("TODO: handle error");
+            let answer = &resp.answers()[0];
+            assert_eq!(answer.ttl(), ttl);
+            assert_eq!(answer.data().to_string(), txt);
+        }
+        assert_eq!(state.zone().domain, "corp.internal");
+    }
 }
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Detached minisign signatures on configs. The config decides which users,
+//! groups and mounts exist on every host, so a server given trusted keys
+//! refuses a config, at startup or on reload, unless it comes with a valid
+//! signature by one of them in a `.minisig` file beside it.
+//!
+//! Both of minisign's Ed25519 formats are accepted: the default, which
+//! signs the BLAKE2b-512 hash of the file, and the legacy one (`-l`),
+//! which signs the file itself.
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use blake2::{Blake2b512, Digest};
+use ring::signature::{ED25519, UnparsedPublicKey};
+use tracing::debug;
+
+use crate::error::{Context, HesiodError, Result};
+
+/// Suffix of the signature file next to a config.
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+
+/// A minisign Ed25519 public key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl PublicKey {
+    /// The key ID, as minisign prints it.
+    pub fn id(&self) -> String {
+        format!("{:016X}", u64::from_le_bytes(self.id))
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = HesiodError;
+
+    /// A key as printed by `minisign -G`, or the contents of its `.pub`
+    /// file.
+    fn from_str(s: &str) -> Result<Self> {
+        let line = s
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .config_err(|| "empty minisign public key")?;
+        let bytes = BASE64
+            .decode(line)
+            .config_err(|| "minisign public key is not base64")?;
+        match bytes.as_slice() {
+            [b'E', b'd', rest @ ..] if rest.len() == 40 => Ok(Self {
+                id: rest[..8].try_into().unwrap_or_default(),
+                key: rest[8..].try_into().unwrap_or_default(),
+            }),
+            _ => Err(HesiodError::config("not a minisign Ed25519 public key")),
+        }
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({})", self.id())
+    }
+}
+
+/// Keys a config may be signed with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedKeys(Vec<PublicKey>);
+
+impl TrustedKeys {
+    pub fn new(keys: Vec<PublicKey>) -> Self {
+        Self(keys)
+    }
+
+    /// IDs of the keys, as minisign prints them.
+    pub fn ids(&self) -> Vec<String> {
+        self.0.iter().map(PublicKey::id).collect()
+    }
+
+    /// Check that `signature`, the contents of a `.minisig` file, is a
+    /// valid signature of `data` by one of the keys.
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<()> {
+        let signature = Signature::parse(signature)?;
+        let key = self
+            .0
+            .iter()
+            .find(|key| key.id == signature.key_id)
+            .config_err(|| {
+                let id = u64::from_le_bytes(signature.key_id);
+                format!("config is signed with untrusted key {id:016X}")
+            })?;
+        let key = UnparsedPublicKey::new(&ED25519, key.key);
+        let signed = if signature.prehashed {
+            Blake2b512::digest(data).to_vec()
+        } else {
+            data.to_vec()
+        };
+        key.verify(&signed, &signature.signature)
+            .map_err(|_| HesiodError::config("config does not match its signature"))?;
+        let global = [
+            &signature.signature[..],
+            signature.trusted_comment.as_bytes(),
+        ]
+        .concat();
+        key.verify(&global, &signature.global_signature)
+            .map_err(|_| HesiodError::config("signature's trusted comment was tampered with"))?;
+        debug!("config signature verified ({})", signature.trusted_comment);
+        Ok(())
+    }
+}
+
+/// A parsed `.minisig` file.
+struct Signature {
+    /// Signs the BLAKE2b-512 hash of the data instead of the data.
+    prehashed: bool,
+    key_id: [u8; 8],
+    signature: [u8; 64],
+    trusted_comment: String,
+    /// Signature of `signature` followed by `trusted_comment`.
+    global_signature: [u8; 64],
+}
+
+impl Signature {
+    fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().map(str::trim_end);
+        let mut line = || lines.next().config_err(|| "truncated minisign signature");
+        line()?
+            .strip_prefix("untrusted comment:")
+            .config_err(|| "minisign signature lacks its untrusted comment")?;
+        let decode = |line: &str| {
+            BASE64
+                .decode(line.trim())
+                .config_err(|| "minisign signature is not base64")
+        };
+        let signature = decode(line()?)?;
+        let trusted_comment = line()?
+            .strip_prefix("trusted comment: ")
+            .config_err(|| "minisign signature lacks its trusted comment")?
+            .to_string();
+        let global_signature = decode(line()?)?
+            .try_into()
+            .map_err(|_| HesiodError::config("malformed minisign global signature"))?;
+        let (prehashed, rest) = match signature.as_slice() {
+            [b'E', b'D', rest @ ..] => (true, rest),
+            [b'E', b'd', rest @ ..] => (false, rest),
+            _ => {
+                return Err(HesiodError::config("not a minisign Ed25519 signature"));
+            }
+        };
+        if rest.len() != 72 {
+            return Err(HesiodError::config("malformed minisign signature"));
+        }
+        Ok(Self {
+            prehashed,
+            key_id: rest[..8].try_into().unwrap_or_default(),
+            signature: rest[8..].try_into().unwrap_or([0; 64]),
+            trusted_comment,
+            global_signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::ConfigSource;
+
+    /// Made with Python's `cryptography` and `hashlib.blake2b` from the
+    /// seed `00 01 .. 1f`, in minisign's formats.
+    const PUBLIC_KEY: &str = "RWQRIjNEVWZ3iAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const CONFIG: &str =
+        "{\"domain\": \"signed.internal\", \"lhs\": \".ns\", \"rhs\": \".signed.internal\"}\n";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQRIjNEVWZ3iB0+vXLaqdtrA8kULtBIlMJ7CTucQMSAc+ycJY0l2GuAeRV2s0z7xP8q4NFFs8hJY0KFHslbA+T+jUEbDMZIfwQ=
+trusted comment: timestamp:1700000000\tfile:hesiod.json\thashed
+pBjZz1/7fHrq1AhNwQveMgRV5ZlU0m3dNMzWoJttjAl7MipOH2cSDDyLMGD6W4eb64KPufAaWl1BRnHPomxUAg==
+";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: legacy
+RWQRIjNEVWZ3iKEN9b69xUwqebZOw3Hz2CetvgAsy6grYqD8bdYTuu3YQuvoqLrXhzr5IvAeK/o7axct4dLTyn6WsguocMBWkwU=
+trusted comment: legacy
+fzlq1mn6WP+dJ+I1n83/d10JqddC/9MlRvfHs2X0AXtwb08XN39wG945jYKJceHVSoM3QV4DgAp2jphEez31DA==
+";
+
+    fn trusted() -> TrustedKeys {
+        TrustedKeys::new(vec![PUBLIC_KEY.parse().expect("TODO: handle error")])
+    }
+
+    #[test]
+    fn verifies_both_minisign_formats() {
+        let keys = trusted();
+        assert_eq!(keys.ids(), ["8877665544332211"]);
+        keys.verify(CONFIG.as_bytes(), SIGNATURE)
+            .expect("TODO: handle error");
+        keys.verify(CONFIG.as_bytes(), LEGACY_SIGNATURE)
+            .expect("TODO: handle error");
+        let public_file = format!("untrusted comment: minisign public key\n{PUBLIC_KEY}\n");
+        assert_eq!(
+            public_file.parse::<PublicKey>().ok(),
+            keys.0.first().cloned()
+        );
+    }
+
+    #[test]
+    fn rejects_tampering_and_unknown_keys() {
+        let keys = trusted();
+        let tampered = CONFIG.replace("signed.internal", "evil.internal");
+        assert!(keys.verify(tampered.as_bytes(), SIGNATURE).is_err());
+        let comment = SIGNATURE.replace("1700000000", "1800000000");
+        assert!(keys.verify(CONFIG.as_bytes(), &comment).is_err());
+        assert!(keys.verify(CONFIG.as_bytes(), "").is_err());
+        assert!(
+            TrustedKeys::default()
+                .verify(CONFIG.as_bytes(), SIGNATURE)
+                .is_err()
+        );
+        assert!("RWQ=".parse::<PublicKey>().is_err());
+    }
+
+    #[tokio::test]
+    async fn signed_sources_need_a_valid_signature() {
+        let dir = std::env::temp_dir().join(format!("hesiod-signed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("TODO: handle error");
+        let path = dir.join("hesiod.json");
+        let signature = dir.join("hesiod.json.minisig");
+        std::fs::write(&path, CONFIG).expect("TODO: handle error");
+        let source = ConfigSource::File(path.clone()).signed(trusted());
+
+        assert!(source.load().await.is_err(), "unsigned config was loaded");
+        std::fs::write(&signature, SIGNATURE).expect("TODO: handle error");
+        let config = source.load().await.expect("TODO: handle error");
+        assert_eq!(config.domain, "signed.internal");
+
+        std::fs::write(&path, CONFIG.replace("signed", "evil")).expect("TODO: handle error");
+        assert!(source.load().await.is_err(), "tampered config was loaded");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,734 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Config sources the served zone is loaded from, and sync bookkeeping.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{
+    ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::{HesiodConfig, SyncConfig};
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::git::GitSource;
+use crate::notify::ZoneChange;
+use crate::s3::S3Source;
+use crate::server::DnsServerState;
+use crate::signature::{SIGNATURE_SUFFIX, TrustedKeys};
+use crate::zone::{HesiodZone, ZoneSet};
+
+/// How long a fetch of an HTTP config may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the server's config (and therefore its records) comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// JSON file on local disk (output of `nickel export`).
+    File(PathBuf),
+    /// JSON config served over HTTP(S), e.g. by an artifact store.
+    Http(HttpSource),
+    /// JSON config object in an S3-compatible bucket.
+    S3(S3Source),
+    /// JSON config file in a git repository.
+    Git(GitSource),
+    /// A config that is only loaded with a valid signature by one of the
+    /// keys, kept beside it with a `.minisig` suffix.
+    Signed(Box<ConfigSource>, TrustedKeys),
+}
+
+impl ConfigSource {
+    /// This source, accepting only configs signed by one of `keys`.
+    pub fn signed(self, keys: TrustedKeys) -> Self {
+        match self {
+            ConfigSource::Signed(source, _) => ConfigSource::Signed(source, keys),
+            source => ConfigSource::Signed(Box::new(source), keys),
+        }
+    }
+
+    /// Fetch and parse the config, and resolve its secrets off the runtime.
+    pub async fn load(&self) -> Result<HesiodConfig> {
+        let mut config = self.fetch().await?;
+        tokio::task::spawn_blocking(move || config.resolve_secrets().map(|()| config))
+            .await
+            .config_err(|| "resolving config secrets")?
+    }
+
+    /// Fetch and parse the config, leaving its secrets unresolved.
+    async fn fetch(&self) -> Result<HesiodConfig> {
+        let (source, keys) = match self {
+            ConfigSource::Signed(source, keys) => (source.as_ref(), Some(keys)),
+            source => (source, None),
+        };
+        match source {
+            ConfigSource::File(path) => read_config(path, keys),
+            ConfigSource::Http(source) => source.fetch(keys).await,
+            ConfigSource::S3(source) => source.fetch(keys).await,
+            ConfigSource::Git(source) => source.fetch(keys).await,
+            ConfigSource::Signed(..) => Err(HesiodError::config(
+                "a signed config source can't wrap another",
+            )),
+        }
+    }
+
+    /// The git commit last loaded, for git sources.
+    pub fn commit(&self) -> Option<String> {
+        match self {
+            ConfigSource::Git(source) => source.commit(),
+            ConfigSource::Signed(source, _) => source.commit(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "file:{}", path.display()),
+            // Headers are left out, as they usually hold credentials.
+            ConfigSource::Http(source) => f.write_str(&source.shown),
+            ConfigSource::S3(source) => source.fmt(f),
+            ConfigSource::Git(source) => source.fmt(f),
+            ConfigSource::Signed(source, _) => write!(f, "{source} (signed)"),
+        }
+    }
+}
+
+/// A config polled over HTTP(S) with conditional requests: once fetched,
+/// it is asked for again with its `ETag` and `Last-Modified`, and a
+/// `304 Not Modified` reuses the config already parsed.
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    url: String,
+    /// `url` without the userinfo, query and fragment, where credentials
+    /// and presigned tokens go, for logs and errors.
+    shown: String,
+    /// Sent with every request, e.g. `Authorization`.
+    headers: Vec<(HeaderName, HeaderValue)>,
+    client: reqwest::Client,
+    last: Arc<LastFetch>,
+}
+
+impl HttpSource {
+    /// A source for the `http` or `https` `url`, sending `headers` (name and
+    /// value pairs) with each request.
+    pub fn new(url: &str, headers: &[(String, String)]) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).config_err(|| format!("invalid config URL {url}"))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(HesiodError::config(format!(
+                "config URL {} is not http or https",
+                without_credentials(parsed)
+            )));
+        }
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.trim().as_bytes())
+                    .config_err(|| format!("invalid header name {name:?}"))?;
+                let mut value = HeaderValue::from_str(value.trim())
+                    .config_err(|| format!("invalid value for header {name}"))?;
+                value.set_sensitive(true);
+                Ok((name, value))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            url: parsed.to_string(),
+            shown: without_credentials(parsed),
+            headers,
+            client: fetch_client()?,
+            last: Arc::default(),
+        })
+    }
+
+    /// The config at the URL, or the one fetched before if it hasn't
+    /// changed since. With `keys`, a new config must be signed, at the
+    /// URL with `.minisig` added to its path.
+    async fn fetch(&self, keys: Option<&TrustedKeys>) -> Result<HesiodConfig> {
+        let signature = match keys {
+            Some(_) => {
+                let mut url = reqwest::Url::parse(&self.url)
+                    .config_err(|| format!("invalid config URL {}", self.shown))?;
+                url.set_path(&format!("{}{SIGNATURE_SUFFIX}", url.path()));
+                let shown = without_credentials(url.clone());
+                let response = self
+                    .request(url.as_str())
+                    .send()
+                    .await
+                    .and_then(Response::error_for_status)
+                    .map_err(reqwest::Error::without_url)
+                    .config_err(|| format!("fetching config signature from {shown}"))?;
+                let text = response.text().await.map_err(reqwest::Error::without_url);
+                Some(text.config_err(|| format!("reading config signature from {shown}"))?)
+            }
+            None => None,
+        };
+        let response = self
+            .last
+            .condition(self.request(&self.url))
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .config_err(|| format!("fetching config from {}", self.shown))?;
+        self.last
+            .read(response, &self.shown, |_, body| {
+                verify_signature(keys, body, signature.as_deref(), &self.shown)
+            })
+            .await
+    }
+
+    fn request(&self, url: &str) -> RequestBuilder {
+        let mut request = self.client.get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+/// `url` without its userinfo, query and fragment.
+fn without_credentials(mut url: reqwest::Url) -> String {
+    // Only URLs that can't have a username refuse these, and they have none.
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.set_fragment(None);
+    url.into()
+}
+
+/// Read the config at `path`. With `keys`, it must be signed, in a file
+/// beside it with a `.minisig` suffix.
+pub(crate) fn read_config(path: &Path, keys: Option<&TrustedKeys>) -> Result<HesiodConfig> {
+    let Some(keys) = keys else {
+        return HesiodConfig::from_file(path);
+    };
+    let content =
+        std::fs::read(path).io_err(|| format!("reading config from {}", path.display()))?;
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(SIGNATURE_SUFFIX);
+    let signature_path = PathBuf::from(signature_path);
+    let signature = std::fs::read_to_string(&signature_path)
+        .io_err(|| format!("reading config signature from {}", signature_path.display()))?;
+    let origin = path.display().to_string();
+    verify_signature(Some(keys), &content, Some(&signature), &origin)?;
+    let json =
+        std::str::from_utf8(&content).config_err(|| format!("config at {origin} is not UTF-8"))?;
+    HesiodConfig::from_json(json)
+}
+
+/// Check `body` from `origin` against `signature` when there are `keys`.
+pub(crate) fn verify_signature(
+    keys: Option<&TrustedKeys>,
+    body: &[u8],
+    signature: Option<&str>,
+    origin: &str,
+) -> Result<()> {
+    let Some(keys) = keys else {
+        return Ok(());
+    };
+    let signature = signature.config_err(|| format!("config from {origin} is not signed"))?;
+    keys.verify(body, signature)
+        .config_err(|| format!("verifying the signature of the config from {origin}"))
+}
+
+/// HTTP client for fetching configs.
+pub(crate) fn fetch_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .config_err(|| "building the HTTP client")
+}
+
+/// The last full response of a polled source, whose validators make the
+/// next request conditional.
+#[derive(Debug, Default)]
+pub(crate) struct LastFetch(Mutex<Option<Fetched>>);
+
+#[derive(Debug)]
+struct Fetched {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    config: HesiodConfig,
+}
+
+impl LastFetch {
+    /// `request` made conditional on the last response, if there was one.
+    pub(crate) fn condition(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(last) = self.lock().as_ref() {
+            if let Some(etag) = &last.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = &last.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, modified);
+            }
+        }
+        request
+    }
+
+    /// The config in `response` from `origin`, or the last one on a `304`.
+    /// `verify` checks a new body against the response headers before it
+    /// is parsed.
+    pub(crate) async fn read(
+        &self,
+        response: Response,
+        origin: &str,
+        verify: impl FnOnce(&HeaderMap, &[u8]) -> Result<()>,
+    ) -> Result<HesiodConfig> {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            debug!("config at {} is unchanged", origin);
+            return self
+                .lock()
+                .as_ref()
+                .map(|last| last.config.clone())
+                .config_err(|| format!("{origin} answered 304 to an unconditional request"));
+        }
+        let response = response
+            .error_for_status()
+            .map_err(reqwest::Error::without_url)
+            .config_err(|| format!("fetching config from {origin}"))?;
+        let headers = response.headers().clone();
+        let body = response
+            .bytes()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .config_err(|| format!("reading config from {origin}"))?;
+        verify(&headers, &body)?;
+        let json = std::str::from_utf8(&body)
+            .config_err(|| format!("config from {origin} is not UTF-8"))?;
+        let config = HesiodConfig::from_json(json)?;
+        *self.lock() = Some(Fetched {
+            etag: headers.get(ETAG).cloned(),
+            last_modified: headers.get(LAST_MODIFIED).cloned(),
+            config: config.clone(),
+        });
+        Ok(config)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Fetched>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sources are the same if they fetch the same URL with the same headers.
+impl PartialEq for HttpSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url && self.headers == other.headers
+    }
+}
+
+impl Eq for HttpSource {}
+
+/// Outcome of the most recent syncs against the config source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SyncStatus {
+    /// Unix time of the last successful load.
+    pub last_success_unix: Option<u64>,
+    /// Unix time of the last attempt, successful or not.
+    pub last_attempt_unix: Option<u64>,
+    /// Error from the last attempt, cleared on success.
+    pub last_error: Option<String>,
+    /// Successful loads since startup.
+    pub successes: u64,
+    /// Failed loads since startup.
+    pub failures: u64,
+    /// Commit of the config being served, for git sources.
+    pub commit: Option<String>,
+}
+
+impl SyncStatus {
+    pub fn record_success(&mut self) {
+        let now = unix_now();
+        self.last_success_unix = Some(now);
+        self.last_attempt_unix = Some(now);
+        self.last_error = None;
+        self.successes += 1;
+    }
+
+    /// A success loading from `source`, noting the commit it was at.
+    pub fn record_load(&mut self, source: &ConfigSource) {
+        self.record_success();
+        self.commit = source.commit();
+    }
+
+    pub fn record_failure(&mut self, error: &HesiodError) {
+        self.last_attempt_unix = Some(unix_now());
+        self.last_error = Some(format!("{error:#}"));
+        self.failures += 1;
+    }
+
+    /// The last sync failed, so the zone being served may be stale.
+    pub fn is_degraded(&self) -> bool {
+        self.last_error.is_some()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Reload the zones from the state's config source, keeping the current
+/// ones if the load fails. Returns the new record count.
+pub async fn reload_zone(state: &DnsServerState) -> Result<usize> {
+    let Some(source) = &state.source else {
+        return Err(HesiodError::config(
+            "server has no config source to reload from",
+        ));
+    };
+    let result = async {
+        let config = source.load().await?;
+        ZoneSet::from_config(&config)
+    }
+    .await;
+
+    match result {
+        Ok(zones) => {
+            let count = zones.record_count();
+            state.update_sync(|sync| sync.record_load(source));
+            let previous = state.zones();
+            let changes: Vec<_> = zones
+                .iter()
+                .filter_map(|zone| zone_change(previous.get(&zone.domain).map(Arc::as_ref), zone))
+                .collect();
+            let removed = previous
+                .iter()
+                .any(|old| zones.iter().all(|zone| zone.domain != old.domain));
+            let settled = zones.iter().all(|zone| {
+                previous
+                    .get(&zone.domain)
+                    .is_some_and(|old| old.same_settings(zone))
+            });
+            if changes.is_empty() && !removed && settled && zones.same_admin_tokens(&previous) {
+                info!("reloaded {} records from {}, no changes", count, source);
+                return Ok(count);
+            }
+
+            // Only record changes are worth a webhook; settings and tokens
+            // are swapped in quietly.
+            let serial = state.replace_zones(zones);
+            if changes.is_empty() {
+                info!(
+                    "reloaded {} records from {} (serial {}: settings only)",
+                    count, source, serial
+                );
+            }
+            for mut change in changes {
+                change.serial = serial;
+                state.notifier.notify(&change);
+                info!(
+                    "reloaded {} from {} (serial {}: +{} -{} ~{})",
+                    change.domain, source, serial, change.added, change.removed, change.changed
+                );
+            }
+            Ok(count)
+        }
+        Err(e) => {
+            state.update_sync(|sync| sync.record_failure(&e));
+            warn!(
+                "reload from {} failed, serving previous zone: {:#}",
+                source, e
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Reload just the zone for `domain` from the state's config source,
+/// leaving the other zones as they are. Returns its new record count.
+pub async fn reload_one_zone(state: &DnsServerState, domain: &str) -> Result<usize> {
+    let Some(source) = &state.source else {
+        return Err(HesiodError::config(
+            "server has no config source to reload from",
+        ));
+    };
+    let fresh = match async { ZoneSet::from_config(&source.load().await?) }.await {
+        Ok(fresh) => fresh,
+        Err(e) => {
+            state.update_sync(|sync| sync.record_failure(&e));
+            warn!("reload of {} from {} failed: {:#}", domain, source, e);
+            return Err(e);
+        }
+    };
+    state.update_sync(|sync| sync.record_load(source));
+    let zones = state
+        .zones()
+        .with_zone_from(&fresh, domain)
+        .zone_err(|| format!("zone {domain} is not in the config"))?;
+    let zone = Arc::clone(zones.get(domain).zone_err(|| "zone vanished")?);
+    let previous = state.zones();
+    let old = previous.get(domain).map(Arc::as_ref);
+    let change = zone_change(old, &zone);
+    let settled = old.is_some_and(|old| old.same_settings(&zone));
+    if change.is_none() && settled && zones.same_admin_tokens(&previous) {
+        info!("reloaded {} from {}, no changes", zone.domain, source);
+        return Ok(zone.record_count());
+    }
+    let serial = state.replace_zones(zones);
+    match change {
+        Some(mut change) => {
+            change.serial = serial;
+            state.notifier.notify(&change);
+            info!(
+                "reloaded {} from {} (serial {}: +{} -{} ~{})",
+                zone.domain, source, serial, change.added, change.removed, change.changed
+            );
+        }
+        None => info!(
+            "reloaded {} from {} (serial {}: settings only)",
+            zone.domain, source, serial
+        ),
+    }
+    Ok(zone.record_count())
+}
+
+/// Spawn the task resyncing with the state's config source every
+/// `interval_secs`, if set. Each wait is jittered, and a failed resync keeps
+/// the current zones and is retried on the next tick, so a source that
+/// stalls or errors shows in the sync status instead of wedging the task.
+pub fn spawn_periodic_sync(
+    state: Arc<DnsServerState>,
+    config: &SyncConfig,
+) -> Option<JoinHandle<()>> {
+    let interval = Duration::from_secs(config.interval_secs?.max(1));
+    let source = state.source.as_ref()?;
+    info!(
+        "resyncing from {} every {}s (±{}%)",
+        source,
+        interval.as_secs(),
+        config.jitter
+    );
+    let (source, jitter) = (source.clone(), config.jitter);
+    Some(tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(jittered(interval, jitter)).await;
+            // Other failures are logged and recorded by the reload itself.
+            if tokio::time::timeout(interval, reload_zone(&state))
+                .await
+                .is_err()
+            {
+                let e = HesiodError::config(format!(
+                    "resync from {source} took over {}s",
+                    interval.as_secs()
+                ));
+                warn!("{e}, serving previous zone");
+                state.update_sync(|sync| sync.record_failure(&e));
+            }
+        }
+    }))
+}
+
+/// `interval` moved by a random amount within ±`percent`% (at most 100).
+fn jittered(interval: Duration, percent: u8) -> Duration {
+    let spread = interval.mul_f64(f64::from(percent.min(100)) / 100.0);
+    if spread.is_zero() {
+        return interval;
+    }
+    rand::random_range(interval - spread..=interval + spread)
+}
+
+/// What changed from `old` (`None` for a zone new to the config) to `new`,
+/// or `None` if nothing did. The serial is left for the caller to fill in.
+fn zone_change(old: Option<&HesiodZone>, new: &HesiodZone) -> Option<ZoneChange> {
+    let empty = HesiodZone::new(&new.domain, &new.lhs, &new.rhs, new.ttl);
+    let old = old.unwrap_or(&empty);
+    let diff = old.diff(new);
+    if diff.is_empty() {
+        return None;
+    }
+    let mut change = ZoneChange {
+        domain: new.domain.clone(),
+        serial: 0,
+        checksum: new.checksum().to_string(),
+        previous_checksum: old.checksum().to_string(),
+        zone_records: new.record_count(),
+        added: 0,
+        removed: 0,
+        changed: 0,
+    };
+    change.counts_from(&diff);
+    Some(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_degrades_until_success() {
+        let mut sync = SyncStatus::default();
+        sync.record_success();
+        assert!(!sync.is_degraded());
+
+        sync.record_failure(&HesiodError::config("connection refused"));
+        assert!(sync.is_degraded());
+        assert!(sync.last_success_unix.is_some());
+        assert_eq!(sync.last_error.as_deref(), Some("connection refused"));
+
+        sync.record_success();
+        assert!(!sync.is_degraded());
+        assert_eq!((sync.successes, sync.failures), (2, 1));
+    }
+
+    #[test]
+    fn jitter_stays_within_the_percent() {
+        let interval = Duration::from_secs(100);
+        assert_eq!(jittered(interval, 0), interval);
+        for _ in 0..100 {
+            let wait = jittered(interval, 10);
+            assert!((90..=110).contains(&wait.as_secs()), "{wait:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn periodic_sync_picks_up_changes() {
+        let path = std::env::temp_dir().join(format!("hesiod-resync-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal"}"#,
+        )
+        .expect("TODO: handle error");
+        let zone = HesiodZone::new("t.internal", ".ns", ".t.internal", 300);
+        let state =
+            Arc::new(DnsServerState::new(zone).with_source(ConfigSource::File(path.clone())));
+        let config = SyncConfig {
+            interval_secs: Some(60),
+            jitter: 0,
+        };
+        let task = spawn_periodic_sync(Arc::clone(&state), &config).expect("interval is set");
+
+        std::fs::write(
+            &path,
+            r#"{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal",
+                "services": [{"name": "web", "host": "web.svc", "port": 443}]}"#,
+        )
+        .expect("TODO: handle error");
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert_eq!(state.zone().record_count(), 1);
+
+        std::fs::write(&path, "not json").expect("TODO: handle error");
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        task.abort();
+        std::fs::remove_file(&path).ok();
+        let sync = state.sync_status();
+        assert_eq!((sync.successes, sync.failures), (2, 1));
+        assert_eq!(state.zone().record_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn http_source_polls_conditionally() {
+        use axum::http::{HeaderMap, StatusCode as HttpStatus};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let full = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&full);
+        let app = axum::Router::new().route(
+            "/hesiod.json",
+            axum::routing::get(move |headers: HeaderMap| async move {
+                let mut response = axum::http::Response::builder();
+                if headers
+                    .get("authorization")
+                    .is_none_or(|v| v != "Bearer t0ken")
+                {
+                    response = response.status(HttpStatus::UNAUTHORIZED);
+                } else if headers.get("if-none-match").is_some_and(|v| v == "\"v1\"") {
+                    response = response.status(HttpStatus::NOT_MODIFIED);
+                } else {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    response = response.header("etag", "\"v1\"");
+                    let body = r#"{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal",
+                        "services": [{"name": "web", "host": "web.svc", "port": 443}]}"#;
+                    return response
+                        .body(axum::body::Body::from(body))
+                        .expect("response");
+                }
+                response.body(axum::body::Body::empty()).expect("response")
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TODO: handle error");
+        let addr = listener.local_addr().expect("TODO: handle error");
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("http://{addr}/hesiod.json");
+        let auth = [("Authorization".to_string(), "Bearer t0ken".to_string())];
+        let source = ConfigSource::Http(HttpSource::new(&url, &auth).expect("valid source"));
+        assert_eq!(source.to_string(), url);
+        let secret_url = format!("http://user:pa55@{addr}/hesiod.json?token=s3cret#top");
+        let shown = HttpSource::new(&secret_url, &auth).expect("valid source");
+        assert_eq!(ConfigSource::Http(shown).to_string(), url);
+        for _ in 0..3 {
+            let config = source.load().await.expect("TODO: handle error");
+            assert_eq!(config.services.len(), 1);
+        }
+        assert_eq!(full.load(Ordering::SeqCst), 1, "later fetches are 304s");
+
+        let anonymous = HttpSource::new(&url, &[]).expect("valid source");
+        assert!(ConfigSource::Http(anonymous).load().await.is_err());
+        assert!(HttpSource::new("ftp://config-host/hesiod.json", &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_zone_on_error() {
+        let path = std::env::temp_dir().join(format!("hesiod-reload-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal",
+                "services": [{"name": "web", "host": "web.svc", "port": 443}]}"#,
+        )
+        .expect("TODO: handle error");
+
+        let zone = HesiodZone::new("t.internal", ".ns", ".t.internal", 300);
+        let state = DnsServerState::new(zone).with_source(ConfigSource::File(path.clone()));
+        let serial = state.zone_serial();
+        assert_eq!(reload_zone(&state).await.expect("TODO: handle error"), 1);
+        let serial = (state.zone_serial() > serial).then_some(state.zone_serial());
+        reload_zone(&state).await.expect("TODO: handle error");
+        assert_eq!(
+            Some(state.zone_serial()),
+            serial,
+            "unchanged reload keeps the serial"
+        );
+
+        std::fs::write(&path, "not json").expect("TODO: handle error");
+        assert!(reload_zone(&state).await.is_err());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(state.zone().record_count(), 1);
+        assert!(state.sync_status().is_degraded());
+    }
+
+    #[tokio::test]
+    async fn reload_applies_ttl_only_changes() {
+        let path = std::env::temp_dir().join(format!("hesiod-ttl-{}.json", std::process::id()));
+        let config = |ttl: u32| {
+            format!(
+                r#"{{"domain": "t.internal", "lhs": ".ns", "rhs": ".t.internal", "ttl": {ttl},
+                    "services": [{{"name": "web", "host": "web.svc", "port": 443}}]}}"#
+            )
+        };
+        std::fs::write(&path, config(300)).expect("TODO: handle error");
+        let zone = HesiodZone::new("t.internal", ".ns", ".t.internal", 300);
+        let state = DnsServerState::new(zone).with_source(ConfigSource::File(path.clone()));
+        reload_zone(&state).await.expect("TODO: handle error");
+        let serial = state.zone_serial();
+
+        std::fs::write(&path, config(600)).expect("TODO: handle error");
+        reload_zone(&state).await.expect("TODO: handle error");
+        assert_eq!(state.zone().ttl, 600);
+        assert!(state.zone_serial() > serial);
+
+        std::fs::write(&path, config(900)).expect("TODO: handle error");
+        reload_one_zone(&state, "t.internal")
+            .await
+            .expect("TODO: handle error");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(state.zone().ttl, 900);
+    }
+}
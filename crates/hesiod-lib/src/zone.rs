@@ -2,14 +2,103 @@
 //! Hesiod zone management: record storage, lookup, and BIND zone file generation.
 
 use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::config::HesiodConfig;
+use crate::config::{HesiodConfig, SoaConfig};
+use crate::error::{Context, HesiodError, Result};
+use crate::idn::{fold_key, key_to_ascii, normalize_key};
 use crate::records::*;
+use crate::secret::Secret;
 
-/// Key for zone lookups: (name, map_type).
-type ZoneKey = (String, MapType);
+/// Values keyed by (map_type, name): an Fx-hashed map per map type, so a
+/// lookup hashes the borrowed name alone instead of building an owned key.
+#[derive(Debug, Clone, PartialEq)]
+struct ZoneIndex<V> {
+    maps: [FxHashMap<String, V>; MapType::ALL.len()],
+}
+
+impl<V> ZoneIndex<V> {
+    fn new() -> Self {
+        Self {
+            maps: Default::default(),
+        }
+    }
+
+    fn map(&self, map_type: MapType) -> &FxHashMap<String, V> {
+        &self.maps[map_type.index()]
+    }
+
+    fn map_mut(&mut self, map_type: MapType) -> &mut FxHashMap<String, V> {
+        &mut self.maps[map_type.index()]
+    }
+
+    fn len(&self) -> usize {
+        self.maps.iter().map(FxHashMap::len).sum()
+    }
+
+    /// Every entry as `((name, map_type), value)`, a map type at a time.
+    fn iter(&self) -> impl Iterator<Item = ((&String, MapType), &V)> {
+        MapType::ALL.into_iter().flat_map(move |map_type| {
+            self.map(map_type)
+                .iter()
+                .map(move |(name, value)| ((name, map_type), value))
+        })
+    }
+}
+
+/// SOA serial written by [`HesiodZone::to_bind_zone`].
+const DEFAULT_SERIAL: u32 = 2026020801;
+
+/// Differences between two zones, as `(name, record)` entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneDiff {
+    pub added: Vec<(String, HesiodRecord)>,
+    pub removed: Vec<(String, HesiodRecord)>,
+    /// `(name, old, new)` for records whose content changed.
+    pub changed: Vec<(String, HesiodRecord, HesiodRecord)>,
+}
+
+impl ZoneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Serializable copy of a zone, as served by `GET /dns/records`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZoneSnapshot {
+    pub domain: String,
+    pub lhs: String,
+    pub rhs: String,
+    pub ttl: u32,
+    /// Checksum of the whole zone, even when `records` is filtered to one map.
+    pub checksum: String,
+    pub records: Vec<SnapshotRecord>,
+}
+
+/// One named record in a [`ZoneSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    pub name: String,
+    pub record: HesiodRecord,
+}
+
+impl ZoneSnapshot {
+    /// Rebuild a zone from the snapshot.
+    pub fn into_zone(self) -> HesiodZone {
+        let mut zone = HesiodZone::new(&self.domain, &self.lhs, &self.rhs, self.ttl);
+        for entry in self.records {
+            zone.add_record(&entry.name, entry.record);
+        }
+        zone
+    }
+}
 
 /// A Hesiod zone holding all records for a domain.
 #[derive(Debug, Clone)]
@@ -18,7 +107,19 @@ pub struct HesiodZone {
     pub lhs: String,
     pub rhs: String,
     pub ttl: u32,
-    records: HashMap<ZoneKey, HesiodRecord>,
+    /// See [`HesiodConfig::negative_ttl`].
+    pub negative_ttl: Option<u32>,
+    /// Header data for [`HesiodZone::to_bind_zone`]; not part of the checksum.
+    pub soa: SoaConfig,
+    records: ZoneIndex<HesiodRecord>,
+    /// Keys of `records` by their [`fold_key`] form, for those that differ
+    /// from it.
+    folded: ZoneIndex<String>,
+    /// Tags of the records that have any, for views; not part of the
+    /// checksum.
+    tags: ZoneIndex<Vec<String>>,
+    /// Cached content hash, cleared whenever records change.
+    checksum: OnceLock<String>,
 }
 
 impl HesiodZone {
@@ -29,19 +130,93 @@ pub fn new(domain: &str, lhs: &str, rhs: &str, ttl: u32) -> Self {
             lhs: lhs.to_string(),
             rhs: rhs.to_string(),
             ttl,
-            records: HashMap::new(),
+            negative_ttl: None,
+            soa: SoaConfig::default(),
+            records: ZoneIndex::new(),
+            folded: ZoneIndex::new(),
+            tags: ZoneIndex::new(),
+            checksum: OnceLock::new(),
         }
     }
 
-    /// Add a record to the zone. The key is derived from the record's name field.
+    /// Add a record to the zone. The key is derived from the record's name
+    /// field and stored in NFC.
     pub fn add_record(&mut self, name: &str, record: HesiodRecord) {
-        let key = (name.to_string(), record.map_type());
-        self.records.insert(key, record);
+        let (key, map_type) = (normalize_key(name).into_owned(), record.map_type());
+        let folded = fold_key(&key);
+        if folded != key {
+            self.folded
+                .map_mut(map_type)
+                .entry(folded.into_owned())
+                .or_insert_with(|| key.clone());
+        }
+        self.records.map_mut(map_type).insert(key, record);
+        self.checksum = OnceLock::new();
     }
 
-    /// Look up a record by name and map type.
+    /// Look up a record by name and map type. A name that matches no key
+    /// exactly matches one differing only in case or Unicode normalization,
+    /// the first added if there are several.
     pub fn lookup(&self, name: &str, map_type: MapType) -> Option<&HesiodRecord> {
-        self.records.get(&(name.to_string(), map_type))
+        self.lookup_entry(name, map_type).map(|(_, record)| record)
+    }
+
+    /// [`HesiodZone::lookup`] of a record carrying at least one of `tags`.
+    pub fn lookup_tagged(
+        &self,
+        name: &str,
+        map_type: MapType,
+        tags: &[String],
+    ) -> Option<&HesiodRecord> {
+        let (key, record) = self.lookup_entry(name, map_type)?;
+        let record_tags = self.tags.map(map_type).get(key)?;
+        record_tags
+            .iter()
+            .any(|tag| tags.contains(tag))
+            .then_some(record)
+    }
+
+    /// Tag a record for views; tags of missing records are ignored.
+    pub fn set_tags(&mut self, name: &str, map_type: MapType, tags: Vec<String>) {
+        let key = normalize_key(name);
+        if tags.is_empty() {
+            self.tags.map_mut(map_type).remove(key.as_ref());
+        } else if self.records.map(map_type).contains_key(key.as_ref()) {
+            self.tags.map_mut(map_type).insert(key.into_owned(), tags);
+        }
+    }
+
+    /// Tags of the record stored under `name`, if it has any.
+    pub fn tags(&self, name: &str, map_type: MapType) -> &[String] {
+        self.tags
+            .map(map_type)
+            .get(normalize_key(name).as_ref())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn lookup_entry(&self, name: &str, map_type: MapType) -> Option<(&String, &HesiodRecord)> {
+        let records = self.records.map(map_type);
+        let exact = normalize_key(name);
+        if let Some(entry) = records.get_key_value(exact.as_ref()) {
+            return Some(entry);
+        }
+        let folded = fold_key(&exact);
+        records.get_key_value(folded.as_ref()).or_else(|| {
+            let key = self.folded.map(map_type).get(folded.as_ref())?;
+            records.get_key_value(key)
+        })
+    }
+
+    /// Whether `other` has the same names, TTLs, SOA settings and record
+    /// tags, whatever its records.
+    pub fn same_settings(&self, other: &HesiodZone) -> bool {
+        self.domain == other.domain
+            && self.lhs == other.lhs
+            && self.rhs == other.rhs
+            && self.ttl == other.ttl
+            && self.negative_ttl == other.negative_ttl
+            && self.soa == other.soa
+            && self.tags == other.tags
     }
 
     /// Total number of records in the zone.
@@ -56,9 +231,90 @@ pub fn records(&self) -> impl Iterator<Item = (&str, &HesiodRecord)> {
             .map(|((name, _), rec)| (name.as_str(), rec))
     }
 
+    /// Stable SHA-256 over the zone parameters and every record, independent of
+    /// insertion order. Servers holding identical data report identical values.
+    pub fn checksum(&self) -> &str {
+        self.checksum.get_or_init(|| {
+            let mut entries: Vec<_> = self.records.iter().collect();
+            entries.sort_by(|((a_name, a_map), _), ((b_name, b_map), _)| {
+                (a_map, a_name).cmp(&(b_map, b_name))
+            });
+
+            let mut hasher = Sha256::new();
+            hasher.update(format!(
+                "{}\0{}\0{}\0{}\n",
+                self.domain, self.lhs, self.rhs, self.ttl
+            ));
+            for ((name, map_type), record) in entries {
+                hasher.update(format!("{}\0{}\0{}\n", map_type, name, record.to_txt()));
+            }
+            let mut hex = String::with_capacity(71);
+            hex.push_str("sha256:");
+            for byte in hasher.finalize() {
+                let _ = write!(hex, "{byte:02x}");
+            }
+            hex
+        })
+    }
+
+    /// Snapshot of the zone, optionally limited to one map, with records
+    /// sorted by map type then name.
+    pub fn snapshot(&self, map: Option<MapType>) -> ZoneSnapshot {
+        let mut records: Vec<SnapshotRecord> = self
+            .records
+            .iter()
+            .filter(|((_, map_type), _)| map.is_none_or(|m| m == *map_type))
+            .map(|((name, _), record)| SnapshotRecord {
+                name: name.clone(),
+                record: record.clone(),
+            })
+            .collect();
+        records.sort_by(|a, b| {
+            (a.record.map_type(), &a.name).cmp(&(b.record.map_type(), &b.name))
+        });
+        ZoneSnapshot {
+            domain: self.domain.clone(),
+            lhs: self.lhs.clone(),
+            rhs: self.rhs.clone(),
+            ttl: self.ttl,
+            checksum: self.checksum().to_string(),
+            records,
+        }
+    }
+
+    /// Record-level differences from `self` to `other`, each list sorted by
+    /// map type then name.
+    pub fn diff(&self, other: &HesiodZone) -> ZoneDiff {
+        let mut diff = ZoneDiff::default();
+        for ((name, map_type), record) in self.records.iter() {
+            match other.records.map(map_type).get(name) {
+                None => diff.removed.push((name.clone(), record.clone())),
+                Some(new) if new != record => {
+                    diff.changed
+                        .push((name.clone(), record.clone(), new.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for ((name, map_type), record) in other.records.iter() {
+            if !self.records.map(map_type).contains_key(name) {
+                diff.added.push((name.clone(), record.clone()));
+            }
+        }
+        diff.added
+            .sort_by(|a, b| (a.1.map_type(), &a.0).cmp(&(b.1.map_type(), &b.0)));
+        diff.removed
+            .sort_by(|a, b| (a.1.map_type(), &a.0).cmp(&(b.1.map_type(), &b.0)));
+        diff.changed
+            .sort_by(|a, b| (a.1.map_type(), &a.0).cmp(&(b.1.map_type(), &b.0)));
+        diff
+    }
+
     /// Build a zone from a `HesiodConfig`.
     pub fn from_config(config: &HesiodConfig) -> Result<Self> {
         let mut zone = Self::new(&config.domain, &config.lhs, &config.rhs, config.ttl);
+        zone.negative_ttl = config.negative_ttl;
+        zone.soa = config.soa.clone();
 
         for svc in &config.services {
             let record = HesiodRecord::Service(ServiceRecord {
@@ -67,6 +323,7 @@ pub fn from_config(config: &HesiodConfig) -> Result<Self> {
                 protocol: svc.protocol.clone(),
             });
             zone.add_record(&svc.name, record);
+            zone.set_tags(&svc.name, MapType::Service, svc.tags.clone());
         }
 
         for user in &config.users {
@@ -90,11 +347,65 @@ pub fn from_config(config: &HesiodConfig) -> Result<Self> {
             zone.add_record(&group.name, record);
         }
 
+        for fs in &config.filesystems {
+            let record = HesiodRecord::Filsys(FilsysRecord {
+                fs_type: fs.fs_type.clone(),
+                mount_path: fs.mount_path.clone(),
+                source: fs.source.clone(),
+                mode: fs.mode.clone(),
+            });
+            zone.add_record(&fs.name, record);
+        }
+
         Ok(zone)
     }
 
     /// Generate a BIND-format zone file string.
     pub fn to_bind_zone(&self) -> String {
+        self.to_bind_zone_with_serial(DEFAULT_SERIAL)
+    }
+
+    /// [`HesiodZone::to_bind_zone`] with the given SOA serial.
+    pub fn to_bind_zone_with_serial(&self, serial: u32) -> String {
+        let mut out = self.bind_header(serial);
+        for map_type in SECTION_ORDER {
+            out.push_str(&self.bind_section(map_type));
+        }
+        out
+    }
+
+    /// Zone split for per-map review and deployment: a master file holding
+    /// the SOA and NS records and one `$INCLUDE` per map, plus the map files
+    /// as `(file name, contents)`, e.g. `passwd.zone`. Every map gets a file,
+    /// empty or not. Include paths are `include_dir` joined with the file name;
+    /// BIND resolves relative ones against its working directory.
+    pub fn to_split_bind_zone(
+        &self,
+        serial: u32,
+        include_dir: &str,
+    ) -> (String, Vec<(String, String)>) {
+        let mut master = self.bind_header(serial);
+        let mut files = Vec::new();
+        for map_type in SECTION_ORDER {
+            let name = format!("{}.zone", map_type.label());
+            let path = match include_dir.trim_end_matches('/') {
+                "" => name.clone(),
+                dir => format!("{dir}/{name}"),
+            };
+            master.push_str(&format!("$INCLUDE {path}\n"));
+            let content = format!(
+                "; {} records for {}, included from the master zone file\n{}",
+                map_type.label(),
+                self.domain,
+                self.bind_section(map_type)
+            );
+            files.push((name, content));
+        }
+        (master, files)
+    }
+
+    /// Header comment, `$ORIGIN`, `$TTL`, SOA and NS records.
+    fn bind_header(&self, serial: u32) -> String {
         let mut out = String::with_capacity(2048);
 
         // Header comment
@@ -103,66 +414,278 @@ pub fn to_bind_zone(&self) -> String {
             self.domain
         ));
 
-        // SOA record
-        out.push_str(&format!(
-            "$ORIGIN {rhs}.\n\
-             @ IN SOA ns{rhs}. admin{rhs}. (\n\
-             \t2026020801 ; serial\n\
-             \t3600       ; refresh\n\
-             \t900        ; retry\n\
-             \t604800     ; expire\n\
-             \t{ttl}        ; minimum TTL\n\
-             )\n\n\
-             @ IN NS ns{rhs}.\n\n",
-            rhs = self.rhs,
+        // Everything is class HS: BIND rejects records whose class differs
+        // from the zone's.
+        out.push_str(&self.soa_header(self.origin(), "HS", serial));
+        out
+    }
+
+    /// `$ORIGIN`, `$TTL`, SOA and NS records for a zone named `origin` in
+    /// `class`. Default SOA names are under this zone's own origin, so
+    /// companion zones such as reverse zones share one primary.
+    pub(crate) fn soa_header(&self, origin: &str, class: &str, serial: u32) -> String {
+        let (mname, rname) = self.soa_names();
+        let mut out = format!(
+            "$ORIGIN {origin}.\n\
+             $TTL {ttl}\n\
+             @ {class} SOA {mname} {rname} (\n\
+             \t{serial} ; serial\n\
+             \t{refresh} ; refresh\n\
+             \t{retry} ; retry\n\
+             \t{expire} ; expire\n\
+             \t{minimum} ; minimum TTL\n\
+             )\n\n",
             ttl = self.ttl,
-        ));
+            refresh = self.soa.refresh,
+            retry = self.soa.retry,
+            expire = self.soa.expire,
+            minimum = self.soa_minimum(),
+        );
+        if self.soa.nameservers.is_empty() {
+            out.push_str(&format!("@ {class} NS {mname}\n"));
+        }
+        for ns in &self.soa.nameservers {
+            out.push_str(&format!("@ {class} NS {}\n", absolute(Some(ns), "")));
+        }
+        out.push('\n');
+        out
+    }
 
-        // Collect records by map type for organized output
-        let mut by_type: HashMap<MapType, Vec<(&str, &HesiodRecord)>> = HashMap::new();
-        for ((name, map_type), record) in &self.records {
-            by_type
-                .entry(*map_type)
-                .or_default()
-                .push((name.as_str(), record));
-        }
-
-        let section_order = [
-            MapType::Service,
-            MapType::Passwd,
-            MapType::Group,
-            MapType::Filsys,
-        ];
-        let section_labels = [
-            "Service records",
-            "Passwd records",
-            "Group records",
-            "Filsys records",
-        ];
-
-        for (map_type, label) in section_order.iter().zip(section_labels.iter()) {
-            if let Some(records) = by_type.get(map_type) {
-                out.push_str(&format!("; --- {} ---\n", label));
-                let mut sorted: Vec<_> = records.clone();
-                sorted.sort_by_key(|(name, _)| *name);
-                for (name, record) in sorted {
-                    out.push_str(&format!(
-                        "{name}.{map}{lhs}\t{ttl} HS TXT \"{txt}\"\n",
-                        name = name,
-                        map = map_type.label(),
-                        lhs = self.lhs,
-                        ttl = self.ttl,
-                        txt = record.to_txt(),
-                    ));
-                }
-                out.push('\n');
+    /// The SOA minimum, which resolvers cache negative answers for:
+    /// `negative_ttl`, else `soa.minimum`, else the zone TTL.
+    pub fn soa_minimum(&self) -> u32 {
+        self.negative_ttl.or(self.soa.minimum).unwrap_or(self.ttl)
+    }
+
+    /// Absolute SOA primary server and contact mailbox, defaulting to
+    /// `ns.` and `hostmaster.` under the zone origin.
+    pub(crate) fn soa_names(&self) -> (String, String) {
+        let home = self.origin();
+        let mname = absolute(self.soa.mname.as_deref(), &format!("ns.{home}"));
+        let rname = self.soa.rname.as_deref().map(|r| r.replacen('@', ".", 1));
+        let rname = absolute(rname.as_deref(), &format!("hostmaster.{home}"));
+        (mname, rname)
+    }
+
+    /// TXT records of one map, sorted by name, under a section comment.
+    /// Empty when the map has no records.
+    fn bind_section(&self, map_type: MapType) -> String {
+        let mut records: Vec<(&str, &HesiodRecord)> = self
+            .records
+            .map(map_type)
+            .iter()
+            .map(|(name, record)| (name.as_str(), record))
+            .collect();
+        if records.is_empty() {
+            return String::new();
+        }
+        records.sort_by_key(|(name, _)| *name);
+
+        let mut out = format!("; --- {} ---\n", section_label(map_type));
+        for (name, record) in records {
+            out.push_str(&format!(
+                "{name}.{map}{lhs}\t{ttl} HS TXT \"{txt}\"\n",
+                name = key_to_ascii(name),
+                map = map_type.label(),
+                lhs = self.lhs,
+                ttl = self.ttl,
+                txt = record.to_txt(),
+            ));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Order map sections appear in generated zone files.
+const SECTION_ORDER: [MapType; 4] = [
+    MapType::Service,
+    MapType::Passwd,
+    MapType::Group,
+    MapType::Filsys,
+];
+
+fn section_label(map_type: MapType) -> &'static str {
+    match map_type {
+        MapType::Service => "Service records",
+        MapType::Passwd => "Passwd records",
+        MapType::Group => "Group records",
+        MapType::Filsys => "Filsys records",
+    }
+}
+
+impl HesiodZone {
+    /// Zone file origin: the rhs without its leading dot, or the domain when
+    /// the rhs is empty.
+    pub(crate) fn origin(&self) -> &str {
+        match self.rhs.trim_matches('.') {
+            "" => self.domain.trim_matches('.'),
+            rhs => rhs,
+        }
+    }
+
+    /// The `<lhs><rhs>` every owner name in the zone ends with, lowercased.
+    fn suffix(&self) -> String {
+        format!("{}{}", self.lhs, self.rhs).to_ascii_lowercase()
+    }
+}
+
+/// The zones one server answers for: the top-level config's first (the
+/// primary zone), then those under [`HesiodConfig::zones`].
+#[derive(Debug, Clone)]
+pub struct ZoneSet {
+    zones: Vec<Arc<HesiodZone>>,
+    /// [`ZoneConfig::admin_token`](crate::config::ZoneConfig::admin_token)
+    /// by lowercased domain, kept as secrets so `Debug` leaves them out.
+    admin_tokens: HashMap<String, Secret>,
+}
+
+impl ZoneSet {
+    /// Just `zone`.
+    pub fn new(zone: HesiodZone) -> Self {
+        Self {
+            zones: vec![Arc::new(zone)],
+            admin_tokens: HashMap::new(),
+        }
+    }
+
+    /// Every zone in `config`. Zones must serve distinct name suffixes.
+    pub fn from_config(config: &HesiodConfig) -> Result<Self> {
+        let mut set = Self::new(HesiodZone::from_config(config)?);
+        for extra in &config.zones {
+            let zone = HesiodZone::from_config(&extra.to_config())
+                .zone_err(|| format!("zone {}", extra.domain))?;
+            if let Some(other) = set.iter().find(|other| other.suffix() == zone.suffix()) {
+                return Err(HesiodError::zone(format!(
+                    "zones {} and {} both serve names under {}",
+                    other.domain,
+                    zone.domain,
+                    zone.suffix()
+                )));
+            }
+            if let Some(token) = extra.admin_token.as_ref().and_then(Secret::expose) {
+                set.admin_tokens
+                    .insert(zone.domain.to_ascii_lowercase(), token.into());
             }
+            set.zones.push(Arc::new(zone));
         }
+        Ok(set)
+    }
 
-        out
+    /// The top-level zone, used where only one zone is reported.
+    pub fn primary(&self) -> &Arc<HesiodZone> {
+        &self.zones[0]
+    }
+
+    /// `zones` with the primary zone replaced.
+    pub fn with_primary(&self, zone: HesiodZone) -> Self {
+        let mut set = self.clone();
+        set.zones[0] = Arc::new(zone);
+        set
+    }
+
+    /// The zone for `domain` (case-insensitive, trailing dot optional).
+    pub fn get(&self, domain: &str) -> Option<&Arc<HesiodZone>> {
+        let domain = domain.trim_end_matches('.');
+        self.zones
+            .iter()
+            .find(|zone| zone.domain.eq_ignore_ascii_case(domain))
+    }
+
+    /// The per-zone admin token of `domain`, if it has one.
+    pub fn admin_token(&self, domain: &str) -> Option<&str> {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.admin_tokens.get(&domain).and_then(Secret::expose)
+    }
+
+    /// Whether both sets give the same zones the same admin tokens.
+    pub fn same_admin_tokens(&self, other: &ZoneSet) -> bool {
+        self.admin_tokens == other.admin_tokens
+    }
+
+    /// `zones` with the zone for `domain` and its token taken from `fresh`,
+    /// or `None` if either set lacks the zone.
+    pub fn with_zone_from(&self, fresh: &ZoneSet, domain: &str) -> Option<Self> {
+        let zone = fresh.get(domain)?;
+        let mut set = self.clone();
+        let slot = set
+            .zones
+            .iter_mut()
+            .find(|old| old.domain.eq_ignore_ascii_case(&zone.domain))?;
+        *slot = Arc::clone(zone);
+        let key = zone.domain.to_ascii_lowercase();
+        match fresh.admin_token(&key) {
+            Some(token) => set.admin_tokens.insert(key, token.into()),
+            None => set.admin_tokens.remove(&key),
+        };
+        Some(set)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HesiodZone> {
+        self.zones.iter().map(|zone| zone.as_ref())
+    }
+
+    /// Records across all zones.
+    pub fn record_count(&self) -> usize {
+        self.iter().map(HesiodZone::record_count).sum()
+    }
+
+    /// The zone a query for `name` goes to, with the key and map type it
+    /// names there. Where suffixes nest, the longest match wins.
+    #[cfg(feature = "net")]
+    pub fn resolve(
+        &self,
+        name: &hickory_proto::rr::Name,
+    ) -> Option<(&HesiodZone, String, MapType)> {
+        let ascii = name.to_ascii();
+        let (zone, key, map_type) = self
+            .iter()
+            .filter_map(|zone| {
+                let (key, map_type) =
+                    crate::naming::split_ascii_name(&ascii, &zone.lhs, &zone.rhs)?;
+                Some((zone, key, map_type))
+            })
+            .max_by_key(|(zone, _, _)| zone.lhs.len() + zone.rhs.len())?;
+        Some((zone, crate::idn::key_from_ascii(key).into_owned(), map_type))
+    }
+}
+
+/// `name` (or `default`) as an absolute domain name.
+pub(crate) fn absolute(name: Option<&str>, default: &str) -> String {
+    let name = name.unwrap_or(default);
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{name}.")
+    }
+}
+
+/// Date-based SOA serial (`YYYYMMDDnn`) following `previous`: the first
+/// serial of the current UTC day, or `previous + 1` if that is not larger.
+pub fn next_serial(previous: Option<u32>, now: SystemTime) -> u32 {
+    let days = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let today = (year as u32) * 1_000_000 + month * 10_000 + day * 100;
+    match previous {
+        Some(previous) if previous >= today => previous.wrapping_add(1),
+        _ => today,
     }
 }
 
+/// Gregorian date of a day count since 1970-01-01 (Howard Hinnant's algorithm).
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,6 +696,7 @@ fn sample_config() -> HesiodConfig {
             lhs: ".ns".into(),
             rhs: ".test.internal".into(),
             ttl: 300,
+            negative_ttl: None,
             dns_port: 53,
             http_port: 8080,
             services: vec![crate::config::ServiceEntry {
@@ -180,6 +704,8 @@ fn sample_config() -> HesiodConfig {
                 host: "web.svc".into(),
                 port: 443,
                 protocol: "tcp".into(),
+                address: None,
+                tags: Vec::new(),
             }],
             users: vec![crate::config::UserEntry {
                 username: "admin".into(),
@@ -194,6 +720,15 @@ fn sample_config() -> HesiodConfig {
                 gid: 1001,
                 members: vec!["admin".into()],
             }],
+            metrics: Default::default(),
+            admin: Default::default(),
+            notify: Default::default(),
+            sync: Default::default(),
+            filesystems: vec![],
+            soa: Default::default(),
+            server: Default::default(),
+            http: Default::default(),
+            zones: vec![],
         }
     }
 
@@ -218,19 +753,164 @@ fn zone_lookup() {
         assert!(zone.lookup("nonexistent", MapType::Passwd).is_none());
     }
 
+    #[test]
+    fn lookup_ignores_case_and_normalization() {
+        let mut zone = HesiodZone::from_config(&sample_config()).expect("TODO: handle error");
+        let service = |host: &str| {
+            HesiodRecord::Service(ServiceRecord {
+                host: host.into(),
+                port: 80,
+                protocol: "tcp".into(),
+            })
+        };
+        zone.add_record("Müller", service("muller.svc"));
+        zone.add_record("WEB", service("upper.svc"));
+
+        assert!(zone.lookup("ADMIN", MapType::Passwd).is_some());
+        assert!(zone.lookup("mu\u{308}ller", MapType::Service).is_some());
+        assert!(zone.lookup("MÜLLER", MapType::Service).is_some());
+        assert!(zone.lookup("müller", MapType::Passwd).is_none());
+        // An exact match wins over one differing only in case.
+        let host = |name| zone.lookup(name, MapType::Service).map(|r| r.to_txt());
+        assert_eq!(host("WEB").as_deref(), Some("upper.svc:80:tcp"));
+        assert_eq!(host("web").as_deref(), Some("web.svc:443:tcp"));
+        assert_eq!(host("Web").as_deref(), Some("web.svc:443:tcp"));
+    }
+
+    #[test]
+    fn tagged_lookup_needs_a_shared_tag() {
+        let mut zone = HesiodZone::from_config(&sample_config()).expect("TODO: handle error");
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        zone.set_tags("web", MapType::Service, tags(&["public", "http"]));
+        zone.set_tags("missing", MapType::Service, tags(&["public"]));
+
+        let web_tags = zone.tags("web", MapType::Service);
+        assert_eq!(web_tags, tags(&["public", "http"]));
+        assert!(zone.tags("missing", MapType::Service).is_empty());
+        let lookup =
+            |name, wanted: &[&str]| zone.lookup_tagged(name, MapType::Service, &tags(wanted));
+        assert!(lookup("web", &["internal", "public"]).is_some());
+        assert!(lookup("WEB", &["http"]).is_some());
+        assert!(lookup("web", &["internal"]).is_none());
+        assert!(lookup("web", &[]).is_none());
+        // Untagged records are in no view.
+        let admin = zone.lookup_tagged("admin", MapType::Passwd, &tags(&["public"]));
+        assert!(admin.is_none());
+    }
+
     #[test]
     fn zone_bind_output() {
         let config = sample_config();
         let zone = HesiodZone::from_config(&config).expect("TODO: handle error");
         let bind = zone.to_bind_zone();
 
-        assert!(bind.contains("$ORIGIN .test.internal."));
+        assert!(bind.contains("$ORIGIN test.internal.\n$TTL 300\n"));
+        assert!(bind.contains("@ HS SOA ns.test.internal. hostmaster.test.internal. ("));
+        assert!(bind.contains("@ HS NS ns.test.internal.\n"));
         assert!(bind.contains("HS TXT"));
         assert!(bind.contains("web.service.ns"));
         assert!(bind.contains("admin.passwd.ns"));
         assert!(bind.contains("ops.group.ns"));
     }
 
+    #[test]
+    fn split_zone_includes_every_map() {
+        let zone = HesiodZone::from_config(&sample_config()).expect("TODO: handle error");
+        let (master, files) = zone.to_split_bind_zone(5, "/var/named/hesiod/");
+
+        assert!(master.contains("\t5 ; serial"));
+        assert!(master.contains("$INCLUDE /var/named/hesiod/passwd.zone\n"));
+        assert!(!master.contains("HS TXT"));
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["service.zone", "passwd.zone", "group.zone", "filsys.zone"]
+        );
+        assert!(files[1].1.contains("admin.passwd.ns\t300 HS TXT"));
+        assert!(!files[3].1.contains("HS TXT"));
+    }
+
+    #[test]
+    fn zone_header_uses_soa_config() {
+        let mut config = sample_config();
+        config.soa = SoaConfig {
+            mname: Some("dns1.test.internal".into()),
+            rname: Some("ops@test.internal".into()),
+            nameservers: vec!["dns1.test.internal.".into(), "dns2.test.internal".into()],
+            refresh: 7200,
+            minimum: Some(60),
+            ..Default::default()
+        };
+        let bind = HesiodZone::from_config(&config)
+            .expect("TODO: handle error")
+            .to_bind_zone();
+
+        assert!(bind.contains("@ HS SOA dns1.test.internal. ops.test.internal. ("));
+        assert!(bind.contains("\t7200 ; refresh\n"));
+        assert!(bind.contains("\t60 ; minimum TTL\n"));
+        assert!(bind.contains("$TTL 300\n"));
+
+        config.negative_ttl = Some(30);
+        let zone = HesiodZone::from_config(&config).expect("TODO: handle error");
+        assert_eq!(zone.soa_minimum(), 30);
+        assert!(zone.to_bind_zone().contains("\t30 ; minimum TTL\n"));
+        assert!(bind.contains("@ HS NS dns1.test.internal.\n@ HS NS dns2.test.internal.\n"));
+    }
+
+    #[test]
+    fn checksum_is_order_independent() {
+        let service = |host: &str| {
+            HesiodRecord::Service(ServiceRecord {
+                host: host.into(),
+                port: 443,
+                protocol: "tcp".into(),
+            })
+        };
+        let mut a = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        a.add_record("web", service("web.svc"));
+        a.add_record("api", service("api.svc"));
+        let mut b = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        b.add_record("api", service("api.svc"));
+        b.add_record("web", service("web.svc"));
+        assert_eq!(a.checksum(), b.checksum());
+        assert!(a.checksum().starts_with("sha256:"));
+
+        let before = a.checksum().to_string();
+        a.add_record("api", service("api2.svc"));
+        assert_ne!(a.checksum(), before);
+    }
+
+    #[test]
+    fn diff_reports_added_removed_changed() {
+        let old = HesiodZone::from_config(&sample_config()).expect("TODO: handle error");
+        let mut new = old.clone();
+        new.records.map_mut(MapType::Group).remove("ops");
+        new.add_record(
+            "web",
+            HesiodRecord::Service(ServiceRecord {
+                host: "web2.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        new.add_record(
+            "home",
+            HesiodRecord::Filsys(FilsysRecord {
+                fs_type: "nfs".into(),
+                mount_path: "/home".into(),
+                source: "nfs:/export".into(),
+                mode: "rw".into(),
+            }),
+        );
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].0, "home");
+        assert_eq!(diff.removed[0].0, "ops");
+        assert_eq!(diff.changed[0].0, "web");
+        assert!(old.diff(&old).is_empty());
+    }
+
     #[test]
     fn add_and_lookup_filsys() {
         let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
@@ -246,4 +926,61 @@ fn add_and_lookup_filsys() {
         let rec = zone.lookup("home", MapType::Filsys).expect("TODO: handle error");
         assert_eq!(rec.to_txt(), "nfs /home nfs:/export rw");
     }
+
+    #[test]
+    fn snapshot_round_trips_and_filters() {
+        let zone = HesiodZone::from_config(&sample_config()).expect("TODO: handle error");
+
+        let full = zone.snapshot(None);
+        assert_eq!(full.records.len(), zone.record_count());
+        let rebuilt = full.into_zone();
+        assert_eq!(rebuilt.checksum(), zone.checksum());
+
+        let passwd = zone.snapshot(Some(MapType::Passwd));
+        assert_eq!(passwd.records.len(), 1);
+        assert_eq!(passwd.records[0].name, "admin");
+        assert_eq!(passwd.checksum, zone.checksum());
+    }
+
+    #[test]
+    fn serials_are_date_based_and_increase() {
+        use std::time::Duration;
+
+        // 2026-10-14T12:00:00Z
+        let now = UNIX_EPOCH + Duration::from_secs(1_791_979_200);
+        assert_eq!(next_serial(None, now), 2026101400);
+        assert_eq!(next_serial(Some(2026020801), now), 2026101400);
+        assert_eq!(next_serial(Some(2026101400), now), 2026101401);
+        assert!(
+            HesiodZone::new("t", ".ns", ".t", 300)
+                .to_bind_zone_with_serial(7)
+                .contains("\t7 ; serial")
+        );
+    }
+    #[test]
+    fn zone_set_from_config() {
+        let json = r#"{
+            "domain": "corp.internal", "lhs": ".ns", "rhs": ".corp.internal",
+            "users": [{"username": "alice", "uid": 1000, "gid": 1000, "home": "/home/alice"}],
+            "zones": [{
+                "domain": "lab.internal", "lhs": ".ns", "rhs": ".lab.internal",
+                "groups": [{"name": "lab", "gid": 50}]
+            }]
+        }"#;
+        let zones =
+            ZoneSet::from_config(&HesiodConfig::from_json(json).expect("TODO: handle error"))
+                .expect("TODO: handle error");
+        assert_eq!(zones.primary().domain, "corp.internal");
+        assert_eq!(zones.iter().count(), 2);
+        assert_eq!(zones.record_count(), 2);
+
+        let clash = json.replace(".lab.internal", ".corp.internal");
+        let err =
+            ZoneSet::from_config(&HesiodConfig::from_json(&clash).expect("TODO: handle error"))
+                .expect_err("suffixes clash");
+        assert!(
+            err.to_string()
+                .contains("both serve names under .ns.corp.internal")
+        );
+    }
 }
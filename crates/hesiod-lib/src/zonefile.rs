@@ -0,0 +1,516 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Reader for BIND-format zone files, enough to recover Hesiod TXT records.
+//!
+//! Handles `$ORIGIN`/`$TTL`, relative and absolute owners, `@`, blank owners
+//! (repeat the previous one), parenthesised multi-line records, comments and
+//! quoted strings with `\"`, `\\` and `\DDD` escapes. Only the serial is
+//! kept from the SOA; other non-TXT records are skipped. [`ZoneFile::read`]
+//! also reads `$INCLUDE`d files.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, HesiodError, IoContext, Result};
+use crate::idn::key_from_ascii;
+use crate::records::{HesiodRecord, MapType};
+use crate::zone::HesiodZone;
+
+/// TTL used when neither the record nor `$TTL` gives one.
+const DEFAULT_TTL: u32 = 300;
+
+/// How deep `$INCLUDE`s may nest before [`ZoneFile::read`] assumes a loop.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// One Hesiod TXT record read from a zone file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneFileEntry {
+    /// File the record was read from, when parsed by [`ZoneFile::read`].
+    pub file: Option<PathBuf>,
+    /// 1-based line the record starts on.
+    pub line: usize,
+    pub key: String,
+    pub map_type: MapType,
+    /// Labels between the map type and the origin, e.g. `.ns`.
+    pub lhs: String,
+    pub ttl: u32,
+    pub txt: String,
+}
+
+impl ZoneFileEntry {
+    /// `path:line` of the record, or `line N` for records parsed from text.
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file.display(), self.line),
+            None => format!("line {}", self.line),
+        }
+    }
+}
+
+/// A `$INCLUDE` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneInclude {
+    pub line: usize,
+    pub path: String,
+    /// Origin the included file starts with: the one given on the
+    /// `$INCLUDE` line, else the current `$ORIGIN`.
+    pub origin: Option<String>,
+}
+
+/// Parsed zone file: its origin and the Hesiod records found in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneFile {
+    /// `$ORIGIN` without the trailing dot, if one was given.
+    pub origin: Option<String>,
+    /// SOA serial, if the file has an SOA record.
+    pub serial: Option<u32>,
+    /// `$INCLUDE` lines. [`ZoneFile::parse`] does not read the included
+    /// records; [`ZoneFile::read`] does.
+    pub includes: Vec<ZoneInclude>,
+    pub entries: Vec<ZoneFileEntry>,
+}
+
+impl ZoneFile {
+    /// Parse zone file text. TXT records whose owner contains no map type
+    /// label are not Hesiod data and are skipped.
+    pub fn parse(content: &str) -> Result<Self> {
+        Self::parse_with_origin(content, None)
+    }
+
+    /// Read and parse a zone file, following `$INCLUDE`s. Relative include
+    /// paths are tried against the working directory (as BIND does), then
+    /// against the including file's directory. Included records are added
+    /// to `entries` with their own file and line.
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::read_nested(path, None, 0)
+    }
+
+    fn read_nested(path: &Path, origin: Option<String>, depth: usize) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).io_err(|| format!("reading {}", path.display()))?;
+        let mut file = Self::parse_with_origin(&content, origin)
+            .zone_err(|| format!("parsing {}", path.display()))?;
+        for entry in &mut file.entries {
+            entry.file = Some(path.to_path_buf());
+        }
+        for include in file.includes.clone() {
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(HesiodError::zone(format!(
+                    "{}:{}: $INCLUDE nested more than {MAX_INCLUDE_DEPTH} deep",
+                    path.display(),
+                    include.line
+                )));
+            }
+            let mut target = PathBuf::from(&include.path);
+            if target.is_relative()
+                && !target.exists()
+                && let Some(dir) = path.parent()
+            {
+                target = dir.join(&include.path);
+            }
+            let included = Self::read_nested(&target, include.origin, depth + 1)
+                .zone_err(|| format!("{}:{}: $INCLUDE", path.display(), include.line))?;
+            file.entries.extend(included.entries);
+        }
+        Ok(file)
+    }
+
+    fn parse_with_origin(content: &str, origin: Option<String>) -> Result<Self> {
+        let mut file = ZoneFile {
+            origin,
+            ..Default::default()
+        };
+        let mut default_ttl = None;
+        let mut last_owner: Option<String> = None;
+
+        for (line, tokens, owner_present) in logical_lines(content)? {
+            let context = || format!("line {line}");
+            let Some(first) = tokens.first() else {
+                continue;
+            };
+
+            match first.as_str() {
+                "$ORIGIN" => {
+                    let origin = tokens
+                        .get(1)
+                        .zone_err(|| format!("line {line}: $ORIGIN needs a name"))?;
+                    file.origin = Some(resolve_origin(origin, file.origin.as_deref()));
+                    continue;
+                }
+                "$TTL" => {
+                    let ttl = tokens
+                        .get(1)
+                        .zone_err(|| format!("line {line}: $TTL needs a value"))?;
+                    default_ttl = Some(ttl.parse().zone_err(context)?);
+                    continue;
+                }
+                "$INCLUDE" => {
+                    let path = tokens
+                        .get(1)
+                        .zone_err(|| format!("line {line}: $INCLUDE needs a file name"))?;
+                    file.includes.push(ZoneInclude {
+                        line,
+                        path: path.clone(),
+                        origin: tokens
+                            .get(2)
+                            .map(|origin| resolve_origin(origin, file.origin.as_deref()))
+                            .or_else(|| file.origin.clone()),
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut rest = tokens.as_slice();
+            let owner = if owner_present {
+                let (owner, tail) = rest.split_first().expect("tokens is non-empty");
+                rest = tail;
+                last_owner = Some(owner.clone());
+                owner.clone()
+            } else {
+                last_owner
+                    .clone()
+                    .zone_err(|| format!("line {line}: record without an owner"))?
+            };
+
+            // [ttl] [class] type, with ttl and class in either order.
+            let mut ttl = None;
+            while let Some(token) = rest.first() {
+                if let Ok(value) = token.parse::<u32>() {
+                    ttl = Some(value);
+                } else if !matches!(token.to_ascii_uppercase().as_str(), "IN" | "HS" | "CH") {
+                    break;
+                }
+                rest = &rest[1..];
+            }
+            let Some((rtype, rdata)) = rest.split_first() else {
+                return Err(HesiodError::zone(format!(
+                    "line {line}: missing record type"
+                )));
+            };
+            if rtype.eq_ignore_ascii_case("SOA") {
+                let serial = rdata
+                    .get(2)
+                    .zone_err(|| format!("line {line}: SOA without a serial"))?;
+                file.serial = Some(serial.parse().zone_err(context)?);
+                continue;
+            }
+            if !rtype.eq_ignore_ascii_case("TXT") {
+                continue;
+            }
+
+            let relative = relative_name(&owner, file.origin.as_deref());
+            let Some((key, map_type, lhs)) = split_hesiod_name(&relative) else {
+                continue;
+            };
+            file.entries.push(ZoneFileEntry {
+                file: None,
+                line,
+                key,
+                map_type,
+                lhs,
+                ttl: ttl.or(default_ttl).unwrap_or(DEFAULT_TTL),
+                txt: rdata.concat(),
+            });
+        }
+        Ok(file)
+    }
+}
+
+impl HesiodZone {
+    /// Build a zone from BIND zone file text, as written by [`HesiodZone::to_bind_zone`].
+    /// Zone-wide lhs and TTL are taken from the first record. Text can't
+    /// `$INCLUDE`; use [`HesiodZone::from_bind_zone_file`] for that.
+    pub fn from_bind_zone(content: &str) -> Result<Self> {
+        let file = ZoneFile::parse(content)?;
+        if let Some(include) = file.includes.first() {
+            return Err(HesiodError::zone(format!(
+                "line {}: $INCLUDE of {} is not supported here",
+                include.line, include.path
+            )));
+        }
+        Self::from_zone_file(file)
+    }
+
+    /// Build a zone from a BIND zone file on disk and the files it `$INCLUDE`s.
+    pub fn from_bind_zone_file(path: &Path) -> Result<Self> {
+        Self::from_zone_file(ZoneFile::read(path)?)
+    }
+
+    fn from_zone_file(file: ZoneFile) -> Result<Self> {
+        let mut zone = file.empty_zone();
+        for entry in file.entries {
+            let record = HesiodRecord::from_txt(entry.map_type, &entry.txt).zone_err(|| {
+                format!(
+                    "{}: invalid {} record",
+                    entry.location(),
+                    entry.map_type.label()
+                )
+            })?;
+            zone.add_record(&entry.key, record);
+        }
+        Ok(zone)
+    }
+}
+
+impl ZoneFile {
+    /// Zone of the records that are valid for their map, skipping the rest
+    /// (which [`HesiodZone::from_bind_zone`] would reject).
+    pub fn valid_zone(&self) -> HesiodZone {
+        let mut zone = self.empty_zone();
+        for entry in &self.entries {
+            if let Ok(record) = HesiodRecord::from_txt(entry.map_type, &entry.txt) {
+                zone.add_record(&entry.key, record);
+            }
+        }
+        zone
+    }
+
+    /// Zone with this file's origin and the lhs and TTL of its first record.
+    fn empty_zone(&self) -> HesiodZone {
+        let origin = self.origin.clone().unwrap_or_default();
+        let rhs = if origin.is_empty() || origin.starts_with('.') {
+            origin.clone()
+        } else {
+            format!(".{origin}")
+        };
+        let (lhs, ttl) = self
+            .entries
+            .first()
+            .map_or((".ns".to_string(), DEFAULT_TTL), |e| (e.lhs.clone(), e.ttl));
+        HesiodZone::new(origin.trim_start_matches('.'), &lhs, &rhs, ttl)
+    }
+}
+
+/// Absolute origin (without the trailing dot) named by a `$ORIGIN` or
+/// `$INCLUDE` argument; relative names are under the current origin.
+fn resolve_origin(name: &str, current: Option<&str>) -> String {
+    match (name.strip_suffix('.'), current) {
+        (Some(absolute), _) => absolute.to_string(),
+        (None, Some(current)) if !current.is_empty() => format!("{name}.{current}"),
+        (None, _) => name.to_string(),
+    }
+}
+
+/// Strip `origin` from an owner name, resolving `@` and absolute names.
+fn relative_name(owner: &str, origin: Option<&str>) -> String {
+    if owner == "@" {
+        return String::new();
+    }
+    let Some(absolute) = owner.strip_suffix('.') else {
+        return owner.to_string();
+    };
+    origin
+        .and_then(|origin| absolute.strip_suffix(origin.trim_start_matches('.')))
+        .map(|name| name.trim_end_matches('.').to_string())
+        .unwrap_or_else(|| absolute.to_string())
+}
+
+/// Split `<key>.<map><lhs>` at the first label after the key that names a map type.
+fn split_hesiod_name(name: &str) -> Option<(String, MapType, String)> {
+    let labels: Vec<&str> = name.split('.').collect();
+    let index = (1..labels.len()).find(|&i| labels[i].parse::<MapType>().is_ok())?;
+    let map_type = labels[index].parse().ok()?;
+    let lhs: String = labels[index + 1..]
+        .iter()
+        .map(|l| format!(".{l}"))
+        .collect();
+    let key = key_from_ascii(&labels[..index].join(".")).into_owned();
+    Some((key, map_type, lhs))
+}
+
+/// Split the file into logical records: `(first line, tokens, owner present)`.
+/// Parentheses join physical lines; quoted strings become single tokens with
+/// escapes resolved.
+fn logical_lines(content: &str) -> Result<Vec<(usize, Vec<String>, bool)>> {
+    let mut out = Vec::new();
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut owner_present = false;
+    let mut depth = 0usize;
+
+    for (index, raw) in content.lines().enumerate() {
+        let line_no = index + 1;
+        if depth == 0 {
+            start = line_no;
+            owner_present = !raw.starts_with([' ', '\t']);
+        }
+
+        let mut chars = raw.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .zone_err(|| format!("line {line_no}: unbalanced ')'"))?;
+                }
+                '"' => {
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            None => {
+                                return Err(HesiodError::zone(format!(
+                                    "line {line_no}: unterminated quoted string"
+                                )));
+                            }
+                            Some('"') => break,
+                            Some('\\') => text.push(unescape(&mut chars, line_no)?),
+                            Some(c) => text.push(c),
+                        }
+                    }
+                    tokens.push(text);
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut word = String::from(c);
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || matches!(next, ';' | '(' | ')' | '"') {
+                            break;
+                        }
+                        word.push(next);
+                        chars.next();
+                    }
+                    tokens.push(word);
+                }
+            }
+        }
+
+        if depth == 0 && !tokens.is_empty() {
+            out.push((start, std::mem::take(&mut tokens), owner_present));
+        }
+    }
+    if depth != 0 {
+        return Err(HesiodError::zone(format!("line {start}: unclosed '('")));
+    }
+    Ok(out)
+}
+
+/// Resolve the character after a backslash: `\DDD` is a decimal byte,
+/// anything else stands for itself.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, line_no: usize) -> Result<char> {
+    let Some(first) = chars.next() else {
+        return Err(HesiodError::zone(format!(
+            "line {line_no}: dangling escape"
+        )));
+    };
+    if !first.is_ascii_digit() {
+        return Ok(first);
+    }
+    let mut digits = String::from(first);
+    for _ in 0..2 {
+        match chars.next() {
+            Some(d) if d.is_ascii_digit() => digits.push(d),
+            _ => {
+                return Err(HesiodError::zone(format!(
+                    "line {line_no}: \\DDD escape needs three digits"
+                )));
+            }
+        }
+    }
+    let value: u8 = digits
+        .parse()
+        .zone_err(|| format!("line {line_no}: escape \\{digits} out of range"))?;
+    Ok(char::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::ServiceRecord;
+
+    #[test]
+    fn round_trips_generated_zone() {
+        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 600);
+        zone.add_record(
+            "web",
+            HesiodRecord::Service(ServiceRecord {
+                host: "web.svc".into(),
+                port: 443,
+                protocol: "tcp".into(),
+            }),
+        );
+        zone.add_record(
+            "josé",
+            HesiodRecord::Service(ServiceRecord {
+                host: "jose.svc".into(),
+                port: 80,
+                protocol: "tcp".into(),
+            }),
+        );
+        let text = zone.to_bind_zone_with_serial(42);
+        assert!(text.contains("\nxn--jos-dma.service.ns\t600 HS TXT"));
+        assert_eq!(
+            ZoneFile::parse(&text).expect("TODO: handle error").serial,
+            Some(42)
+        );
+        let parsed = HesiodZone::from_bind_zone(&text).expect("TODO: handle error");
+        assert_eq!(parsed.lhs, ".ns");
+        assert_eq!(parsed.rhs, ".test.internal");
+        assert_eq!(parsed.ttl, 600);
+        assert_eq!(parsed.checksum(), zone.checksum());
+    }
+
+    #[test]
+    fn handles_multiline_escapes_and_absolute_owners() {
+        let content = "$ORIGIN example.com.\n\
+                       $TTL 120\n\
+                       ops.group.ns TXT ( \"ops:*\" \n\
+                       \t\":1001:alice\" )\n\
+                       \tIN TXT \"second\" ; same owner\n\
+                       bob.passwd.ns.example.com. HS 60 TXT \"bob:*:1:1:B \\\"Bob\\\" \\059:/h:/bin/sh\"\n";
+        let file = ZoneFile::parse(content).expect("TODO: handle error");
+        assert_eq!(file.entries.len(), 3);
+        assert_eq!(file.entries[0].txt, "ops:*:1001:alice");
+        assert_eq!(file.entries[0].ttl, 120);
+        assert_eq!(file.entries[1].line, 5);
+        assert_eq!(file.entries[1].key, "ops");
+        assert_eq!(file.entries[2].key, "bob");
+        assert_eq!(file.entries[2].txt, "bob:*:1:1:B \"Bob\" ;:/h:/bin/sh");
+        assert_eq!(file.entries[2].ttl, 60);
+    }
+
+    #[test]
+    fn reports_line_of_bad_record() {
+        let err = HesiodZone::from_bind_zone("x.passwd.ns HS TXT \"not-a-passwd\"\n")
+            .expect_err("invalid record");
+        assert!(format!("{err:#}").starts_with("line 1"));
+    }
+
+    #[test]
+    fn reads_included_files() {
+        let dir = std::env::temp_dir().join(format!("hesiod-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("TODO: handle error");
+        let master = dir.join("master.zone");
+        std::fs::write(
+            &master,
+            "$ORIGIN example.com.\n\
+             @ HS SOA ns hostmaster ( 5 1 1 1 1 )\n\
+             $INCLUDE users.zone\n\
+             $INCLUDE svc.zone sub\n",
+        )
+        .expect("TODO: handle error");
+        std::fs::write(
+            dir.join("users.zone"),
+            "; users\nbob.passwd.ns HS TXT \"bob:*:1:1:Bob:/h:/bin/sh\"\n",
+        )
+        .expect("TODO: handle error");
+        std::fs::write(
+            dir.join("svc.zone"),
+            "web.service.ns.sub.example.com. HS TXT \"web:80:tcp\"\n",
+        )
+        .expect("TODO: handle error");
+
+        let file = ZoneFile::read(&master).expect("TODO: handle error");
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(file.serial, Some(5));
+        assert_eq!(file.entries.len(), 2);
+        assert_eq!(file.entries[0].key, "bob");
+        assert_eq!(
+            file.entries[0].location(),
+            format!("{}:2", dir.join("users.zone").display())
+        );
+        assert_eq!(file.entries[1].key, "web");
+        assert_eq!(file.entries[1].lhs, ".ns");
+        assert!(HesiodZone::from_bind_zone("$INCLUDE users.zone\n").is_err());
+    }
+}
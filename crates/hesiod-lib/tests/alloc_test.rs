@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Allocation budget of the DNS answer path, counted with a global
+//! allocator wrapper. Only one test lives here, so nothing else allocates
+//! while it counts.
+#![cfg(feature = "net")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hesiod_lib::config::ServerConfig;
+use hesiod_lib::records::{HesiodRecord, ServiceRecord};
+use hesiod_lib::server::{DnsServerState, handle_query};
+use hesiod_lib::zone::HesiodZone;
+use hickory_proto::op::{Message, Query};
+use hickory_proto::rr::{DNSClass, RecordType};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn query(name: &str) -> Vec<u8> {
+    let mut query = Query::new();
+    query.set_name(name.parse().expect("TODO: handle error"));
+    query.set_query_type(RecordType::TXT);
+    query.set_query_class(DNSClass::HS);
+    let mut msg = Message::new();
+    msg.set_id(7);
+    msg.add_query(query);
+    msg.to_vec().expect("TODO: handle error")
+}
+
+fn test_zone() -> HesiodZone {
+    let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+    let web = ServiceRecord {
+        host: "web.svc".into(),
+        port: 443,
+        protocol: "tcp".into(),
+    };
+    zone.add_record("web", HesiodRecord::Service(web));
+    zone
+}
+
+/// Average allocations (and reallocations) per answer to `wire`.
+fn allocations_per_query(wire: &[u8], state: &DnsServerState) -> usize {
+    handle_query(wire, state).expect("TODO: handle error");
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..100 {
+        handle_query(wire, state).expect("TODO: handle error");
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / 100
+}
+
+#[test]
+fn cached_answers_allocate_little() {
+    let wire = query("web.service.ns.test.internal");
+
+    // Parsing the query, copying it in (as the UDP listener does) and
+    // copying the cached response out.
+    let cached = DnsServerState::new(test_zone());
+    let per_query = allocations_per_query(&wire, &cached);
+    assert!(per_query <= 5, "{per_query} allocations per cached query");
+
+    let server = ServerConfig {
+        answer_cache: 0,
+        ..ServerConfig::default()
+    };
+    let uncached = DnsServerState::new(test_zone()).with_server(server);
+    let per_query = allocations_per_query(&wire, &uncached);
+    assert!(
+        per_query <= 30,
+        "{per_query} allocations per uncached query"
+    );
+}
@@ -209,7 +209,7 @@ fn aspect_negative_numeric_rejection() {
     let result = HesiodConfig::from_json(json);
     // Should either fail to parse or reject negative value
     // (Rust u32 will naturally reject negative in JSON)
-    assert!(result.is_err() || result.unwrap().ttl != std::u32::MAX);
+    assert!(result.is_err() || result.unwrap().ttl != u32::MAX);
 }
 
 #[test]
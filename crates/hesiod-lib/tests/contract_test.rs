@@ -96,7 +96,7 @@ fn contract_record_count_accuracy() {
         zone.add_record(
             &format!("rec{}", i),
             HesiodRecord::Service(ServiceRecord {
-                host: format!("svc{}", i).into(),
+                host: format!("svc{}", i),
                 port: 1000 + i as u16,
                 protocol: "tcp".into(),
             }),
@@ -148,8 +148,8 @@ fn contract_bind_zone_structure() {
     let bind = zone.to_bind_zone();
 
     assert!(bind.contains("$ORIGIN"));
-    assert!(bind.contains("@ IN SOA"));
-    assert!(bind.contains("@ IN NS"));
+    assert!(bind.contains("@ HS SOA"));
+    assert!(bind.contains("@ HS NS"));
     assert!(bind.contains("serial"));
 }
 
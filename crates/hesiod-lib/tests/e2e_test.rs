@@ -63,6 +63,7 @@ fn e2e_multi_record_lookup() {
         lhs: ".ns".into(),
         rhs: ".test.internal".into(),
         ttl: 300,
+        negative_ttl: None,
         dns_port: 53,
         http_port: 8080,
         services: vec![
@@ -71,16 +72,29 @@ fn e2e_multi_record_lookup() {
                 host: "host1.local".into(),
                 port: 443,
                 protocol: "tcp".into(),
+                address: None,
+                tags: Vec::new(),
             },
             ServiceEntry {
                 name: "svc2".into(),
                 host: "host2.local".into(),
                 port: 8080,
                 protocol: "tcp".into(),
+                address: None,
+                tags: Vec::new(),
             },
         ],
         users: vec![],
         groups: vec![],
+        metrics: Default::default(),
+        admin: Default::default(),
+        notify: Default::default(),
+        sync: Default::default(),
+        filesystems: vec![],
+        soa: Default::default(),
+        server: Default::default(),
+        http: Default::default(),
+        zones: vec![],
     };
 
     let zone = HesiodZone::from_config(&config).expect("failed to build zone");
@@ -101,6 +115,7 @@ fn e2e_missing_hostname_returns_none() {
         lhs: ".ns".into(),
         rhs: ".test.internal".into(),
         ttl: 300,
+        negative_ttl: None,
         dns_port: 53,
         http_port: 8080,
         services: vec![ServiceEntry {
@@ -108,9 +123,20 @@ fn e2e_missing_hostname_returns_none() {
             host: "web.svc".into(),
             port: 443,
             protocol: "tcp".into(),
+            address: None,
+            tags: Vec::new(),
         }],
         users: vec![],
         groups: vec![],
+        metrics: Default::default(),
+        admin: Default::default(),
+        notify: Default::default(),
+        sync: Default::default(),
+        filesystems: vec![],
+        soa: Default::default(),
+        server: Default::default(),
+        http: Default::default(),
+        zones: vec![],
     };
 
     let zone = HesiodZone::from_config(&config).expect("failed to build zone");
@@ -127,6 +153,7 @@ fn e2e_zone_bind_output_contains_all_records() {
         lhs: ".ns".into(),
         rhs: ".example.com".into(),
         ttl: 600,
+        negative_ttl: None,
         dns_port: 53,
         http_port: 8080,
         services: vec![ServiceEntry {
@@ -134,6 +161,8 @@ fn e2e_zone_bind_output_contains_all_records() {
             host: "web.example.com".into(),
             port: 80,
             protocol: "tcp".into(),
+            address: None,
+            tags: Vec::new(),
         }],
         users: vec![UserEntry {
             username: "admin".into(),
@@ -148,15 +177,24 @@ fn e2e_zone_bind_output_contains_all_records() {
             gid: 1000,
             members: vec!["admin".into()],
         }],
+        metrics: Default::default(),
+        admin: Default::default(),
+        notify: Default::default(),
+        sync: Default::default(),
+        filesystems: vec![],
+        soa: Default::default(),
+        server: Default::default(),
+        http: Default::default(),
+        zones: vec![],
     };
 
     let zone = HesiodZone::from_config(&config).expect("failed to build zone");
     let bind_output = zone.to_bind_zone();
 
     // Verify BIND zone file structure
-    assert!(bind_output.contains("$ORIGIN .example.com."));
-    assert!(bind_output.contains("@ IN SOA"));
-    assert!(bind_output.contains("@ IN NS"));
+    assert!(bind_output.contains("$ORIGIN example.com."));
+    assert!(bind_output.contains("@ HS SOA"));
+    assert!(bind_output.contains("@ HS NS"));
     assert!(bind_output.contains("HS TXT"));
     assert!(bind_output.contains("Service records"));
     assert!(bind_output.contains("Passwd records"));
@@ -236,6 +274,7 @@ fn e2e_zone_serialization() {
         lhs: ".ns".into(),
         rhs: ".test.internal".into(),
         ttl: 300,
+        negative_ttl: None,
         dns_port: 53,
         http_port: 8080,
         services: vec![ServiceEntry {
@@ -243,9 +282,20 @@ fn e2e_zone_serialization() {
             host: "postgres.svc".into(),
             port: 5432,
             protocol: "tcp".into(),
+            address: None,
+            tags: Vec::new(),
         }],
         users: vec![],
         groups: vec![],
+        metrics: Default::default(),
+        admin: Default::default(),
+        notify: Default::default(),
+        sync: Default::default(),
+        filesystems: vec![],
+        soa: Default::default(),
+        server: Default::default(),
+        http: Default::default(),
+        zones: vec![],
     };
 
     let zone = HesiodZone::from_config(&config).expect("failed to build zone");
@@ -290,6 +340,7 @@ fn e2e_special_characters_in_gecos() {
         lhs: ".ns".into(),
         rhs: ".test.internal".into(),
         ttl: 300,
+        negative_ttl: None,
         dns_port: 53,
         http_port: 8080,
         services: vec![],
@@ -302,6 +353,15 @@ fn e2e_special_characters_in_gecos() {
             shell: "/bin/bash".into(),
         }],
         groups: vec![],
+        metrics: Default::default(),
+        admin: Default::default(),
+        notify: Default::default(),
+        sync: Default::default(),
+        filesystems: vec![],
+        soa: Default::default(),
+        server: Default::default(),
+        http: Default::default(),
+        zones: vec![],
     };
 
     let zone = HesiodZone::from_config(&config).expect("failed to build zone");
@@ -335,3 +395,46 @@ fn e2e_zone_iteration() {
     assert!(names.contains(&"web"));
     assert!(names.contains(&"api"));
 }
+
+#[cfg(feature = "net")]
+#[tokio::test]
+async fn e2e_client_resolves_all_maps_from_server() {
+    use hesiod_lib::client::HesiodClient;
+    use hesiod_lib::hesiod_conf::HesiodConf;
+
+    let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+    zone.add_record("alice", HesiodRecord::Passwd(PasswdRecord {
+        username: "alice".into(),
+        uid: 1000,
+        gid: 1000,
+        gecos: "Alice".into(),
+        home: "/home/alice".into(),
+        shell: "/bin/bash".into(),
+    }));
+    zone.add_record("alice", HesiodRecord::Group(GroupRecord {
+        name: "alice".into(),
+        gid: 1000,
+        members: vec![],
+    }));
+
+    let state = hesiod_lib::server::run_dns_server(zone, 0)
+        .await
+        .expect("failed to start server");
+    let port = state.dns_addr().expect("server address").port();
+    assert_ne!(port, 0);
+
+    let naming = HesiodConf {
+        lhs: ".ns".into(),
+        rhs: ".test.internal".into(),
+        ..Default::default()
+    };
+    let client = HesiodClient::new(vec![format!("127.0.0.1:{port}")], naming)
+        .expect("failed to build client");
+    let all = client.resolve_all("alice").await.expect("lookups failed");
+
+    assert_eq!(all.len(), MapType::ALL.len());
+    assert_eq!(all[&MapType::Passwd].len(), 1);
+    assert_eq!(all[&MapType::Group].len(), 1);
+    assert!(all[&MapType::Service].is_empty());
+    assert!(all[&MapType::Filsys].is_empty());
+}
@@ -1,12 +1,13 @@
 // SPDX-License-Identifier: MPL-2.0
 //! Property-based tests for hesiod-lib using proptest.
 
+use hesiod_lib::naming::{from_bind_name, to_bind_name};
 use hesiod_lib::records::*;
 use hesiod_lib::zone::HesiodZone;
 use proptest::prelude::*;
 
-/// Property: PasswdRecord round-trip serialization never panics.
 proptest! {
+    /// Property: PasswdRecord round-trip serialization never panics.
     #[test]
     fn prop_passwd_roundtrip_no_panic(
         username in r"[a-zA-Z0-9_]{1,32}",
@@ -35,8 +36,8 @@ fn prop_passwd_roundtrip_no_panic(
     }
 }
 
-/// Property: GroupRecord with any member list round-trips correctly.
 proptest! {
+    /// Property: GroupRecord with any member list round-trips correctly.
     #[test]
     fn prop_group_roundtrip(
         name in r"[a-zA-Z0-9_]{1,32}",
@@ -56,8 +57,8 @@ fn prop_group_roundtrip(
     }
 }
 
-/// Property: ServiceRecord port range is always valid.
 proptest! {
+    /// Property: ServiceRecord port range is always valid.
     #[test]
     fn prop_service_valid_port(
         host in r"[a-zA-Z0-9.-]{1,64}",
@@ -76,8 +77,8 @@ fn prop_service_valid_port(
     }
 }
 
-/// Property: FilsysRecord with valid FS types round-trips.
 proptest! {
+    /// Property: FilsysRecord with valid FS types round-trips.
     #[test]
     fn prop_filsys_roundtrip(
         fs_type in r"(nfs|ext4|tmpfs|fuse)",
@@ -98,8 +99,8 @@ fn prop_filsys_roundtrip(
     }
 }
 
-/// Property: MapType parsing is case-insensitive and always valid for known types.
 proptest! {
+    /// Property: MapType parsing is case-insensitive and always valid for known types.
     #[test]
     fn prop_maptype_case_insensitive(
         variant in r"(passwd|group|service|filsys)"
@@ -119,8 +120,8 @@ fn prop_maptype_case_insensitive(
     }
 }
 
-/// Property: HesiodZone never panics on lookup with any name string.
 proptest! {
+    /// Property: HesiodZone never panics on lookup with any name string.
     #[test]
     fn prop_zone_lookup_no_panic(
         key_name in r"[a-zA-Z0-9_-]{1,32}",
@@ -149,8 +150,8 @@ fn prop_zone_lookup_no_panic(
     }
 }
 
-/// Property: Zone record_count is always >= 0 and matches iteration.
 proptest! {
+    /// Property: Zone record_count is always >= 0 and matches iteration.
     #[test]
     fn prop_zone_record_count_consistency(
         records_count in 0usize..20
@@ -177,8 +178,8 @@ fn prop_zone_record_count_consistency(
     }
 }
 
-/// Property: TTL values in config are always > 0.
 proptest! {
+    /// Property: TTL values in config are always > 0.
     #[test]
     fn prop_ttl_always_positive(ttl in 1u32..=86400) {
         let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", ttl);
@@ -187,8 +188,8 @@ fn prop_ttl_always_positive(ttl in 1u32..=86400) {
     }
 }
 
-/// Property: Zone domain is never empty.
 proptest! {
+    /// Property: Zone domain is never empty.
     #[test]
     fn prop_zone_domain_not_empty(domain in r"[a-zA-Z0-9.-]{1,128}") {
         let zone = HesiodZone::new(&domain, ".ns", ".internal", 300);
@@ -197,8 +198,8 @@ fn prop_zone_domain_not_empty(domain in r"[a-zA-Z0-9.-]{1,128}") {
     }
 }
 
-/// Property: Record type enum is always correctly identified.
 proptest! {
+    /// Property: Record type enum is always correctly identified.
     #[test]
     fn prop_record_type_consistency(which in 0u8..4) {
         let record = match which {
@@ -237,8 +238,8 @@ fn prop_record_type_consistency(which in 0u8..4) {
     }
 }
 
-/// Property: Empty or whitespace-only usernames should be rejected (or handled gracefully).
 proptest! {
+    /// Property: Empty or whitespace-only usernames should be rejected (or handled gracefully).
     #[test]
     fn prop_username_validation(username in r"[a-zA-Z0-9_]{0,32}") {
         let txt = if username.is_empty() {
@@ -254,14 +255,154 @@ fn prop_username_validation(username in r"[a-zA-Z0-9_]{0,32}") {
     }
 }
 
-/// Property: Oversized hostnames don't cause panics.
 proptest! {
+    /// Property: Oversized hostnames don't cause panics.
     #[test]
     fn prop_large_hostname_no_panic(hostname_len in 1usize..=8192) {
         let hostname = "x".repeat(hostname_len);
         // Should not panic when looking up large hostname
-        let mut zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
+        let zone = HesiodZone::new("test.internal", ".ns", ".test.internal", 300);
         let _result = zone.lookup(&hostname, MapType::Passwd);
         // Should either return None or not crash
     }
 }
+
+// ---------------------------------------------------------------------------
+// Exact round-trips
+// ---------------------------------------------------------------------------
+//
+// TXT data has no escaping, so a field may hold anything except its own
+// delimiter; the last field of a record is taken verbatim and may hold even
+// that. The strategies below cover that whole space, with empty fields,
+// punctuation and non-ASCII text included.
+
+/// Any character, with the record delimiters and the password placeholder
+/// drawn often.
+fn awkward_char() -> impl Strategy<Value = char> {
+    prop_oneof![any::<char>(), Just(':'), Just(' '), Just(','), Just('*')]
+}
+
+/// Any text without `delimiter`, possibly empty.
+fn field(delimiter: char) -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        awkward_char().prop_filter("delimiter", move |c| *c != delimiter),
+        0..16,
+    )
+    .prop_map(|chars| chars.into_iter().collect())
+}
+
+/// Any text, possibly empty: the last field of a record.
+fn last_field() -> impl Strategy<Value = String> {
+    prop::collection::vec(awkward_char(), 0..16).prop_map(|chars| chars.into_iter().collect())
+}
+
+fn passwd() -> impl Strategy<Value = PasswdRecord> {
+    (
+        field(':'),
+        any::<u32>(),
+        any::<u32>(),
+        field(':'),
+        field(':'),
+        last_field(),
+    )
+        .prop_map(|(username, uid, gid, gecos, home, shell)| PasswdRecord {
+            username,
+            uid,
+            gid,
+            gecos,
+            home,
+            shell,
+        })
+}
+
+fn group() -> impl Strategy<Value = GroupRecord> {
+    // A member may hold colons, but not commas, and can't be the lone empty
+    // string, which reads back as no members.
+    let member = field(',').prop_filter("empty", |m| !m.is_empty());
+    (
+        field(':'),
+        any::<u32>(),
+        prop::collection::vec(member, 0..6),
+    )
+        .prop_map(|(name, gid, members)| GroupRecord { name, gid, members })
+}
+
+fn service() -> impl Strategy<Value = ServiceRecord> {
+    (field(':'), any::<u16>(), last_field()).prop_map(|(host, port, protocol)| ServiceRecord {
+        host,
+        port,
+        protocol,
+    })
+}
+
+fn filsys() -> impl Strategy<Value = FilsysRecord> {
+    (field(' '), field(' '), field(' '), last_field()).prop_map(
+        |(fs_type, mount_path, source, mode)| FilsysRecord {
+            fs_type,
+            mount_path,
+            source,
+            mode,
+        },
+    )
+}
+
+fn record() -> impl Strategy<Value = HesiodRecord> {
+    prop_oneof![
+        passwd().prop_map(HesiodRecord::Passwd),
+        group().prop_map(HesiodRecord::Group),
+        service().prop_map(HesiodRecord::Service),
+        filsys().prop_map(HesiodRecord::Filsys),
+    ]
+}
+
+proptest! {
+    /// Property: from_txt inverts to_txt for every passwd record.
+    #[test]
+    fn prop_passwd_exact_roundtrip(record in passwd()) {
+        prop_assert_eq!(PasswdRecord::from_txt(&record.to_txt()).expect("parses"), record);
+    }
+
+    /// Property: from_txt inverts to_txt for every group record.
+    #[test]
+    fn prop_group_exact_roundtrip(record in group()) {
+        prop_assert_eq!(GroupRecord::from_txt(&record.to_txt()).expect("parses"), record);
+    }
+
+    /// Property: from_txt inverts to_txt for every service record.
+    #[test]
+    fn prop_service_exact_roundtrip(record in service()) {
+        prop_assert_eq!(ServiceRecord::from_txt(&record.to_txt()).expect("parses"), record);
+    }
+
+    /// Property: from_txt inverts to_txt for every filsys record.
+    #[test]
+    fn prop_filsys_exact_roundtrip(record in filsys()) {
+        prop_assert_eq!(FilsysRecord::from_txt(&record.to_txt()).expect("parses"), record);
+    }
+
+    /// Property: HesiodRecord::from_txt inverts to_txt under the record's map type.
+    #[test]
+    fn prop_record_exact_roundtrip(record in record()) {
+        let parsed = HesiodRecord::from_txt(record.map_type(), &record.to_txt()).expect("parses");
+        prop_assert_eq!(parsed, record);
+    }
+}
+
+fn map_type() -> impl Strategy<Value = MapType> {
+    prop::sample::select(MapType::ALL.to_vec())
+}
+
+proptest! {
+    /// Property: from_bind_name inverts to_bind_name, for dotted and
+    /// mixed-case keys under any lhs and rhs.
+    #[test]
+    fn prop_bind_name_roundtrip(
+        key in r"[A-Za-z0-9_][A-Za-z0-9_-]{0,19}(\.[A-Za-z0-9_][A-Za-z0-9_-]{0,19}){0,3}",
+        map_type in map_type(),
+        lhs in r"(\.[a-z0-9][a-z0-9-]{0,9}){1,2}",
+        rhs in r"(\.[a-z0-9][a-z0-9-]{0,9}){1,3}",
+    ) {
+        let name = to_bind_name(&key, map_type, &lhs, &rhs).expect("valid name");
+        prop_assert_eq!(from_bind_name(&name, &lhs, &rhs), Some((key, map_type)));
+    }
+}
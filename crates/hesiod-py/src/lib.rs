@@ -0,0 +1,439 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Python bindings for hesiod-lib, built with maturin into a `hesiod`
+//! module: record parsing, config loading, zone building and linting, and
+//! lookups through the blocking client.
+//!
+//! ```python
+//! import hesiod
+//!
+//! zone = hesiod.Zone.from_config(hesiod.Config.from_file("hesiod.json"))
+//! assert not [f for f in zone.lint() if f["severity"] == "error"]
+//!
+//! client = hesiod.Client(["ns1.example.com"], rhs=".example.com")
+//! for record in client.resolve("alice", "passwd"):
+//!     print(record.to_dict())
+//! ```
+//!
+//! Parse and config errors raise `ValueError`; failed lookups raise
+//! `OSError`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use hesiod_lib::blocking::BlockingHesiodClient;
+use hesiod_lib::client::{RetryPolicy, Transport};
+use hesiod_lib::config::HesiodConfig;
+use hesiod_lib::error::HesiodError;
+use hesiod_lib::hesiod_conf::HesiodConf;
+use hesiod_lib::lint;
+use hesiod_lib::records::{HesiodRecord, MapType};
+use hesiod_lib::zone::HesiodZone;
+use hickory_proto::rr::DNSClass;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use serde::Serialize;
+
+fn value_error(e: HesiodError) -> PyErr {
+    PyValueError::new_err(format!("{e:#}"))
+}
+
+/// `OSError` when the lookup itself failed, `ValueError` when the answer
+/// didn't parse.
+fn lookup_error(e: HesiodError) -> PyErr {
+    match e {
+        HesiodError::Dns { .. } | HesiodError::Io { .. } => PyOSError::new_err(format!("{e:#}")),
+        _ => value_error(e),
+    }
+}
+
+fn map_type(name: &str) -> PyResult<MapType> {
+    name.parse().map_err(value_error)
+}
+
+/// Plain Python dicts and lists for `value`, via `json.loads`.
+fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// A passwd, group, service or filsys record.
+#[pyclass(name = "Record", module = "hesiod", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyRecord(HesiodRecord);
+
+#[pymethods]
+impl PyRecord {
+    /// Parse the TXT data of a `map_type` record.
+    #[staticmethod]
+    fn from_txt(map_type: &str, txt: &str) -> PyResult<Self> {
+        HesiodRecord::from_txt(self::map_type(map_type)?, txt)
+            .map(Self)
+            .map_err(value_error)
+    }
+
+    /// Field dict as written in JSON configs and exports, with a `type` key.
+    #[staticmethod]
+    fn from_dict(py: Python<'_>, fields: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let json: String = py
+            .import("json")?
+            .call_method1("dumps", (fields,))?
+            .extract()?;
+        serde_json::from_str(&json)
+            .map(Self)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn map_type(&self) -> &'static str {
+        self.0.map_type().label()
+    }
+
+    #[getter]
+    fn key(&self) -> &str {
+        self.0.key()
+    }
+
+    fn to_txt(&self) -> String {
+        self.0.to_txt()
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.0)
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_txt()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Record.from_txt({:?}, {:?})",
+            self.0.map_type().label(),
+            self.0.to_txt()
+        )
+    }
+}
+
+/// A zone config, as exported from Nickel.
+#[pyclass(name = "Config", module = "hesiod", frozen)]
+struct PyConfig(HesiodConfig);
+
+#[pymethods]
+impl PyConfig {
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        HesiodConfig::from_json(json).map(Self).map_err(value_error)
+    }
+
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<Self> {
+        HesiodConfig::from_file(&path)
+            .map(Self)
+            .map_err(value_error)
+    }
+
+    #[getter]
+    fn domain(&self) -> &str {
+        &self.0.domain
+    }
+
+    #[getter]
+    fn lhs(&self) -> &str {
+        &self.0.lhs
+    }
+
+    #[getter]
+    fn rhs(&self) -> &str {
+        &self.0.rhs
+    }
+
+    #[getter]
+    fn ttl(&self) -> u32 {
+        self.0.ttl
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.0)
+    }
+
+    /// Findings for the zone built from this config, as dicts.
+    fn lint<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let findings = lint::lint_config(&self.0).map_err(value_error)?;
+        to_python(py, &findings)
+    }
+}
+
+/// An in-memory Hesiod zone.
+#[pyclass(name = "Zone", module = "hesiod", frozen)]
+struct PyZone(HesiodZone);
+
+#[pymethods]
+impl PyZone {
+    #[staticmethod]
+    fn from_config(config: &PyConfig) -> PyResult<Self> {
+        HesiodZone::from_config(&config.0)
+            .map(Self)
+            .map_err(value_error)
+    }
+
+    /// Read records back from BIND zone file text.
+    #[staticmethod]
+    fn from_bind(text: &str) -> PyResult<Self> {
+        HesiodZone::from_bind_zone(text)
+            .map(Self)
+            .map_err(value_error)
+    }
+
+    #[getter]
+    fn domain(&self) -> &str {
+        &self.0.domain
+    }
+
+    #[getter]
+    fn checksum(&self) -> &str {
+        self.0.checksum()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.record_count()
+    }
+
+    fn lookup(&self, key: &str, map_type: &str) -> PyResult<Option<PyRecord>> {
+        Ok(self
+            .0
+            .lookup(key, self::map_type(map_type)?)
+            .cloned()
+            .map(PyRecord))
+    }
+
+    /// `(name, record)` pairs in zone order.
+    fn records(&self) -> Vec<(String, PyRecord)> {
+        self.0
+            .records()
+            .map(|(name, record)| (name.to_string(), PyRecord(record.clone())))
+            .collect()
+    }
+
+    #[pyo3(signature = (serial = None))]
+    fn to_bind(&self, serial: Option<u32>) -> String {
+        match serial {
+            Some(serial) => self.0.to_bind_zone_with_serial(serial),
+            None => self.0.to_bind_zone(),
+        }
+    }
+
+    fn lint<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &lint::lint_zone(&self.0))
+    }
+}
+
+/// Queries Hesiod servers. Names are built from `/etc/hesiod.conf` and
+/// `HES_DOMAIN` unless `lhs` or `rhs` is given. Lookups release the GIL.
+#[pyclass(name = "Client", module = "hesiod", frozen)]
+struct PyClient(BlockingHesiodClient);
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    #[pyo3(signature = (
+        servers,
+        *,
+        lhs = None,
+        rhs = None,
+        class_ = "HS",
+        transport = "udp",
+        port = None,
+        timeout = None,
+        retries = 0,
+        cache = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        servers: Vec<String>,
+        lhs: Option<&str>,
+        rhs: Option<&str>,
+        class_: &str,
+        transport: &str,
+        port: Option<u16>,
+        timeout: Option<f64>,
+        retries: u32,
+        cache: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut naming = HesiodConf::from_system().map_err(value_error)?;
+        if let Some(lhs) = lhs {
+            naming.set_lhs(lhs);
+        }
+        if let Some(rhs) = rhs {
+            naming.set_rhs(rhs);
+        }
+        let class = match class_.to_ascii_uppercase().as_str() {
+            "HS" => DNSClass::HS,
+            "IN" => DNSClass::IN,
+            other => return Err(PyValueError::new_err(format!("unknown class: {other}"))),
+        };
+        let transport = match transport.to_ascii_lowercase().as_str() {
+            "udp" => Transport::Udp,
+            "tcp" => Transport::Tcp,
+            "tls" => Transport::Tls,
+            "https" => Transport::Https,
+            other => {
+                return Err(PyValueError::new_err(format!("unknown transport: {other}")));
+            }
+        };
+        let mut client = BlockingHesiodClient::new(servers, naming)
+            .map_err(value_error)?
+            .with_class(class)
+            .with_transport(transport)
+            .with_retries(RetryPolicy::retries(retries));
+        if let Some(port) = port {
+            client = client.with_port(port);
+        }
+        if let Some(max_entries) = cache {
+            client = client.with_cache(max_entries);
+        }
+        if let Some(timeout) = timeout {
+            let timeout = Duration::try_from_secs_f64(timeout)
+                .map_err(|e| PyValueError::new_err(format!("invalid timeout: {e}")))?;
+            client = client.with_timeout(timeout);
+        }
+        Ok(Self(client))
+    }
+
+    /// Raw TXT strings for `key` in `map_type`.
+    fn lookup(&self, py: Python<'_>, key: &str, map_type: &str) -> PyResult<Vec<String>> {
+        let map_type = self::map_type(map_type)?;
+        py.allow_threads(|| self.0.lookup(key, map_type))
+            .map(|answer| answer.txts)
+            .map_err(lookup_error)
+    }
+
+    /// Parsed records for `key` in `map_type`; empty if there are none.
+    fn resolve(&self, py: Python<'_>, key: &str, map_type: &str) -> PyResult<Vec<PyRecord>> {
+        let map_type = self::map_type(map_type)?;
+        let records = py
+            .allow_threads(|| self.0.resolve(key, map_type))
+            .map_err(lookup_error)?;
+        Ok(records.into_iter().map(PyRecord).collect())
+    }
+
+    /// Records for `key` in every map type, keyed by map name.
+    fn resolve_all(
+        &self,
+        py: Python<'_>,
+        key: &str,
+    ) -> PyResult<std::collections::BTreeMap<&'static str, Vec<PyRecord>>> {
+        let all = py
+            .allow_threads(|| self.0.resolve_all(key))
+            .map_err(lookup_error)?;
+        Ok(all
+            .into_iter()
+            .map(|(map_type, records)| {
+                (
+                    map_type.label(),
+                    records.into_iter().map(PyRecord).collect(),
+                )
+            })
+            .collect())
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+
+    fn cache_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.0.cache_stats())
+    }
+}
+
+#[pymodule]
+fn hesiod(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyRecord>()?;
+    m.add_class::<PyConfig>()?;
+    m.add_class::<PyZone>()?;
+    m.add_class::<PyClient>()?;
+    m.add("MAP_TYPES", ["passwd", "group", "service", "filsys"])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use pyo3::sync::GILOnceCell;
+    use pyo3::types::PyDict;
+
+    use super::*;
+
+    /// Run `code` with the module imported as `hesiod`. Modules built for
+    /// the abi3 API can only be initialized once per process.
+    fn run(code: &CStr) -> PyResult<()> {
+        static MODULE: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = MODULE.get_or_init(py, || pyo3::wrap_pymodule!(hesiod)(py));
+            let globals = PyDict::new(py);
+            globals.set_item("hesiod", module)?;
+            py.run(code, Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn records_round_trip_through_python() {
+        run(c"
+r = hesiod.Record.from_txt('service', 'web:443:tcp')
+assert (r.map_type, r.key, r.to_txt()) == ('service', 'web', 'web:443:tcp')
+assert r.to_dict() == {'type': 'service', 'host': 'web', 'port': 443, 'protocol': 'tcp'}
+assert hesiod.Record.from_dict(r.to_dict()) == r
+assert repr(r) == 'Record.from_txt(\"service\", \"web:443:tcp\")'
+for txt in ('web:https:tcp', 'web:443'):
+    try:
+        hesiod.Record.from_txt('service', txt)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(txt)
+")
+        .expect("TODO: handle error");
+    }
+
+    #[test]
+    fn zones_build_from_config_json() {
+        run(c"
+config = hesiod.Config.from_json('''{
+    \"domain\": \"example.internal\", \"lhs\": \".ns\", \"rhs\": \".example.internal\",
+    \"services\": [{\"name\": \"web\", \"host\": \"web.svc\", \"port\": 443, \"protocol\": \"tcp\"}]
+}''')
+zone = hesiod.Zone.from_config(config)
+assert (zone.domain, len(zone)) == ('example.internal', 1)
+assert zone.lookup('web', 'service').to_txt() == 'web.svc:443:tcp'
+assert zone.lookup('nobody', 'passwd') is None
+bind = zone.to_bind(serial=7)
+assert 'web.service.ns' in bind and '\t7 ; serial' in bind
+try:
+    hesiod.Config.from_json('{}')
+except ValueError:
+    pass
+else:
+    raise AssertionError('empty config')
+")
+        .expect("TODO: handle error");
+    }
+
+    #[test]
+    fn lookup_errors_map_to_python_exceptions_by_kind() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let parse = HesiodError::record_parse(MapType::Service, "port", "not a number");
+            assert!(lookup_error(parse).is_instance_of::<PyValueError>(py));
+            let config = HesiodError::config("bad hesiod.conf");
+            assert!(lookup_error(config).is_instance_of::<PyValueError>(py));
+            let dns = HesiodError::dns("timed out");
+            assert!(lookup_error(dns).is_instance_of::<PyOSError>(py));
+            let io = HesiodError::Io {
+                message: "send".into(),
+                source: std::io::ErrorKind::ConnectionRefused.into(),
+            };
+            assert!(lookup_error(io).is_instance_of::<PyOSError>(py));
+        });
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Answer arbitrary bytes as a DNS query, as the UDP listener does.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use hesiod_lib::server::{DnsServerState, handle_query};
+use hesiod_lib::zone::HesiodZone;
+use libfuzzer_sys::fuzz_target;
+
+static STATE: LazyLock<DnsServerState> = LazyLock::new(|| {
+    let zone = HesiodZone::from_bind_zone(include_str!("../../zones/example.hs"))
+        .expect("example zone parses");
+    DnsServerState::new(zone)
+});
+
+fuzz_target!(|data: &[u8]| {
+    let _ = handle_query(data, &STATE);
+});
@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Parse arbitrary TXT data as a record of every map type.
+
+#![no_main]
+
+use hesiod_lib::records::{HesiodRecord, MapType};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|txt: &str| {
+    for map_type in MapType::ALL {
+        let _ = HesiodRecord::from_txt(map_type, txt);
+    }
+});
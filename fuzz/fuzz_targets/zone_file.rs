@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: MPL-2.0
+//! Load arbitrary text as a BIND zone file.
+
+#![no_main]
+
+use hesiod_lib::zone::HesiodZone;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|content: &str| {
+    let _ = HesiodZone::from_bind_zone(content);
+});